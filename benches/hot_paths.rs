@@ -0,0 +1,248 @@
+//! Benchmarks for the paths every payment goes through: recording and summarizing in `Db`,
+//! parsing the `/payments` body, encoding the `/payments-summary` response and submitting to a
+//! processor over HTTP/1.1 and HTTP/2, the latter printing latency percentiles of each as well.
+//!
+//! Run with `cargo bench`, add `--features fast-json` to include the hand-rolled parser.
+
+use std::{
+    hint::black_box,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use axum::{Router, http::StatusCode, routing::post, serve::ListenerExt};
+use client_full::{
+    Db, PaymentPayload, ProcessorSummaries, Summary, bench::BenchReport,
+    correlation::CorrelationId, processor::HttpSettings,
+};
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use futures_util::future::join_all;
+
+// Payments each thread records per iteration of the contended benchmark
+const SETS_PER_THREAD: u64 = 1_000;
+// Distinct timestamps the summary benchmarks are spread over
+const ENTRIES: i64 = 100_000;
+// Submissions in flight at once per iteration of the processor benchmark, enough for HTTP/1.1 to
+// open a connection for each while HTTP/2 multiplexes them onto one
+const SUBMISSIONS: usize = 64;
+
+const PAYLOAD: &[u8] =
+    br#"{"correlationId":"4a7901b8-7d26-4d9d-aa19-4dc1c7cf60b3","amount":19.90}"#;
+
+fn correlation_id() -> CorrelationId {
+    "4a7901b8-7d26-4d9d-aa19-4dc1c7cf60b3".parse().unwrap()
+}
+
+fn db_set(c: &mut Criterion) {
+    let mut group = c.benchmark_group("db_set");
+    let id = correlation_id();
+
+    for threads in [1, 2, 4, 8] {
+        group.throughput(Throughput::Elements(threads * SETS_PER_THREAD));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(threads),
+            &threads,
+            |b, &threads| {
+                let db = Db::new(false);
+
+                b.iter(|| {
+                    thread::scope(|s| {
+                        for t in 0..threads {
+                            let db = &db;
+                            let id = &id;
+
+                            s.spawn(move || {
+                                for i in 0..SETS_PER_THREAD {
+                                    let ts = (t * SETS_PER_THREAD + i) as i64;
+                                    db.set(id, ts, 1990);
+                                }
+                            });
+                        }
+                    });
+                });
+            },
+        );
+    }
+
+    group.finish();
+}
+
+fn db_get(c: &mut Criterion) {
+    let mut group = c.benchmark_group("db_get");
+    let db = Db::new(false);
+
+    for ts in 0..ENTRIES {
+        db.add(ts, 1, 1990);
+    }
+
+    group.bench_function("cached", |b| {
+        b.iter(|| db.get(black_box(Some(ENTRIES / 4)), black_box(Some(ENTRIES / 2))))
+    });
+
+    // A different range every call, so each one walks the map
+    let mut from = 0;
+
+    group.bench_function("uncached", |b| {
+        b.iter(|| {
+            from = (from + 1) % ENTRIES;
+            db.get(black_box(Some(from)), black_box(None))
+        })
+    });
+
+    // Readers while a writer keeps invalidating their cached ranges
+    group.bench_function("with_writer", |b| {
+        let writer = Db::clone(&db);
+        let id = correlation_id();
+        let done = AtomicBool::new(false);
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                let mut ts = ENTRIES;
+
+                while !done.load(Ordering::Relaxed) {
+                    writer.set(&id, ts, 1990);
+                    ts += 1;
+                }
+            });
+
+            b.iter(|| db.get(black_box(None), black_box(None)));
+            done.store(true, Ordering::Relaxed);
+        });
+    });
+
+    group.finish();
+}
+
+fn payload(c: &mut Criterion) {
+    let mut group = c.benchmark_group("payload");
+    group.throughput(Throughput::Bytes(PAYLOAD.len() as u64));
+
+    group.bench_function("serde", |b| {
+        b.iter(|| serde_json::from_slice::<PaymentPayload>(black_box(PAYLOAD)).unwrap())
+    });
+
+    #[cfg(feature = "fast-json")]
+    group.bench_function("fast_json", |b| {
+        b.iter(|| client_full::fast_json::parse_payload(black_box(PAYLOAD)).unwrap())
+    });
+
+    // The processor request body, written into the arena
+    #[cfg(feature = "fast-json")]
+    group.bench_function("fast_json_body", |b| {
+        let payment = client_full::Payment {
+            correlation_id: correlation_id(),
+            amount: 19.9,
+            requested_at: chrono::Utc::now(),
+            trace: client_full::trace::TraceContext::generate(),
+            tenant: None,
+        };
+
+        b.iter(|| client_full::fast_json::payment_body(black_box(&payment)))
+    });
+
+    group.finish();
+}
+
+fn summary(c: &mut Criterion) {
+    let mut summaries = ProcessorSummaries {
+        default_sum: Summary {
+            total_requests: 14_352,
+            total_amount: 285_604.8,
+            ..Default::default()
+        },
+        fallback: Summary {
+            total_requests: 1_207,
+            total_amount: 24_019.3,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    c.bench_function("summary/serialize", |b| {
+        b.iter(|| serde_json::to_vec(black_box(&summaries)).unwrap())
+    });
+
+    summaries.apply_fees(0.05, 0.15);
+
+    c.bench_function("summary/serialize_with_fees", |b| {
+        b.iter(|| serde_json::to_vec(black_box(&summaries)).unwrap())
+    });
+}
+
+/// A processor accepting every payment right away, so only the client and the protocol are timed.
+async fn mock_processor() -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/payments", listener.local_addr().unwrap());
+    // Otherwise small HTTP/2 frames wait out delayed ACKs, which would be timed instead
+    let listener = listener.tap_io(|stream| stream.set_nodelay(true).unwrap());
+    let app = Router::new().route("/payments", post(|| async { StatusCode::OK }));
+
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    url
+}
+
+fn processor_submit(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let url = rt.block_on(mock_processor());
+    let mut group = c.benchmark_group("processor_submit");
+    group.throughput(Throughput::Elements(SUBMISSIONS as u64));
+
+    for (name, http2) in [("http1", false), ("http2", true)] {
+        let http = HttpSettings {
+            http2,
+            pool_max_idle: SUBMISSIONS,
+            connect_timeout: Duration::ZERO,
+        }
+        .build();
+        let mut latencies = Vec::new();
+
+        group.bench_function(name, |b| {
+            b.iter_custom(|iters| {
+                rt.block_on(async {
+                    let start = Instant::now();
+
+                    for _ in 0..iters {
+                        let submissions = (0..SUBMISSIONS).map(|_| async {
+                            let sent_at = Instant::now();
+                            let resp = http
+                                .post(&url)
+                                .header("content-type", "application/json")
+                                .body(PAYLOAD)
+                                .send()
+                                .await
+                                .unwrap();
+                            assert!(resp.status().is_success());
+
+                            sent_at.elapsed()
+                        });
+
+                        latencies.extend(join_all(submissions).await);
+                    }
+
+                    start.elapsed()
+                })
+            })
+        });
+
+        latencies.sort_unstable();
+        let report = BenchReport {
+            failed: 0,
+            elapsed: Duration::ZERO,
+            latencies,
+        };
+        println!(
+            "processor_submit/{name}: p50 {:?}, p99 {:?}, max {:?} over {} submissions",
+            report.percentile(50.0),
+            report.percentile(99.0),
+            report.percentile(100.0),
+            report.latencies.len()
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, db_set, db_get, payload, summary, processor_submit);
+criterion_main!(benches);
@@ -0,0 +1,22 @@
+use std::{env, process::Command};
+
+// Embeds the commit being built as `GIT_SHA`, reported by `GET /admin/info`. Builds without the
+// repository, like the Docker one, can pass it through the `GIT_SHA` env var instead.
+fn main() {
+    let sha = env::var("GIT_SHA").ok().or_else(|| {
+        Command::new("git")
+            .args(["rev-parse", "--short", "HEAD"])
+            .output()
+            .ok()
+            .filter(|out| out.status.success())
+            .and_then(|out| String::from_utf8(out.stdout).ok())
+    });
+
+    println!(
+        "cargo:rustc-env=GIT_SHA={}",
+        sha.as_deref().map_or("unknown", str::trim)
+    );
+    println!("cargo:rerun-if-env-changed=GIT_SHA");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+}
@@ -0,0 +1,84 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::correlation::CorrelationId;
+
+/// The latest requests served, oldest dropped first, to look into slow requests during a load
+/// test without logging every one of them.
+pub struct AccessLog {
+    capacity: usize,
+    entries: Mutex<VecDeque<AccessEntry>>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct AccessEntry {
+    pub at: DateTime<Utc>,
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    pub latency_micros: u64,
+    #[serde(rename = "correlationId", skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<CorrelationId>,
+}
+
+/// Response extension naming the payment a request was about, picked up by `access_log`.
+#[derive(Clone)]
+pub struct LoggedPayment(pub CorrelationId);
+
+impl AccessLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    pub fn record(&self, entry: AccessEntry) {
+        let mut entries = self.entries.lock().unwrap();
+
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+
+        entries.push_back(entry);
+    }
+
+    /// Every entry kept, oldest first.
+    pub fn recent(&self) -> Vec<AccessEntry> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+pub async fn access_log(State(log): State<Arc<AccessLog>>, req: Request, next: Next) -> Response {
+    let at = Utc::now();
+    let started = Instant::now();
+    let method = req.method().to_string();
+    let path = req.uri().path().to_string();
+
+    let resp = next.run(req).await;
+
+    log.record(AccessEntry {
+        at,
+        method,
+        path,
+        status: resp.status().as_u16(),
+        latency_micros: started.elapsed().as_micros() as u64,
+        correlation_id: resp
+            .extensions()
+            .get::<LoggedPayment>()
+            .map(|LoggedPayment(id)| id.clone()),
+    });
+
+    resp
+}
@@ -0,0 +1,28 @@
+use std::cell::RefCell;
+
+use bytes::{Bytes, BytesMut};
+
+// Bytes set aside at once for the buffers built on a thread
+const CHUNK: usize = 64 * 1024;
+// A buffer is started in a new chunk when less than this is left in the current one
+const MIN_FREE: usize = 1024;
+
+thread_local! {
+    static ARENA: RefCell<BytesMut> = RefCell::new(BytesMut::with_capacity(CHUNK));
+}
+
+/// Builds a request body with `f` in the calling thread's arena instead of allocating a buffer of
+/// its own. Bodies are carved off one chunk until it runs out, and a chunk is reused rather than
+/// reallocated once every body carved off it has been dropped, so at a steady rate the buffers
+/// sent to the processors and peers hardly ever reach the allocator.
+pub fn build(f: impl FnOnce(&mut BytesMut)) -> Bytes {
+    ARENA.with_borrow_mut(|arena| {
+        if arena.capacity() < MIN_FREE {
+            arena.reserve(CHUNK);
+        }
+
+        f(arena);
+
+        arena.split().freeze()
+    })
+}
@@ -0,0 +1,42 @@
+//! Shared-secret check for the `/admin` and `/internal` endpoints, once `INTERNAL_TOKEN` is set.
+//! Peers send it in `X-Internal-Token` on their internal requests, operators on admin ones.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+pub const INTERNAL_TOKEN_HEADER: &str = "x-internal-token";
+
+/// Whether `presented` is `token`. Compared in constant time, so how long the check takes doesn't
+/// tell how much of the token was right.
+pub fn verify(token: &str, presented: Option<&[u8]>) -> bool {
+    let Some(presented) = presented else {
+        return false;
+    };
+
+    presented.len() == token.len()
+        && presented
+            .iter()
+            .zip(token.as_bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Rejects requests with 401 unless they carry the token.
+pub async fn require_token(State(token): State<Arc<str>>, req: Request, next: Next) -> Response {
+    let presented = req
+        .headers()
+        .get(INTERNAL_TOKEN_HEADER)
+        .map(|value| value.as_bytes());
+
+    if !verify(&token, presented) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    next.run(req).await
+}
@@ -0,0 +1,209 @@
+use std::{fmt, str::FromStr};
+
+use async_trait::async_trait;
+
+use crate::{Config, Db, Processor, correlation::CorrelationId, dedup::DedupStats};
+
+pub type BackendError = Box<dyn std::error::Error + Send + Sync>;
+
+// (request_count, total_amount) for the default and the fallback processor
+pub type Totals = [(u64, u64); 2];
+
+/// Where confirmed payments are recorded and summarized from. Handlers only ever go through this
+/// trait, so stores can be swapped through `DB_BACKEND` alone.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    async fn record(
+        &self,
+        processor: Processor,
+        correlation_id: &CorrelationId,
+        timestamp: i64,
+        amount: u64,
+    ) -> Result<(), BackendError>;
+
+    /// Returns (request_count, total_amount) recorded for `processor` within `[from, to]`.
+    async fn summarize(
+        &self,
+        processor: Processor,
+        from: Option<i64>,
+        to: Option<i64>,
+    ) -> Result<(u64, u64), BackendError>;
+
+    /// Like `summarize` for both processors at once, in a single pass where the store allows it.
+    async fn summarize_all(
+        &self,
+        from: Option<i64>,
+        to: Option<i64>,
+    ) -> Result<Totals, BackendError> {
+        Ok([
+            self.summarize(Processor::Default, from, to).await?,
+            self.summarize(Processor::Fallback, from, to).await?,
+        ])
+    }
+
+    /// Whether every instance writes to this same store, so summaries need no peer query.
+    fn is_shared(&self) -> bool;
+}
+
+/// The in-process Dbs, one per processor.
+#[derive(Clone, Default)]
+pub struct MemoryStorage {
+    pub default: Db,
+    pub fallback: Db,
+}
+
+impl MemoryStorage {
+    pub fn new(config: &Config) -> Self {
+        let db = || {
+            Db::with_resolution(config.db_dedup, config.storage_resolution)
+                .with_limit(
+                    config.db_max_entries(),
+                    config.compaction_bucket.as_micros() as i64,
+                )
+                .with_dedup_window(config.db_dedup_ttl, config.db_dedup_capacity)
+        };
+
+        Self {
+            default: db(),
+            fallback: db(),
+        }
+    }
+
+    /// See `Db::memory_bytes`.
+    pub fn memory_bytes(&self) -> usize {
+        self.default.memory_bytes() + self.fallback.memory_bytes()
+    }
+
+    /// Both Dbs' `Db::dedup_stats` added up.
+    pub fn dedup_stats(&self) -> Option<DedupStats> {
+        let mut stats = self.default.dedup_stats()?;
+        stats.add(&self.fallback.dedup_stats()?);
+
+        Some(stats)
+    }
+
+    pub fn db(&self, processor: Processor) -> &Db {
+        match processor {
+            Processor::Default => &self.default,
+            Processor::Fallback => &self.fallback,
+        }
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn record(
+        &self,
+        processor: Processor,
+        correlation_id: &CorrelationId,
+        timestamp: i64,
+        amount: u64,
+    ) -> Result<(), BackendError> {
+        self.db(processor).set(correlation_id, timestamp, amount);
+
+        Ok(())
+    }
+
+    async fn summarize(
+        &self,
+        processor: Processor,
+        from: Option<i64>,
+        to: Option<i64>,
+    ) -> Result<(u64, u64), BackendError> {
+        Ok(self.db(processor).summarize_async(from, to).await)
+    }
+
+    fn is_shared(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BackendKind {
+    // The in-process Dbs
+    #[default]
+    Memory,
+    Mmap,
+    Redis,
+    Postgres,
+}
+
+impl FromStr for BackendKind {
+    type Err = UnknownBackend;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "memory" => Ok(Self::Memory),
+            "mmap" => Ok(Self::Mmap),
+            "redis" => Ok(Self::Redis),
+            "postgres" => Ok(Self::Postgres),
+            _ => Err(UnknownBackend),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownBackend;
+
+impl fmt::Display for UnknownBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown Db backend")
+    }
+}
+
+impl std::error::Error for UnknownBackend {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(n: u32) -> CorrelationId {
+        format!("4a7901b8-7d26-4d9d-aa19-{n:012}").parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn memory_storage_keeps_processors_apart() {
+        let storage = MemoryStorage::default();
+
+        storage
+            .record(Processor::Default, &id(0), 10, 100)
+            .await
+            .unwrap();
+        storage
+            .record(Processor::Default, &id(1), 20, 200)
+            .await
+            .unwrap();
+        storage
+            .record(Processor::Fallback, &id(2), 30, 300)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            storage.summarize_all(None, None).await.unwrap(),
+            [(2, 300), (1, 300)]
+        );
+        assert_eq!(
+            storage
+                .summarize(Processor::Default, Some(15), None)
+                .await
+                .unwrap(),
+            (1, 200)
+        );
+        assert!(!storage.is_shared());
+    }
+
+    #[test]
+    fn backends_parse_from_config_names() {
+        assert_eq!(
+            "memory".parse::<BackendKind>().unwrap(),
+            BackendKind::Memory
+        );
+        assert_eq!("mmap".parse::<BackendKind>().unwrap(), BackendKind::Mmap);
+        assert_eq!("redis".parse::<BackendKind>().unwrap(), BackendKind::Redis);
+        assert_eq!(
+            "postgres".parse::<BackendKind>().unwrap(),
+            BackendKind::Postgres
+        );
+        assert!("sqlite".parse::<BackendKind>().is_err());
+    }
+}
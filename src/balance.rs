@@ -0,0 +1,127 @@
+//! Spreading `/payments` across instances without a load balancer in front. The instance taking
+//! public traffic keeps part of it and proxies the rest to its peers' `/internal/payments`, which
+//! enqueue it without balancing again.
+
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use crate::{Config, correlation::CorrelationId, partition};
+
+// Resolution of the proxied fraction
+const SHARES: u64 = 10_000;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BalanceMode {
+    // Something in front spreads the traffic
+    #[default]
+    Off,
+    // A given correlationId always goes to the same peer
+    Hash,
+    // To the peer with the fewest proxied payments still in flight
+    LeastLoaded,
+}
+
+impl FromStr for BalanceMode {
+    type Err = UnknownBalanceMode;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Self::Off),
+            "hash" => Ok(Self::Hash),
+            "least-loaded" => Ok(Self::LeastLoaded),
+            _ => Err(UnknownBalanceMode),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownBalanceMode;
+
+impl fmt::Display for UnknownBalanceMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown balance mode")
+    }
+}
+
+impl std::error::Error for UnknownBalanceMode {}
+
+/// Picks which payments are proxied, and to which peer.
+pub struct Balancer {
+    mode: BalanceMode,
+    // Out of `SHARES`
+    shares: u64,
+    in_flight: Vec<AtomicUsize>,
+}
+
+impl Balancer {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            mode: config.balance_mode,
+            shares: (config.balance_fraction.clamp(0.0, 1.0) * SHARES as f64).round() as u64,
+            in_flight: config
+                .peer_urls
+                .iter()
+                .map(|_| AtomicUsize::new(0))
+                .collect(),
+        }
+    }
+
+    /// Index into `peer_urls` of the peer `correlation_id` is proxied to, `None` to keep it.
+    ///
+    /// Whether a payment is proxied depends only on its correlationId, so a client retrying it
+    /// sees the same instance take it.
+    pub fn pick(&self, correlation_id: &CorrelationId) -> Option<usize> {
+        if self.mode == BalanceMode::Off || self.in_flight.is_empty() {
+            return None;
+        }
+
+        let hash = partition::hash(correlation_id);
+
+        if hash % SHARES >= self.shares {
+            return None;
+        }
+
+        match self.mode {
+            BalanceMode::Off => None,
+            // The bits the fraction didn't use, so the peers still see even shares
+            BalanceMode::Hash => Some((hash / SHARES) as usize % self.in_flight.len()),
+            BalanceMode::LeastLoaded => self
+                .in_flight
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, n)| n.load(Ordering::Relaxed))
+                .map(|(peer, _)| peer),
+        }
+    }
+
+    /// Counts a payment proxied to `peer` as in flight until the guard is dropped.
+    pub fn track(self: &Arc<Self>, peer: usize) -> InFlight {
+        self.in_flight[peer].fetch_add(1, Ordering::Relaxed);
+
+        InFlight {
+            balancer: self.clone(),
+            peer,
+        }
+    }
+
+    pub fn in_flight(&self, peer: usize) -> usize {
+        self.in_flight[peer].load(Ordering::Relaxed)
+    }
+}
+
+pub struct InFlight {
+    balancer: Arc<Balancer>,
+    peer: usize,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.balancer.in_flight[self.peer].fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,180 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use uuid::Uuid;
+
+use crate::{PaymentPayload, client::ShowdownClient, correlation::CorrelationId};
+
+pub struct BenchOptions {
+    pub target: String,
+    pub requests: usize,
+    pub concurrency: usize,
+    pub amount: f64,
+}
+
+pub struct BenchReport {
+    pub failed: usize,
+    pub elapsed: Duration,
+    // Sorted latencies of every request, failed ones included
+    pub latencies: Vec<Duration>,
+}
+
+impl BenchReport {
+    pub fn percentile(&self, p: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+
+        let idx = ((self.latencies.len() - 1) as f64 * p / 100.0).round() as usize;
+
+        self.latencies[idx]
+    }
+
+    pub fn throughput(&self) -> f64 {
+        self.latencies.len() as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// Fires `requests` synthetic payments at `{target}/payments` from `concurrency` tasks.
+pub async fn run(opts: &BenchOptions) -> BenchReport {
+    let http = reqwest::Client::builder()
+        .tcp_nodelay(true)
+        .build()
+        .unwrap();
+    let client = ShowdownClient::with_http(http, opts.target.trim_end_matches('/'));
+    let next = Arc::new(AtomicUsize::new(0));
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as u64;
+    let start = Instant::now();
+    let mut tasks = Vec::with_capacity(opts.concurrency);
+
+    for _ in 0..opts.concurrency {
+        let client = client.clone();
+        let next = next.clone();
+        let total = opts.requests;
+        let amount = opts.amount;
+
+        tasks.push(tokio::spawn(async move {
+            let mut latencies = Vec::new();
+            let mut failed = 0;
+
+            loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+
+                if i >= total {
+                    break;
+                }
+
+                let payload = PaymentPayload {
+                    correlation_id: synthetic_id(seed, i as u64),
+                    amount,
+                    tenant_id: None,
+                };
+                let sent_at = Instant::now();
+                let ok = client.submit_payment(&payload).await.is_ok();
+
+                latencies.push(sent_at.elapsed());
+
+                if !ok {
+                    failed += 1;
+                }
+            }
+
+            (latencies, failed)
+        }));
+    }
+
+    let mut latencies = Vec::with_capacity(opts.requests);
+    let mut failed = 0;
+
+    for task in tasks {
+        let (task_latencies, task_failed) = task.await.unwrap();
+        latencies.extend(task_latencies);
+        failed += task_failed;
+    }
+
+    latencies.sort_unstable();
+
+    BenchReport {
+        failed,
+        elapsed: start.elapsed(),
+        latencies,
+    }
+}
+
+// Formats a UUID-shaped id that is unique per run and request index
+fn synthetic_id(seed: u64, i: u64) -> CorrelationId {
+    let high = (seed & !0xffff) | 0x4000 | (seed & 0xfff);
+    let low = (0x8000 | ((i >> 48) & 0xfff)) << 48 | (i & 0xffff_ffff_ffff);
+
+    Uuid::from_u64_pair(high, low).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use axum::{Router, http::StatusCode, routing::post};
+
+    use super::*;
+
+    #[test]
+    fn percentiles_index_the_sorted_latencies() {
+        let report = BenchReport {
+            failed: 0,
+            elapsed: Duration::from_secs(1),
+            latencies: (1..=100).map(Duration::from_millis).collect(),
+        };
+
+        assert_eq!(report.percentile(0.0), Duration::from_millis(1));
+        assert_eq!(report.percentile(50.0), Duration::from_millis(51));
+        assert_eq!(report.percentile(99.0), Duration::from_millis(99));
+        assert_eq!(report.percentile(100.0), Duration::from_millis(100));
+        assert_eq!(report.throughput(), 100.0);
+    }
+
+    #[test]
+    fn synthetic_ids_are_distinct_v4_uuids() {
+        let ids: HashSet<_> = (0..1000)
+            .map(|i| {
+                synthetic_id(0x1234_5678_9abc_def0, i)
+                    .to_string()
+                    .parse::<Uuid>()
+                    .unwrap()
+            })
+            .collect();
+
+        assert_eq!(ids.len(), 1000);
+        assert!(
+            ids.iter()
+                .all(|id| id.get_version_num() == 4 && id.get_variant() == uuid::Variant::RFC4122)
+        );
+    }
+
+    #[tokio::test]
+    async fn every_request_is_timed_and_failures_counted() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = format!("http://{}/", listener.local_addr().unwrap());
+        let app = Router::new().route("/payments", post(|| async { StatusCode::BAD_GATEWAY }));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let report = run(&BenchOptions {
+            target,
+            requests: 20,
+            concurrency: 3,
+            amount: 19.9,
+        })
+        .await;
+
+        assert_eq!(report.latencies.len(), 20);
+        assert_eq!(report.failed, 20);
+        assert!(report.latencies.is_sorted());
+    }
+}
@@ -0,0 +1,140 @@
+//! Fault injection into outgoing processor requests and peer queries, to see retries, circuit
+//! breakers and lookups at work. Only active when built with the `chaos` feature, without it
+//! `inject` never faults and `/admin/chaos` answers 404, so call sites need no feature gates.
+
+use std::fmt;
+
+#[cfg(feature = "chaos")]
+use std::{
+    hash::{BuildHasher, Hasher, RandomState},
+    time::Duration,
+};
+
+use arc_swap::ArcSwap;
+use serde::{Deserialize, Serialize};
+
+/// Odds of each fault for one kind of request, every one of them 0 by default.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Faults {
+    // Chance of waiting `delay_ms` before sending
+    pub delay_probability: f64,
+    pub delay_ms: u64,
+    // Chance the request is sent but its answer lost
+    pub drop_probability: f64,
+    // Chance the request fails without being sent
+    pub fail_probability: f64,
+}
+
+impl Faults {
+    fn is_valid(&self) -> bool {
+        [
+            self.delay_probability,
+            self.drop_probability,
+            self.fail_probability,
+        ]
+        .iter()
+        .all(|p| (0.0..=1.0).contains(p))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ChaosSettings {
+    pub processor: Faults,
+    pub peer: Faults,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Target {
+    Processor,
+    Peer,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    Drop,
+    Fail,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Drop => write!(f, "answer dropped by chaos injection"),
+            Self::Fail => write!(f, "request failed by chaos injection"),
+        }
+    }
+}
+
+impl std::error::Error for Fault {}
+
+#[derive(Default)]
+pub struct Chaos {
+    settings: ArcSwap<ChaosSettings>,
+}
+
+impl Chaos {
+    pub fn settings(&self) -> ChaosSettings {
+        **self.settings.load()
+    }
+
+    pub fn set(&self, settings: ChaosSettings) -> Result<(), InvalidChaos> {
+        if !settings.processor.is_valid() || !settings.peer.is_valid() {
+            return Err(InvalidChaos);
+        }
+
+        self.settings.store(settings.into());
+
+        Ok(())
+    }
+
+    /// Rolls for one request to `target`, waiting here when it is to be delayed. `None` lets the
+    /// request through untouched, a `Drop` is still sent but its answer must be discarded.
+    pub async fn inject(&self, target: Target) -> Option<Fault> {
+        #[cfg(feature = "chaos")]
+        {
+            let settings = self.settings();
+            let faults = match target {
+                Target::Processor => settings.processor,
+                Target::Peer => settings.peer,
+            };
+
+            if roll() < faults.delay_probability {
+                tokio::time::sleep(Duration::from_millis(faults.delay_ms)).await;
+            }
+
+            if roll() < faults.fail_probability {
+                return Some(Fault::Fail);
+            }
+
+            if roll() < faults.drop_probability {
+                return Some(Fault::Drop);
+            }
+
+            None
+        }
+
+        #[cfg(not(feature = "chaos"))]
+        {
+            let _ = target;
+            None
+        }
+    }
+}
+
+/// Between 0 and 1. Every `RandomState` is seeded differently, plenty for injecting faults.
+#[cfg(feature = "chaos")]
+fn roll() -> f64 {
+    RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64
+}
+
+#[derive(Debug)]
+pub struct InvalidChaos;
+
+impl fmt::Display for InvalidChaos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "probabilities must be between 0 and 1")
+    }
+}
+
+impl std::error::Error for InvalidChaos {}
@@ -0,0 +1,276 @@
+//! Typed client for the HTTP API, for programs and tests driving an instance. Instances also talk
+//! to their HTTP peers through it.
+
+use std::time::Duration;
+
+use axum::http::header;
+use bytes::BufMut;
+use chrono::{DateTime, Utc};
+
+use crate::{
+    INTERNAL_SUMMARY_HEADER, PaymentPayload, ProcessorSummaries, SnapshotQueryParams,
+    SnapshotScope, SummaryQueryParams, arena,
+    auth::INTERNAL_TOKEN_HEADER,
+    clock::ClockReading,
+    consistency::Checksums,
+    protocol::{self, PROTOCOL_VERSION, ProtocolVersion},
+    trace::{TRACEPARENT, TraceContext},
+    transport::{MSGPACK, PeerEncoding},
+};
+
+pub type ClientError = Box<dyn std::error::Error + Send + Sync>;
+
+/// One instance, at `base_url`. Cheap to clone, clones share their connection pool.
+#[derive(Clone)]
+pub struct ShowdownClient {
+    http: reqwest::Client,
+    base_url: String,
+    // Sent to the `/admin` and `/internal` endpoints, for instances with `INTERNAL_TOKEN` set
+    token: Option<String>,
+    // Version of the `/internal` endpoints asked for, see `protocol`
+    protocol: u32,
+}
+
+impl ShowdownClient {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self::with_http(reqwest::Client::new(), base_url)
+    }
+
+    /// Sends requests through `http`, keeping its settings and connection pool.
+    pub fn with_http(http: reqwest::Client, base_url: impl Into<String>) -> Self {
+        Self {
+            http,
+            base_url: base_url.into(),
+            token: None,
+            protocol: PROTOCOL_VERSION,
+        }
+    }
+
+    /// Authenticates admin and internal requests with the instance's `INTERNAL_TOKEN`.
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Talks `version` of the internal API, as negotiated with the instance.
+    pub fn with_protocol(mut self, version: u32) -> Self {
+        self.protocol = version;
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// `POST /payments`. Succeeds once the payment is queued, or processed with the instance's
+    /// `SYNC_SUBMISSION`.
+    pub async fn submit_payment(&self, payload: &PaymentPayload) -> Result<(), ClientError> {
+        self.http
+            .post(self.url("/payments"))
+            .json(payload)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    /// `GET /payments-summary`.
+    pub async fn get_summary(
+        &self,
+        params: &SummaryQueryParams,
+    ) -> Result<ProcessorSummaries, ClientError> {
+        let resp = self
+            .http
+            .get(self.url("/payments-summary"))
+            .query(params)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(resp.json().await?)
+    }
+
+    /// `POST /admin/purge-payments`, dropping every payment the instance recorded.
+    pub async fn purge(&self) -> Result<(), ClientError> {
+        self.authenticated(self.http.post(self.url("/admin/purge-payments")))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    /// Whether `GET /healthz` answers with a success.
+    pub async fn is_healthy(&self) -> bool {
+        self.http
+            .get(self.url("/healthz"))
+            .send()
+            .await
+            .is_ok_and(|resp| resp.status().is_success())
+    }
+
+    /// Hands a payment this instance owns over to it.
+    pub(crate) async fn forward_payment(
+        &self,
+        payload: &PaymentPayload,
+        encoding: PeerEncoding,
+        trace: TraceContext,
+        timeout: Duration,
+    ) -> Result<(), ClientError> {
+        let request = self
+            .authenticated(self.http.post(self.internal_url("/payments")))
+            .header(TRACEPARENT, trace.header_value());
+        let request = match encoding {
+            PeerEncoding::Json => request.json(payload),
+            PeerEncoding::MessagePack => {
+                request
+                    .header(header::CONTENT_TYPE, MSGPACK)
+                    .body(arena::build(|buf| {
+                        rmp_serde::encode::write_named(&mut buf.writer(), payload).unwrap()
+                    }))
+            }
+        };
+
+        request.timeout(timeout).send().await?.error_for_status()?;
+
+        Ok(())
+    }
+
+    /// What this instance recorded itself, broken down by instance.
+    pub(crate) async fn local_summary(
+        &self,
+        params: &SummaryQueryParams,
+        encoding: PeerEncoding,
+        trace: TraceContext,
+    ) -> Result<ProcessorSummaries, ClientError> {
+        let resp = self
+            .http
+            .get(self.url("/payments-summary"))
+            .query(params)
+            .header(INTERNAL_SUMMARY_HEADER, "true")
+            .header(TRACEPARENT, trace.header_value())
+            .header(header::ACCEPT, encoding.content_type())
+            .send()
+            .await?
+            .error_for_status()?;
+
+        // Peers that don't know the asked encoding answer JSON
+        match PeerEncoding::negotiate(resp.headers().get(header::CONTENT_TYPE)) {
+            PeerEncoding::Json => Ok(resp.json().await?),
+            PeerEncoding::MessagePack => Ok(rmp_serde::from_slice(&resp.bytes().await?)?),
+        }
+    }
+
+    /// `GET /internal/ping`, the heartbeat between peers.
+    pub(crate) async fn ping(&self, timeout: Duration) -> Result<(), ClientError> {
+        self.authenticated(self.http.get(self.internal_url("/ping")))
+            .timeout(timeout)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    /// This instance's Db, or the backup it holds of its peer's, as `StateSnapshot` bytes.
+    pub(crate) async fn snapshot(
+        &self,
+        scope: SnapshotScope,
+        timeout: Duration,
+    ) -> Result<Vec<u8>, ClientError> {
+        self.snapshot_range(scope, None, None, timeout).await
+    }
+
+    /// As `snapshot`, with only the records within `[from, to]`.
+    pub(crate) async fn snapshot_range(
+        &self,
+        scope: SnapshotScope,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        timeout: Duration,
+    ) -> Result<Vec<u8>, ClientError> {
+        let params = SnapshotQueryParams {
+            scope: Some(scope),
+            from,
+            to,
+        };
+        let resp = self
+            .authenticated(self.http.get(self.internal_url("/state-snapshot")))
+            .query(&params)
+            .timeout(timeout)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(resp.bytes().await?.to_vec())
+    }
+
+    /// Checksums of this instance's Db, or of the backup it holds of its peer's, see
+    /// `consistency`. Only served from version 1 of the internal API on.
+    pub(crate) async fn checksums(
+        &self,
+        scope: SnapshotScope,
+        timeout: Duration,
+    ) -> Result<Checksums, ClientError> {
+        let params = SnapshotQueryParams {
+            scope: Some(scope),
+            from: None,
+            to: None,
+        };
+        let resp = self
+            .authenticated(self.http.get(self.internal_url("/checksums")))
+            .query(&params)
+            .timeout(timeout)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(resp.json().await?)
+    }
+
+    /// `GET /internal/clock`, read when measuring clock skew.
+    pub(crate) async fn clock(&self, timeout: Duration) -> Result<ClockReading, ClientError> {
+        let resp = self
+            .authenticated(self.http.get(self.internal_url("/clock")))
+            .timeout(timeout)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(resp.json().await?)
+    }
+
+    /// `GET /internal/version`, `ProtocolVersion::UNVERSIONED` for instances that predate it.
+    pub(crate) async fn protocol_version(
+        &self,
+        timeout: Duration,
+    ) -> Result<ProtocolVersion, ClientError> {
+        let resp = self
+            .authenticated(self.http.get(self.url("/internal/version")))
+            .timeout(timeout)
+            .send()
+            .await?;
+
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(ProtocolVersion::UNVERSIONED);
+        }
+
+        Ok(resp.error_for_status()?.json().await?)
+    }
+
+    fn authenticated(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.token {
+            Some(token) => request.header(INTERNAL_TOKEN_HEADER, token),
+            None => request,
+        }
+    }
+
+    fn internal_url(&self, path: &str) -> String {
+        self.url(&protocol::internal_path(self.protocol, path))
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.base_url)
+    }
+}
@@ -0,0 +1,83 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Mutex,
+        atomic::{AtomicI64, Ordering},
+    },
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// A peer's wall clock in microseconds since the epoch, answered by `/internal/clock`.
+#[derive(Deserialize, Serialize)]
+pub struct ClockReading {
+    pub now: i64,
+}
+
+/// How far ahead of ours each peer's clock is, in microseconds, as last measured.
+#[derive(Default)]
+pub struct ClockSkew {
+    offsets: Mutex<BTreeMap<String, i64>>,
+}
+
+impl ClockSkew {
+    pub fn record(&self, peer: &str, offset: i64) {
+        self.offsets
+            .lock()
+            .unwrap()
+            .insert(peer.to_string(), offset);
+    }
+
+    pub fn offset(&self, peer: &str) -> Option<i64> {
+        self.offsets.lock().unwrap().get(peer).copied()
+    }
+
+    pub fn all(&self) -> BTreeMap<String, i64> {
+        self.offsets.lock().unwrap().clone()
+    }
+}
+
+/// Offset of a `remote` clock read between `sent` and `received` on ours, NTP-style: the reading
+/// is taken to be from halfway through the round trip, so the error is at most half of it.
+pub fn offset(sent: i64, remote: i64, received: i64) -> i64 {
+    remote - (sent + (received - sent) / 2)
+}
+
+/// Stamps `requestedAt` on accepted payments. The wall clock as is, or a hybrid logical clock
+/// with `MONOTONIC_REQUESTED_AT`: the wall clock in microseconds unless that isn't past the last
+/// stamp, in which case one microsecond after it. Stamps are then strictly increasing within the
+/// instance through bursts and the wall clock stepping back, and catch up with it once it passes
+/// them again.
+pub struct RequestClock {
+    monotonic: bool,
+    last: AtomicI64,
+}
+
+impl RequestClock {
+    pub fn new(monotonic: bool) -> Self {
+        Self {
+            monotonic,
+            last: AtomicI64::new(i64::MIN),
+        }
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        let wall = Utc::now();
+
+        if !self.monotonic {
+            return wall;
+        }
+
+        let wall = wall.timestamp_micros();
+        let next = |last: i64| wall.max(last.saturating_add(1));
+        let last = self
+            .last
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |last| {
+                Some(next(last))
+            })
+            .unwrap();
+
+        DateTime::from_timestamp_micros(next(last)).unwrap()
+    }
+}
@@ -0,0 +1,85 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tokio::sync::OnceCell;
+
+// The value along with when it was fetched
+type Slot<V> = Arc<OnceCell<(V, Instant)>>;
+
+/// Shares one fetch between every concurrent caller asking for the same key, and hands its result
+/// to later callers for `ttl` after it completed.
+pub struct Coalescer<K, V> {
+    ttl: Duration,
+    slots: Mutex<HashMap<K, Slot<V>>>,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> Coalescer<K, V> {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            slots: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn get_or_fetch<F, Fut>(&self, key: K, fetch: F) -> V
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+    {
+        let slot = {
+            let mut slots = self.slots.lock().unwrap();
+
+            // Completed fetches are only dropped once stale, in-flight ones are always kept
+            slots.retain(|_, slot| slot.get().is_none_or(|(_, at)| at.elapsed() < self.ttl));
+            slots.entry(key).or_default().clone()
+        };
+
+        let (value, _) = slot
+            .get_or_init(|| async { (fetch().await, Instant::now()) })
+            .await;
+
+        value.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn concurrent_callers_share_one_fetch() {
+        let coalescer = Coalescer::new(Duration::from_secs(60));
+        let fetches = AtomicUsize::new(0);
+        let fetch = || async {
+            fetches.fetch_add(1, Ordering::Relaxed);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            1
+        };
+
+        let (a, b) = tokio::join!(
+            coalescer.get_or_fetch("key", fetch),
+            coalescer.get_or_fetch("key", fetch)
+        );
+
+        assert_eq!((a, b), (1, 1));
+        assert_eq!(coalescer.get_or_fetch("key", || async { 2 }).await, 1);
+        assert_eq!(coalescer.get_or_fetch("other", || async { 3 }).await, 3);
+        assert_eq!(fetches.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn stale_results_are_fetched_again() {
+        let coalescer = Coalescer::new(Duration::from_millis(10));
+
+        assert_eq!(coalescer.get_or_fetch("key", || async { 1 }).await, 1);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(coalescer.get_or_fetch("key", || async { 2 }).await, 2);
+    }
+}
@@ -0,0 +1,127 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::telemetry;
+
+// Share of the limit kept when a processor pushes back
+const BACKOFF: f64 = 0.9;
+
+/// Dispatcher slots sized by AIMD: the limit grows by one slot for every limit's worth of
+/// submissions answered within `target`, and shrinks by `BACKOFF` when a processor pushes back
+/// with a 429, a server error or a timeout. With a zero `target` it stays wherever it is set.
+pub struct AdaptiveLimit {
+    permits: Arc<Semaphore>,
+    target: Duration,
+    min: usize,
+    max: usize,
+    state: Mutex<State>,
+}
+
+struct State {
+    limit: usize,
+    // Fraction of a slot earned by fast answers since the last increase
+    credit: f64,
+    // Pushback within `target` of a decrease is taken as the same congestion
+    last_decrease: Option<Instant>,
+}
+
+impl AdaptiveLimit {
+    pub fn new(limit: usize, min: usize, max: usize, target: Duration) -> Self {
+        telemetry::concurrency_limit(limit);
+
+        Self {
+            permits: Arc::new(Semaphore::new(limit)),
+            target,
+            min: min.max(1),
+            max,
+            state: Mutex::new(State {
+                limit,
+                credit: 0.0,
+                last_decrease: None,
+            }),
+        }
+    }
+
+    pub async fn acquire(&self) -> OwnedSemaphorePermit {
+        self.permits.clone().acquire_owned().await.unwrap()
+    }
+
+    pub fn limit(&self) -> usize {
+        self.state.lock().unwrap().limit
+    }
+
+    /// Moves the limit to `limit`, which adapting then starts from.
+    pub fn set(&self, limit: usize) {
+        let mut state = self.state.lock().unwrap();
+        self.resize(&mut state, limit);
+    }
+
+    pub fn on_success(&self, latency: Duration) {
+        if self.target.is_zero() || latency > self.target {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+
+        if state.limit >= self.max {
+            return;
+        }
+
+        state.credit += 1.0 / state.limit as f64;
+
+        if state.credit >= 1.0 {
+            state.credit = 0.0;
+            let limit = state.limit + 1;
+            self.resize(&mut state, limit);
+        }
+    }
+
+    pub fn on_overload(&self) {
+        if self.target.is_zero() {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+
+        if state
+            .last_decrease
+            .is_some_and(|at| at.elapsed() < self.target)
+        {
+            return;
+        }
+
+        let limit = ((state.limit as f64 * BACKOFF) as usize).max(self.min);
+        state.last_decrease = Some(Instant::now());
+        state.credit = 0.0;
+        self.resize(&mut state, limit);
+    }
+
+    fn resize(&self, state: &mut State, limit: usize) {
+        let old = state.limit;
+        state.limit = limit;
+
+        if limit > old {
+            self.permits.add_permits(limit - old);
+        } else if limit < old {
+            // Slots in use are only taken away once their payment is done
+            let missing = (old - limit) - self.permits.forget_permits(old - limit);
+
+            if missing > 0 {
+                let permits = self.permits.clone();
+                tokio::spawn(async move {
+                    permits
+                        .acquire_many_owned(missing as u32)
+                        .await
+                        .unwrap()
+                        .forget();
+                });
+            }
+        }
+
+        telemetry::concurrency_limit(limit);
+    }
+}
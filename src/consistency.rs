@@ -0,0 +1,78 @@
+//! Checks the backup an instance keeps of its peer's Db against the peer's own, minute by minute.
+//! Both sides boil their Db down to the count and sum of each minute, served by
+//! `/internal/v1/checksums`, so a check costs a few bytes per minute rather than a whole
+//! snapshot. Minutes that differ are logged and, with `CONSISTENCY_RESYNC`, fetched again from
+//! the peer alone.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Processor, db::StateSnapshot};
+
+// Length of a checksummed bucket in micro seconds
+pub const BUCKET: i64 = 60_000_000;
+
+/// Start of the bucket `timestamp` falls in.
+pub fn bucket(timestamp: i64) -> i64 {
+    timestamp - timestamp.rem_euclid(BUCKET)
+}
+
+/// `(request_count, total_amount)` of every bucket holding records, by bucket start.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Checksums {
+    pub default: BTreeMap<i64, (u64, u64)>,
+    pub fallback: BTreeMap<i64, (u64, u64)>,
+}
+
+impl Checksums {
+    pub fn of(snapshot: &StateSnapshot) -> Self {
+        let mut checksums = Self::default();
+
+        for processor in [Processor::Default, Processor::Fallback] {
+            let buckets = checksums.buckets_mut(processor);
+
+            for (timestamp, count, amount) in snapshot.records(processor) {
+                let entry = buckets.entry(bucket(timestamp)).or_default();
+                entry.0 += count;
+                entry.1 += amount;
+            }
+        }
+
+        checksums
+    }
+
+    /// Buckets starting before `before` that differ from `other`'s, one side missing a bucket
+    /// the other has counting as a difference.
+    pub fn diverging(&self, other: &Checksums, before: i64) -> Vec<(Processor, i64)> {
+        let mut diverging = Vec::new();
+
+        for processor in [Processor::Default, Processor::Fallback] {
+            let ours = self.buckets(processor);
+            let theirs = other.buckets(processor);
+            let starts: BTreeSet<_> = ours.keys().chain(theirs.keys()).collect();
+
+            for start in starts.into_iter().take_while(|start| **start < before) {
+                if ours.get(start) != theirs.get(start) {
+                    diverging.push((processor, *start));
+                }
+            }
+        }
+
+        diverging
+    }
+
+    fn buckets(&self, processor: Processor) -> &BTreeMap<i64, (u64, u64)> {
+        match processor {
+            Processor::Default => &self.default,
+            Processor::Fallback => &self.fallback,
+        }
+    }
+
+    fn buckets_mut(&mut self, processor: Processor) -> &mut BTreeMap<i64, (u64, u64)> {
+        match processor {
+            Processor::Default => &mut self.default,
+            Processor::Fallback => &mut self.fallback,
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use uuid::{Uuid, fmt::Hyphenated};
+
+/// Room for a `CorrelationId` written out as text, see `CorrelationId::encode`.
+pub const ENCODED_LEN: usize = Hyphenated::LENGTH;
+
+/// The `correlationId` of a payment. A UUID kept in 16 bytes, so a malformed id is rejected while
+/// the body is parsed and no id is ever allocated. Built with `string-ids`, any string is taken
+/// instead.
+#[cfg(not(feature = "string-ids"))]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct CorrelationId(Uuid);
+
+#[cfg(feature = "string-ids")]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct CorrelationId(Box<str>);
+
+impl CorrelationId {
+    /// The id as it is sent to the processors, written into `buf` when it isn't kept as text.
+    #[cfg(not(feature = "string-ids"))]
+    pub fn encode<'a>(&'a self, buf: &'a mut [u8; ENCODED_LEN]) -> &'a str {
+        self.0.hyphenated().encode_lower(buf)
+    }
+
+    #[cfg(feature = "string-ids")]
+    pub fn encode<'a>(&'a self, _buf: &'a mut [u8; ENCODED_LEN]) -> &'a str {
+        &self.0
+    }
+}
+
+impl From<Uuid> for CorrelationId {
+    #[cfg(not(feature = "string-ids"))]
+    fn from(id: Uuid) -> Self {
+        Self(id)
+    }
+
+    #[cfg(feature = "string-ids")]
+    fn from(id: Uuid) -> Self {
+        Self(id.hyphenated().to_string().into())
+    }
+}
+
+impl fmt::Display for CorrelationId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for CorrelationId {
+    type Err = InvalidCorrelationId;
+
+    #[cfg(not(feature = "string-ids"))]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(Self).map_err(|_| InvalidCorrelationId)
+    }
+
+    #[cfg(feature = "string-ids")]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(s.into()))
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidCorrelationId;
+
+impl fmt::Display for InvalidCorrelationId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "correlationId must be a UUID")
+    }
+}
+
+impl std::error::Error for InvalidCorrelationId {}
@@ -1,32 +1,752 @@
 use std::{
-    collections::BTreeMap,
-    ops::Bound::{Included, Unbounded},
+    collections::{BTreeMap, HashMap},
+    fmt,
+    ops::Bound::{Excluded, Included, Unbounded},
+    str::FromStr,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
+use crate::{
+    Processor,
+    correlation::CorrelationId,
+    dedup::{DedupStats, DedupWindow},
+};
+
+// Each snapshot record is (timestamp, request_count, total_amount) as little-endian 8-byte words
+const RECORD_LEN: usize = 24;
+// Cached summaries are all dropped once there are more distinct ranges than this
+const MAX_CACHED_SUMMARIES: usize = 1024;
+// Rough heap cost of one entry, BTreeMap node overhead included
+pub const ENTRY_BYTES: usize = 40;
+// Lowest cap eviction is guaranteed to get under, see `State::evict`
+const MIN_MAX_ENTRIES: usize = 8;
+// Entries `Db::summarize_async` sums per hold of the lock, Dbs this small are summed inline
+pub(crate) const SCAN_CHUNK: usize = 4096;
+
+/// Precision timestamps are kept at. Every payment within the same step shares one entry, so a
+/// coarser step keeps the map smaller at the cost of summaries only telling payments apart to
+/// within a step.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Resolution {
+    #[default]
+    Micro,
+    Milli,
+    Second,
+}
+
+impl Resolution {
+    /// Length of a step in micro seconds.
+    pub fn step(self) -> i64 {
+        match self {
+            Self::Micro => 1,
+            Self::Milli => 1_000,
+            Self::Second => 1_000_000,
+        }
+    }
+
+    /// Start of the step `timestamp` falls in.
+    pub fn truncate(self, timestamp: i64) -> i64 {
+        timestamp - timestamp.rem_euclid(self.step())
+    }
+}
+
+impl FromStr for Resolution {
+    type Err = UnknownResolution;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "micro" => Ok(Self::Micro),
+            "milli" => Ok(Self::Milli),
+            "second" => Ok(Self::Second),
+            _ => Err(UnknownResolution),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownResolution;
+
+impl fmt::Display for UnknownResolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown storage resolution")
+    }
+}
+
+impl std::error::Error for UnknownResolution {}
+
 #[derive(Clone, Default)]
 pub struct Db {
-    // Stores the pair (request_count, total_amount) sorted by timestamp in micro seconds
-    data: Arc<Mutex<BTreeMap<i64, (u64, u64)>>>,
+    data: Arc<Mutex<State>>,
+    resolution: Resolution,
+}
+
+#[derive(Default)]
+struct State {
+    // Correlation ids confirmed lately, `None` unless dedup is enabled
+    confirmed: Option<DedupWindow>,
+    // Stores the pair (request_count, total_amount) sorted by timestamp in micro seconds, each
+    // truncated to the Db's resolution
+    entries: BTreeMap<i64, (u64, u64)>,
+    // Results of `Db::get` by range, dropped as soon as a write lands within the range
+    summaries: HashMap<(Option<i64>, Option<i64>), (u64, u64)>,
+    // Entries are evicted past this many, 0 means unlimited
+    max_entries: usize,
+    // Smallest bucket eviction compacts into, in micro seconds
+    eviction_bucket: i64,
+    // Bumped by every change to `entries`, a summary scanned in chunks is only cached when this
+    // didn't move under it
+    writes: u64,
+}
+
+impl State {
+    fn cache(&mut self, range: (Option<i64>, Option<i64>), summary: (u64, u64)) {
+        if self.summaries.len() >= MAX_CACHED_SUMMARIES {
+            self.summaries.clear();
+        }
+
+        self.summaries.insert(range, summary);
+    }
+
+    fn compact(&mut self, horizon: i64, bucket: i64) {
+        let old: Vec<(i64, (u64, u64))> = self
+            .entries
+            .range((Unbounded, Excluded(horizon)))
+            .map(|(ts, v)| (*ts, *v))
+            .collect();
+
+        for (ts, (count, sum)) in old {
+            let bucket_ts = ts - ts.rem_euclid(bucket);
+
+            if bucket_ts == ts {
+                continue;
+            }
+
+            self.entries.remove(&ts);
+            let entry = self.entries.entry(bucket_ts).or_insert((0, 0));
+            entry.0 += count;
+            entry.1 += sum;
+        }
+
+        self.writes += 1;
+        // Moving entries to their bucket start can move them out of a cached range
+        self.summaries.clear();
+    }
+
+    /// Compacts all but the newest half of the cap into buckets twice as coarse every round,
+    /// until a quarter of the cap is free again so the next eviction is a while away. The oldest
+    /// half ends up in two buckets at worst, which is under the target from `MIN_MAX_ENTRIES` on.
+    fn evict(&mut self) {
+        let target = self.max_entries * 3 / 4;
+        let keep = self.max_entries / 2;
+        let mut bucket = self.eviction_bucket.max(1);
+
+        while self.entries.len() > target {
+            let horizon = *self.entries.keys().nth(self.entries.len() - keep).unwrap();
+            self.compact(horizon, bucket);
+
+            if bucket == i64::MAX {
+                break;
+            }
+
+            bucket = bucket.saturating_mul(2);
+        }
+    }
+}
+
+/// Adds up the entries within `[from, to]`, none when the range ends before it starts.
+fn sum(entries: &BTreeMap<i64, (u64, u64)>, (from, to): (Option<i64>, Option<i64>)) -> (u64, u64) {
+    // `BTreeMap::range` panics on a range that ends before it starts
+    if let (Some(from), Some(to)) = (from, to)
+        && from > to
+    {
+        return (0, 0);
+    }
+
+    entries
+        .range((
+            from.map(Included).unwrap_or(Unbounded),
+            to.map(Included).unwrap_or(Unbounded),
+        ))
+        .fold((0, 0), |acc, (_ts, (count, sum))| {
+            (acc.0 + count, acc.1 + sum)
+        })
 }
 
 impl Db {
+    /// With `dedup`, `Db::set` ignores a correlation id it already recorded at the same timestamp,
+    /// which a retry succeeding after a timed out attempt that did go through would otherwise
+    /// count twice. Every id is remembered unless `Db::with_dedup_window` bounds them.
+    pub fn new(dedup: bool) -> Self {
+        Self::with_resolution(dedup, Resolution::Micro)
+    }
+
+    /// Keeps timestamps at `resolution`, see `Db::new` for `dedup`.
+    pub fn with_resolution(dedup: bool, resolution: Resolution) -> Self {
+        let state = State {
+            confirmed: dedup.then(DedupWindow::default),
+            ..Default::default()
+        };
+
+        Self {
+            data: Arc::new(Mutex::new(state)),
+            resolution,
+        }
+    }
+
+    /// Caps the Db at `max_entries`, 0 being unlimited. Past it, the oldest entries are compacted
+    /// into buckets of at least `bucket` micro seconds, so totals are kept exact and only the
+    /// oldest timestamps lose precision.
+    pub fn with_limit(self, max_entries: usize, bucket: i64) -> Self {
+        {
+            let mut state = self.data.lock().unwrap();
+            state.max_entries = match max_entries {
+                0 => 0,
+                n => n.max(MIN_MAX_ENTRIES),
+            };
+            state.eviction_bucket = bucket;
+        }
+
+        self
+    }
+
+    /// Forgets correlation ids last seen `ttl` ago, and the least recently seen past `capacity`,
+    /// 0 turning either bound off. Has no effect without dedup.
+    pub fn with_dedup_window(self, ttl: Duration, capacity: usize) -> Self {
+        {
+            let mut state = self.data.lock().unwrap();
+
+            if state.confirmed.is_some() {
+                state.confirmed = Some(DedupWindow::new(ttl, capacity));
+            }
+        }
+
+        self
+    }
+
+    /// How the dedup window fared, `None` without dedup.
+    pub fn dedup_stats(&self) -> Option<DedupStats> {
+        self.data
+            .lock()
+            .unwrap()
+            .confirmed
+            .as_ref()
+            .map(DedupWindow::stats)
+    }
+
+    /// Correlation ids the dedup window remembers, least recently seen first, none without dedup.
+    pub fn dedup_keys(&self) -> Vec<(CorrelationId, i64)> {
+        self.data
+            .lock()
+            .unwrap()
+            .confirmed
+            .as_ref()
+            .map_or_else(Vec::new, |confirmed| confirmed.keys().cloned().collect())
+    }
+
+    /// Remembers correlation ids as `Db::set` does, without recording anything.
+    pub fn remember(&self, keys: impl IntoIterator<Item = (CorrelationId, i64)>) {
+        if let Some(confirmed) = &mut self.data.lock().unwrap().confirmed {
+            for (correlation_id, timestamp) in keys {
+                confirmed.check(&correlation_id, timestamp);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Estimate of the heap held by entries and remembered correlation ids.
+    pub fn memory_bytes(&self) -> usize {
+        let state = self.data.lock().unwrap();
+        let confirmed = state
+            .confirmed
+            .as_ref()
+            .map_or(0, DedupWindow::memory_bytes);
+
+        state.entries.len() * ENTRY_BYTES + confirmed
+    }
+
+    /// Counts a payment as within the range when its step is, so `from` partway through a step
+    /// still takes in the whole step instead of skipping it.
     pub fn get(&self, from: Option<i64>, to: Option<i64>) -> (u64, u64) {
+        let range = self.range(from, to);
+        let mut state = self.data.lock().unwrap();
+
+        if let Some(summary) = state.summaries.get(&range) {
+            return *summary;
+        }
+
+        let summary = sum(&state.entries, range);
+        state.cache(range, summary);
+
+        summary
+    }
+
+    /// Same as `Db::get`, but a Db too large to sum at once is summed on the blocking pool, a few
+    /// thousand entries per hold of the lock, so neither the runtime nor the payments being
+    /// recorded meanwhile wait on the whole scan. Payments recorded during the scan may or may
+    /// not be counted, as with any summary racing them.
+    pub async fn summarize_async(&self, from: Option<i64>, to: Option<i64>) -> (u64, u64) {
+        let range = self.range(from, to);
+
+        {
+            let state = self.data.lock().unwrap();
+
+            if let Some(summary) = state.summaries.get(&range) {
+                return *summary;
+            }
+
+            if state.entries.len() <= SCAN_CHUNK {
+                drop(state);
+                return self.get(from, to);
+            }
+        }
+
+        let db = self.clone();
+
+        tokio::task::spawn_blocking(move || db.scan(range))
+            .await
+            .unwrap()
+    }
+
+    fn scan(&self, (from, to): (Option<i64>, Option<i64>)) -> (u64, u64) {
+        if let (Some(from), Some(to)) = (from, to)
+            && from > to
+        {
+            return (0, 0);
+        }
+
+        let writes = self.data.lock().unwrap().writes;
+        let mut start = from.map(Included).unwrap_or(Unbounded);
+        let end = to.map(Included).unwrap_or(Unbounded);
+        let mut summary = (0, 0);
+
+        loop {
+            let mut state = self.data.lock().unwrap();
+            let mut last = None;
+
+            for (ts, (count, sum)) in state.entries.range((start, end)).take(SCAN_CHUNK) {
+                summary = (summary.0 + count, summary.1 + sum);
+                last = Some(*ts);
+            }
+
+            match last {
+                Some(last) => start = Excluded(last),
+                None => {
+                    if state.writes == writes {
+                        state.cache((from, to), summary);
+                    }
+
+                    return summary;
+                }
+            }
+        }
+    }
+
+    /// `from` and `to` truncated to the Db's resolution, as entries are keyed.
+    fn range(&self, from: Option<i64>, to: Option<i64>) -> (Option<i64>, Option<i64>) {
+        (
+            from.map(|from| self.resolution.truncate(from)),
+            to.map(|to| self.resolution.truncate(to)),
+        )
+    }
+
+    /// Every entry within `[from, to]` as `(timestamp, request_count, total_amount)`, bounded the
+    /// same way as `Db::get`. Taken at once, later writes don't show up.
+    pub fn export(
+        &self,
+        from: Option<i64>,
+        to: Option<i64>,
+    ) -> impl Iterator<Item = (i64, u64, u64)> + use<> {
+        let (from, to) = self.range(from, to);
         let state = self.data.lock().unwrap();
-        let start_bound = from.map(Included).unwrap_or(Unbounded);
-        let end_bound = to.map(Included).unwrap_or(Unbounded);
+        let entries: Vec<_> = match (from, to) {
+            (Some(from), Some(to)) if from > to => Vec::new(),
+            _ => state
+                .entries
+                .range((
+                    from.map(Included).unwrap_or(Unbounded),
+                    to.map(Included).unwrap_or(Unbounded),
+                ))
+                .map(|(ts, (count, sum))| (*ts, *count, *sum))
+                .collect(),
+        };
 
-        state
-            .range((start_bound, end_bound))
-            .fold((0, 0), |acc, (_ts, (count, sum))| {
-                (acc.0 + count, acc.1 + sum)
-            })
+        entries.into_iter()
     }
 
-    pub fn set(&self, timestamp: i64, amount: u64) {
+    pub fn set(&self, correlation_id: &CorrelationId, timestamp: i64, amount: u64) {
+        let timestamp = self.resolution.truncate(timestamp);
+
+        if let Some(confirmed) = &mut self.data.lock().unwrap().confirmed
+            && confirmed.check(correlation_id, timestamp)
+        {
+            return;
+        }
+
+        self.add(timestamp, 1, amount);
+    }
+
+    /// Records `count` payments adding up to `amount` at once.
+    pub fn add(&self, timestamp: i64, count: u64, amount: u64) {
+        let timestamp = self.resolution.truncate(timestamp);
         let mut state = self.data.lock().unwrap();
-        let entry = state.entry(timestamp).or_insert((0, 0));
-        entry.0 += 1;
+        let entry = state.entries.entry(timestamp).or_insert((0, 0));
+        entry.0 += count;
         entry.1 += amount;
+        state.writes += 1;
+
+        state.summaries.retain(|(from, to), _| {
+            from.is_some_and(|from| timestamp < from) || to.is_some_and(|to| timestamp > to)
+        });
+
+        if state.max_entries > 0 && state.entries.len() > state.max_entries {
+            state.evict();
+        }
+    }
+
+    /// Drops every entry, along with the remembered correlation ids.
+    pub fn clear(&self) {
+        let mut state = self.data.lock().unwrap();
+        state.entries.clear();
+        state.summaries.clear();
+        state.writes += 1;
+
+        if let Some(confirmed) = &mut state.confirmed {
+            confirmed.clear();
+        }
+    }
+
+    /// Rolls every entry older than `horizon` into buckets of `bucket` micro seconds, keyed by the
+    /// bucket start. Counts and amounts are kept exact, only the timestamp precision is lost.
+    /// `bucket` must be positive, which `Config` makes sure of.
+    pub fn compact(&self, horizon: i64, bucket: i64) {
+        self.data.lock().unwrap().compact(horizon, bucket);
+    }
+
+    /// Serializes the whole map to bytes, see `RECORD_LEN` for the layout.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let state = self.data.lock().unwrap();
+        let mut buf = Vec::with_capacity(state.entries.len() * RECORD_LEN);
+
+        for (ts, (count, sum)) in state.entries.iter() {
+            buf.extend_from_slice(&ts.to_le_bytes());
+            buf.extend_from_slice(&count.to_le_bytes());
+            buf.extend_from_slice(&sum.to_le_bytes());
+        }
+
+        buf
+    }
+
+    /// Adds every record of a snapshot produced by `Db::snapshot` into this Db.
+    pub fn merge_snapshot(&self, bytes: &[u8]) -> Result<(), SnapshotError> {
+        if !bytes.len().is_multiple_of(RECORD_LEN) {
+            return Err(SnapshotError);
+        }
+
+        let mut state = self.data.lock().unwrap();
+
+        for (ts, count, sum) in bytes.chunks_exact(RECORD_LEN).map(decode) {
+            // The peer may keep a finer resolution
+            let ts = self.resolution.truncate(ts);
+            let entry = state.entries.entry(ts).or_insert((0, 0));
+            entry.0 += count;
+            entry.1 += sum;
+        }
+
+        state.writes += 1;
+        state.summaries.clear();
+
+        if state.max_entries > 0 && state.entries.len() > state.max_entries {
+            state.evict();
+        }
+
+        Ok(())
+    }
+}
+
+/// Snapshot of both processors' Dbs, encoded as the length of the default snapshot followed by
+/// the default and fallback snapshots.
+#[derive(Default)]
+pub struct StateSnapshot {
+    pub default: Vec<u8>,
+    pub fallback: Vec<u8>,
+}
+
+impl StateSnapshot {
+    pub fn capture(default_db: &Db, fallback_db: &Db) -> Self {
+        Self {
+            default: default_db.snapshot(),
+            fallback: fallback_db.snapshot(),
+        }
+    }
+
+    /// Appends one record for `processor`.
+    pub fn push(&mut self, processor: Processor, timestamp: i64, count: u64, amount: u64) {
+        let buf = match processor {
+            Processor::Default => &mut self.default,
+            Processor::Fallback => &mut self.fallback,
+        };
+
+        buf.extend_from_slice(&timestamp.to_le_bytes());
+        buf.extend_from_slice(&count.to_le_bytes());
+        buf.extend_from_slice(&amount.to_le_bytes());
+    }
+
+    /// Every record of `processor`'s Db as `(timestamp, request_count, total_amount)`.
+    pub fn records(&self, processor: Processor) -> impl Iterator<Item = (i64, u64, u64)> + '_ {
+        let buf = match processor {
+            Processor::Default => &self.default,
+            Processor::Fallback => &self.fallback,
+        };
+
+        buf.chunks_exact(RECORD_LEN).map(decode)
+    }
+
+    /// Keeps only the records `keep` returns true for.
+    pub fn retain(&mut self, mut keep: impl FnMut(Processor, i64) -> bool) {
+        let mut kept = Self::default();
+
+        for processor in [Processor::Default, Processor::Fallback] {
+            for (timestamp, count, amount) in self.records(processor) {
+                if keep(processor, timestamp) {
+                    kept.push(processor, timestamp, count, amount);
+                }
+            }
+        }
+
+        *self = kept;
+    }
+
+    /// Appends the records of `other`, merging the result adds up both.
+    pub fn extend(&mut self, other: &StateSnapshot) {
+        self.default.extend_from_slice(&other.default);
+        self.fallback.extend_from_slice(&other.fallback);
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(8 + self.default.len() + self.fallback.len());
+        buf.extend_from_slice(&(self.default.len() as u64).to_le_bytes());
+        buf.extend_from_slice(&self.default);
+        buf.extend_from_slice(&self.fallback);
+
+        buf
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let (len, rest) = bytes.split_first_chunk::<8>().ok_or(SnapshotError)?;
+        let len = usize::try_from(u64::from_le_bytes(*len)).map_err(|_| SnapshotError)?;
+
+        if len > rest.len() {
+            return Err(SnapshotError);
+        }
+
+        let (default, fallback) = rest.split_at(len);
+
+        if !default.len().is_multiple_of(RECORD_LEN) || !fallback.len().is_multiple_of(RECORD_LEN) {
+            return Err(SnapshotError);
+        }
+
+        Ok(Self {
+            default: default.to_vec(),
+            fallback: fallback.to_vec(),
+        })
+    }
+
+    pub fn merge_into(&self, default_db: &Db, fallback_db: &Db) -> Result<(), SnapshotError> {
+        default_db.merge_snapshot(&self.default)?;
+        fallback_db.merge_snapshot(&self.fallback)
+    }
+}
+
+fn decode(record: &[u8]) -> (i64, u64, u64) {
+    (
+        i64::from_le_bytes(record[0..8].try_into().unwrap()),
+        u64::from_le_bytes(record[8..16].try_into().unwrap()),
+        u64::from_le_bytes(record[16..24].try_into().unwrap()),
+    )
+}
+
+#[derive(Debug)]
+pub struct SnapshotError;
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "malformed Db snapshot")
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db(entries: &[(i64, u64)]) -> Db {
+        let db = Db::new(false);
+
+        for (timestamp, amount) in entries {
+            db.add(*timestamp, 1, *amount);
+        }
+
+        db
+    }
+
+    #[test]
+    fn compaction_rolls_old_entries_into_bucket_starts() {
+        let db = db(&[(-1500, 1), (-500, 2), (1000, 4), (1500, 8), (2500, 16)]);
+
+        db.compact(2000, 1000);
+
+        assert_eq!(
+            db.export(None, None).collect::<Vec<_>>(),
+            [(-2000, 1, 1), (-1000, 1, 2), (1000, 2, 12), (2500, 1, 16)]
+        );
+        assert_eq!(db.get(None, None), (5, 31));
+    }
+
+    #[test]
+    fn compaction_leaves_entries_past_the_horizon() {
+        let db = db(&[(1500, 1), (2500, 2)]);
+
+        db.compact(1500, 1000);
+
+        assert_eq!(
+            db.export(None, None).collect::<Vec<_>>(),
+            [(1500, 1, 1), (2500, 1, 2)]
+        );
+    }
+
+    #[test]
+    fn snapshots_round_trip() {
+        let db = db(&[(-5, 1), (0, 2), (7, 4)]);
+        let copy = Db::new(false);
+
+        copy.merge_snapshot(&db.snapshot()).unwrap();
+
+        assert_eq!(
+            copy.export(None, None).collect::<Vec<_>>(),
+            db.export(None, None).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn truncated_snapshots_are_rejected() {
+        let snapshot = db(&[(1, 1)]).snapshot();
+
+        assert!(
+            Db::new(false)
+                .merge_snapshot(&snapshot[..RECORD_LEN - 1])
+                .is_err()
+        );
+    }
+
+    fn cached(db: &Db) -> usize {
+        db.data.lock().unwrap().summaries.len()
+    }
+
+    #[test]
+    fn writes_within_a_cached_range_are_counted() {
+        let db = db(&[(10, 1)]);
+
+        for range in [
+            (Some(10), Some(20)),
+            (None, Some(20)),
+            (Some(20), None),
+            (None, None),
+        ] {
+            db.get(range.0, range.1);
+        }
+        assert_eq!(cached(&db), 4);
+
+        // On both ends of the ranges, which are inclusive
+        db.add(20, 1, 2);
+
+        assert_eq!(cached(&db), 0);
+        assert_eq!(db.get(Some(10), Some(20)), (2, 3));
+        assert_eq!(db.get(None, Some(20)), (2, 3));
+        assert_eq!(db.get(Some(20), None), (1, 2));
+        assert_eq!(db.get(None, None), (2, 3));
+    }
+
+    #[test]
+    fn writes_outside_a_cached_range_keep_it() {
+        let db = db(&[(10, 1)]);
+
+        db.get(Some(10), Some(20));
+        db.get(None, Some(20));
+        db.add(21, 1, 2);
+        db.add(30, 1, 4);
+
+        assert_eq!(cached(&db), 2);
+        assert_eq!(db.get(Some(10), Some(20)), (1, 1));
+
+        db.get(Some(22), None);
+        db.add(9, 1, 8);
+
+        // Only `(None, Some(20))` took it in
+        assert_eq!(cached(&db), 2);
+        assert_eq!(db.get(Some(22), None), (1, 4));
+    }
+
+    #[test]
+    fn bulk_changes_drop_every_cached_range() {
+        let db = db(&[(10, 1), (1500, 2)]);
+        let snapshot = db.snapshot();
+
+        db.get(Some(1000), None);
+        db.merge_snapshot(&snapshot).unwrap();
+        assert_eq!(db.get(Some(1000), None), (2, 4));
+
+        db.compact(2000, 1000);
+        assert_eq!(cached(&db), 0);
+        // 1500 moved to 1000
+        assert_eq!(db.get(Some(1000), Some(1000)), (2, 4));
+
+        db.clear();
+        assert_eq!(db.get(Some(1000), Some(1000)), (0, 0));
+    }
+
+    #[test]
+    fn the_cache_is_bounded() {
+        let db = db(&[(10, 1)]);
+
+        for from in 0..MAX_CACHED_SUMMARIES as i64 + 10 {
+            db.get(Some(from), None);
+        }
+
+        assert!(cached(&db) <= MAX_CACHED_SUMMARIES);
+    }
+
+    #[test]
+    fn reversed_ranges_are_empty() {
+        let db = db(&[(10, 1)]);
+
+        assert_eq!(db.get(Some(11), Some(9)), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn large_dbs_are_summed_in_chunks_all_the_same() {
+        let db = Db::new(false);
+
+        for timestamp in 0..3 * SCAN_CHUNK as i64 {
+            db.add(timestamp, 1, 2);
+        }
+
+        let (from, to) = (Some(100), Some(2 * SCAN_CHUNK as i64));
+        let scanned = db.summarize_async(from, to).await;
+
+        assert_eq!(cached(&db), 1);
+        assert_eq!(scanned, db.get(from, to));
+        assert_eq!(
+            scanned,
+            (2 * SCAN_CHUNK as u64 - 99, 2 * (2 * SCAN_CHUNK as u64 - 99))
+        );
     }
 }
@@ -0,0 +1,147 @@
+//! The correlation ids a `Db` with dedup remembers, so memory stays bounded however many payments
+//! go through. An id is forgotten once it was last seen `ttl` ago, or when the window is full and
+//! it is the least recently seen, after which a duplicate of it is counted again.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use crate::{correlation::CorrelationId, telemetry};
+
+// Rough heap cost of one remembered id on top of the id itself, both maps' overhead included
+const ENTRY_OVERHEAD: usize = 64;
+
+type Key = (CorrelationId, i64);
+
+/// How a window fared so far, summed across Dbs for `/admin/info`.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct DedupStats {
+    // Ids remembered right now
+    pub len: usize,
+    // Duplicates ignored
+    pub hits: u64,
+    // Ids forgotten to make room
+    pub evictions: u64,
+    // Ids forgotten after `ttl`
+    pub expirations: u64,
+}
+
+impl DedupStats {
+    pub fn add(&mut self, other: &DedupStats) {
+        self.len += other.len;
+        self.hits += other.hits;
+        self.evictions += other.evictions;
+        self.expirations += other.expirations;
+    }
+}
+
+/// Ids by `(correlation_id, timestamp)`, in the order they were last seen.
+#[derive(Default)]
+pub struct DedupWindow {
+    // 0 keeps ids until they are evicted
+    ttl: Duration,
+    // 0 means unlimited
+    capacity: usize,
+    // Each id's position in `order`
+    seen: HashMap<Key, u64>,
+    // Ids by when they were last seen, oldest first
+    order: BTreeMap<u64, (Instant, Key)>,
+    next: u64,
+    stats: DedupStats,
+}
+
+impl DedupWindow {
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            ttl,
+            capacity,
+            ..Default::default()
+        }
+    }
+
+    /// Remembers `correlation_id` at `timestamp`, returning whether it was already. Seeing it
+    /// again makes it the most recent.
+    pub fn check(&mut self, correlation_id: &CorrelationId, timestamp: i64) -> bool {
+        let now = Instant::now();
+        self.expire(now);
+
+        let key = (correlation_id.clone(), timestamp);
+        let position = self.next;
+        self.next += 1;
+
+        let duplicate = match self.seen.insert(key.clone(), position) {
+            Some(previous) => {
+                self.order.remove(&previous);
+                self.stats.hits += 1;
+                telemetry::dedup("hit");
+                true
+            }
+            None => false,
+        };
+
+        self.order.insert(position, (now, key));
+
+        if self.capacity > 0 {
+            while self.seen.len() > self.capacity {
+                self.pop();
+                self.stats.evictions += 1;
+                telemetry::dedup("evicted");
+            }
+        }
+
+        duplicate
+    }
+
+    pub fn clear(&mut self) {
+        self.seen.clear();
+        self.order.clear();
+    }
+
+    /// Every id remembered, least recently seen first.
+    pub fn keys(&self) -> impl Iterator<Item = &(CorrelationId, i64)> {
+        self.order.values().map(|(_, key)| key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+
+    pub fn stats(&self) -> DedupStats {
+        DedupStats {
+            len: self.seen.len(),
+            ..self.stats
+        }
+    }
+
+    /// Estimate of the heap held by remembered ids.
+    pub fn memory_bytes(&self) -> usize {
+        self.seen.len() * (2 * size_of::<Key>() + ENTRY_OVERHEAD)
+    }
+
+    fn expire(&mut self, now: Instant) {
+        if self.ttl.is_zero() {
+            return;
+        }
+
+        while let Some((_, (seen_at, _))) = self.order.first_key_value()
+            && now.duration_since(*seen_at) >= self.ttl
+        {
+            self.pop();
+            self.stats.expirations += 1;
+            telemetry::dedup("expired");
+        }
+    }
+
+    fn pop(&mut self) {
+        if let Some((_, (_, key))) = self.order.pop_first() {
+            self.seen.remove(&key);
+        }
+    }
+}
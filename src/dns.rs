@@ -0,0 +1,94 @@
+//! Processor hostnames resolve to new addresses when their containers restart, while pooled
+//! connections keep going to the old ones until they fail. `DnsWatch` re-resolves the hosts every
+//! `DNS_REFRESH_INTERVAL_MS`, and right away after a connect error, so the processor client can
+//! be rebuilt with an empty pool once an address changed.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::Mutex,
+};
+
+use reqwest::Url;
+use tokio::sync::Notify;
+
+/// The addresses each watched host resolved to last.
+pub struct DnsWatch {
+    // `host:port` pairs, IP literals left out since they never change
+    hosts: Vec<String>,
+    addrs: Mutex<HashMap<String, Vec<SocketAddr>>>,
+    wake: Notify,
+}
+
+impl DnsWatch {
+    pub fn new<'a>(urls: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut hosts: Vec<String> = urls
+            .into_iter()
+            .filter_map(|url| Url::parse(url).ok())
+            .filter_map(|url| {
+                let host = url.host_str()?.to_string();
+                let port = url.port_or_known_default()?;
+
+                // `host_str` keeps IPv6 literals bracketed
+                let literal = host.trim_matches(['[', ']']).parse::<IpAddr>().is_ok();
+                (!literal).then(|| format!("{host}:{port}"))
+            })
+            .collect();
+        hosts.sort();
+        hosts.dedup();
+
+        Self {
+            hosts,
+            addrs: Mutex::new(HashMap::new()),
+            wake: Notify::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hosts.is_empty()
+    }
+
+    /// Resolves every host again, returning whether any of them moved. Hosts that fail to
+    /// resolve keep their last addresses.
+    pub async fn refresh(&self) -> bool {
+        let mut changed = false;
+
+        for host in &self.hosts {
+            match tokio::net::lookup_host(host.as_str()).await {
+                Ok(addrs) => changed |= self.record(host, addrs.collect()),
+                Err(e) => eprintln!("Failed to resolve {host}: {e}"),
+            }
+        }
+
+        changed
+    }
+
+    /// Stores what `host` resolved to, returning whether it differs from a previous resolution.
+    /// The first one only sets the baseline.
+    pub fn record(&self, host: &str, mut addrs: Vec<SocketAddr>) -> bool {
+        addrs.sort();
+        addrs.dedup();
+
+        match self
+            .addrs
+            .lock()
+            .unwrap()
+            .insert(host.to_string(), addrs.clone())
+        {
+            Some(previous) if previous != addrs => {
+                println!("{host} moved from {previous:?} to {addrs:?}");
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Asks for a refresh ahead of schedule, after a connect error.
+    pub fn wake(&self) {
+        self.wake.notify_one();
+    }
+
+    pub async fn woken(&self) {
+        self.wake.notified().await;
+    }
+}
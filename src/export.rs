@@ -0,0 +1,115 @@
+use std::fmt::{self, Write};
+
+use axum::Json;
+use serde::{Deserialize, Serialize};
+
+use crate::Processor;
+
+pub const CSV_HEADER: &str = "processor,timestamp,count,amount\n";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    // One JSON object per line
+    Jsonl,
+    // The binary `StateSnapshot` peers exchange
+    Snapshot,
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv",
+            Self::Jsonl => "application/x-ndjson",
+            Self::Snapshot => "application/octet-stream",
+        }
+    }
+}
+
+/// One Db entry: `count` payments adding up to `amount` at `timestamp`. The timestamp is in
+/// micro seconds and the amount in units of `AMOUNT_SCALE`, exactly as the Db keeps them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ExportRecord {
+    pub processor: Processor,
+    pub timestamp: i64,
+    pub count: u64,
+    pub amount: u64,
+}
+
+impl ExportRecord {
+    /// Appends the record as one line of `format`, which must be `Csv` or `Jsonl`.
+    pub fn write(&self, format: ExportFormat, out: &mut String) {
+        let Self {
+            processor,
+            timestamp,
+            count,
+            amount,
+        } = self;
+
+        match format {
+            ExportFormat::Csv => writeln!(out, "{processor},{timestamp},{count},{amount}"),
+            ExportFormat::Jsonl => writeln!(
+                out,
+                "{{\"processor\":\"{processor}\",\"timestamp\":{timestamp},\"count\":{count},\"amount\":{amount}}}"
+            ),
+            ExportFormat::Snapshot => unreachable!("snapshots are built whole, see `StateSnapshot`"),
+        }
+        .unwrap();
+    }
+}
+
+/// Reads back what `ExportRecord::write` wrote, every record or none. Blank lines and the CSV
+/// header are skipped.
+pub fn parse(format: ExportFormat, body: &[u8]) -> Result<Vec<ExportRecord>, ImportError> {
+    let body = str::from_utf8(body).map_err(|_| ImportError { line: 0 })?;
+
+    body.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && *line != CSV_HEADER.trim_end())
+        .map(|(i, line)| {
+            let record = match format {
+                ExportFormat::Csv => parse_csv(line),
+                ExportFormat::Jsonl => Json::from_bytes(line.as_bytes()).ok().map(|Json(r)| r),
+                ExportFormat::Snapshot => None,
+            };
+
+            record.ok_or(ImportError { line: i + 1 })
+        })
+        .collect()
+}
+
+fn parse_csv(line: &str) -> Option<ExportRecord> {
+    let mut fields = line.trim_end().split(',');
+    let processor = match fields.next()? {
+        "default" => Processor::Default,
+        "fallback" => Processor::Fallback,
+        _ => return None,
+    };
+    let record = ExportRecord {
+        processor,
+        timestamp: fields.next()?.parse().ok()?,
+        count: fields.next()?.parse().ok()?,
+        amount: fields.next()?.parse().ok()?,
+    };
+
+    fields.next().is_none().then_some(record)
+}
+
+/// The first malformed line of an import, 0 when the body isn't even UTF-8.
+#[derive(Debug)]
+pub struct ImportError {
+    pub line: usize,
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            0 => write!(f, "import must be UTF-8"),
+            line => write!(f, "malformed record on line {line}"),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
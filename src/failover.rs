@@ -0,0 +1,134 @@
+use std::{
+    fmt,
+    str::FromStr,
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Role {
+    // Every instance submits the payments it receives
+    #[default]
+    Off,
+    Primary,
+    // Hands payments to the primary while it is healthy
+    Standby,
+}
+
+impl FromStr for Role {
+    type Err = UnknownRole;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Self::Off),
+            "primary" => Ok(Self::Primary),
+            "standby" => Ok(Self::Standby),
+            _ => Err(UnknownRole),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownRole;
+
+impl fmt::Display for UnknownRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown failover role")
+    }
+}
+
+impl std::error::Error for UnknownRole {}
+
+/// Whether this instance currently submits payments itself. A standby takes over once `threshold`
+/// health checks of the primary fail in a row, and hands submission back on the first successful
+/// one.
+pub struct Failover {
+    role: Role,
+    threshold: u32,
+    active: AtomicBool,
+    consecutive_failures: AtomicU32,
+}
+
+impl Failover {
+    pub fn new(role: Role, threshold: u32) -> Self {
+        Self {
+            role,
+            threshold,
+            active: AtomicBool::new(role != Role::Standby),
+            consecutive_failures: AtomicU32::new(0),
+        }
+    }
+
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// Records a health check of the primary, returning whether submission changed hands.
+    pub fn record_check(&self, healthy: bool) -> bool {
+        if self.role != Role::Standby {
+            return false;
+        }
+
+        let active = if healthy {
+            self.consecutive_failures.store(0, Ordering::Relaxed);
+            false
+        } else {
+            let failures = self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+            failures >= self.threshold
+        };
+
+        self.active.swap(active, Ordering::Relaxed) != active
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn standby_takes_over_after_threshold_failed_checks() {
+        let failover = Failover::new(Role::Standby, 2);
+        assert!(!failover.is_active());
+
+        assert!(!failover.record_check(false));
+        assert!(failover.record_check(false));
+        assert!(failover.is_active());
+        assert!(!failover.record_check(false));
+
+        assert!(failover.record_check(true));
+        assert!(!failover.is_active());
+    }
+
+    #[test]
+    fn a_healthy_check_resets_the_count() {
+        let failover = Failover::new(Role::Standby, 2);
+
+        failover.record_check(false);
+        failover.record_check(true);
+        failover.record_check(false);
+
+        assert!(!failover.is_active());
+    }
+
+    #[test]
+    fn other_roles_always_submit() {
+        for role in [Role::Off, Role::Primary] {
+            let failover = Failover::new(role, 1);
+
+            assert!(!failover.record_check(false));
+            assert!(!failover.record_check(true));
+            assert!(failover.is_active());
+        }
+    }
+
+    #[test]
+    fn roles_parse() {
+        assert_eq!("standby".parse::<Role>().unwrap(), Role::Standby);
+        assert_eq!("primary".parse::<Role>().unwrap(), Role::Primary);
+        assert_eq!("off".parse::<Role>().unwrap(), Role::Off);
+        assert!("leader".parse::<Role>().is_err());
+    }
+}
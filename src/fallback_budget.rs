@@ -0,0 +1,119 @@
+//! Caps the share of payments routed to the fallback, which charges higher fees. Past the cap,
+//! payments meant for the fallback wait for the default to recover instead.
+
+use std::{
+    fmt,
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::Processor;
+
+/// What the share routed to the fallback is measured in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BudgetBasis {
+    #[default]
+    Requests,
+    Amount,
+}
+
+impl FromStr for BudgetBasis {
+    type Err = UnknownBudgetBasis;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "requests" => Ok(Self::Requests),
+            "amount" => Ok(Self::Amount),
+            _ => Err(UnknownBudgetBasis),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownBudgetBasis;
+
+impl fmt::Display for UnknownBudgetBasis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown fallback budget basis")
+    }
+}
+
+impl std::error::Error for UnknownBudgetBasis {}
+
+/// Submissions routed to each processor within the current window, starting over each `window`.
+pub struct FallbackBudget {
+    max_share: f64,
+    basis: BudgetBasis,
+    window: Duration,
+    state: Mutex<Window>,
+}
+
+struct Window {
+    started: Instant,
+    default: f64,
+    fallback: f64,
+}
+
+impl FallbackBudget {
+    pub fn new(max_share: f64, basis: BudgetBasis, window: Duration) -> Self {
+        Self {
+            max_share,
+            basis,
+            window,
+            state: Mutex::new(Window {
+                started: Instant::now(),
+                default: 0.0,
+                fallback: 0.0,
+            }),
+        }
+    }
+
+    /// Counts a submission of `amount` to `processor`, unless it is meant for the fallback and
+    /// would take the fallback's share of the window past the cap. Returns whether it may go.
+    pub fn try_route(&self, processor: Processor, amount: f64) -> bool {
+        let weight = match self.basis {
+            BudgetBasis::Requests => 1.0,
+            BudgetBasis::Amount => amount,
+        };
+        let mut state = self.state.lock().unwrap();
+
+        if state.started.elapsed() >= self.window {
+            *state = Window {
+                started: Instant::now(),
+                default: 0.0,
+                fallback: 0.0,
+            };
+        }
+
+        match processor {
+            Processor::Default => state.default += weight,
+            Processor::Fallback => {
+                let share = (state.fallback + weight) / (state.default + state.fallback + weight);
+
+                if share > self.max_share {
+                    return false;
+                }
+
+                state.fallback += weight;
+            }
+        }
+
+        true
+    }
+
+    /// Share of the current window routed to the fallback so far.
+    pub fn used(&self) -> f64 {
+        let state = self.state.lock().unwrap();
+        let total = state.default + state.fallback;
+
+        if state.started.elapsed() >= self.window || total == 0.0 {
+            return 0.0;
+        }
+
+        state.fallback / total
+    }
+}
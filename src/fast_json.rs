@@ -0,0 +1,332 @@
+use std::fmt::Write;
+
+use axum::http::{HeaderMap, header};
+use bytes::{BufMut, Bytes};
+
+use crate::{Payment, PaymentPayload, arena, correlation::ENCODED_LEN};
+
+/// Borrowed view of a `/payments` body, parsed without going through serde.
+pub struct RawPayload<'a> {
+    pub correlation_id: &'a str,
+    pub amount: f64,
+}
+
+impl RawPayload<'_> {
+    /// `None` when the correlation id is malformed, for serde to report it.
+    pub fn to_payload(&self) -> Option<PaymentPayload> {
+        Some(PaymentPayload {
+            correlation_id: self.correlation_id.parse().ok()?,
+            amount: self.amount,
+            tenant_id: None,
+        })
+    }
+}
+
+/// Whether `headers` announce a JSON body, `application/json` or `application/*+json` as axum's
+/// `Json` extractor requires.
+pub fn is_json(headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+    else {
+        return false;
+    };
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+
+    essence
+        .strip_prefix("application/")
+        .is_some_and(|subtype| subtype == "json" || subtype.ends_with("+json"))
+}
+
+/// Parses a flat object holding only `correlationId` and `amount`. Anything else (escapes, extra
+/// or repeated keys, nested values, numbers out of `f64`'s range) returns `None` so the caller
+/// can fall back to serde, which then accepts or rejects the body as it would without this.
+pub fn parse_payload(body: &[u8]) -> Option<RawPayload<'_>> {
+    let mut cursor = Cursor { body, pos: 0 };
+    let mut correlation_id = None;
+    let mut amount = None;
+
+    cursor.expect(b'{')?;
+
+    loop {
+        let key = cursor.string()?;
+        cursor.expect(b':')?;
+
+        match key {
+            "correlationId" if correlation_id.is_none() => correlation_id = Some(cursor.string()?),
+            "amount" if amount.is_none() => amount = Some(cursor.number()?),
+            _ => return None,
+        }
+
+        match cursor.next()? {
+            b',' => continue,
+            b'}' => break,
+            _ => return None,
+        }
+    }
+
+    cursor.skip_whitespace();
+
+    if cursor.pos != body.len() {
+        return None;
+    }
+
+    Some(RawPayload {
+        correlation_id: correlation_id?,
+        amount: amount?,
+    })
+}
+
+/// Writes the processor request body for `p` straight into the arena, only going through serde
+/// when the correlation id needs escaping.
+pub fn payment_body(p: &Payment) -> Bytes {
+    let mut id = [0; ENCODED_LEN];
+    let correlation_id = p.correlation_id.encode(&mut id);
+    let needs_escape = correlation_id
+        .bytes()
+        .any(|b| b == b'"' || b == b'\\' || b < 0x20);
+
+    arena::build(|buf| {
+        if needs_escape {
+            serde_json::to_writer(buf.writer(), p).unwrap();
+            return;
+        }
+
+        buf.extend_from_slice(b"{\"correlationId\":\"");
+        buf.extend_from_slice(correlation_id.as_bytes());
+        buf.extend_from_slice(b"\",\"amount\":");
+        write!(buf, "{}", p.amount).unwrap();
+        buf.extend_from_slice(b",\"requestedAt\":\"");
+        // Same as `to_rfc3339_opts(SecondsFormat::AutoSi, true)`, without the `String`
+        write!(buf, "{}", p.requested_at.format("%Y-%m-%dT%H:%M:%S%.fZ")).unwrap();
+        buf.extend_from_slice(b"\"}");
+    })
+}
+
+struct Cursor<'a> {
+    body: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn skip_whitespace(&mut self) {
+        while self
+            .body
+            .get(self.pos)
+            .is_some_and(|b| matches!(b, b' ' | b'\t' | b'\n' | b'\r'))
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+
+        let b = *self.body.get(self.pos)?;
+        self.pos += 1;
+
+        Some(b)
+    }
+
+    fn expect(&mut self, expected: u8) -> Option<()> {
+        (self.next()? == expected).then_some(())
+    }
+
+    fn string(&mut self) -> Option<&'a str> {
+        self.expect(b'"')?;
+
+        let start = self.pos;
+        let len = self.body[start..].iter().position(|b| *b == b'"')?;
+        let raw = &self.body[start..start + len];
+
+        if raw.iter().any(|b| *b == b'\\' || *b < 0x20) {
+            return None;
+        }
+
+        self.pos = start + len + 1;
+
+        std::str::from_utf8(raw).ok()
+    }
+
+    /// A number as JSON spells them, `-?(0|[1-9][0-9]*)(\.[0-9]+)?([eE][+-]?[0-9]+)?`, which
+    /// Rust's own parser is laxer than. `None` past `f64::MAX`, which serde rejects too.
+    fn number(&mut self) -> Option<f64> {
+        self.skip_whitespace();
+
+        let start = self.pos;
+
+        self.eat(b'-');
+
+        match self.body.get(self.pos)? {
+            b'0' => self.pos += 1,
+            b'1'..=b'9' => self.digits()?,
+            _ => return None,
+        }
+
+        if self.eat(b'.') {
+            self.digits()?;
+        }
+
+        if self.eat(b'e') || self.eat(b'E') {
+            let _ = self.eat(b'+') || self.eat(b'-');
+            self.digits()?;
+        }
+
+        let n: f64 = std::str::from_utf8(&self.body[start..self.pos])
+            .ok()?
+            .parse()
+            .ok()?;
+
+        n.is_finite().then_some(n)
+    }
+
+    fn eat(&mut self, expected: u8) -> bool {
+        let matched = self.body.get(self.pos) == Some(&expected);
+        self.pos += usize::from(matched);
+        matched
+    }
+
+    /// One or more digits.
+    fn digits(&mut self) -> Option<()> {
+        let start = self.pos;
+
+        while self.body.get(self.pos).is_some_and(u8::is_ascii_digit) {
+            self.pos += 1;
+        }
+
+        (self.pos > start).then_some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Timelike, Utc};
+
+    use super::*;
+
+    const ID: &str = "4a7901b8-7d26-4d9d-aa19-4dc1c7cf60b3";
+
+    /// What the fast path makes of `body`, checked against serde: it may give up on a body serde
+    /// takes, never take one serde rejects or read it differently.
+    fn parse(body: &str) -> Option<(String, f64)> {
+        let serde = serde_json::from_str::<PaymentPayload>(body).ok();
+        let fast = parse_payload(body.as_bytes()).and_then(|raw| raw.to_payload());
+
+        if let Some(fast) = &fast {
+            let serde = serde.as_ref().expect("fast path took a body serde rejects");
+            assert_eq!(fast.correlation_id, serde.correlation_id);
+            assert_eq!(fast.amount.to_bits(), serde.amount.to_bits());
+        }
+
+        fast.map(|p| (p.correlation_id.to_string(), p.amount))
+    }
+
+    fn body(amount: &str) -> String {
+        format!("{{\"correlationId\":\"{ID}\",\"amount\":{amount}}}")
+    }
+
+    #[test]
+    fn takes_what_serde_takes() {
+        let bodies = [
+            body("19.9"),
+            body("0"),
+            body("-0.5"),
+            body("1e2"),
+            body("1.5E-2"),
+            body("123456789.123456789"),
+            format!(" {{ \"amount\" : 19.9 ,\t\"correlationId\" : \"{ID}\" }}\r\n"),
+        ];
+
+        for body in &bodies {
+            assert!(parse(body).is_some(), "{body}");
+        }
+
+        assert_eq!(parse(&body("19.9")), Some((ID.to_string(), 19.9)));
+    }
+
+    #[test]
+    fn leaves_other_valid_bodies_to_serde() {
+        let bodies = [
+            format!("{{\"correlationId\":\"{ID}\",\"amount\":1,\"tenantId\":\"acme\"}}"),
+            r#"{"correlationId":"4a7901b8-7d26-4d9d-aa19-4dc1c7cf60b\u0033","amount":1}"#
+                .to_string(),
+        ];
+
+        for body in &bodies {
+            assert!(
+                serde_json::from_str::<PaymentPayload>(body).is_ok(),
+                "{body}"
+            );
+            assert!(parse(body).is_none(), "{body}");
+        }
+    }
+
+    #[test]
+    fn rejects_what_serde_rejects() {
+        let bodies = [
+            body("1e999"),
+            body("-1e999"),
+            body("+1"),
+            body("01"),
+            body("1."),
+            body(".5"),
+            body("1e"),
+            body("-"),
+            body("\"1\""),
+            body("null"),
+            format!("{{\"correlationId\":\"{ID}\",\"amount\":1,\"amount\":2}}"),
+            format!("{{\"correlationId\":\"{ID}\",\"correlationId\":\"{ID}\",\"amount\":1}}"),
+            format!("{{\"correlationId\":\"{ID}\"}}"),
+            format!("{{\"correlationId\":\"{ID}\",\"amount\":1,}}"),
+            format!("{{\"correlationId\":\"{ID}\",\"amount\":1}} x"),
+            format!("{{\"correlationId\":\"{ID}\",\"amount\":1}}\x0c"),
+            "{}".to_string(),
+            String::new(),
+        ];
+
+        for body in &bodies {
+            assert!(parse(body).is_none(), "{body}");
+        }
+    }
+
+    #[test]
+    fn payment_bodies_match_serde() {
+        for nanos in [0, 120_000_000, 123_456_789] {
+            let payment = Payment {
+                correlation_id: ID.parse().unwrap(),
+                amount: 19.9,
+                requested_at: Utc
+                    .with_ymd_and_hms(2025, 7, 10, 12, 0, 0)
+                    .unwrap()
+                    .with_nanosecond(nanos)
+                    .unwrap(),
+                trace: crate::trace::TraceContext::generate(),
+                tenant: None,
+            };
+            let fast: serde_json::Value = serde_json::from_slice(&payment_body(&payment)).unwrap();
+
+            assert_eq!(fast, serde_json::to_value(&payment).unwrap());
+        }
+    }
+
+    #[test]
+    fn json_content_types_are_recognized() {
+        let is_json = |content_type: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::CONTENT_TYPE, content_type.parse().unwrap());
+            is_json(&headers)
+        };
+
+        assert!(is_json("application/json"));
+        assert!(is_json("Application/JSON; charset=utf-8"));
+        assert!(is_json("application/vnd.api+json"));
+        assert!(!is_json("text/plain"));
+        assert!(!is_json("application/jsonx"));
+        assert!(!super::is_json(&HeaderMap::new()));
+    }
+}
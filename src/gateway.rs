@@ -0,0 +1,3093 @@
+#[cfg(feature = "fast-json")]
+use crate::fast_json;
+#[cfg(feature = "grpc-peer")]
+use crate::grpc::{
+    PeerClient, ReplicationService, ReplicationServiceServer, RequireToken, SnapshotReply,
+    SnapshotRequest, SummaryReply, SummaryRequest, SummaryService, SummaryServiceServer,
+};
+#[cfg(feature = "postgres-backend")]
+use crate::postgres_db::PostgresDb;
+#[cfg(feature = "redis-backend")]
+use crate::redis_db::RedisDb;
+use crate::{
+    Config, ExportQueryParams, INTERNAL_SUMMARY_HEADER, ImportQueryParams, Info, Payment,
+    PaymentPayload, PaymentReceipt, Processor, ProcessorDiff, ProcessorSummaries, Readiness,
+    ReconcileReport, ReconcileRequest, SnapshotQueryParams, SnapshotScope, StateSnapshot, Summary,
+    SummaryQueryParams, SummaryStreamParams, TimestampBasis,
+    access_log::{AccessEntry, AccessLog, LoggedPayment, access_log},
+    auth::require_token,
+    backend::{BackendError, BackendKind, MemoryStorage, Storage},
+    balance::Balancer,
+    chaos::{Chaos, ChaosSettings, Fault, Target},
+    client::ShowdownClient,
+    clock::{self, ClockReading, ClockSkew, RequestClock},
+    coalesce::Coalescer,
+    concurrency::AdaptiveLimit,
+    consistency::{self, Checksums},
+    correlation::CorrelationId,
+    dedup::DedupStats,
+    export::{self, CSV_HEADER, ExportFormat, ExportRecord},
+    failover::{Failover, Role},
+    heartbeat::Heartbeats,
+    inflight::Inflight,
+    lifecycle::{EventKind, EventLog, PaymentTrace},
+    listener,
+    load_shed::{LagMonitor, load_shed},
+    pacing::Pacer,
+    partition,
+    payload::{Payload, ResponseStyle, error_response},
+    processor::{ProcessorError, ProcessorRouter},
+    protocol::{
+        IncompatiblePeer, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION, PeerVersions, ProtocolVersion,
+        internal_path,
+    },
+    queue::{PriorityQueue, SoftCap},
+    rate_limit::{RateLimiter, rate_limit},
+    route_limit::{RouteLimit, route_limit},
+    routing::{Outcome, RoutingUpdate},
+    status::{PaymentStatus, StatusMap},
+    telemetry::{self, Span},
+    tenant::{TenantId, Tenants},
+    trace::{TRACEPARENT, TraceContext, trace_context},
+    transport::{MSGPACK, PeerEncoding, PeerTransport},
+    tunables::{InvalidTunables, Tunables, TunablesUpdate},
+    webhook::{Confirmation, Webhook},
+    worker::{WorkerPool, WorkerStats, shard_index},
+};
+#[cfg(feature = "persistence")]
+use crate::{
+    journal::{Journal, JournalEntry, Replay},
+    mmap_db::MmapDb,
+    shutdown_snapshot::{Parking, PendingPayment, ShutdownSnapshot},
+    spill::Spill,
+};
+use arc_swap::ArcSwap;
+use axum::body::{Body, Bytes};
+#[cfg(feature = "dashboard")]
+use axum::response::Html;
+use axum::{
+    Json, Router,
+    extract::{DefaultBodyLimit, Extension, Path, Query, State},
+    http::{HeaderMap, HeaderName, HeaderValue, header},
+    middleware,
+    response::{
+        IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+    routing::{MethodRouter, get, post, put},
+    serve::ListenerExt,
+};
+use chrono::{DateTime, TimeDelta, Utc};
+use futures_util::{Stream, future::join_all, stream};
+use reqwest::StatusCode;
+#[cfg(feature = "grpc-peer")]
+use std::collections::HashMap;
+use std::{
+    convert::Infallible,
+    iter,
+    net::SocketAddr,
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use tokio::{
+    signal::unix::{SignalKind, signal},
+    sync::{OwnedSemaphorePermit, mpsc, watch},
+};
+#[cfg(feature = "compression")]
+use tower_http::{compression::CompressionLayer, decompression::RequestDecompressionLayer};
+
+type PeerError = Box<dyn std::error::Error + Send + Sync>;
+
+// Submissions of a payment taken with `SYNC_SUBMISSION`, one per processor
+const SYNC_ATTEMPTS: u64 = 2;
+// How long a retry waits before checking the retry budget again
+const RETRY_BUDGET_WAIT: Duration = Duration::from_millis(10);
+// How long a failed preflight waits before pinging the processors again
+const PREFLIGHT_RETRY: Duration = Duration::from_secs(1);
+// Round trips per clock probe of a peer, only the shortest one is used
+const CLOCK_SAMPLES: usize = 4;
+// How often `shutdown` checks whether every payment taken was submitted
+const SHUTDOWN_POLL: Duration = Duration::from_millis(10);
+// How long `shutdown` waits for the dispatcher to park what is left in the queues
+#[cfg(feature = "persistence")]
+const PARK_WAIT: Duration = Duration::from_secs(1);
+// Records written per chunk of an export body
+const EXPORT_CHUNK: usize = 1024;
+
+/// Everything the handlers and background tasks of one instance share. Clones are cheap and all
+/// refer to the same instance.
+#[derive(Clone)]
+pub struct AppState {
+    config: Arc<Config>,
+    // Swapped at runtime through `PUT /admin/config` or SIGHUP
+    tunables: Arc<ArcSwap<Tunables>>,
+    // Dispatcher slots, reset to `Tunables::dispatch_concurrency` when it changes
+    dispatch_limit: Arc<AdaptiveLimit>,
+    // Payments taken by the handlers, moved into the submit queue as it frees up
+    accept_queue_tx: mpsc::Sender<(Payment, u64)>,
+    // How much of the accept queue's capacity is used, set through the `queue_capacity` tunable
+    queue_cap: Arc<SoftCap>,
+    // Payments the dispatcher submits from, retries included
+    submit_queue_tx: mpsc::Sender<(Payment, u64)>,
+    // The in-process Dbs, kept for peer snapshots and compaction whichever storage is used
+    memory: MemoryStorage,
+    // Every shard of the in-process Dbs, `memory` first. More than one only with a worker pool
+    shards: Arc<[MemoryStorage]>,
+    processors: Arc<ProcessorRouter>,
+    http: reqwest::Client,
+    // Latest snapshot of the backup target's Db, handed back to it when it restarts
+    peer_backup: Arc<Mutex<Vec<u8>>>,
+    // Latest timestamp the peer backup restored at start. Backups only hold requested times
+    // without tenants, so processed and tenant summaries reaching back to it are partial
+    bootstrapped_until: Arc<OnceLock<i64>>,
+    #[cfg(feature = "persistence")]
+    journal: Option<Arc<Journal>>,
+    #[cfg(feature = "persistence")]
+    spill: Option<Arc<Spill>>,
+    // Queued payments held back for the shutdown snapshot
+    #[cfg(feature = "persistence")]
+    parking: Arc<Parking>,
+    // Records confirmed payments and answers summaries
+    storage: Arc<dyn Storage>,
+    // The actors behind `storage` in `worker` mode, for the `/admin/worker` endpoints
+    workers: Option<WorkerPool>,
+    // Latest requests served, when `ACCESS_LOG_CAPACITY` is set
+    access_log: Option<Arc<AccessLog>>,
+    // Notified of every confirmed payment, with `WEBHOOK_URL`
+    webhook: Option<Arc<Webhook>>,
+    // Latest payment lifecycle events, when `PAYMENT_EVENTS_CAPACITY` is set
+    events: Option<Arc<EventLog>>,
+    // Confirmed payments by the time they were confirmed, only ever kept in memory
+    processed: MemoryStorage,
+    // Dbs of payments tagged with a tenant, kept apart from `storage` and `processed`
+    tenants: Arc<Tenants>,
+    // Payments recorded since start, watched by summary streams
+    recorded: Arc<watch::Sender<u64>>,
+    peer_summaries: Arc<Coalescer<SummaryRange, ProcessorSummaries>>,
+    failover: Arc<Failover>,
+    balancer: Arc<Balancer>,
+    heartbeats: Arc<Heartbeats>,
+    // Measured by `clock_probe`, empty when probing is off
+    clock_skew: Arc<ClockSkew>,
+    // Stamps `requestedAt` on payments accepted here
+    request_clock: Arc<RequestClock>,
+    // Internal API version negotiated with each peer
+    peer_versions: Arc<PeerVersions>,
+    // Faults injected into outgoing requests, never any without the `chaos` feature
+    chaos: Arc<Chaos>,
+    // Limits how fast failed payments are re-queued across the whole instance
+    retry_budget: Arc<RateLimiter>,
+    statuses: Arc<StatusMap>,
+    // Timestamps of payments submitted but not recorded yet
+    inflight: Arc<Inflight>,
+    // Set once the preflight reached a processor, or right away when that isn't required
+    processor_reachable: Arc<AtomicBool>,
+    // gRPC clients by peer URL, empty unless the peer transport is gRPC
+    #[cfg(feature = "grpc-peer")]
+    grpc_peers: Arc<HashMap<String, PeerClient>>,
+}
+
+type SummaryRange = (
+    Option<DateTime<Utc>>,
+    Option<DateTime<Utc>>,
+    TimestampBasis,
+    Option<TenantId>,
+);
+
+/// The payment proxy: the dispatcher submitting queued payments, storage, peer replication and
+/// every background task, served over HTTP by the binary or driven directly by other programs.
+#[derive(Clone)]
+pub struct PaymentGateway {
+    app_state: AppState,
+    stopping: Arc<watch::Sender<bool>>,
+}
+
+impl PaymentGateway {
+    /// Starts the instance, see `AppState::start`. Payments can be enqueued as soon as it
+    /// returns.
+    pub async fn new(config: Config) -> Self {
+        Self {
+            app_state: AppState::start(config).await,
+            stopping: Arc::new(watch::Sender::new(false)),
+        }
+    }
+
+    /// Takes a payment as `POST /payments` does, forwarding it to its owner when that is a peer.
+    /// With `SYNC_SUBMISSION`, this also waits on the processors.
+    pub async fn enqueue(&self, payload: PaymentPayload) {
+        accept(self.app_state.clone(), payload, TraceContext::generate()).await;
+    }
+
+    /// The settings that can change while running, as `GET /admin/config` answers.
+    pub fn tunables(&self) -> Tunables {
+        Tunables::clone(&self.app_state.tunables.load())
+    }
+
+    /// Applies `update` as `PUT /admin/config` does, resizing the dispatcher's limit and the
+    /// accept queue right away.
+    pub fn update_tunables(&self, update: &TunablesUpdate) -> Result<Tunables, InvalidTunables> {
+        apply_tunables(&self.app_state, update)
+    }
+
+    /// Answers as `GET /payments-summary` does, failing when the storage backend can't be read.
+    pub async fn summary(
+        &self,
+        params: SummaryQueryParams,
+    ) -> Result<ProcessorSummaries, BackendError> {
+        let only_local = params.only_local.unwrap_or(false);
+
+        summarize(
+            &self.app_state,
+            params,
+            only_local,
+            TraceContext::generate(),
+        )
+        .await
+        .map(|(total, _)| total)
+    }
+
+    /// Serves the HTTP API on `PORT`, and on `UNIX_SOCKET` when set, until `shutdown`.
+    pub async fn serve(&self) {
+        let config = &self.app_state.config;
+        let app = router(self.app_state.clone());
+        let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
+        let tuning = config.clone();
+        let listener = listener::bind(addr, config).unwrap().tap_io(move |stream| {
+            if let Err(e) = listener::tune(stream, &tuning) {
+                eprintln!("Could not tune accepted connection: {e}");
+            }
+        });
+
+        println!("Listening on {addr}");
+
+        let tcp = axum::serve(
+            listener,
+            app.clone()
+                .into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown(self.stopped());
+
+        // The same router on a Unix socket too, for a proxy on the same host
+        let Some(path) = &config.unix_socket else {
+            return tcp.await.unwrap();
+        };
+        let unix = axum::serve(listener::bind_unix(path).unwrap(), app.into_make_service())
+            .with_graceful_shutdown(self.stopped());
+
+        println!("Listening on {path}");
+
+        let (tcp, unix) = tokio::join!(tcp.into_future(), unix.into_future());
+        tcp.unwrap();
+        unix.unwrap();
+    }
+
+    /// Stops serving new requests and waits, for up to `SHUTDOWN_GRACE_MS`, for the payments
+    /// already taken to be submitted.
+    pub async fn shutdown(&self) {
+        self.stopping.send_replace(true);
+
+        let grace = self.app_state.config.shutdown_grace;
+        let drained = tokio::time::timeout(grace, async {
+            while self.pending() > 0 || self.app_state.inflight.is_locked(None, None) {
+                tokio::time::sleep(SHUTDOWN_POLL).await;
+            }
+        })
+        .await;
+
+        if drained.is_err() {
+            eprintln!(
+                "Shutting down with {} payments still queued",
+                self.pending()
+            );
+        }
+
+        #[cfg(feature = "persistence")]
+        if let Some(path) = &self.app_state.config.shutdown_snapshot_path {
+            write_snapshot(&self.app_state, path).await;
+        }
+    }
+
+    // Payments in the queue, not counting spilled ones
+    fn pending(&self) -> usize {
+        queued(&self.app_state)
+    }
+
+    fn stopped(&self) -> impl Future<Output = ()> + Send + 'static {
+        let mut stopping = self.stopping.subscribe();
+
+        async move {
+            let _ = stopping.wait_for(|&stopping| stopping).await;
+        }
+    }
+}
+
+impl AppState {
+    /// Opens storage, recovers whatever the last run or the peer left behind and starts the
+    /// dispatcher and background tasks, ready to be served by `router`.
+    pub async fn start(config: Config) -> Self {
+        let config = Arc::new(config);
+        let memory = MemoryStorage::new(&config);
+        let shard_count = if config.worker {
+            config.worker_shards
+        } else {
+            1
+        };
+        let shards: Arc<[MemoryStorage]> = iter::once(memory.clone())
+            .chain((1..shard_count).map(|_| MemoryStorage::new(&config)))
+            .collect();
+        // Db reads and writes go through the worker actors instead of the Db locks
+        let workers = (config.worker && config.db_backend == BackendKind::Memory)
+            .then(|| WorkerPool::spawn(&shards, config.worker_queue_capacity));
+        let (tx, accepted) = mpsc::channel::<(Payment, u64)>(config.accept_queue_capacity);
+        let (submit_tx, rx) = mpsc::channel::<(Payment, u64)>(config.submit_queue_capacity);
+        let queue_cap = Arc::new(SoftCap::new(config.accept_queue_capacity));
+        #[cfg(feature = "persistence")]
+        let (journal, replay) = match &config.journal_path {
+            Some(path) => {
+                let horizon = (Utc::now() - config.compaction_horizon).timestamp_micros();
+                let bucket = config.compaction_bucket.as_micros() as i64;
+                let (journal, replay) = Journal::open(path, horizon, bucket).unwrap();
+                (Some(Arc::new(journal)), replay)
+            }
+            None => (None, Replay::default()),
+        };
+        #[cfg(feature = "persistence")]
+        let snapshot = match &config.shutdown_snapshot_path {
+            Some(path) => ShutdownSnapshot::take(path).unwrap().unwrap_or_default(),
+            None => ShutdownSnapshot::default(),
+        };
+        #[cfg(not(feature = "persistence"))]
+        if config.journal_path.is_some()
+            || config.spill_path.is_some()
+            || config.shutdown_snapshot_path.is_some()
+        {
+            panic!("Built without the persistence feature");
+        }
+
+        let tenants = Arc::new(Tenants::new(config.clone()));
+
+        #[cfg(feature = "persistence")]
+        let replay_db = |processor, tenant: Option<&TenantId>| match tenant {
+            None => Ok(memory.db(processor).clone()),
+            Some(tenant) => tenants
+                .get_or_insert(tenant)
+                .map(|storage| storage.requested.db(processor).clone()),
+        };
+
+        #[cfg(feature = "persistence")]
+        for total in &replay.totals {
+            match replay_db(total.processor, total.tenant.as_ref()) {
+                Ok(db) => db.add(total.timestamp, total.count, total.amount),
+                Err(e) => eprintln!("Not replaying {} payments: {e}", total.count),
+            }
+        }
+
+        #[cfg(feature = "persistence")]
+        for entry in &replay.confirmed {
+            match replay_db(entry.processor, entry.tenant.as_ref()) {
+                Ok(db) => db.set(&entry.correlation_id, entry.timestamp, entry.amount),
+                Err(e) => eprintln!("Not replaying {}: {e}", entry.correlation_id),
+            }
+        }
+
+        let app_state = AppState {
+            config: config.clone(),
+            tunables: Arc::new(ArcSwap::from_pointee(Tunables::from_config(&config))),
+            dispatch_limit: Arc::new(AdaptiveLimit::new(
+                config.dispatch_concurrency,
+                config.concurrency_min,
+                config.concurrency_max,
+                config.concurrency_target_latency,
+            )),
+            accept_queue_tx: tx.clone(),
+            queue_cap: queue_cap.clone(),
+            submit_queue_tx: submit_tx.clone(),
+            memory: memory.clone(),
+            shards: shards.clone(),
+            processors: Arc::new(ProcessorRouter::new(&config)),
+            http: reqwest::Client::builder()
+                .tcp_nodelay(true)
+                .build()
+                .unwrap(),
+            peer_backup: Arc::new(Mutex::new(Vec::new())),
+            bootstrapped_until: Arc::new(OnceLock::new()),
+            #[cfg(feature = "persistence")]
+            journal,
+            #[cfg(feature = "persistence")]
+            parking: Arc::new(Parking::default()),
+            #[cfg(feature = "persistence")]
+            spill: config
+                .spill_path
+                .as_ref()
+                .map(|path| Arc::new(Spill::open(path, tx.clone(), queue_cap.clone()).unwrap())),
+            storage: open_storage(&config, memory, workers.clone()).await,
+            workers,
+            processed: MemoryStorage::new(&config),
+            tenants,
+            recorded: Arc::new(watch::Sender::new(0)),
+            peer_summaries: Arc::new(Coalescer::new(config.peer_summary_ttl)),
+            balancer: Arc::new(Balancer::new(&config)),
+            heartbeats: Arc::new(Heartbeats::new(config.heartbeat_misses)),
+            failover: Arc::new(Failover::new(
+                config.failover_role,
+                config.failover_threshold,
+            )),
+            clock_skew: Arc::new(ClockSkew::default()),
+            request_clock: Arc::new(RequestClock::new(config.monotonic_requested_at)),
+            peer_versions: Arc::new(PeerVersions::default()),
+            chaos: Arc::new(Chaos::default()),
+            statuses: Arc::new(StatusMap::default()),
+            inflight: Arc::new(Inflight::default()),
+            webhook: config
+                .webhook_url
+                .clone()
+                .map(|url| Arc::new(Webhook::spawn(url, &config))),
+            access_log: (config.access_log_capacity > 0)
+                .then(|| Arc::new(AccessLog::new(config.access_log_capacity))),
+            events: (config.payment_events_capacity > 0)
+                .then(|| Arc::new(EventLog::new(config.payment_events_capacity))),
+            processor_reachable: Arc::new(AtomicBool::new(
+                !(config.preflight && config.preflight_require_processor),
+            )),
+            #[cfg(feature = "grpc-peer")]
+            grpc_peers: Arc::new(grpc_peers(&config)),
+            retry_budget: Arc::new(RateLimiter::new(
+                config.retry_budget_rps,
+                config.retry_budget_burst,
+                0.0,
+                0.0,
+            )),
+        };
+
+        if let Some(path) = &config.config_file {
+            reload_config_file(&app_state, path);
+            tokio::spawn(reload_on_sighup(app_state.clone(), path.clone()));
+        }
+
+        if config.preflight && !preflight(&app_state).await && config.preflight_require_processor {
+            eprintln!("Preflight: no processor reachable, not ready until one is");
+            tokio::spawn(retry_preflight(app_state.clone()));
+        }
+
+        join_all(
+            config
+                .peer_urls
+                .iter()
+                .map(|peer| negotiate_version(&app_state, peer)),
+        )
+        .await;
+
+        #[cfg(feature = "persistence")]
+        let journaled = !replay.is_empty();
+        #[cfg(feature = "persistence")]
+        let replayed = journaled || restore_snapshot(&config, &shards, &snapshot, journaled);
+        #[cfg(not(feature = "persistence"))]
+        let replayed = false;
+
+        // The journal already holds everything this instance recorded, the peer backup would only
+        // add it a second time
+        if app_state.storage.is_shared() {
+            println!("Using shared Db, skipping peer bootstrap");
+        } else if !replayed && !config.peer_urls.is_empty() {
+            bootstrap(&app_state).await;
+        }
+
+        #[cfg(feature = "persistence")]
+        if journaled && !app_state.storage.is_shared() {
+            println!(
+                "Replayed {} confirmed payments from the journal",
+                replay.confirmed_count()
+            );
+            reconcile_pending(&app_state, replay.pending).await;
+        }
+
+        tokio::spawn(transfer(accepted, submit_tx, queue_cap));
+
+        if config.priority_queue {
+            tokio::spawn(priority_dispatcher(rx, app_state.clone()));
+        } else {
+            tokio::spawn(dispatcher(rx, app_state.clone()));
+        }
+        #[cfg(feature = "persistence")]
+        if let Some(spill) = &app_state.spill {
+            tokio::spawn(spill.clone().drain());
+        }
+        #[cfg(feature = "persistence")]
+        if !snapshot.pending.is_empty() {
+            println!(
+                "Requeueing {} payments from the shutdown snapshot",
+                snapshot.pending.len()
+            );
+            tokio::spawn(requeue(app_state.clone(), snapshot.pending));
+        }
+        // A lone instance has nobody to back up, nor anybody backing it up
+        if !config.peer_urls.is_empty() {
+            tokio::spawn(peer_backup(app_state.clone()));
+
+            if !config.consistency_check_interval.is_zero() && !app_state.storage.is_shared() {
+                tokio::spawn(consistency_check(app_state.clone()));
+            }
+        }
+        tokio::spawn(compactor(app_state.clone()));
+
+        if !config.clock_probe_interval.is_zero() {
+            tokio::spawn(clock_probe(app_state.clone()));
+        }
+        if !config.heartbeat_interval.is_zero() {
+            tokio::spawn(heartbeat(app_state.clone()));
+        }
+        if !config.dns_refresh_interval.is_zero() {
+            for processor in [Processor::Default, Processor::Fallback] {
+                if !app_state.processors.get(processor).dns.is_empty() {
+                    tokio::spawn(dns_refresh(app_state.clone(), processor));
+                }
+            }
+        }
+
+        match config.peer_transport {
+            PeerTransport::Http => {}
+            #[cfg(feature = "grpc-peer")]
+            PeerTransport::Grpc => {
+                tokio::spawn(serve_grpc(app_state.clone()));
+            }
+            #[cfg(not(feature = "grpc-peer"))]
+            PeerTransport::Grpc => panic!("Built without the grpc-peer feature"),
+        }
+
+        if app_state.failover.role() == Role::Standby {
+            tokio::spawn(watch_primary(app_state.clone()));
+        }
+
+        app_state
+    }
+
+    /// Journals `p` as `state` says, through `processor`, when journaling.
+    #[cfg(feature = "persistence")]
+    fn journal(&self, p: &Payment, processor: Processor, state: fn(&Journal, &JournalEntry)) {
+        if let Some(journal) = &self.journal {
+            let entry = JournalEntry {
+                correlation_id: p.correlation_id.clone(),
+                processor,
+                amount: p.amount_units(self.config.amount_scale),
+                timestamp: p.requested_at.timestamp_micros(),
+                tenant: p.tenant.clone(),
+            };
+
+            state(journal, &entry);
+        }
+    }
+
+    /// Adds to the payment's lifecycle, when the event log is on.
+    fn event(&self, correlation_id: &CorrelationId, kind: EventKind) {
+        if let Some(events) = &self.events {
+            events.record(correlation_id, kind);
+        }
+    }
+}
+
+/// Every route of the HTTP API along with its middleware, for `PaymentGateway::serve` or to be
+/// mounted into a larger app.
+pub fn router(app_state: AppState) -> Router {
+    let config = &app_state.config;
+    let limiter = Arc::new(
+        RateLimiter::new(
+            config.rate_limit_rps,
+            config.rate_limit_burst,
+            config.rate_limit_per_ip_rps,
+            config.rate_limit_per_ip_burst,
+        )
+        .with_trusted_forwarding(config.rate_limit_trust_forwarded),
+    );
+    let mut payments_route =
+        post(payments).layer(DefaultBodyLimit::max(config.payments_body_limit));
+    let payments_limit = RouteLimit::new(
+        config.payments_max_concurrent,
+        config.payments_queue_timeout,
+    );
+
+    // Innermost, requests turned away by the other layers never wait for a slot
+    if payments_limit.is_enabled() {
+        payments_route =
+            payments_route.layer(middleware::from_fn_with_state(payments_limit, route_limit));
+    }
+
+    if limiter.is_enabled() {
+        payments_route = payments_route.layer(middleware::from_fn_with_state(limiter, rate_limit));
+    }
+
+    // Shedding runs before rate limiting so rejected requests do not use up tokens
+    if !config.load_shed_lag_budget.is_zero() {
+        let monitor = LagMonitor::spawn(
+            config.load_shed_sample_interval,
+            config.load_shed_lag_budget,
+        );
+        payments_route = payments_route.layer(middleware::from_fn_with_state(monitor, load_shed));
+    }
+
+    let mut summary_route = get(payments_summary);
+    let summary_limit =
+        RouteLimit::new(config.summary_max_concurrent, config.summary_queue_timeout);
+
+    // Not on the stream, which would hold its slot for as long as it is open
+    if summary_limit.is_enabled() {
+        summary_route =
+            summary_route.layer(middleware::from_fn_with_state(summary_limit, route_limit));
+    }
+
+    let export_route = get(export);
+    let import_route: MethodRouter<AppState> = post(import).layer(DefaultBodyLimit::disable());
+
+    // Only where bodies get large, the SSE stream must not be buffered by an encoder
+    #[cfg(feature = "compression")]
+    let (summary_route, export_route, import_route) = (
+        summary_route.layer(CompressionLayer::new()),
+        export_route.layer(CompressionLayer::new()),
+        import_route.layer(RequestDecompressionLayer::new()),
+    );
+
+    let mut protected = Router::new()
+        .route("/admin/info", get(info))
+        .route("/admin/recent-requests", get(recent_requests))
+        .route("/admin/trace/{correlation_id}", get(payment_trace))
+        .route("/admin/config", get(get_config).put(put_config))
+        .route("/admin/routing", put(set_routing))
+        .route("/admin/chaos", get(get_chaos).put(put_chaos))
+        .route("/admin/worker/stats", get(worker_stats))
+        .route("/admin/worker/flush", post(worker_flush))
+        .route("/admin/worker/purge", post(worker_purge))
+        .route("/admin/purge-payments", post(purge_payments))
+        .route("/admin/reconcile", post(reconcile))
+        .route("/admin/export", export_route)
+        .route("/admin/import", import_route)
+        .route("/internal/version", get(protocol_version));
+
+    // Served unversioned too, for peers from before versioning
+    for version in [0, PROTOCOL_VERSION] {
+        protected = protected
+            .route(
+                &internal_path(version, "/payments"),
+                post(internal_payments),
+            )
+            .route(
+                &internal_path(version, "/state-snapshot"),
+                get(state_snapshot),
+            )
+            .route(&internal_path(version, "/clock"), get(clock))
+            .route(&internal_path(version, "/ping"), get(ping));
+    }
+
+    protected = protected.route(
+        &internal_path(PROTOCOL_VERSION, "/checksums"),
+        get(checksums),
+    );
+
+    #[cfg(feature = "dashboard")]
+    {
+        protected = protected.route("/admin/dashboard", get(dashboard));
+    }
+
+    if let Some(token) = &config.internal_token {
+        protected = protected.route_layer(middleware::from_fn_with_state(
+            Arc::from(token.as_str()),
+            require_token,
+        ));
+    }
+
+    let mut app = Router::new()
+        .route("/payments", payments_route)
+        .route("/payments/{correlation_id}/status", get(payment_status))
+        .route("/payments-summary", summary_route)
+        .route("/payments-summary/stream", get(payments_summary_stream))
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .merge(protected)
+        .layer(middleware::from_fn(trace_context))
+        .with_state(app_state.clone());
+
+    if let Some(log) = app_state.access_log {
+        app = app.layer(middleware::from_fn_with_state(log, access_log));
+    }
+
+    app
+}
+
+/// Pings both processors and every peer, logging which ones answered. Any response counts, the
+/// processors rate limit `/payments/service-health`. Returns whether a processor answered.
+async fn preflight(app_state: &AppState) -> bool {
+    let processors = [Processor::Default, Processor::Fallback].map(|processor| {
+        let url = app_state.processors.get(processor).url.clone();
+        (
+            format!("{processor:?} processor"),
+            format!("{url}/payments/service-health"),
+        )
+    });
+    let peers = app_state
+        .config
+        .peer_urls
+        .iter()
+        .map(|peer| (format!("Peer {peer}"), format!("{peer}/healthz")));
+    let targets: Vec<_> = processors.into_iter().chain(peers).collect();
+    let checks = targets.iter().map(|(_, url)| {
+        app_state
+            .http
+            .get(url)
+            .timeout(app_state.config.preflight_timeout)
+            .send()
+    });
+    let results = join_all(checks).await;
+
+    for ((name, url), result) in targets.iter().zip(&results) {
+        match result {
+            Ok(resp) => println!(
+                "Preflight: {name} reachable ({url} answered {})",
+                resp.status()
+            ),
+            Err(e) => eprintln!("Preflight: {name} unreachable ({url}: {e})"),
+        }
+    }
+
+    let reachable = results[..2].iter().any(Result::is_ok);
+
+    if reachable {
+        app_state.processor_reachable.store(true, Ordering::Relaxed);
+    }
+
+    reachable
+}
+
+async fn retry_preflight(app_state: AppState) {
+    loop {
+        tokio::time::sleep(PREFLIGHT_RETRY).await;
+
+        if preflight(&app_state).await {
+            println!("Preflight: processor reachable, ready");
+            return;
+        }
+    }
+}
+
+/// Moves accepted payments into the submit queue, waiting for room there so the dispatcher is
+/// only ever handed as many as it can keep up with while the handlers keep accepting.
+async fn transfer(
+    mut accepted: mpsc::Receiver<(Payment, u64)>,
+    submit_tx: mpsc::Sender<(Payment, u64)>,
+    queue_cap: Arc<SoftCap>,
+) {
+    while let Some(entry) = accepted.recv().await {
+        queue_cap.release();
+
+        if submit_tx.send(entry).await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn dispatcher(mut rx: mpsc::Receiver<(Payment, u64)>, app_state: AppState) {
+    while let Some((p, retries)) = rx.recv().await {
+        #[cfg(feature = "persistence")]
+        let Some((p, retries)) = app_state.parking.park(p, retries) else {
+            continue;
+        };
+        let permit = app_state.dispatch_limit.acquire().await;
+
+        spawn_payment(p, retries, permit, app_state.clone());
+    }
+}
+
+/// Like `dispatcher`, but moves everything waiting in the channel into a `PriorityQueue` each time
+/// a slot frees up and submits the largest payment first.
+async fn priority_dispatcher(mut rx: mpsc::Receiver<(Payment, u64)>, app_state: AppState) {
+    // Bounded like the channel so a backlog still pushes back on the handlers
+    let capacity = rx.max_capacity();
+    let mut queue = PriorityQueue::default();
+
+    loop {
+        let permit = app_state.dispatch_limit.acquire().await;
+
+        if queue.is_empty() {
+            match rx.recv().await {
+                Some((p, retries)) => queue.push(p, retries),
+                None => break,
+            }
+        }
+
+        while queue.len() < capacity
+            && let Ok((p, retries)) = rx.try_recv()
+        {
+            queue.push(p, retries);
+        }
+
+        let (p, retries) = queue.pop().unwrap();
+        #[cfg(feature = "persistence")]
+        let Some((p, retries)) = app_state.parking.park(p, retries) else {
+            continue;
+        };
+
+        spawn_payment(p, retries, permit, app_state.clone());
+    }
+}
+
+fn spawn_payment(p: Payment, retries: u64, permit: OwnedSemaphorePermit, app_state: AppState) {
+    tokio::spawn(async move {
+        let attempt = process_payment(p, retries, &app_state).await;
+
+        // Release the permit before re-queueing, a full queue must not stall the dispatcher
+        drop(permit);
+
+        let (p, next) = match attempt {
+            Attempt::Retry(p) => {
+                // Out of budget, the retry waits here rather than taking a dispatcher slot
+                while !app_state.retry_budget.check(None)
+                    && !is_expired(&p, &app_state.tunables.load())
+                {
+                    tokio::time::sleep(RETRY_BUDGET_WAIT).await;
+                }
+
+                (p, retries + 1)
+            }
+            // Waits for the default to recover, which doesn't count as a retry
+            Attempt::Deferred(p) => {
+                tokio::time::sleep(app_state.config.fallback_budget_delay).await;
+
+                (p, retries)
+            }
+            Attempt::Confirmed | Attempt::Rejected => return,
+        };
+
+        if is_expired(&p, &app_state.tunables.load()) {
+            app_state.statuses.dead_lettered(&p.correlation_id);
+            app_state.event(&p.correlation_id, EventKind::GaveUp);
+            eprintln!(
+                "Dropping {} after {retries} retries, too old (trace {})",
+                p.correlation_id,
+                p.trace.trace_id()
+            );
+            return;
+        }
+
+        // Retries were accepted already and skip the accept queue
+        app_state.submit_queue_tx.send((p, next)).await.unwrap();
+    });
+}
+
+fn is_expired(p: &Payment, tunables: &Tunables) -> bool {
+    let max_age = tunables.payment_max_age();
+
+    !max_age.is_zero() && (Utc::now() - p.requested_at).to_std().unwrap_or_default() > max_age
+}
+
+async fn open_storage(
+    config: &Config,
+    memory: MemoryStorage,
+    workers: Option<WorkerPool>,
+) -> Arc<dyn Storage> {
+    match config.db_backend {
+        BackendKind::Memory => match workers {
+            Some(workers) => Arc::new(workers),
+            None => Arc::new(memory),
+        },
+        #[cfg(feature = "persistence")]
+        BackendKind::Mmap => {
+            Arc::new(MmapDb::open(&config.mmap_db_path, config.mmap_db_capacity).unwrap())
+        }
+        #[cfg(not(feature = "persistence"))]
+        BackendKind::Mmap => panic!("Built without the persistence feature"),
+        #[cfg(feature = "redis-backend")]
+        BackendKind::Redis => Arc::new(
+            RedisDb::connect(&config.redis_url, config.redis_prefix.clone())
+                .await
+                .unwrap(),
+        ),
+        #[cfg(not(feature = "redis-backend"))]
+        BackendKind::Redis => panic!("Built without the redis-backend feature"),
+        #[cfg(feature = "postgres-backend")]
+        BackendKind::Postgres => Arc::new(
+            PostgresDb::connect(
+                &config.postgres_url,
+                config.postgres_batch_size,
+                config.postgres_batch_delay,
+            )
+            .await
+            .unwrap(),
+        ),
+        #[cfg(not(feature = "postgres-backend"))]
+        BackendKind::Postgres => panic!("Built without the postgres-backend feature"),
+    }
+}
+
+async fn bootstrap(app_state: &AppState) {
+    let peer = app_state.config.backup_holder();
+    let snapshot = match fetch_snapshot(app_state, peer, SnapshotScope::Peer).await {
+        Ok(bytes) if bytes.is_empty() => return,
+        Ok(bytes) => StateSnapshot::from_bytes(&bytes),
+        Err(e) => {
+            eprintln!("Skipping bootstrap, peer unavailable: {e}");
+            return;
+        }
+    };
+
+    let merged = snapshot.and_then(|s| {
+        s.merge_into(&app_state.memory.default, &app_state.memory.fallback)?;
+        Ok(s)
+    });
+
+    match merged {
+        Ok(snapshot) => {
+            let latest = [Processor::Default, Processor::Fallback]
+                .into_iter()
+                .flat_map(|processor| snapshot.records(processor))
+                .map(|(timestamp, _, _)| timestamp)
+                .max();
+
+            if let Some(latest) = latest {
+                let _ = app_state.bootstrapped_until.set(latest);
+            }
+
+            println!("Bootstrapped local Db from peer backup");
+        }
+        Err(e) => eprintln!("Skipping bootstrap: {e}"),
+    }
+}
+
+/// Merges the Dbs and dedup ids of a shutdown snapshot into the in-process Dbs. The Dbs are left
+/// out when the journal replayed anything, it holds the same payments, or when they aren't
+/// in-process. Returns whether they were merged.
+#[cfg(feature = "persistence")]
+fn restore_snapshot(
+    config: &Config,
+    shards: &[MemoryStorage],
+    snapshot: &ShutdownSnapshot,
+    journaled: bool,
+) -> bool {
+    // Each id goes back to the shard it was on, where its timestamp hashes to unless the shard
+    // count changed since
+    for (i, keys) in snapshot.dedup.iter().enumerate() {
+        let shard = &shards[i % shards.len()];
+
+        for (processor, correlation_id, timestamp) in keys {
+            shard
+                .db(*processor)
+                .remember([(correlation_id.clone(), *timestamp)]);
+        }
+    }
+
+    if snapshot.db.is_empty() || journaled || config.db_backend != BackendKind::Memory {
+        return false;
+    }
+
+    let merged = StateSnapshot::from_bytes(&snapshot.db)
+        .and_then(|s| s.merge_into(&shards[0].default, &shards[0].fallback));
+
+    match merged {
+        Ok(()) => {
+            println!("Restored the Dbs from the shutdown snapshot");
+            true
+        }
+        Err(e) => {
+            eprintln!("Skipping the shutdown snapshot's Dbs: {e}");
+            false
+        }
+    }
+}
+
+/// Puts payments left queued at the last shutdown back into the accept queue.
+#[cfg(feature = "persistence")]
+async fn requeue(app_state: AppState, pending: Vec<PendingPayment>) {
+    for payment in pending {
+        let (p, retries) = payment.into_payment();
+        app_state.statuses.queued(&p.correlation_id, p.requested_at);
+
+        match &app_state.spill {
+            Some(spill) => spill.send(p, retries).await,
+            None => {
+                app_state
+                    .queue_cap
+                    .reserve(&app_state.accept_queue_tx)
+                    .await;
+                app_state.accept_queue_tx.send((p, retries)).await.unwrap();
+            }
+        }
+    }
+}
+
+/// Parks whatever is still queued and writes it to `path`, along with the in-process Dbs and the
+/// ids their dedup windows remember.
+#[cfg(feature = "persistence")]
+async fn write_snapshot(app_state: &AppState, path: &str) {
+    app_state.parking.start();
+
+    // Payments in flight either get recorded or come back through the queue to be parked
+    let _ = tokio::time::timeout(PARK_WAIT, async {
+        while queued(app_state) > 0 || app_state.inflight.is_locked(None, None) {
+            tokio::time::sleep(SHUTDOWN_POLL).await;
+        }
+    })
+    .await;
+
+    if let Some(workers) = &app_state.workers {
+        workers.flush().await;
+    }
+
+    let db = match app_state.config.db_backend {
+        BackendKind::Memory => snapshot_bytes(app_state, SnapshotScope::Local),
+        _ => Vec::new(),
+    };
+    let dedup = app_state
+        .shards
+        .iter()
+        .map(|shard| {
+            [Processor::Default, Processor::Fallback]
+                .into_iter()
+                .flat_map(|processor| {
+                    shard.db(processor).dedup_keys().into_iter().map(
+                        move |(correlation_id, timestamp)| (processor, correlation_id, timestamp),
+                    )
+                })
+                .collect()
+        })
+        .collect();
+    let snapshot = ShutdownSnapshot {
+        db,
+        dedup,
+        pending: app_state.parking.take(),
+    };
+
+    match snapshot.write(path) {
+        Ok(()) => println!(
+            "Wrote shutdown snapshot with {} queued payments",
+            snapshot.pending.len()
+        ),
+        Err(e) => eprintln!("Could not write shutdown snapshot: {e}"),
+    }
+}
+
+/// Resolves payments that were submitted but never confirmed before the last shutdown by checking
+/// whether the processor counted a payment at their exact `requestedAt`.
+#[cfg(feature = "persistence")]
+async fn reconcile_pending(app_state: &AppState, pending: Vec<JournalEntry>) {
+    let Some(journal) = &app_state.journal else {
+        return;
+    };
+
+    let timestamps = pending.iter().map(|entry| entry.timestamp);
+    let _pending = match (timestamps.clone().min(), timestamps.max()) {
+        (Some(from), Some(to)) => app_state.inflight.register_range(from, to),
+        _ => return,
+    };
+
+    for entry in pending {
+        let at = DateTime::from_timestamp_micros(entry.timestamp);
+        let remote = processor_summary(app_state, entry.processor, at, at).await;
+
+        // The peer may also hold a payment at the very same micro second, or another tenant, which
+        // is rare enough to accept counting it here
+        let local = local_summary(
+            app_state,
+            (at, at, TimestampBasis::Requested, entry.tenant.clone()),
+        )
+        .await;
+        let local = match local {
+            Ok(local) => local,
+            // Left pending for the next start
+            Err(e) => {
+                eprintln!("Could not reconcile {}: {e}", entry.correlation_id);
+                continue;
+            }
+        };
+        let local = match entry.processor {
+            Processor::Default => local.default_sum.total_requests,
+            Processor::Fallback => local.fallback.total_requests,
+        };
+
+        match remote {
+            Ok(remote) if remote.total_requests > local => {
+                let recorded = record(
+                    app_state,
+                    entry.tenant.as_ref(),
+                    entry.processor,
+                    &entry.correlation_id,
+                    entry.timestamp,
+                    entry.amount,
+                )
+                .await;
+
+                match recorded {
+                    Ok(()) => journal.confirmed(&entry),
+                    Err(e) => eprintln!("Could not reconcile {}: {e}", entry.correlation_id),
+                }
+            }
+            Ok(_) => journal.failed(&entry),
+            // Left pending for the next start
+            Err(e) => eprintln!("Could not reconcile {}: {e}", entry.correlation_id),
+        }
+    }
+}
+
+/// Queries the totals a processor itself reports through its admin API.
+async fn processor_summary(
+    app_state: &AppState,
+    processor: Processor,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+) -> reqwest::Result<Summary> {
+    let client = app_state.processors.get(processor);
+    let endpoint = format!(
+        "{}/admin/payments-summary",
+        client.admin_url.trim_end_matches('/')
+    );
+    let params: Vec<_> = [("from", from), ("to", to)]
+        .into_iter()
+        .filter_map(|(key, value)| value.map(|value| (key, value)))
+        .collect();
+
+    client
+        .http()
+        .get(endpoint)
+        .query(&params)
+        .header("X-Rinha-Token", &app_state.config.processor_admin_token)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+}
+
+/// Health-checks the primary so the standby takes over submission when it stops responding.
+async fn watch_primary(app_state: AppState) {
+    let endpoint = format!("{}/healthz", app_state.config.peer_urls[0]);
+
+    loop {
+        tokio::time::sleep(app_state.tunables.load().failover_check_interval()).await;
+
+        let healthy = app_state
+            .http
+            .get(&endpoint)
+            .timeout(app_state.config.peer_health_timeout)
+            .send()
+            .await
+            .is_ok_and(|resp| resp.status().is_success());
+
+        if app_state.failover.record_check(healthy) {
+            if app_state.failover.is_active() {
+                println!("Primary unreachable, taking over payment submission");
+            } else {
+                println!("Primary is back, handing payment submission over");
+            }
+        }
+    }
+}
+
+async fn peer_backup(app_state: AppState) {
+    let mut interval = tokio::time::interval(app_state.config.peer_backup_interval);
+
+    loop {
+        interval.tick().await;
+
+        // Keep the previous backup while the peer is down, it is exactly what it needs on restart
+        let peer = app_state.config.backup_target();
+
+        if let Ok(bytes) = fetch_snapshot(&app_state, peer, SnapshotScope::Local).await {
+            *app_state.peer_backup.lock().unwrap() = bytes;
+        }
+    }
+}
+
+/// Compares the backup of the peer's Db against the peer's own, see `consistency`.
+async fn consistency_check(app_state: AppState) {
+    let period = app_state.config.consistency_check_interval;
+    // The first backup may not be in yet right away
+    let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+
+    loop {
+        interval.tick().await;
+
+        let peer = app_state.config.backup_target();
+
+        // Checksums are only served from v1 on
+        if app_state
+            .peer_versions
+            .speak(peer)
+            .is_none_or(|version| version < 1)
+        {
+            continue;
+        }
+
+        let theirs = match peer_client(&app_state, peer)
+            .checksums(SnapshotScope::Local, app_state.config.bootstrap_timeout)
+            .await
+        {
+            Ok(checksums) => checksums,
+            Err(e) => {
+                eprintln!("Skipping consistency check, {peer} unavailable: {e}");
+                continue;
+            }
+        };
+        let backup = app_state.peer_backup.lock().unwrap().clone();
+        let ours = Checksums::of(&StateSnapshot::from_bytes(&backup).unwrap_or_default());
+        let settled = Utc::now().timestamp_micros()
+            - app_state.config.consistency_check_settle.as_micros() as i64;
+        let diverging = ours.diverging(&theirs, consistency::bucket(settled));
+
+        telemetry::peer_consistency(peer, diverging.len());
+
+        let Some(earliest) = diverging.iter().map(|(_, start)| *start).min() else {
+            continue;
+        };
+
+        eprintln!(
+            "Backup of {peer} differs from its Db in {} minutes, the earliest from {}",
+            diverging.len(),
+            DateTime::from_timestamp_micros(earliest).unwrap_or_default()
+        );
+
+        if app_state.config.consistency_resync {
+            match resync(&app_state, peer, &diverging).await {
+                Ok(()) => println!("Fetched {} minutes of {peer} again", diverging.len()),
+                Err(e) => eprintln!("Could not fetch diverging minutes of {peer} again: {e}"),
+            }
+        }
+    }
+}
+
+/// Fetches the `diverging` minutes of `peer`'s Db again and puts them in place of the backup's.
+async fn resync(
+    app_state: &AppState,
+    peer: &str,
+    diverging: &[(Processor, i64)],
+) -> Result<(), PeerError> {
+    let from = diverging.iter().map(|(_, start)| *start).min();
+    let to = diverging
+        .iter()
+        .map(|(_, start)| start + consistency::BUCKET - 1)
+        .max();
+    let bytes = peer_client(app_state, peer)
+        .snapshot_range(
+            SnapshotScope::Local,
+            from.and_then(DateTime::from_timestamp_micros),
+            to.and_then(DateTime::from_timestamp_micros),
+            app_state.config.bootstrap_timeout,
+        )
+        .await?;
+    let diverges =
+        |processor, timestamp| diverging.contains(&(processor, consistency::bucket(timestamp)));
+    let mut fetched = StateSnapshot::from_bytes(&bytes)?;
+    fetched.retain(diverges);
+
+    let mut backup = app_state.peer_backup.lock().unwrap();
+    let mut snapshot = StateSnapshot::from_bytes(&backup).unwrap_or_default();
+    snapshot.retain(|processor, timestamp| !diverges(processor, timestamp));
+    snapshot.extend(&fetched);
+    *backup = snapshot.to_bytes();
+
+    Ok(())
+}
+
+async fn fetch_snapshot(
+    app_state: &AppState,
+    peer: &str,
+    scope: SnapshotScope,
+) -> Result<Vec<u8>, PeerError> {
+    #[cfg(feature = "grpc-peer")]
+    if let Some(client) = app_state.grpc_peers.get(peer) {
+        let request = SnapshotRequest {
+            peer: matches!(scope, SnapshotScope::Peer),
+        };
+        let reply = client
+            .snapshot(request, app_state.config.bootstrap_timeout)
+            .await?;
+
+        return Ok(reply.snapshot);
+    }
+
+    if !app_state.peer_versions.is_compatible(peer) {
+        return Err(IncompatiblePeer.into());
+    }
+
+    peer_client(app_state, peer)
+        .snapshot(scope, app_state.config.bootstrap_timeout)
+        .await
+}
+
+/// Asks `peer` which internal API versions it speaks and records the one to talk to it. A peer
+/// that doesn't answer is asked again by `heartbeat` once it does.
+async fn negotiate_version(app_state: &AppState, peer: &str) {
+    let reported = peer_client(app_state, peer)
+        .protocol_version(app_state.config.bootstrap_timeout)
+        .await;
+    let theirs = match reported {
+        Ok(theirs) => theirs,
+        Err(e) => {
+            eprintln!("Could not ask {peer} for its internal API version: {e}");
+            return;
+        }
+    };
+    let negotiated = ProtocolVersion::OURS.negotiate(&theirs);
+
+    if negotiated.is_none() {
+        eprintln!(
+            "Peer {peer} speaks internal API v{}-v{}, this instance v{MIN_PROTOCOL_VERSION}-v{PROTOCOL_VERSION}, leaving it out",
+            theirs.min_version, theirs.version
+        );
+    }
+
+    app_state.peer_versions.record(peer, negotiated);
+}
+
+/// Measures how far each peer's clock is from ours. Of every probe's samples, the one with the
+/// shortest round trip is kept since it bounds the error tightest.
+async fn clock_probe(app_state: AppState) {
+    let mut interval = tokio::time::interval(app_state.config.clock_probe_interval);
+
+    loop {
+        interval.tick().await;
+
+        for peer in &app_state.config.peer_urls {
+            let client = peer_client(&app_state, peer);
+            // Round trip and offset of the best sample so far
+            let mut best: Option<(i64, i64)> = None;
+
+            for _ in 0..CLOCK_SAMPLES {
+                let sent = Utc::now().timestamp_micros();
+                let Ok(reading) = client.clock(app_state.config.bootstrap_timeout).await else {
+                    break;
+                };
+                let received = Utc::now().timestamp_micros();
+                let round_trip = received - sent;
+
+                if best.is_none_or(|(shortest, _)| round_trip < shortest) {
+                    best = Some((round_trip, clock::offset(sent, reading.now, received)));
+                }
+            }
+
+            if let Some((_, offset)) = best {
+                app_state.clock_skew.record(peer, offset);
+            }
+        }
+    }
+}
+
+/// Pings every peer concurrently, marking those that stop answering dead.
+async fn heartbeat(app_state: AppState) {
+    let mut interval = tokio::time::interval(app_state.config.heartbeat_interval);
+
+    loop {
+        interval.tick().await;
+
+        let timeout = app_state.config.heartbeat_timeout;
+        let pings = app_state.config.peer_urls.iter().map(|peer| {
+            let client = peer_client(&app_state, peer);
+
+            async move {
+                let started = Instant::now();
+                let answered = client.ping(timeout).await.is_ok();
+
+                (peer, answered.then(|| started.elapsed()))
+            }
+        });
+
+        for (peer, rtt) in join_all(pings).await {
+            let was_alive = app_state.heartbeats.is_alive(peer);
+            let liveness = app_state
+                .heartbeats
+                .record(peer, rtt.map(|rtt| rtt.as_micros() as u64));
+
+            telemetry::peer_heartbeat(peer, liveness.alive, rtt);
+
+            if liveness.alive != was_alive {
+                let state = if liveness.alive { "back up" } else { "down" };
+                eprintln!("Peer {peer} is {state}");
+            }
+
+            // It may have come back up on another version
+            if liveness.alive && (!was_alive || !app_state.peer_versions.asked(peer)) {
+                negotiate_version(&app_state, peer).await;
+            }
+        }
+    }
+}
+
+/// Resolves the processor's hostnames on every tick, or sooner after a connect error, and
+/// replaces its client when one of them moved, so no request goes to a pooled connection to the
+/// old address.
+async fn dns_refresh(app_state: AppState, processor: Processor) {
+    let client = app_state.processors.get(processor);
+    let mut interval = tokio::time::interval(app_state.config.dns_refresh_interval);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = client.dns.woken() => {}
+        }
+
+        if client.dns.refresh().await {
+            println!("{processor} processor address changed, reconnecting");
+            client.reconnect();
+        }
+    }
+}
+
+async fn ping() -> StatusCode {
+    StatusCode::OK
+}
+
+/// The client requests to `peer` go through, with the internal token when one is set.
+fn peer_client(app_state: &AppState, peer: impl Into<String>) -> ShowdownClient {
+    let peer = peer.into();
+    let version = app_state
+        .peer_versions
+        .speak(&peer)
+        .unwrap_or(PROTOCOL_VERSION);
+    let client = ShowdownClient::with_http(app_state.http.clone(), peer).with_protocol(version);
+
+    match &app_state.config.internal_token {
+        Some(token) => client.with_token(token),
+        None => client,
+    }
+}
+
+async fn protocol_version() -> Json<ProtocolVersion> {
+    Json(ProtocolVersion::OURS)
+}
+
+async fn clock() -> Json<ClockReading> {
+    Json(ClockReading {
+        now: Utc::now().timestamp_micros(),
+    })
+}
+
+/// Every in-process Db pair, tenants' included.
+fn memory_storages(app_state: &AppState) -> Vec<MemoryStorage> {
+    let mut storages: Vec<_> = app_state.shards.iter().cloned().collect();
+    storages.push(app_state.processed.clone());
+
+    for tenant in app_state.tenants.storages() {
+        storages.extend([tenant.requested, tenant.processed]);
+    }
+
+    storages
+}
+
+/// Estimate of what every in-process Db holds, see `Db::memory_bytes`.
+fn db_memory_bytes(app_state: &AppState) -> usize {
+    memory_storages(app_state)
+        .iter()
+        .map(MemoryStorage::memory_bytes)
+        .sum()
+}
+
+/// Summed across every in-process Db, `None` without dedup.
+fn dedup_stats(app_state: &AppState) -> Option<DedupStats> {
+    let mut total: Option<DedupStats> = None;
+
+    for storage in memory_storages(app_state) {
+        if let Some(stats) = storage.dedup_stats() {
+            total.get_or_insert_default().add(&stats);
+        }
+    }
+
+    total
+}
+
+async fn compactor(app_state: AppState) {
+    let mut interval = tokio::time::interval(app_state.config.compaction_interval);
+    let horizon = app_state.config.compaction_horizon;
+    let bucket = app_state.config.compaction_bucket.as_micros() as i64;
+
+    loop {
+        interval.tick().await;
+
+        let horizon = (Utc::now() - horizon).timestamp_micros();
+
+        for shard in app_state.shards.iter() {
+            shard.default.compact(horizon, bucket);
+            shard.fallback.compact(horizon, bucket);
+        }
+
+        app_state.processed.default.compact(horizon, bucket);
+        app_state.processed.fallback.compact(horizon, bucket);
+
+        for tenant in app_state.tenants.storages() {
+            for storage in [tenant.requested, tenant.processed] {
+                storage.default.compact(horizon, bucket);
+                storage.fallback.compact(horizon, bucket);
+            }
+        }
+        telemetry::db_memory(db_memory_bytes(&app_state));
+    }
+}
+
+/// How one submission of a payment ended.
+enum Attempt {
+    Confirmed,
+    // Refused by the processor, retrying would not help
+    Rejected,
+    Retry(Payment),
+    // Not submitted, the fallback was chosen but is over its budget
+    Deferred(Payment),
+}
+
+/// Submits the payment once, handing it back when the attempt should be retried.
+async fn process_payment(p: Payment, retries: u64, task_state: &AppState) -> Attempt {
+    let chosen = task_state.processors.choose(retries);
+
+    if !task_state.processors.try_route(chosen, p.amount) {
+        task_state.event(&p.correlation_id, EventKind::Deferred);
+        return Attempt::Deferred(p);
+    }
+
+    let mut span = Span::start("process_payment", p.trace);
+    let amount = p.amount_units(task_state.config.amount_scale);
+    let timestamp = p.requested_at.timestamp_micros();
+
+    #[cfg(feature = "persistence")]
+    task_state.journal(&p, chosen, Journal::submitted);
+
+    task_state.statuses.submitted(&p.correlation_id, chosen);
+
+    let _pending = task_state.inflight.register(timestamp);
+    let sent_at = Instant::now();
+    let (processor, status) = submit_hedged(task_state, chosen, &p, span.context()).await;
+    let elapsed = sent_at.elapsed();
+    let client = task_state.processors.get(processor);
+    let attempt = |outcome, error: Option<&ProcessorError>| EventKind::Attempt {
+        processor,
+        retries,
+        outcome,
+        error: error.map(ToString::to_string),
+        latency_micros: elapsed.as_micros() as u64,
+    };
+
+    let status = match status {
+        // A timed out request may still have gone through, which only the processor knows
+        Err(e) if e.is_timeout() && task_state.config.processor_lookup => {
+            if lookup_payment(task_state, processor, &p.correlation_id).await {
+                println!("Timed out {} was processed after all", p.correlation_id);
+                Ok(())
+            } else {
+                Err(e)
+            }
+        }
+        // On a retry, the attempt that failed on our end went through on the processor's. On the
+        // first attempt, the client sent the same correlationId twice
+        Err(ProcessorError::DuplicateCorrelationId) if retries > 0 => {
+            println!("Retried {} was processed after all", p.correlation_id);
+            Ok(())
+        }
+        status => status,
+    };
+
+    match status {
+        Ok(()) => {
+            client.breaker.record_success();
+            client.success.record(true);
+            task_state.dispatch_limit.on_success(elapsed);
+            task_state
+                .processors
+                .feedback(processor, Outcome::Accepted { latency: elapsed });
+
+            if let Some(pacer) = &client.pacer {
+                pacer.on_accepted();
+            }
+
+            telemetry::payment_processed(processor, "success", p.amount, elapsed);
+            task_state.event(&p.correlation_id, attempt("success", None));
+
+            let stored = record(
+                task_state,
+                p.tenant.as_ref(),
+                processor,
+                &p.correlation_id,
+                timestamp,
+                amount,
+            )
+            .await;
+
+            if let Err(e) = stored {
+                span.set_error(e.to_string());
+                eprintln!(
+                    "Dropping {} (trace {}): {e}",
+                    p.correlation_id,
+                    p.trace.trace_id()
+                );
+            }
+
+            #[cfg(feature = "persistence")]
+            task_state.journal(&p, processor, Journal::confirmed);
+
+            task_state.statuses.confirmed(&p.correlation_id);
+
+            if let Some(webhook) = &task_state.webhook {
+                webhook.notify(Confirmation::new(&p, processor));
+            }
+
+            Attempt::Confirmed
+        }
+        Err(e) if !e.is_retryable() => {
+            telemetry::payment_processed(processor, "rejected", p.amount, elapsed);
+            task_state.event(&p.correlation_id, attempt("rejected", Some(&e)));
+            span.set_error(e.to_string());
+            eprintln!(
+                "Dropping {} (trace {}): {e}",
+                p.correlation_id,
+                p.trace.trace_id()
+            );
+
+            #[cfg(feature = "persistence")]
+            task_state.journal(&p, processor, Journal::failed);
+
+            task_state.statuses.failed(&p.correlation_id);
+
+            Attempt::Rejected
+        }
+        // Rate limits, server errors, timeouts and transport errors
+        Err(e) => {
+            task_state.event(&p.correlation_id, attempt("retry", Some(&e)));
+            task_state.dispatch_limit.on_overload();
+            report_failure(task_state, processor, &e);
+
+            telemetry::payment_processed(processor, "retry", p.amount, elapsed);
+
+            Attempt::Retry(p)
+        }
+    }
+}
+
+/// Tells the breaker, the router and the pacer of `processor` a request to it failed with `e`.
+/// Refusals say nothing about the processor's health and are left out.
+fn report_failure(task_state: &AppState, processor: Processor, e: &ProcessorError) {
+    let client = task_state.processors.get(processor);
+
+    match e {
+        e if !e.is_retryable() => return,
+        // Rate limiting only says the processor is busy, not that it is unhealthy
+        ProcessorError::RateLimited => {
+            if let Some(pacer) = &client.pacer {
+                pacer.on_rate_limited();
+            }
+        }
+        _ => {
+            client.breaker.record_failure();
+            client.success.record(false);
+        }
+    }
+
+    task_state.processors.feedback(processor, Outcome::Failed);
+}
+
+/// Records a confirmed payment by requested and by processed time, into its tenant's Dbs when it
+/// has one.
+async fn record(
+    app_state: &AppState,
+    tenant: Option<&TenantId>,
+    processor: Processor,
+    correlation_id: &CorrelationId,
+    timestamp: i64,
+    amount: u64,
+) -> Result<(), BackendError> {
+    if let Some(tenant) = tenant {
+        app_state
+            .tenants
+            .record(tenant, processor, correlation_id, timestamp, amount)?;
+    } else {
+        let stored = app_state
+            .storage
+            .record(processor, correlation_id, timestamp, amount)
+            .await;
+
+        app_state.processed.db(processor).set(
+            correlation_id,
+            Utc::now().timestamp_micros(),
+            amount,
+        );
+
+        stored?;
+    }
+
+    app_state.recorded.send_modify(|recorded| *recorded += 1);
+
+    Ok(())
+}
+
+/// Submits to `chosen`, and with a hedge delay also to the other processor once `chosen` took
+/// that long to answer. Returns the processor the outcome is from. A request failing while the
+/// other is still running is reported by `report_failure` here, the outcome returned is left to
+/// the caller.
+///
+/// The first success wins and the other request is dropped, but a dropped request may already
+/// have reached its processor, which then processes the payment too. It is only ever recorded
+/// once, so both processors' own summaries then count a payment ours counts once: hedging trades
+/// that inconsistency for latency, and is off unless `HEDGE_DELAY_MS` is set.
+async fn submit_hedged(
+    task_state: &AppState,
+    chosen: Processor,
+    p: &Payment,
+    trace: TraceContext,
+) -> (Processor, Result<(), ProcessorError>) {
+    let primary = submit(task_state, chosen, p, trace);
+    let hedge_delay = task_state.config.hedge_delay;
+
+    if hedge_delay.is_zero() {
+        return (chosen, primary.await);
+    }
+
+    tokio::pin!(primary);
+
+    tokio::select! {
+        status = &mut primary => return (chosen, status),
+        _ = tokio::time::sleep(hedge_delay) => {}
+    }
+
+    let other = match chosen {
+        Processor::Default => Processor::Fallback,
+        Processor::Fallback => Processor::Default,
+    };
+
+    if !task_state.processors.try_route(other, p.amount) {
+        return (chosen, primary.await);
+    }
+
+    let hedge = submit(task_state, other, p, trace);
+    tokio::pin!(hedge);
+
+    // A failure only decides the outcome once the other request failed too
+    tokio::select! {
+        status = &mut primary => match status {
+            Ok(()) => (chosen, Ok(())),
+            Err(e) => {
+                report_failure(task_state, chosen, &e);
+                (other, hedge.await)
+            }
+        },
+        status = &mut hedge => match status {
+            Ok(()) => (other, Ok(())),
+            Err(e) => {
+                report_failure(task_state, other, &e);
+                (chosen, primary.await)
+            }
+        },
+    }
+}
+
+async fn submit(
+    task_state: &AppState,
+    processor: Processor,
+    p: &Payment,
+    trace: TraceContext,
+) -> Result<(), ProcessorError> {
+    let client = task_state.processors.get(processor);
+    let url = format!("{}/payments", client.url.trim_end_matches('/'));
+    let req = client
+        .http()
+        .post(url)
+        .header(TRACEPARENT, trace.header_value())
+        .timeout(task_state.tunables.load().processor_timeout());
+    #[cfg(feature = "fast-json")]
+    let req = req
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(fast_json::payment_body(p));
+    #[cfg(not(feature = "fast-json"))]
+    let req = req.json(p);
+
+    let _inflight = client.acquire().await;
+    let fault = task_state.chaos.inject(Target::Processor).await;
+
+    if fault == Some(Fault::Fail) {
+        return Err(ProcessorError::Internal(StatusCode::INTERNAL_SERVER_ERROR));
+    }
+
+    let resp = req.send().await.map_err(ProcessorError::Transport);
+
+    if let Err(e) = &resp
+        && e.is_connect()
+    {
+        client.dns.wake();
+    }
+
+    let resp = resp?;
+    let status = resp.status();
+
+    if fault == Some(Fault::Drop) {
+        return Err(ProcessorError::Lost);
+    }
+
+    if status.is_success() {
+        return Ok(());
+    }
+
+    // Only failures are worth reading the body of
+    let body = resp.bytes().await.unwrap_or_default();
+
+    Err(ProcessorError::parse(status, &body))
+}
+
+/// Whether the processor has a payment with this correlationId, false when it can't be asked.
+async fn lookup_payment(
+    app_state: &AppState,
+    processor: Processor,
+    correlation_id: &CorrelationId,
+) -> bool {
+    let client = app_state.processors.get(processor);
+    let url = format!(
+        "{}/payments/{correlation_id}",
+        client.url.trim_end_matches('/')
+    );
+
+    client
+        .http()
+        .get(url)
+        .timeout(app_state.tunables.load().processor_timeout())
+        .send()
+        .await
+        .is_ok_and(|resp| resp.status().is_success())
+}
+
+async fn payments(
+    State(app_state): State<AppState>,
+    Extension(trace): Extension<TraceContext>,
+    Payload(payload): Payload,
+) -> Response {
+    let style = app_state.config.payments_response;
+
+    // Refused up front, there would be nowhere to record it once processed
+    if let Some(tenant) = &payload.tenant_id
+        && let Err(e) = app_state.tenants.get_or_insert(tenant)
+    {
+        let status = StatusCode::UNPROCESSABLE_ENTITY;
+
+        return match style {
+            ResponseStyle::Plain => (status, e.to_string()).into_response(),
+            ResponseStyle::Detailed => error_response(status, "too_many_tenants", &e.to_string()),
+        };
+    }
+
+    let logged = app_state
+        .access_log
+        .is_some()
+        .then(|| Extension(LoggedPayment(payload.correlation_id.clone())));
+
+    let response = match (accept(app_state, payload, trace).await, style) {
+        (Accepted::Queued | Accepted::Processed(_), ResponseStyle::Plain) => {
+            StatusCode::OK.into_response()
+        }
+        (Accepted::Rejected | Accepted::Unavailable, ResponseStyle::Plain) => {
+            StatusCode::BAD_GATEWAY.into_response()
+        }
+        (Accepted::Queued, ResponseStyle::Detailed) => {
+            let receipt = PaymentReceipt {
+                status: "queued".to_string(),
+                requested_at: None,
+            };
+
+            (StatusCode::ACCEPTED, Json(receipt)).into_response()
+        }
+        (Accepted::Processed(requested_at), ResponseStyle::Detailed) => {
+            let receipt = PaymentReceipt {
+                status: "processed".to_string(),
+                requested_at: Some(requested_at),
+            };
+
+            (StatusCode::CREATED, Json(receipt)).into_response()
+        }
+        (Accepted::Rejected, ResponseStyle::Detailed) => error_response(
+            StatusCode::BAD_GATEWAY,
+            "processor_rejected",
+            "the processor refused the payment",
+        ),
+        (Accepted::Unavailable, ResponseStyle::Detailed) => error_response(
+            StatusCode::BAD_GATEWAY,
+            "processor_unavailable",
+            "no processor confirmed the payment",
+        ),
+    };
+
+    (logged, response).into_response()
+}
+
+/// How `accept` left a payment.
+enum Accepted {
+    // Queued here or handed to a peer, to be submitted later
+    Queued,
+    // Confirmed by a processor, with `SYNC_SUBMISSION`
+    Processed(DateTime<Utc>),
+    Rejected,
+    // No processor confirmed it in time, it was not recorded
+    Unavailable,
+}
+
+/// Payments proxied by the peer because this instance owns them.
+async fn internal_payments(
+    State(app_state): State<AppState>,
+    Extension(trace): Extension<TraceContext>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> StatusCode {
+    let payload = match PeerEncoding::negotiate(headers.get(header::CONTENT_TYPE)) {
+        PeerEncoding::Json => Json::from_bytes(&body).map(|Json(p)| p).ok(),
+        PeerEncoding::MessagePack => rmp_serde::from_slice(&body).ok(),
+    };
+    let Some(payload) = payload else {
+        return StatusCode::BAD_REQUEST;
+    };
+
+    enqueue(app_state, payload, trace).await;
+
+    StatusCode::OK
+}
+
+/// Enqueues the payment unless a peer should submit it: the primary while this instance is an
+/// idle standby, or the owner under ownership partitioning, so a client retrying the same
+/// correlationId against either instance is always processed by the same one. With
+/// `SYNC_SUBMISSION`, payments kept here are submitted before answering instead, see
+/// `submit_inline`.
+async fn accept(app_state: AppState, payload: PaymentPayload, trace: TraceContext) -> Accepted {
+    telemetry::payment_received();
+
+    if !app_state.failover.is_active() {
+        let primary = app_state.config.peer_urls[0].clone();
+        tokio::spawn(forward(app_state, primary, payload, trace));
+    } else if let Some(index) = app_state.config.instance_index
+        && let owner = partition::owner(&payload.correlation_id, app_state.config.instances())
+        && owner != index
+    {
+        let peer = app_state.config.peer_url(owner).to_string();
+        tokio::spawn(forward(app_state, peer, payload, trace));
+    } else if let Some(peer) = app_state.balancer.pick(&payload.correlation_id) {
+        tokio::spawn(balance(app_state, peer, payload, trace));
+    } else if app_state.config.sync_submission {
+        return submit_inline(app_state, payload, trace).await;
+    } else {
+        enqueue(app_state, payload, trace).await;
+    }
+
+    Accepted::Queued
+}
+
+/// Submits the payment within the request. Each processor is tried at most once and nothing is
+/// retried after answering, so unless it was processed the payment was not recorded and the
+/// client is free to send it again.
+async fn submit_inline(
+    app_state: AppState,
+    payload: PaymentPayload,
+    trace: TraceContext,
+) -> Accepted {
+    let mut p = Payment {
+        correlation_id: payload.correlation_id,
+        amount: payload.amount,
+        requested_at: app_state.request_clock.now(),
+        trace,
+        tenant: payload.tenant_id,
+    };
+
+    let requested_at = p.requested_at;
+    app_state.statuses.queued(&p.correlation_id, requested_at);
+    app_state.event(&p.correlation_id, EventKind::Enqueued);
+
+    let _permit = app_state.dispatch_limit.acquire().await;
+
+    for retries in 0..SYNC_ATTEMPTS {
+        match process_payment(p, retries, &app_state).await {
+            Attempt::Confirmed => return Accepted::Processed(requested_at),
+            Attempt::Rejected => return Accepted::Rejected,
+            Attempt::Retry(retry) | Attempt::Deferred(retry) => p = retry,
+        }
+    }
+
+    app_state.statuses.failed(&p.correlation_id);
+    app_state.event(&p.correlation_id, EventKind::GaveUp);
+
+    Accepted::Unavailable
+}
+
+async fn forward(app_state: AppState, peer: String, payload: PaymentPayload, trace: TraceContext) {
+    if !app_state.peer_versions.is_compatible(&peer) {
+        eprintln!(
+            "Processing {} locally, {peer} shares no internal API version (trace {})",
+            payload.correlation_id,
+            trace.trace_id()
+        );
+        return enqueue(app_state, payload, trace).await;
+    }
+
+    let forwarded = peer_client(&app_state, peer)
+        .forward_payment(
+            &payload,
+            app_state.config.peer_encoding,
+            trace.child(),
+            app_state.config.peer_proxy_timeout,
+        )
+        .await
+        .is_ok();
+
+    // Losing the payment is worse than the rare duplicate if the peer did get it
+    if !forwarded {
+        eprintln!(
+            "Processing {} locally, peer unreachable (trace {})",
+            payload.correlation_id,
+            trace.trace_id()
+        );
+        enqueue(app_state, payload, trace).await;
+    }
+}
+
+async fn balance(app_state: AppState, peer: usize, payload: PaymentPayload, trace: TraceContext) {
+    let _in_flight = app_state.balancer.track(peer);
+    let url = app_state.config.peer_urls[peer].clone();
+
+    forward(app_state, url, payload, trace).await;
+}
+
+async fn enqueue(app_state: AppState, payload: PaymentPayload, trace: TraceContext) {
+    let p = Payment {
+        correlation_id: payload.correlation_id,
+        amount: payload.amount,
+        requested_at: app_state.request_clock.now(),
+        trace,
+        tenant: payload.tenant_id,
+    };
+
+    app_state.statuses.queued(&p.correlation_id, p.requested_at);
+    app_state.event(&p.correlation_id, EventKind::Enqueued);
+
+    #[cfg(feature = "persistence")]
+    if let Some(spill) = &app_state.spill {
+        spill.send(p, 0).await;
+        return;
+    }
+
+    app_state
+        .queue_cap
+        .reserve(&app_state.accept_queue_tx)
+        .await;
+    app_state.accept_queue_tx.send((p, 0)).await.unwrap();
+}
+
+async fn payment_status(
+    State(app_state): State<AppState>,
+    Path(correlation_id): Path<CorrelationId>,
+) -> Result<Json<PaymentStatus>, StatusCode> {
+    app_state
+        .statuses
+        .get(&correlation_id)
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn payments_summary(
+    State(app_state): State<AppState>,
+    Extension(trace): Extension<TraceContext>,
+    headers: HeaderMap,
+    Query(params): Query<SummaryQueryParams>,
+) -> Response {
+    let internal = headers
+        .get(INTERNAL_SUMMARY_HEADER)
+        .is_some_and(|v| v.as_bytes().eq_ignore_ascii_case(b"true"));
+    if params.is_inverted() {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            "invalid_range",
+            "from must not be after to",
+        );
+    }
+
+    let only_local = internal || params.only_local.unwrap_or(false);
+    let (total, timings) = match summarize(&app_state, params, only_local, trace).await {
+        Ok(summarized) => summarized,
+        Err(e) => {
+            return error_response(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "storage_unavailable",
+                &e.to_string(),
+            );
+        }
+    };
+
+    let mut resp = if internal
+        && PeerEncoding::negotiate(headers.get(header::ACCEPT)) == PeerEncoding::MessagePack
+    {
+        let body = rmp_serde::to_vec_named(&total).unwrap();
+        ([(header::CONTENT_TYPE, MSGPACK)], body).into_response()
+    } else {
+        Json(total).into_response()
+    };
+
+    if app_state.config.summary_timing_headers {
+        timings.annotate(resp.headers_mut());
+    }
+
+    resp
+}
+
+/// Where a summary's time went, reported in its response headers with `SUMMARY_TIMING_HEADERS`.
+#[derive(Default)]
+struct SummaryTimings {
+    // Waiting on payments in flight within the range
+    wait_inflight: Duration,
+    // Reading this instance's Dbs
+    local: Duration,
+    // Getting the peers' summaries, cached ones included
+    remote: Duration,
+}
+
+impl SummaryTimings {
+    const WAIT_INFLIGHT: HeaderName = HeaderName::from_static("x-wait-inflight-us");
+    const LOCAL: HeaderName = HeaderName::from_static("x-local-us");
+    const REMOTE: HeaderName = HeaderName::from_static("x-remote-us");
+
+    fn annotate(&self, headers: &mut HeaderMap) {
+        for (name, elapsed) in [
+            (Self::WAIT_INFLIGHT, self.wait_inflight),
+            (Self::LOCAL, self.local),
+            (Self::REMOTE, self.remote),
+        ] {
+            headers.insert(name, HeaderValue::from(elapsed.as_micros() as u64));
+        }
+    }
+}
+
+/// Pushes the summary `params` ask for as an SSE `summary` event right away, then again every
+/// `interval_ms` and once `every` more payments were recorded here, whichever comes first.
+async fn payments_summary_stream(
+    State(app_state): State<AppState>,
+    Query(params): Query<SummaryQueryParams>,
+    Query(stream_params): Query<SummaryStreamParams>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, (StatusCode, &'static str)> {
+    let config = &app_state.config;
+    let interval = stream_params
+        .interval_ms
+        .map_or(config.summary_stream_interval, Duration::from_millis);
+    let every = stream_params.every.unwrap_or(config.summary_stream_every);
+
+    if interval.is_zero() && every == 0 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "interval_ms and every can't both be 0",
+        ));
+    }
+
+    if params.is_inverted() {
+        return Err((StatusCode::BAD_REQUEST, "from must not be after to"));
+    }
+
+    let only_local = params.only_local.unwrap_or(false);
+    let recorded = app_state.recorded.subscribe();
+    // `seen` is how many payments were recorded as of the last push, `None` before the first
+    let events = stream::unfold(
+        (app_state, params, recorded, None),
+        move |(app_state, params, mut recorded, seen)| async move {
+            if let Some(seen) = seen {
+                let enough = recorded.wait_for(|&now| every > 0 && now - seen >= every);
+
+                if interval.is_zero() {
+                    enough.await.ok()?;
+                } else if let Ok(Err(_)) = tokio::time::timeout(interval, enough).await {
+                    // Timing out is the periodic push, only a closed channel ends the stream
+                    return None;
+                }
+            }
+
+            let seen = *recorded.borrow_and_update();
+            let summarized = summarize(
+                &app_state,
+                params.clone(),
+                only_local,
+                TraceContext::generate(),
+            )
+            .await;
+            // A storage outage is reported in its own event, the next push may well succeed
+            let event = match summarized {
+                Ok((total, _)) => Event::default().event("summary").json_data(total),
+                Err(e) => Ok(Event::default().event("error").data(e.to_string())),
+            };
+
+            Some((event, (app_state, params, recorded, Some(seen))))
+        },
+    );
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// The summary `params` ask for, after waiting on payments in flight within the range when
+/// `SUMMARY_INFLIGHT_WAIT_MS` is set, and where its time went.
+async fn summarize(
+    app_state: &AppState,
+    params: SummaryQueryParams,
+    only_local: bool,
+    trace: TraceContext,
+) -> Result<(ProcessorSummaries, SummaryTimings), BackendError> {
+    let basis = params
+        .timestamp_basis
+        .unwrap_or(app_state.config.timestamp_basis);
+    let started = Instant::now();
+    let mut span = Span::start("payments_summary", trace);
+    let wait = app_state.config.summary_inflight_wait;
+    let mut wait_inflight = Duration::ZERO;
+
+    // Processed times are only known once a payment is recorded, so there is nothing to wait for
+    if !wait.is_zero() && basis == TimestampBasis::Requested {
+        let from = params.from.map(|dt| dt.timestamp_micros());
+        let to = params.to.map(|dt| dt.timestamp_micros());
+        let timed_out = app_state
+            .inflight
+            .wait_until_unlocked_timeout(from, to, wait)
+            .await;
+
+        wait_inflight = started.elapsed();
+        span.set_attribute("inflight_wait_ms", wait_inflight.as_secs_f64() * 1000.0);
+        telemetry::summary_waited(started.elapsed(), timed_out);
+
+        if timed_out {
+            eprintln!("Answering summary with payments still in flight after {wait:?}");
+        }
+    }
+
+    let (mut total, mut timings) = summary(
+        app_state,
+        (params.from, params.to, basis, params.tenant),
+        only_local,
+        span.context(),
+    )
+    .await?;
+
+    if !params.breakdown.unwrap_or(false) {
+        total.instances.clear();
+    }
+
+    if params.include_fees.unwrap_or(false) {
+        total.apply_fees(
+            app_state.config.default_processor_fee,
+            app_state.config.fallback_processor_fee,
+        );
+    }
+
+    telemetry::summary_served(started.elapsed());
+    timings.wait_inflight = wait_inflight;
+
+    Ok((total, timings))
+}
+
+async fn summary(
+    app_state: &AppState,
+    range: SummaryRange,
+    only_local: bool,
+    trace: TraceContext,
+) -> Result<(ProcessorSummaries, SummaryTimings), BackendError> {
+    let mut timings = SummaryTimings::default();
+    let started = Instant::now();
+    let mut total = local_summary(app_state, range.clone()).await?;
+    timings.local = started.elapsed();
+
+    // A shared Db already holds what every instance recorded, but processed times and tenants are
+    // only ever kept by the instance that confirmed the payment
+    let shared =
+        app_state.storage.is_shared() && range.2 == TimestampBasis::Requested && range.3.is_none();
+
+    // Nothing tells apart which instance recorded what in a shared Db
+    if !shared {
+        total = total.attributed_to(app_state.config.instance_id.clone());
+    }
+
+    if !only_local && !shared {
+        let started = Instant::now();
+        let remote_data = app_state
+            .peer_summaries
+            .get_or_fetch(range.clone(), || remote_summary(app_state, range, trace))
+            .await;
+        timings.remote = started.elapsed();
+
+        total.merge(remote_data);
+    }
+
+    Ok((total, timings))
+}
+
+/// Compares the merged totals of both instances with what each processor reports for the same
+/// window. With `patch`, payments the processor counted but we missed are recorded locally at the
+/// end of the window. Only the in-process Dbs can take such a lump sum, other backends are never
+/// patched and say so with `patched: false`.
+async fn reconcile(
+    State(app_state): State<AppState>,
+    Extension(trace): Extension<TraceContext>,
+    Json(req): Json<ReconcileRequest>,
+) -> Result<Json<ReconcileReport>, (StatusCode, String)> {
+    let range = (req.from, req.to, TimestampBasis::Requested, None);
+    let (ours, _) = summary(&app_state, range, false, trace)
+        .await
+        .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, e.to_string()))?;
+    let mut diffs = Vec::with_capacity(2);
+
+    for (processor, local) in [
+        (Processor::Default, ours.default_sum),
+        (Processor::Fallback, ours.fallback),
+    ] {
+        let remote = processor_summary(&app_state, processor, req.from, req.to)
+            .await
+            .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
+        let mut diff = ProcessorDiff::new(remote, local);
+
+        if req.patch
+            && app_state.config.db_backend == BackendKind::Memory
+            && diff.missing_requests > 0
+            && diff.missing_amount > 0.0
+        {
+            let at = req.to.unwrap_or_else(Utc::now).timestamp_micros();
+            let db = app_state.memory.db(processor);
+
+            // Recorded as a single entry since the individual payments are unknown
+            db.add(
+                at,
+                diff.missing_requests as u64,
+                app_state.config.amount_scale.to_units(diff.missing_amount),
+            );
+            diff.patched = true;
+        }
+
+        diffs.push(diff);
+    }
+
+    let fallback = diffs.pop().unwrap();
+    let default = diffs.pop().unwrap();
+
+    Ok(Json(ReconcileReport { default, fallback }))
+}
+
+async fn state_snapshot(
+    State(app_state): State<AppState>,
+    Query(params): Query<SnapshotQueryParams>,
+) -> impl IntoResponse {
+    let mut bytes = snapshot_bytes(&app_state, params.scope.unwrap_or_default());
+
+    if params.from.is_some() || params.to.is_some() {
+        // The backup is empty until the first one is taken
+        let mut snapshot = StateSnapshot::from_bytes(&bytes).unwrap_or_default();
+        let from = params.from.map_or(i64::MIN, |dt| dt.timestamp_micros());
+        let to = params.to.map_or(i64::MAX, |dt| dt.timestamp_micros());
+
+        snapshot.retain(|_, timestamp| (from..=to).contains(&timestamp));
+        bytes = snapshot.to_bytes();
+    }
+
+    ([(header::CONTENT_TYPE, "application/octet-stream")], bytes)
+}
+
+/// Per-minute checksums of what `state_snapshot` would send, see `consistency`.
+async fn checksums(
+    State(app_state): State<AppState>,
+    Query(params): Query<SnapshotQueryParams>,
+) -> Json<Checksums> {
+    let bytes = snapshot_bytes(&app_state, params.scope.unwrap_or_default());
+    let snapshot = StateSnapshot::from_bytes(&bytes).unwrap_or_default();
+
+    Json(Checksums::of(&snapshot))
+}
+
+fn snapshot_bytes(app_state: &AppState, scope: SnapshotScope) -> Vec<u8> {
+    match scope {
+        SnapshotScope::Local => {
+            // Records for the same timestamp add up when merged, so shards are simply appended
+            let mut snapshot = StateSnapshot::default();
+
+            for shard in app_state.shards.iter() {
+                snapshot.extend(&StateSnapshot::capture(&shard.default, &shard.fallback));
+            }
+
+            snapshot.to_bytes()
+        }
+        SnapshotScope::Peer => app_state.peer_backup.lock().unwrap().clone(),
+    }
+}
+
+/// Streams every entry this instance recorded within the range, see `ExportRecord`. Each Db is
+/// read once up front, so writes landing while the body streams aren't included.
+async fn export(
+    State(app_state): State<AppState>,
+    Query(params): Query<ExportQueryParams>,
+) -> Response {
+    let format = params.format.unwrap_or_default();
+    let from = params.from.map(|from| from.timestamp_micros());
+    let to = params.to.map(|to| to.timestamp_micros());
+    // Shards split the Dbs by timestamp, so no two of them hold the same one
+    let exports: Vec<_> = [Processor::Default, Processor::Fallback]
+        .into_iter()
+        .flat_map(|processor| {
+            app_state
+                .shards
+                .iter()
+                .map(move |shard| (processor, shard.db(processor).export(from, to)))
+        })
+        .collect();
+    let mut records = exports.into_iter().flat_map(|(processor, entries)| {
+        entries.map(move |(timestamp, count, amount)| ExportRecord {
+            processor,
+            timestamp,
+            count,
+            amount,
+        })
+    });
+
+    if format == ExportFormat::Snapshot {
+        let mut snapshot = StateSnapshot::default();
+
+        for r in records {
+            snapshot.push(r.processor, r.timestamp, r.count, r.amount);
+        }
+
+        return (
+            [(header::CONTENT_TYPE, format.content_type())],
+            snapshot.to_bytes(),
+        )
+            .into_response();
+    }
+
+    let chunks = iter::from_fn(move || {
+        let mut chunk = String::new();
+
+        for record in records.by_ref().take(EXPORT_CHUNK) {
+            record.write(format, &mut chunk);
+        }
+
+        (!chunk.is_empty()).then_some(chunk)
+    });
+    let header = (format == ExportFormat::Csv).then(|| CSV_HEADER.to_string());
+    let body = Body::from_stream(stream::iter(
+        header.into_iter().chain(chunks).map(Ok::<_, Infallible>),
+    ));
+
+    ([(header::CONTENT_TYPE, format.content_type())], body).into_response()
+}
+
+/// Adds an export to this instance's own Db, to restore it or to seed a fresh instance from the
+/// peer's. Records add up with what is already there, so importing the same export twice counts
+/// it twice.
+async fn import(
+    State(app_state): State<AppState>,
+    Query(params): Query<ImportQueryParams>,
+    body: Bytes,
+) -> Result<StatusCode, (StatusCode, String)> {
+    if app_state.config.db_backend != BackendKind::Memory {
+        return Err((
+            StatusCode::NOT_FOUND,
+            "only the in-process Db can be imported into".to_string(),
+        ));
+    }
+
+    let shards = &app_state.shards;
+    // Each record goes to the shard the worker pool would have recorded it on
+    let add = |processor, timestamp, count, amount| {
+        shards[shard_index(timestamp, shards.len())]
+            .db(processor)
+            .add(timestamp, count, amount);
+    };
+    let bad_request = |e: &dyn std::error::Error| (StatusCode::BAD_REQUEST, e.to_string());
+    let imported = match params.format.unwrap_or_default() {
+        ExportFormat::Snapshot => {
+            let snapshot = StateSnapshot::from_bytes(&body).map_err(|e| bad_request(&e))?;
+
+            for processor in [Processor::Default, Processor::Fallback] {
+                for (timestamp, count, amount) in snapshot.records(processor) {
+                    add(processor, timestamp, count, amount);
+                }
+            }
+
+            "snapshot".to_string()
+        }
+        format => {
+            let records = export::parse(format, &body).map_err(|e| bad_request(&e))?;
+
+            for r in &records {
+                add(r.processor, r.timestamp, r.count, r.amount);
+            }
+
+            format!("{} records", records.len())
+        }
+    };
+
+    println!("Imported {imported} into the local Db");
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Payments waiting in either queue, not counting spilled ones.
+fn queued(app_state: &AppState) -> usize {
+    depth(&app_state.accept_queue_tx) + depth(&app_state.submit_queue_tx)
+}
+
+fn depth(queue: &mpsc::Sender<(Payment, u64)>) -> usize {
+    queue.max_capacity() - queue.capacity()
+}
+
+async fn healthz() -> StatusCode {
+    StatusCode::OK
+}
+
+async fn readyz(State(app_state): State<AppState>) -> impl IntoResponse {
+    let queue_depth = queued(&app_state);
+    let checks = app_state.config.peer_urls.iter().map(|peer| {
+        app_state
+            .http
+            .get(format!("{peer}/healthz"))
+            .timeout(app_state.config.peer_health_timeout)
+            .send()
+    });
+    let peer_reachable = join_all(checks)
+        .await
+        .into_iter()
+        .all(|resp| resp.is_ok_and(|resp| resp.status().is_success()));
+    let processor_reachable = app_state.processor_reachable.load(Ordering::Relaxed);
+    let default_open = app_state.processors.default.breaker.is_open();
+    let fallback_open = app_state.processors.fallback.breaker.is_open();
+    let ready = queue_depth <= app_state.config.ready_queue_threshold
+        && peer_reachable
+        && processor_reachable
+        && !(default_open && fallback_open);
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (
+        status,
+        Json(Readiness {
+            ready,
+            queue_depth,
+            peer_reachable,
+            processor_reachable,
+            default_open,
+            fallback_open,
+        }),
+    )
+}
+
+/// A page polling `/admin/info` and following `/payments-summary/stream`, to watch a run from a
+/// browser.
+#[cfg(feature = "dashboard")]
+async fn dashboard() -> Html<&'static str> {
+    Html(include_str!("dashboard.html"))
+}
+
+async fn info(State(app_state): State<AppState>) -> Json<Info> {
+    let config = &app_state.config;
+    let accept = &app_state.accept_queue_tx;
+    let submit = &app_state.submit_queue_tx;
+
+    Json(Info {
+        version: env!("CARGO_PKG_VERSION"),
+        git_sha: env!("GIT_SHA"),
+        routing_strategy: app_state.processors.strategy(),
+        queue_capacity: app_state.queue_cap.limit(),
+        queue_depth: depth(accept),
+        submit_queue_capacity: submit.max_capacity(),
+        submit_queue_depth: depth(submit),
+        dispatch_concurrency: app_state.tunables.load().dispatch_concurrency,
+        dispatch_limit: app_state.dispatch_limit.limit(),
+        processor_max_inflight: config.processor_max_inflight,
+        default_processor_rate: app_state.processors.default.pacer.as_ref().map(Pacer::rate),
+        fallback_processor_rate: app_state
+            .processors
+            .fallback
+            .pacer
+            .as_ref()
+            .map(Pacer::rate),
+        default_success_rate: app_state.processors.default.success.rate(),
+        fallback_budget_used: app_state.processors.fallback_budget_used(),
+        fallback_success_rate: app_state.processors.fallback.success.rate(),
+        default_processor_url: config.default_processor_url.clone(),
+        fallback_processor_url: config.fallback_processor_url.clone(),
+        peer_urls: config.peer_urls.clone(),
+        clock_skew_micros: app_state.clock_skew.all(),
+        peers: app_state.heartbeats.all(),
+        peer_protocol_versions: app_state.peer_versions.all(),
+        db_memory_bytes: db_memory_bytes(&app_state),
+        dedup: dedup_stats(&app_state),
+    })
+}
+
+async fn get_config(State(app_state): State<AppState>) -> Json<Tunables> {
+    Json(Tunables::clone(&app_state.tunables.load()))
+}
+
+async fn put_config(
+    State(app_state): State<AppState>,
+    Json(update): Json<TunablesUpdate>,
+) -> Result<Json<Tunables>, (StatusCode, String)> {
+    apply_tunables(&app_state, &update)
+        .map(Json)
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))
+}
+
+async fn reload_on_sighup(app_state: AppState, path: String) {
+    let mut hangup = signal(SignalKind::hangup()).unwrap();
+
+    while hangup.recv().await.is_some() {
+        reload_config_file(&app_state, &path);
+    }
+}
+
+fn reload_config_file(app_state: &AppState, path: &str) {
+    let applied = std::fs::read(path)
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| Json::<TunablesUpdate>::from_bytes(&bytes).map_err(|e| e.body_text()))
+        .and_then(|Json(update)| apply_tunables(app_state, &update).map_err(|e| e.to_string()));
+
+    if let Err(e) = applied {
+        eprintln!("Keeping the current config, could not load {path}: {e}");
+    }
+}
+
+/// Swaps in the updated tunables and carries them over to the breakers and dispatcher slots.
+fn apply_tunables(
+    app_state: &AppState,
+    update: &TunablesUpdate,
+) -> Result<Tunables, InvalidTunables> {
+    let current = app_state.tunables.load_full();
+    let tunables = current.apply(update)?;
+
+    if tunables.queue_capacity > app_state.config.accept_queue_capacity {
+        return Err(InvalidTunables(
+            "queue_capacity can't exceed ACCEPT_QUEUE_CAPACITY",
+        ));
+    }
+
+    for processor in [Processor::Default, Processor::Fallback] {
+        app_state
+            .processors
+            .get(processor)
+            .breaker
+            .set_limits(tunables.breaker_threshold, tunables.breaker_cooldown());
+    }
+
+    if tunables.dispatch_concurrency != current.dispatch_concurrency {
+        app_state.dispatch_limit.set(tunables.dispatch_concurrency);
+    }
+    if tunables.queue_capacity != current.queue_capacity {
+        app_state.queue_cap.set(tunables.queue_capacity);
+    }
+
+    app_state.tunables.store(Arc::new(tunables.clone()));
+    println!("Applied config {tunables:?}");
+
+    Ok(tunables)
+}
+
+async fn set_routing(
+    State(app_state): State<AppState>,
+    Json(update): Json<RoutingUpdate>,
+) -> Json<RoutingUpdate> {
+    app_state.processors.set_strategy(update.strategy);
+    println!("Routing strategy set to {:?}", update.strategy);
+
+    Json(update)
+}
+
+async fn get_chaos(State(app_state): State<AppState>) -> Result<Json<ChaosSettings>, StatusCode> {
+    if !cfg!(feature = "chaos") {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(Json(app_state.chaos.settings()))
+}
+
+async fn put_chaos(
+    State(app_state): State<AppState>,
+    Json(settings): Json<ChaosSettings>,
+) -> Result<Json<ChaosSettings>, (StatusCode, String)> {
+    if !cfg!(feature = "chaos") {
+        return Err((StatusCode::NOT_FOUND, String::new()));
+    }
+
+    app_state
+        .chaos
+        .set(settings)
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
+    println!("Chaos set to {settings:?}");
+
+    Ok(Json(settings))
+}
+
+async fn recent_requests(
+    State(app_state): State<AppState>,
+) -> Result<Json<Vec<AccessEntry>>, StatusCode> {
+    let log = app_state.access_log.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(log.recent()))
+}
+
+async fn payment_trace(
+    State(app_state): State<AppState>,
+    Path(correlation_id): Path<CorrelationId>,
+) -> Result<Json<PaymentTrace>, StatusCode> {
+    let log = app_state.events.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+    let events = log.of(&correlation_id);
+    let status = app_state.statuses.get(&correlation_id);
+
+    if events.is_empty() && status.is_none() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(Json(PaymentTrace {
+        correlation_id,
+        events,
+        status,
+    }))
+}
+
+async fn worker_stats(
+    State(app_state): State<AppState>,
+) -> Result<Json<Vec<WorkerStats>>, StatusCode> {
+    let workers = app_state.workers.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(workers.stats().await))
+}
+
+async fn worker_flush(State(app_state): State<AppState>) -> StatusCode {
+    match &app_state.workers {
+        Some(workers) => {
+            workers.flush().await;
+            StatusCode::NO_CONTENT
+        }
+        None => StatusCode::NOT_FOUND,
+    }
+}
+
+/// Drops every payment the in-process Dbs recorded, tenants' included. Shared stores are left
+/// alone, the other instances still count on them.
+async fn purge_payments(State(app_state): State<AppState>) -> StatusCode {
+    if app_state.config.db_backend != BackendKind::Memory {
+        return StatusCode::NOT_FOUND;
+    }
+
+    match &app_state.workers {
+        Some(workers) => workers.purge().await,
+        None => {
+            app_state.memory.default.clear();
+            app_state.memory.fallback.clear();
+        }
+    }
+
+    app_state.processed.default.clear();
+    app_state.processed.fallback.clear();
+    app_state.tenants.clear();
+    println!("Purged every recorded payment");
+
+    StatusCode::NO_CONTENT
+}
+
+async fn worker_purge(State(app_state): State<AppState>) -> StatusCode {
+    match &app_state.workers {
+        Some(workers) => {
+            workers.purge().await;
+            println!("Purged every worker shard");
+            StatusCode::NO_CONTENT
+        }
+        None => StatusCode::NOT_FOUND,
+    }
+}
+
+async fn local_summary(
+    app_state: &AppState,
+    range: SummaryRange,
+) -> Result<ProcessorSummaries, BackendError> {
+    let (from, to, basis, tenant) = range;
+    let from = from.map(|dt| dt.timestamp_micros());
+    let to = to.map(|dt| dt.timestamp_micros());
+    // Only requested times came back with the peer backup, the rest of those payments is missing
+    let partial = (basis == TimestampBasis::Processed || tenant.is_some())
+        && app_state
+            .bootstrapped_until
+            .get()
+            .is_some_and(|&latest| from.is_none_or(|from| from <= latest));
+    let tenant = match tenant {
+        None => None,
+        Some(tenant) => match app_state.tenants.get(&tenant) {
+            Some(storage) => Some(storage),
+            // Nothing was ever recorded for it here
+            None => {
+                return Ok(ProcessorSummaries {
+                    partial,
+                    ..Default::default()
+                });
+            }
+        },
+    };
+    let storage: &dyn Storage = match (basis, &tenant) {
+        (TimestampBasis::Requested, None) => app_state.storage.as_ref(),
+        (TimestampBasis::Processed, None) => &app_state.processed,
+        (TimestampBasis::Requested, Some(tenant)) => &tenant.requested,
+        (TimestampBasis::Processed, Some(tenant)) => &tenant.processed,
+    };
+
+    let [(d_count, d_total), (f_count, f_total)] = storage.summarize_all(from, to).await?;
+    let scale = app_state.config.amount_scale;
+
+    let default_sum = Summary {
+        total_requests: d_count,
+        total_amount: scale.to_amount(d_total),
+        ..Default::default()
+    };
+    let fallback = Summary {
+        total_requests: f_count,
+        total_amount: scale.to_amount(f_total),
+        ..Default::default()
+    };
+
+    Ok(ProcessorSummaries {
+        default_sum,
+        fallback,
+        partial,
+        ..Default::default()
+    })
+}
+
+/// Merges the local summaries of every peer, queried concurrently, by instance. Peers that fail
+/// to answer, or that heartbeats marked dead or share no internal API version and are not asked
+/// at all, are left out of the total, which is then partial.
+async fn remote_summary(
+    app_state: &AppState,
+    range: SummaryRange,
+    trace: TraceContext,
+) -> ProcessorSummaries {
+    let mut total = ProcessorSummaries::default();
+    let (alive, dead): (Vec<_>, Vec<_>) = app_state.config.peer_urls.iter().partition(|peer| {
+        app_state.heartbeats.is_alive(peer) && app_state.peer_versions.is_compatible(peer)
+    });
+
+    if !dead.is_empty() {
+        total.partial = true;
+    }
+
+    let queries = alive.iter().map(|peer| {
+        peer_summary(
+            app_state,
+            peer,
+            widen(app_state, peer, range.clone()),
+            trace.child(),
+        )
+    });
+
+    let own_id = &app_state.config.instance_id;
+
+    for (peer, result) in alive.iter().zip(join_all(queries).await) {
+        match result {
+            // Peers predating `INSTANCE_ID` are named by their URL
+            Ok(summary) if summary.instances.is_empty() => {
+                total.merge(summary.attributed_to(peer.to_string()));
+            }
+            // Merged, the larger of its counts and ours would hide the other's payments
+            Ok(summary) if summary.instances.contains_key(own_id) => {
+                eprintln!(
+                    "Leaving {peer} out of the summary: it also goes by INSTANCE_ID {own_id:?}, \
+                     every instance needs its own"
+                );
+                total.partial = true;
+            }
+            Ok(summary) => total.merge(summary),
+            Err(e) => {
+                eprintln!(
+                    "Leaving {peer} out of the summary (trace {}): {e}",
+                    trace.trace_id()
+                );
+                total.partial = true;
+            }
+        }
+    }
+
+    total
+}
+
+/// Widens `range` by how far the peer's clock is off when `SUMMARY_WIDEN_BY_SKEW` is set, so a
+/// payment the peer stamped just outside the range by its clock is still counted.
+fn widen(app_state: &AppState, peer: &str, range: SummaryRange) -> SummaryRange {
+    let (from, to, basis, tenant) = range;
+    let skew = match app_state.clock_skew.offset(peer) {
+        Some(offset) if app_state.config.summary_widen_by_skew => {
+            TimeDelta::microseconds(offset.abs())
+        }
+        _ => return (from, to, basis, tenant),
+    };
+
+    (
+        from.map(|dt| dt - skew),
+        to.map(|dt| dt + skew),
+        basis,
+        tenant,
+    )
+}
+
+async fn peer_summary(
+    app_state: &AppState,
+    peer: &str,
+    range: SummaryRange,
+    trace: TraceContext,
+) -> Result<ProcessorSummaries, PeerError> {
+    match app_state.chaos.inject(Target::Peer).await {
+        None => query_peer_summary(app_state, peer, range, trace).await,
+        Some(Fault::Fail) => Err(Fault::Fail.into()),
+        Some(Fault::Drop) => {
+            let _ = query_peer_summary(app_state, peer, range, trace).await;
+            Err(Fault::Drop.into())
+        }
+    }
+}
+
+async fn query_peer_summary(
+    app_state: &AppState,
+    peer: &str,
+    range: SummaryRange,
+    trace: TraceContext,
+) -> Result<ProcessorSummaries, PeerError> {
+    let (from, to, basis, tenant) = range;
+
+    #[cfg(feature = "grpc-peer")]
+    if let Some(client) = app_state.grpc_peers.get(peer) {
+        let mut request = tonic::Request::new(SummaryRequest {
+            from: from.map(|dt| dt.timestamp_micros()),
+            to: to.map(|dt| dt.timestamp_micros()),
+            processed: basis == TimestampBasis::Processed,
+            tenant: tenant.as_ref().map(|tenant| tenant.to_string()),
+        });
+        request
+            .metadata_mut()
+            .insert("traceparent", trace.to_string().parse().unwrap());
+
+        let reply = client.local_summary(request).await?;
+
+        let summary = ProcessorSummaries {
+            default_sum: Summary {
+                total_requests: reply.default_requests,
+                total_amount: reply.default_amount,
+                ..Default::default()
+            },
+            fallback: Summary {
+                total_requests: reply.fallback_requests,
+                total_amount: reply.fallback_amount,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        // Left unattributed by peers predating `instance_id`
+        if reply.instance_id.is_empty() {
+            return Ok(summary);
+        }
+
+        return Ok(summary.attributed_to(reply.instance_id));
+    }
+
+    let params = SummaryQueryParams {
+        from,
+        to,
+        only_local: None,
+        include_fees: None,
+        timestamp_basis: Some(basis),
+        breakdown: Some(true),
+        tenant,
+    };
+
+    peer_client(app_state, peer)
+        .local_summary(&params, app_state.config.peer_encoding, trace)
+        .await
+}
+
+#[cfg(feature = "grpc-peer")]
+fn grpc_peers(config: &Config) -> HashMap<String, PeerClient> {
+    if config.peer_transport != PeerTransport::Grpc {
+        return HashMap::new();
+    }
+
+    config
+        .peer_urls
+        .iter()
+        .map(|peer| {
+            let client = PeerClient::connect_lazy(
+                config.peer_grpc_url(peer),
+                config.internal_token.as_deref(),
+            )
+            .unwrap();
+            (peer.clone(), client)
+        })
+        .collect()
+}
+
+#[cfg(feature = "grpc-peer")]
+async fn serve_grpc(app_state: AppState) {
+    let addr = SocketAddr::from(([0, 0, 0, 0], app_state.config.grpc_port));
+    // Both services answer with what this instance recorded, so both take the token
+    let require_token = RequireToken::new(app_state.config.internal_token.as_deref());
+    let peer = Arc::new(GrpcPeer(app_state));
+
+    println!("Serving peer gRPC on {addr}");
+
+    tonic::transport::Server::builder()
+        .add_service(tonic::service::interceptor::InterceptedService::new(
+            SummaryServiceServer::from_arc(peer.clone()),
+            require_token.clone(),
+        ))
+        .add_service(tonic::service::interceptor::InterceptedService::new(
+            ReplicationServiceServer::from_arc(peer),
+            require_token,
+        ))
+        .serve(addr)
+        .await
+        .unwrap();
+}
+
+/// The gRPC counterpart of the `/payments-summary?only_local` and `/internal/state-snapshot`
+/// handlers.
+#[cfg(feature = "grpc-peer")]
+struct GrpcPeer(AppState);
+
+#[cfg(feature = "grpc-peer")]
+#[tonic::async_trait]
+impl SummaryService for GrpcPeer {
+    async fn local_summary(
+        &self,
+        request: tonic::Request<SummaryRequest>,
+    ) -> Result<tonic::Response<SummaryReply>, tonic::Status> {
+        let request = request.into_inner();
+        let from = request.from.and_then(DateTime::from_timestamp_micros);
+        let to = request.to.and_then(DateTime::from_timestamp_micros);
+        let basis = if request.processed {
+            TimestampBasis::Processed
+        } else {
+            TimestampBasis::Requested
+        };
+        let tenant = request
+            .tenant
+            .map(|tenant| tenant.parse::<TenantId>())
+            .transpose()
+            .map_err(|e| tonic::Status::invalid_argument(e.to_string()))?;
+        let total = local_summary(&self.0, (from, to, basis, tenant))
+            .await
+            .map_err(|e| tonic::Status::unavailable(e.to_string()))?;
+
+        Ok(tonic::Response::new(SummaryReply {
+            default_requests: total.default_sum.total_requests,
+            default_amount: total.default_sum.total_amount,
+            fallback_requests: total.fallback.total_requests,
+            fallback_amount: total.fallback.total_amount,
+            instance_id: self.0.config.instance_id.clone(),
+        }))
+    }
+}
+
+#[cfg(feature = "grpc-peer")]
+#[tonic::async_trait]
+impl ReplicationService for GrpcPeer {
+    async fn snapshot(
+        &self,
+        request: tonic::Request<SnapshotRequest>,
+    ) -> Result<tonic::Response<SnapshotReply>, tonic::Status> {
+        let scope = if request.into_inner().peer {
+            SnapshotScope::Peer
+        } else {
+            SnapshotScope::Local
+        };
+
+        Ok(tonic::Response::new(SnapshotReply {
+            snapshot: snapshot_bytes(&self.0, scope),
+        }))
+    }
+}
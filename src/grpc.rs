@@ -0,0 +1,446 @@
+//! gRPC services from `proto/peer.proto`, written out the way `tonic-build` would generate them so
+//! the build does not need `protoc`.
+
+use std::{convert::Infallible, future::Future, time::Duration};
+
+use tonic::{
+    Request, Response, Status,
+    body::Body,
+    client::Grpc,
+    codegen::{Arc, BoxFuture, Context, Poll, Service, StdError, http},
+    server::{NamedService, UnaryService},
+    service::Interceptor,
+    transport::Channel,
+};
+use tonic_prost::ProstCodec;
+
+use crate::auth::{self, INTERNAL_TOKEN_HEADER};
+
+const LOCAL_SUMMARY: &str = "/peer.SummaryService/LocalSummary";
+const SNAPSHOT: &str = "/peer.ReplicationService/Snapshot";
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SummaryRequest {
+    #[prost(int64, optional, tag = "1")]
+    pub from: Option<i64>,
+    #[prost(int64, optional, tag = "2")]
+    pub to: Option<i64>,
+    #[prost(bool, tag = "3")]
+    pub processed: bool,
+    #[prost(string, optional, tag = "4")]
+    pub tenant: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SummaryReply {
+    #[prost(uint64, tag = "1")]
+    pub default_requests: u64,
+    #[prost(double, tag = "2")]
+    pub default_amount: f64,
+    #[prost(uint64, tag = "3")]
+    pub fallback_requests: u64,
+    #[prost(double, tag = "4")]
+    pub fallback_amount: f64,
+    #[prost(string, tag = "5")]
+    pub instance_id: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SnapshotRequest {
+    #[prost(bool, tag = "1")]
+    pub peer: bool,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SnapshotReply {
+    #[prost(bytes = "vec", tag = "1")]
+    pub snapshot: Vec<u8>,
+}
+
+#[tonic::async_trait]
+pub trait SummaryService: Send + Sync + 'static {
+    async fn local_summary(
+        &self,
+        request: Request<SummaryRequest>,
+    ) -> Result<Response<SummaryReply>, Status>;
+}
+
+#[tonic::async_trait]
+pub trait ReplicationService: Send + Sync + 'static {
+    async fn snapshot(
+        &self,
+        request: Request<SnapshotRequest>,
+    ) -> Result<Response<SnapshotReply>, Status>;
+}
+
+/// Rejects calls with `Unauthenticated` unless they carry the token in `x-internal-token`
+/// metadata, as `auth::require_token` does for HTTP. Without a token every call goes through.
+#[derive(Clone)]
+pub struct RequireToken(Option<Arc<str>>);
+
+impl RequireToken {
+    pub fn new(token: Option<&str>) -> Self {
+        Self(token.map(Arc::from))
+    }
+}
+
+impl Interceptor for RequireToken {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        let Some(token) = &self.0 else {
+            return Ok(request);
+        };
+        let presented = request
+            .metadata()
+            .get(INTERNAL_TOKEN_HEADER)
+            .map(|value| value.as_bytes());
+
+        if !auth::verify(token, presented) {
+            return Err(Status::unauthenticated("missing or wrong internal token"));
+        }
+
+        Ok(request)
+    }
+}
+
+/// Client for both services of a single peer. Connects on first use and is cheap to clone.
+#[derive(Clone)]
+pub struct PeerClient {
+    inner: Grpc<Channel>,
+    // Sent as `x-internal-token` metadata, for peers with `INTERNAL_TOKEN` set
+    token: Option<Arc<str>>,
+}
+
+impl PeerClient {
+    pub fn connect_lazy(url: String, token: Option<&str>) -> Result<Self, http::uri::InvalidUri> {
+        let channel = Channel::from_shared(url)?.connect_lazy();
+
+        Ok(Self {
+            inner: Grpc::new(channel),
+            token: token.map(Arc::from),
+        })
+    }
+
+    pub async fn local_summary(
+        &self,
+        request: Request<SummaryRequest>,
+    ) -> Result<SummaryReply, Status> {
+        self.unary(request, LOCAL_SUMMARY).await
+    }
+
+    pub async fn snapshot(
+        &self,
+        request: SnapshotRequest,
+        timeout: Duration,
+    ) -> Result<SnapshotReply, Status> {
+        let mut request = Request::new(request);
+        request.set_timeout(timeout);
+
+        self.unary(request, SNAPSHOT).await
+    }
+
+    async fn unary<Req, Reply>(
+        &self,
+        request: Request<Req>,
+        path: &'static str,
+    ) -> Result<Reply, Status>
+    where
+        Req: prost::Message + Send + Sync + 'static,
+        Reply: prost::Message + Default + Send + Sync + 'static,
+    {
+        let mut request = request;
+        let mut grpc = self.inner.clone();
+
+        if let Some(token) = &self.token {
+            let token = token
+                .parse()
+                .map_err(|_| Status::internal("invalid token"))?;
+            request.metadata_mut().insert(INTERNAL_TOKEN_HEADER, token);
+        }
+
+        grpc.ready()
+            .await
+            .map_err(|e| Status::unavailable(e.to_string()))?;
+
+        let path = http::uri::PathAndQuery::from_static(path);
+        let response = grpc.unary(request, path, ProstCodec::default()).await?;
+
+        Ok(response.into_inner())
+    }
+}
+
+pub struct SummaryServiceServer<T> {
+    inner: Arc<T>,
+}
+
+impl<T> SummaryServiceServer<T> {
+    pub fn from_arc(inner: Arc<T>) -> Self {
+        Self { inner }
+    }
+}
+
+impl<T> Clone for SummaryServiceServer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> NamedService for SummaryServiceServer<T> {
+    const NAME: &'static str = "peer.SummaryService";
+}
+
+impl<T, B> Service<http::Request<B>> for SummaryServiceServer<T>
+where
+    T: SummaryService,
+    B: http_body::Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<Body>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let inner = self.inner.clone();
+
+        match req.uri().path() {
+            LOCAL_SUMMARY => serve_unary(req, move |request| async move {
+                inner.local_summary(request).await
+            }),
+            _ => unimplemented(),
+        }
+    }
+}
+
+pub struct ReplicationServiceServer<T> {
+    inner: Arc<T>,
+}
+
+impl<T> ReplicationServiceServer<T> {
+    pub fn from_arc(inner: Arc<T>) -> Self {
+        Self { inner }
+    }
+}
+
+impl<T> Clone for ReplicationServiceServer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> NamedService for ReplicationServiceServer<T> {
+    const NAME: &'static str = "peer.ReplicationService";
+}
+
+impl<T, B> Service<http::Request<B>> for ReplicationServiceServer<T>
+where
+    T: ReplicationService,
+    B: http_body::Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<Body>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let inner = self.inner.clone();
+
+        match req.uri().path() {
+            SNAPSHOT => serve_unary(
+                req,
+                move |request| async move { inner.snapshot(request).await },
+            ),
+            _ => unimplemented(),
+        }
+    }
+}
+
+// Adapts a single-call closure to tonic's `UnaryService`
+struct Unary<F>(Option<F>);
+
+impl<Req, Reply, F, Fut> UnaryService<Req> for Unary<F>
+where
+    F: FnOnce(Request<Req>) -> Fut,
+    Fut: Future<Output = Result<Response<Reply>, Status>>,
+{
+    type Response = Reply;
+    type Future = Fut;
+
+    fn call(&mut self, request: Request<Req>) -> Self::Future {
+        (self.0.take().expect("unary service called twice"))(request)
+    }
+}
+
+fn serve_unary<B, Req, Reply, F, Fut>(
+    req: http::Request<B>,
+    handler: F,
+) -> BoxFuture<http::Response<Body>, Infallible>
+where
+    B: http_body::Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+    Req: prost::Message + Default + Send + Sync + 'static,
+    Reply: prost::Message + Send + Sync + 'static,
+    F: FnOnce(Request<Req>) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<Response<Reply>, Status>> + Send + 'static,
+{
+    Box::pin(async move {
+        let mut grpc = tonic::server::Grpc::new(ProstCodec::<Reply, Req>::default());
+
+        Ok(grpc.unary(Unary(Some(handler)), req).await)
+    })
+}
+
+fn unimplemented() -> BoxFuture<http::Response<Body>, Infallible> {
+    Box::pin(async { Ok(Status::unimplemented("unknown method").into_http()) })
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+    use tonic::{
+        service::interceptor::InterceptedService,
+        transport::{Server, server::TcpIncoming},
+    };
+
+    use super::*;
+
+    /// Echoes the request back, as far as the replies allow.
+    struct Echo;
+
+    #[tonic::async_trait]
+    impl SummaryService for Echo {
+        async fn local_summary(
+            &self,
+            request: Request<SummaryRequest>,
+        ) -> Result<Response<SummaryReply>, Status> {
+            let token = request
+                .metadata()
+                .get(INTERNAL_TOKEN_HEADER)
+                .map(|token| token.to_str().unwrap().to_string());
+            let request = request.into_inner();
+
+            Ok(Response::new(SummaryReply {
+                default_requests: request.from.unwrap_or_default() as u64,
+                default_amount: 19.9,
+                fallback_requests: request.to.unwrap_or_default() as u64,
+                fallback_amount: 0.0,
+                instance_id: token.unwrap_or_default(),
+            }))
+        }
+    }
+
+    #[tonic::async_trait]
+    impl ReplicationService for Echo {
+        async fn snapshot(
+            &self,
+            request: Request<SnapshotRequest>,
+        ) -> Result<Response<SnapshotReply>, Status> {
+            Ok(Response::new(SnapshotReply {
+                snapshot: vec![request.into_inner().peer as u8],
+            }))
+        }
+    }
+
+    async fn serve() -> String {
+        serve_requiring(None).await
+    }
+
+    async fn serve_requiring(token: Option<&str>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let echo = Arc::new(Echo);
+        let require_token = RequireToken::new(token);
+
+        tokio::spawn(
+            Server::builder()
+                .add_service(InterceptedService::new(
+                    SummaryServiceServer::from_arc(echo.clone()),
+                    require_token.clone(),
+                ))
+                .add_service(InterceptedService::new(
+                    ReplicationServiceServer::from_arc(echo),
+                    require_token,
+                ))
+                .serve_with_incoming(TcpIncoming::from(listener)),
+        );
+
+        url
+    }
+
+    #[tokio::test]
+    async fn both_services_answer_over_the_wire() {
+        let client = PeerClient::connect_lazy(serve().await, Some("secret")).unwrap();
+
+        let summary = client
+            .local_summary(Request::new(SummaryRequest {
+                from: Some(3),
+                to: Some(7),
+                processed: false,
+                tenant: None,
+            }))
+            .await
+            .unwrap();
+        assert_eq!(summary.default_requests, 3);
+        assert_eq!(summary.default_amount, 19.9);
+        assert_eq!(summary.fallback_requests, 7);
+        assert_eq!(summary.instance_id, "secret");
+
+        let snapshot = client
+            .snapshot(SnapshotRequest { peer: true }, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(snapshot.snapshot, [1]);
+    }
+
+    #[tokio::test]
+    async fn calls_without_the_token_are_unauthenticated() {
+        let url = serve_requiring(Some("secret")).await;
+        let request = || SummaryRequest {
+            from: None,
+            to: None,
+            processed: false,
+            tenant: None,
+        };
+
+        for token in [None, Some("wrong")] {
+            let client = PeerClient::connect_lazy(url.clone(), token).unwrap();
+
+            let status = client
+                .local_summary(Request::new(request()))
+                .await
+                .unwrap_err();
+            assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+            let status = client
+                .snapshot(SnapshotRequest { peer: false }, Duration::from_secs(1))
+                .await
+                .unwrap_err();
+            assert_eq!(status.code(), tonic::Code::Unauthenticated);
+        }
+
+        let client = PeerClient::connect_lazy(url, Some("secret")).unwrap();
+        let summary = client.local_summary(Request::new(request())).await.unwrap();
+        assert_eq!(summary.instance_id, "secret");
+    }
+
+    #[tokio::test]
+    async fn unreachable_peers_are_unavailable() {
+        let client = PeerClient::connect_lazy("http://127.0.0.1:1".to_string(), None).unwrap();
+        let status = client
+            .snapshot(SnapshotRequest { peer: false }, Duration::from_secs(1))
+            .await
+            .unwrap_err();
+
+        assert_eq!(status.code(), tonic::Code::Unavailable);
+    }
+}
@@ -0,0 +1,163 @@
+use std::{
+    collections::VecDeque,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Tracks consecutive failures of a processor. The breaker opens once `threshold` failures happen
+/// in a row and stays open for `cooldown`, after which requests are let through again and a single
+/// failure reopens it.
+pub struct CircuitBreaker {
+    state: Mutex<BreakerState>,
+}
+
+struct BreakerState {
+    threshold: u32,
+    cooldown: Duration,
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            state: Mutex::new(BreakerState {
+                threshold,
+                cooldown,
+                consecutive_failures: 0,
+                opened_at: None,
+            }),
+        }
+    }
+
+    /// Takes effect from the next failure, an open breaker stays open for its new cooldown.
+    pub fn set_limits(&self, threshold: u32, cooldown: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.threshold = threshold;
+        state.cooldown = cooldown;
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures = 0;
+        state.opened_at = None;
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures += 1;
+
+        if state.consecutive_failures >= state.threshold {
+            state.opened_at = Some(Instant::now());
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        let state = self.state.lock().unwrap();
+
+        state
+            .opened_at
+            .is_some_and(|opened_at| opened_at.elapsed() < state.cooldown)
+    }
+}
+
+/// Share of the last `window` requests to a processor that succeeded.
+pub struct SuccessRate {
+    window: usize,
+    outcomes: Mutex<VecDeque<bool>>,
+}
+
+impl SuccessRate {
+    pub fn new(window: usize) -> Self {
+        Self {
+            window,
+            outcomes: Mutex::new(VecDeque::with_capacity(window)),
+        }
+    }
+
+    pub fn record(&self, success: bool) {
+        let mut outcomes = self.outcomes.lock().unwrap();
+
+        if outcomes.len() >= self.window {
+            outcomes.pop_front();
+        }
+
+        outcomes.push_back(success);
+    }
+
+    /// Between 0 and 1, a processor nothing was sent to yet counts as healthy.
+    pub fn rate(&self) -> f64 {
+        let outcomes = self.outcomes.lock().unwrap();
+
+        if outcomes.is_empty() {
+            return 1.0;
+        }
+
+        outcomes.iter().filter(|success| **success).count() as f64 / outcomes.len() as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn breaker_opens_after_threshold_failures_in_a_row() {
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(60));
+
+        breaker.record_failure();
+        breaker.record_failure();
+        breaker.record_success();
+        breaker.record_failure();
+        breaker.record_failure();
+        assert!(!breaker.is_open());
+
+        breaker.record_failure();
+        assert!(breaker.is_open());
+
+        breaker.record_success();
+        assert!(!breaker.is_open());
+    }
+
+    #[test]
+    fn one_failure_reopens_a_breaker_past_its_cooldown() {
+        let breaker = CircuitBreaker::new(2, Duration::from_millis(10));
+
+        breaker.record_failure();
+        breaker.record_failure();
+        assert!(breaker.is_open());
+
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(!breaker.is_open());
+
+        breaker.record_failure();
+        assert!(breaker.is_open());
+    }
+
+    #[test]
+    fn new_limits_apply_from_the_next_failure() {
+        let breaker = CircuitBreaker::new(5, Duration::from_secs(60));
+
+        breaker.record_failure();
+        breaker.set_limits(2, Duration::from_secs(60));
+        assert!(!breaker.is_open());
+
+        breaker.record_failure();
+        assert!(breaker.is_open());
+    }
+
+    #[test]
+    fn success_rate_covers_the_last_window() {
+        let success = SuccessRate::new(4);
+        assert_eq!(success.rate(), 1.0);
+
+        success.record(false);
+        success.record(true);
+        assert_eq!(success.rate(), 0.5);
+
+        for _ in 0..3 {
+            success.record(true);
+        }
+        assert_eq!(success.rate(), 1.0);
+    }
+}
@@ -0,0 +1,66 @@
+//! Peers ping each other's `/internal/ping` every `HEARTBEAT_INTERVAL_MS`. A peer missing
+//! `HEARTBEAT_MISSES` in a row is marked dead and left out of summaries right away, rather than
+//! each summary waiting on its connect timeout, until it answers again.
+
+use std::{collections::BTreeMap, sync::Mutex};
+
+use serde::Serialize;
+
+/// What the last heartbeats said of one peer.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct PeerLiveness {
+    pub alive: bool,
+    // Round trip of the last ping answered
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rtt_micros: Option<u64>,
+    #[serde(skip)]
+    misses: u32,
+}
+
+/// Liveness of every peer pinged so far. Peers not pinged yet count as alive.
+pub struct Heartbeats {
+    misses: u32,
+    peers: Mutex<BTreeMap<String, PeerLiveness>>,
+}
+
+impl Heartbeats {
+    pub fn new(misses: u32) -> Self {
+        Self {
+            misses: misses.max(1),
+            peers: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Records a ping answered after `rtt_micros`, or missed with `None`, returning the peer's
+    /// liveness after it.
+    pub fn record(&self, peer: &str, rtt_micros: Option<u64>) -> PeerLiveness {
+        let mut peers = self.peers.lock().unwrap();
+        let liveness = peers.entry(peer.to_string()).or_default();
+
+        match rtt_micros {
+            Some(rtt) => {
+                liveness.alive = true;
+                liveness.rtt_micros = Some(rtt);
+                liveness.misses = 0;
+            }
+            None => {
+                liveness.misses = liveness.misses.saturating_add(1);
+                liveness.alive = liveness.misses < self.misses;
+            }
+        }
+
+        *liveness
+    }
+
+    pub fn is_alive(&self, peer: &str) -> bool {
+        self.peers
+            .lock()
+            .unwrap()
+            .get(peer)
+            .is_none_or(|liveness| liveness.alive)
+    }
+
+    pub fn all(&self) -> BTreeMap<String, PeerLiveness> {
+        self.peers.lock().unwrap().clone()
+    }
+}
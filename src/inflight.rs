@@ -0,0 +1,101 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::sync::Notify;
+
+/// Timestamps of payments that are still being submitted, so a summary covering them can tell
+/// its totals may still change. Each registration is held by an `InflightGuard` and released when
+/// the guard is dropped.
+#[derive(Default)]
+pub struct Inflight {
+    state: Mutex<State>,
+    released: Notify,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    // Registered `[from, to]` ranges keyed by (from, id), a single timestamp being `[ts, ts]`
+    ranges: BTreeMap<(i64, u64), i64>,
+}
+
+pub struct InflightGuard {
+    inflight: Arc<Inflight>,
+    key: (i64, u64),
+}
+
+impl Inflight {
+    pub fn register(self: &Arc<Self>, timestamp: i64) -> InflightGuard {
+        self.register_range(timestamp, timestamp)
+    }
+
+    /// Registers every timestamp within `[from, to]` at once, for work spanning several payments.
+    pub fn register_range(self: &Arc<Self>, from: i64, to: i64) -> InflightGuard {
+        let mut state = self.state.lock().unwrap();
+        let key = (from.min(to), state.next_id);
+        state.next_id += 1;
+        state.ranges.insert(key, from.max(to));
+
+        InflightGuard {
+            inflight: self.clone(),
+            key,
+        }
+    }
+
+    /// Whether any registered range overlaps `[from, to]`, bounds included and `None` leaving that
+    /// side unbounded.
+    pub fn is_locked(&self, from: Option<i64>, to: Option<i64>) -> bool {
+        self.overlaps(from, to, u64::MAX)
+    }
+
+    /// Returns once nothing registered before the call overlaps `[from, to]`. Later registrations
+    /// are not waited for, so a steady stream of new payments can't hold the caller forever.
+    pub async fn wait_until_unlocked(&self, from: Option<i64>, to: Option<i64>) {
+        let before = self.state.lock().unwrap().next_id;
+
+        loop {
+            let released = self.released.notified();
+
+            if !self.overlaps(from, to, before) {
+                return;
+            }
+
+            released.await;
+        }
+    }
+
+    /// Like `wait_until_unlocked`, giving up after `timeout` in case a guard is never released.
+    /// Returns whether it timed out.
+    pub async fn wait_until_unlocked_timeout(
+        &self,
+        from: Option<i64>,
+        to: Option<i64>,
+        timeout: Duration,
+    ) -> bool {
+        tokio::time::timeout(timeout, self.wait_until_unlocked(from, to))
+            .await
+            .is_err()
+    }
+
+    // Only registrations with an id below `before` are considered
+    fn overlaps(&self, from: Option<i64>, to: Option<i64>, before: u64) -> bool {
+        let state = self.state.lock().unwrap();
+        let mut starting_before_to = match to {
+            Some(to) => state.ranges.range(..=(to, u64::MAX)),
+            None => state.ranges.range(..),
+        };
+
+        starting_before_to
+            .any(|((_, id), end)| *id < before && from.is_none_or(|from| *end >= from))
+    }
+}
+
+impl Drop for InflightGuard {
+    fn drop(&mut self) {
+        self.inflight.state.lock().unwrap().ranges.remove(&self.key);
+        self.inflight.released.notify_waiters();
+    }
+}
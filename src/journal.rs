@@ -0,0 +1,450 @@
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{Processor, correlation::CorrelationId, tenant::TenantId};
+
+/// Append-only record of every submission, written as one line per event:
+/// `<kind> <processor>[:<tenant>] <amount> <timestamp> <correlation_id>`.
+///
+/// A payment is journaled as submitted (`S`) before the processor call and as confirmed (`C`) once
+/// it has been written into the Db, so a crash in between leaves an in-doubt entry that can be
+/// reconciled against the processor on the next start. In-doubt entries found not to have been
+/// processed are marked as failed (`F`).
+///
+/// Confirmed payments older than the compaction horizon are rolled into totals (`T`), one line
+/// per bucket: `T <processor>[:<tenant>] <amount> <bucket_start> <count>`.
+pub struct Journal {
+    file: Mutex<BufWriter<File>>,
+}
+
+#[derive(Clone)]
+pub struct JournalEntry {
+    pub correlation_id: CorrelationId,
+    pub processor: Processor,
+    pub amount: u64,
+    pub timestamp: i64,
+    pub tenant: Option<TenantId>,
+}
+
+/// Confirmed payments of one bucket, rolled together when the journal was compacted.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JournalTotal {
+    pub processor: Processor,
+    pub tenant: Option<TenantId>,
+    pub timestamp: i64,
+    pub count: u64,
+    pub amount: u64,
+}
+
+#[derive(Default)]
+pub struct Replay {
+    pub confirmed: Vec<JournalEntry>,
+    pub totals: Vec<JournalTotal>,
+    // Submitted but never confirmed nor failed
+    pub pending: Vec<JournalEntry>,
+}
+
+impl Replay {
+    pub fn is_empty(&self) -> bool {
+        self.confirmed.is_empty() && self.totals.is_empty() && self.pending.is_empty()
+    }
+
+    /// Number of confirmed payments, rolled up ones included.
+    pub fn confirmed_count(&self) -> u64 {
+        self.confirmed.len() as u64 + self.totals.iter().map(|t| t.count).sum::<u64>()
+    }
+
+    /// Rolls confirmed payments older than `horizon` into totals by `bucket`, as `Db::compact`
+    /// does with entries. Their correlation ids are forgotten, a retry of one of them arriving
+    /// after a restart would be counted again.
+    fn compact(&mut self, horizon: i64, bucket: i64) {
+        let mut totals: HashMap<(Processor, Option<TenantId>, i64), (u64, u64)> = HashMap::new();
+        let mut add = |processor, tenant, timestamp: i64, count, amount| {
+            let start = timestamp - timestamp.rem_euclid(bucket);
+            let total = totals.entry((processor, tenant, start)).or_default();
+            total.0 += count;
+            total.1 += amount;
+        };
+
+        for total in self.totals.drain(..) {
+            add(
+                total.processor,
+                total.tenant,
+                total.timestamp,
+                total.count,
+                total.amount,
+            );
+        }
+
+        self.confirmed.retain(|entry| {
+            if entry.timestamp >= horizon {
+                return true;
+            }
+
+            add(
+                entry.processor,
+                entry.tenant.clone(),
+                entry.timestamp,
+                1,
+                entry.amount,
+            );
+            false
+        });
+
+        self.totals = totals
+            .into_iter()
+            .map(
+                |((processor, tenant, timestamp), (count, amount))| JournalTotal {
+                    processor,
+                    tenant,
+                    timestamp,
+                    count,
+                    amount,
+                },
+            )
+            .collect();
+        self.totals.sort_by_key(|total| total.timestamp);
+    }
+}
+
+impl Journal {
+    /// Reads back whatever a previous run left in `path` and compacts it, keeping only confirmed
+    /// and in-doubt entries and rolling confirmed ones older than `horizon` into totals by
+    /// `bucket` micro seconds. The compacted journal is written beside `path` and renamed over
+    /// it, so a crash midway leaves the previous one intact.
+    pub fn open(path: impl AsRef<Path>, horizon: i64, bucket: i64) -> io::Result<(Self, Replay)> {
+        let path = path.as_ref();
+        let mut replay = match File::open(path) {
+            Ok(file) => replay(BufReader::new(file))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Replay::default(),
+            Err(e) => return Err(e),
+        };
+
+        replay.compact(horizon, bucket);
+        rewrite(path, &replay)?;
+
+        let file = OpenOptions::new().append(true).open(path)?;
+        let journal = Self {
+            file: Mutex::new(BufWriter::new(file)),
+        };
+
+        Ok((journal, replay))
+    }
+
+    pub fn submitted(&self, entry: &JournalEntry) {
+        self.append('S', entry);
+    }
+
+    pub fn confirmed(&self, entry: &JournalEntry) {
+        self.append('C', entry);
+    }
+
+    pub fn failed(&self, entry: &JournalEntry) {
+        self.append('F', entry);
+    }
+
+    fn append(&self, kind: char, entry: &JournalEntry) {
+        let mut file = self.file.lock().unwrap();
+
+        // Flushing on every event keeps the journal intact if the process dies, the OS still
+        // holds the written bytes
+        if let Err(e) = write_line(&mut *file, kind, entry).and_then(|()| file.flush()) {
+            eprintln!("Failed to write journal: {e}");
+        }
+    }
+}
+
+/// Writes `replay` to `path.tmp`, syncs it and renames it over `path`.
+fn rewrite(path: &Path, replay: &Replay) -> io::Result<()> {
+    let tmp = PathBuf::from({
+        let mut tmp = OsString::from(path);
+        tmp.push(".tmp");
+        tmp
+    });
+    let mut file = BufWriter::new(File::create(&tmp)?);
+
+    for total in &replay.totals {
+        write_fields(
+            &mut file,
+            'T',
+            total.processor,
+            total.tenant.as_ref(),
+            total.amount,
+            total.timestamp,
+            total.count,
+        )?;
+    }
+
+    for entry in &replay.confirmed {
+        write_line(&mut file, 'C', entry)?;
+    }
+
+    for entry in &replay.pending {
+        write_line(&mut file, 'S', entry)?;
+    }
+
+    file.into_inner()?.sync_all()?;
+    fs::rename(&tmp, path)?;
+
+    // The rename itself only lasts once the directory is synced
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+
+    File::open(dir)?.sync_all()
+}
+
+fn write_line(w: &mut impl Write, kind: char, entry: &JournalEntry) -> io::Result<()> {
+    write_fields(
+        w,
+        kind,
+        entry.processor,
+        entry.tenant.as_ref(),
+        entry.amount,
+        entry.timestamp,
+        &entry.correlation_id,
+    )
+}
+
+fn write_fields(
+    w: &mut impl Write,
+    kind: char,
+    processor: Processor,
+    tenant: Option<&TenantId>,
+    amount: u64,
+    timestamp: i64,
+    last: impl std::fmt::Display,
+) -> io::Result<()> {
+    let processor = match processor {
+        Processor::Default => "default",
+        Processor::Fallback => "fallback",
+    };
+
+    let tenant = tenant.map_or(String::new(), |tenant| format!(":{tenant}"));
+
+    writeln!(w, "{kind} {processor}{tenant} {amount} {timestamp} {last}")
+}
+
+fn replay(reader: impl BufRead) -> io::Result<Replay> {
+    let mut confirmed = Vec::new();
+    let mut totals = Vec::new();
+    let mut pending = HashMap::new();
+
+    for line in reader.lines() {
+        let line = line?;
+        let Some(fields) = parse_line(&line) else {
+            continue;
+        };
+
+        match fields.kind {
+            "T" => {
+                if let Some(total) = fields.total() {
+                    totals.push(total);
+                }
+            }
+            kind => {
+                let Some(entry) = fields.entry() else {
+                    continue;
+                };
+
+                match kind {
+                    "S" => {
+                        pending.insert(entry.correlation_id.clone(), entry);
+                    }
+                    "C" => {
+                        pending.remove(&entry.correlation_id);
+                        confirmed.push(entry);
+                    }
+                    "F" => {
+                        pending.remove(&entry.correlation_id);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    Ok(Replay {
+        confirmed,
+        totals,
+        pending: pending.into_values().collect(),
+    })
+}
+
+/// The fields every line shares, `last` being the correlation id or, for totals, the count.
+struct Line<'a> {
+    kind: &'a str,
+    processor: Processor,
+    tenant: Option<TenantId>,
+    amount: u64,
+    timestamp: i64,
+    last: &'a str,
+}
+
+impl Line<'_> {
+    fn entry(self) -> Option<JournalEntry> {
+        Some(JournalEntry {
+            correlation_id: self.last.parse().ok()?,
+            processor: self.processor,
+            amount: self.amount,
+            timestamp: self.timestamp,
+            tenant: self.tenant,
+        })
+    }
+
+    fn total(self) -> Option<JournalTotal> {
+        Some(JournalTotal {
+            processor: self.processor,
+            tenant: self.tenant,
+            timestamp: self.timestamp,
+            count: self.last.parse().ok()?,
+            amount: self.amount,
+        })
+    }
+}
+
+fn parse_line(line: &str) -> Option<Line<'_>> {
+    let mut fields = line.splitn(5, ' ');
+    let kind = fields.next()?;
+    let processor = fields.next()?;
+    let (processor, tenant) = match processor.split_once(':') {
+        Some((processor, tenant)) => (processor, Some(tenant.parse().ok()?)),
+        None => (processor, None),
+    };
+    let processor = match processor {
+        "default" => Processor::Default,
+        "fallback" => Processor::Fallback,
+        _ => return None,
+    };
+
+    Some(Line {
+        kind,
+        processor,
+        tenant,
+        amount: fields.next()?.parse().ok()?,
+        timestamp: fields.next()?.parse().ok()?,
+        last: fields.next()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    const ID: &str = "4a7901b8-7d26-4d9d-aa19-4dc1c7cf60b3";
+
+    fn path(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("journal-{name}-{}.log", std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn entry(id: u32, timestamp: i64, amount: u64) -> JournalEntry {
+        JournalEntry {
+            correlation_id: format!("4a7901b8-7d26-4d9d-aa19-{id:012}").parse().unwrap(),
+            processor: Processor::Default,
+            amount,
+            timestamp,
+            tenant: None,
+        }
+    }
+
+    #[test]
+    fn failed_and_confirmed_submissions_are_dropped_on_open() {
+        let path = path("open");
+        let (journal, replay) = Journal::open(&path, i64::MIN, 1).unwrap();
+        assert!(replay.is_empty());
+
+        journal.submitted(&entry(1, 10, 100));
+        journal.confirmed(&entry(1, 10, 100));
+        journal.submitted(&entry(2, 20, 200));
+        journal.failed(&entry(2, 20, 200));
+        journal.submitted(&entry(3, 30, 300));
+        drop(journal);
+
+        let (_, replay) = Journal::open(&path, i64::MIN, 1).unwrap();
+        assert_eq!(replay.confirmed.len(), 1);
+        assert_eq!(replay.pending.len(), 1);
+        assert_eq!(replay.pending[0].timestamp, 30);
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+        assert!(!fs::exists(format!("{}.tmp", path.display())).unwrap());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn old_confirmed_payments_are_rolled_into_totals() {
+        let path = path("compact");
+        let (journal, _) = Journal::open(&path, i64::MIN, 1000).unwrap();
+
+        for (id, timestamp) in [(1, 1100), (2, 1900), (3, -100), (4, 2500)] {
+            journal.confirmed(&entry(id, timestamp, 10));
+        }
+        drop(journal);
+
+        let (journal, replay) = Journal::open(&path, 2000, 1000).unwrap();
+        assert_eq!(
+            replay.totals,
+            [
+                JournalTotal {
+                    processor: Processor::Default,
+                    tenant: None,
+                    timestamp: -1000,
+                    count: 1,
+                    amount: 10,
+                },
+                JournalTotal {
+                    processor: Processor::Default,
+                    tenant: None,
+                    timestamp: 1000,
+                    count: 2,
+                    amount: 20,
+                },
+            ]
+        );
+        assert_eq!(replay.confirmed.len(), 1);
+        assert_eq!(replay.confirmed_count(), 4);
+
+        // Totals survive the next compaction, merged with what is rolled up then
+        journal.confirmed(&entry(5, 1500, 10));
+        drop(journal);
+
+        let (_, replay) = Journal::open(&path, 3000, 1000).unwrap();
+        assert!(replay.confirmed.is_empty());
+        assert_eq!(replay.confirmed_count(), 5);
+        assert_eq!(
+            replay
+                .totals
+                .iter()
+                .map(|t| (t.timestamp, t.count))
+                .collect::<Vec<_>>(),
+            [(-1000, 1), (1000, 3), (2000, 1)]
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn unreadable_lines_are_skipped() {
+        let path = path("garbage");
+        fs::write(
+            &path,
+            format!("C default 100 10 {ID}\nC default 100\nT nowhere 1 2 3\nC default 5 6 {ID}"),
+        )
+        .unwrap();
+
+        let (_, replay) = Journal::open(&path, i64::MIN, 1).unwrap();
+        assert_eq!(replay.confirmed.len(), 2);
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -1,48 +1,438 @@
+use std::{
+    collections::{BTreeMap, btree_map::Entry},
+    fmt,
+    str::FromStr,
+};
+
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+pub mod access_log;
+pub mod arena;
+pub mod auth;
+pub mod backend;
+pub mod balance;
+pub mod bench;
+pub mod chaos;
+pub mod client;
+pub mod clock;
+pub mod coalesce;
+pub mod concurrency;
+pub mod config;
+pub mod consistency;
+pub mod correlation;
 pub mod db;
-pub use db::Db;
+pub mod dedup;
+pub mod dns;
+pub mod export;
+pub mod failover;
+pub mod fallback_budget;
+#[cfg(feature = "fast-json")]
+pub mod fast_json;
+pub mod gateway;
+#[cfg(feature = "grpc-peer")]
+pub mod grpc;
+pub mod health;
+pub mod heartbeat;
+pub mod inflight;
+#[cfg(feature = "persistence")]
+pub mod journal;
+pub mod lifecycle;
+pub mod listener;
+pub mod load_shed;
+#[cfg(feature = "persistence")]
+pub mod mmap_db;
+pub mod money;
+pub mod pacing;
+pub mod partition;
+pub mod payload;
+#[cfg(feature = "postgres-backend")]
+pub mod postgres_db;
+pub mod processor;
+pub mod protocol;
+pub mod queue;
+pub mod rate_limit;
+#[cfg(feature = "redis-backend")]
+pub mod redis_db;
+pub mod route_limit;
+pub mod routing;
+#[cfg(feature = "persistence")]
+pub mod shutdown_snapshot;
+pub mod simulator;
+#[cfg(feature = "persistence")]
+pub mod spill;
+pub mod status;
+pub mod telemetry;
+pub mod tenant;
+pub mod timestamp;
+pub mod trace;
+pub mod transport;
+pub mod tunables;
+pub mod webhook;
+pub mod worker;
+pub use config::Config;
+pub use db::{Db, StateSnapshot};
+pub use gateway::{AppState, PaymentGateway, router};
+
+use correlation::CorrelationId;
+use money::Scale;
+use tenant::TenantId;
+use trace::TraceContext;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Processor {
     Default,
     Fallback,
 }
 
+impl fmt::Display for Processor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Default => write!(f, "default"),
+            Self::Fallback => write!(f, "fallback"),
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct PaymentPayload {
     #[serde(rename = "correlationId")]
-    pub correlation_id: String,
+    pub correlation_id: CorrelationId,
     pub amount: f64,
+    // Falls back to the `X-Tenant-Id` header, see `tenant`
+    #[serde(rename = "tenantId", default, skip_serializing_if = "Option::is_none")]
+    pub tenant_id: Option<TenantId>,
 }
 
 #[derive(Clone, Serialize)]
 pub struct Payment {
     #[serde(rename = "correlationId")]
-    pub correlation_id: String,
+    pub correlation_id: CorrelationId,
     pub amount: f64,
     #[serde(rename = "requestedAt")]
     pub requested_at: DateTime<Utc>,
+    #[serde(skip)]
+    pub trace: TraceContext,
+    // Never sent to the processors
+    #[serde(skip)]
+    pub tenant: Option<TenantId>,
 }
 
-#[derive(Clone, Deserialize, Serialize)]
+impl Payment {
+    pub fn amount_units(&self, scale: Scale) -> u64 {
+        scale.to_units(self.amount)
+    }
+}
+
+/// Set to `true` on peer summary queries, which must only cover what the receiving instance
+/// recorded itself.
+pub const INTERNAL_SUMMARY_HEADER: &str = "x-internal-summary";
+
+#[derive(Clone, Default, Deserialize, Serialize)]
 pub struct SummaryQueryParams {
+    // Either end may be left out, or empty, for an open range
+    #[serde(default, deserialize_with = "timestamp::deserialize")]
     pub from: Option<DateTime<Utc>>,
+    #[serde(default, deserialize_with = "timestamp::deserialize")]
     pub to: Option<DateTime<Utc>>,
+    // Kept for peers that still send it as a query param, see `INTERNAL_SUMMARY_HEADER`
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub only_local: Option<bool>,
+    // Adds `totalFee` and `netAmount` to each processor's summary
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub include_fees: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp_basis: Option<TimestampBasis>,
+    // Adds what each instance recorded under `instances`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub breakdown: Option<bool>,
+    // Only that tenant's payments, untagged payments when absent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<TenantId>,
+}
+
+impl SummaryQueryParams {
+    /// Whether the range ends before it starts, which summaries refuse.
+    pub fn is_inverted(&self) -> bool {
+        self.from.zip(self.to).is_some_and(|(from, to)| from > to)
+    }
 }
 
+/// Body of a `/payments` answer with `PAYMENTS_RESPONSE=detailed`.
 #[derive(Debug, Deserialize, Serialize)]
+pub struct PaymentReceipt {
+    // `queued`, or `processed` once a processor confirmed it
+    pub status: String,
+    // Only known once processed
+    #[serde(rename = "requestedAt", skip_serializing_if = "Option::is_none")]
+    pub requested_at: Option<DateTime<Utc>>,
+}
+
+/// How often `/payments-summary/stream` pushes, overriding `SUMMARY_STREAM_INTERVAL_MS` and
+/// `SUMMARY_STREAM_EVERY`. The summary itself is asked for with `SummaryQueryParams`.
+#[derive(Deserialize, Serialize)]
+pub struct SummaryStreamParams {
+    pub interval_ms: Option<u64>,
+    pub every: Option<u64>,
+}
+
+/// Which timestamp `from` and `to` of a summary are compared with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimestampBasis {
+    // When the payment was received, as the spec asks
+    #[default]
+    Requested,
+    // When the processor confirmed it
+    Processed,
+}
+
+impl FromStr for TimestampBasis {
+    type Err = UnknownBasis;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "requested" => Ok(Self::Requested),
+            "processed" => Ok(Self::Processed),
+            _ => Err(UnknownBasis),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownBasis;
+
+impl fmt::Display for UnknownBasis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown timestamp basis")
+    }
+}
+
+impl std::error::Error for UnknownBasis {}
+
+#[derive(Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SnapshotScope {
+    // This instance's own Db
+    #[default]
+    Local,
+    // The backup this instance keeps of its peer's Db
+    Peer,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct SnapshotQueryParams {
+    pub scope: Option<SnapshotScope>,
+    // Only the records within `[from, to]`, every record without either
+    #[serde(default, deserialize_with = "timestamp::deserialize")]
+    pub from: Option<DateTime<Utc>>,
+    #[serde(default, deserialize_with = "timestamp::deserialize")]
+    pub to: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ImportQueryParams {
+    pub format: Option<export::ExportFormat>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ExportQueryParams {
+    pub format: Option<export::ExportFormat>,
+    #[serde(default, deserialize_with = "timestamp::deserialize")]
+    pub from: Option<DateTime<Utc>>,
+    #[serde(default, deserialize_with = "timestamp::deserialize")]
+    pub to: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct ProcessorSummaries {
     #[serde(rename = "default")]
     pub default_sum: Summary,
     pub fallback: Summary,
+    // What each instance recorded by `INSTANCE_ID`, filled in with `breakdown=true`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub instances: BTreeMap<String, ProcessorSummaries>,
+    // Some peer was left out, being down or failing to answer, or the tenant or processed times of
+    // payments restored from a peer backup are missing
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub partial: bool,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+impl ProcessorSummaries {
+    /// Lists these totals as recorded by `instance` alone.
+    pub fn attributed_to(mut self, instance: String) -> Self {
+        self.instances.insert(instance, self.clone());
+        self
+    }
+
+    /// Joins what `other` holds for each instance into this breakdown and recomputes the totals
+    /// from it. Each instance's counts are a grow-only counter: an instance on both sides keeps
+    /// whichever counted more, so the same instance reported by two peers, or merged twice, is
+    /// only counted once, in whatever order answers arrive. Two instances sharing an id would hide
+    /// each other's payments, so peers reporting ours are never merged. Unattributed totals are
+    /// dropped, and the result is partial if either side was.
+    pub fn merge(&mut self, other: ProcessorSummaries) {
+        self.partial |= other.partial;
+
+        for (instance, theirs) in other.instances {
+            match self.instances.entry(instance) {
+                Entry::Vacant(entry) => {
+                    entry.insert(theirs);
+                }
+                Entry::Occupied(mut entry) => {
+                    entry.get_mut().default_sum.join(&theirs.default_sum);
+                    entry.get_mut().fallback.join(&theirs.fallback);
+                }
+            }
+        }
+
+        let mut total = ProcessorSummaries::default();
+
+        for summary in self.instances.values() {
+            total.add(summary);
+        }
+
+        self.default_sum = total.default_sum;
+        self.fallback = total.fallback;
+    }
+
+    pub fn add(&mut self, other: &ProcessorSummaries) {
+        self.default_sum.total_requests += other.default_sum.total_requests;
+        self.default_sum.total_amount += other.default_sum.total_amount;
+        self.fallback.total_requests += other.fallback.total_requests;
+        self.fallback.total_amount += other.fallback.total_amount;
+    }
+
+    /// Fills in the fees charged at the given rates and what is left after them.
+    pub fn apply_fees(&mut self, default_rate: f64, fallback_rate: f64) {
+        self.default_sum.apply_fee(default_rate);
+        self.fallback.apply_fee(fallback_rate);
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct Summary {
     #[serde(rename = "totalRequests")]
     pub total_requests: u64,
     #[serde(rename = "totalAmount")]
     pub total_amount: f64,
+    #[serde(rename = "totalFee", skip_serializing_if = "Option::is_none")]
+    pub total_fee: Option<f64>,
+    #[serde(rename = "netAmount", skip_serializing_if = "Option::is_none")]
+    pub net_amount: Option<f64>,
+}
+
+impl Summary {
+    /// Least upper bound of two counts of the same instance, which only ever grow.
+    pub fn join(&mut self, other: &Summary) {
+        self.total_requests = self.total_requests.max(other.total_requests);
+        self.total_amount = self.total_amount.max(other.total_amount);
+    }
+
+    pub fn apply_fee(&mut self, rate: f64) {
+        let fee = self.total_amount * rate;
+
+        self.total_fee = Some(fee);
+        self.net_amount = Some(self.total_amount - fee);
+    }
+}
+
+#[derive(Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub queue_depth: usize,
+    pub peer_reachable: bool,
+    // False until the boot preflight reached a processor, when it is required to
+    pub processor_reachable: bool,
+    pub default_open: bool,
+    pub fallback_open: bool,
+}
+
+#[derive(Serialize)]
+pub struct Info {
+    pub version: &'static str,
+    pub git_sha: &'static str,
+    pub routing_strategy: routing::Strategy,
+    // Where the accept queue's soft cap currently is
+    pub queue_capacity: usize,
+    // Payments waiting in the accept queue, not counting spilled ones
+    pub queue_depth: usize,
+    pub submit_queue_capacity: usize,
+    // Payments waiting for the dispatcher, retries included
+    pub submit_queue_depth: usize,
+    // Payments submitted at once by the dispatcher
+    pub dispatch_concurrency: usize,
+    // Where the adaptive limit currently is
+    pub dispatch_limit: usize,
+    // Per-processor limit on requests in flight, 0 means unlimited
+    pub processor_max_inflight: usize,
+    // Submissions per second each processor is currently paced to, absent when pacing is off
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_processor_rate: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fallback_processor_rate: Option<f64>,
+    // Share of recent submissions each processor accepted
+    pub default_success_rate: f64,
+    pub fallback_success_rate: f64,
+    // Share of the current fallback budget window that went to the fallback, absent without a
+    // budget
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fallback_budget_used: Option<f64>,
+    pub default_processor_url: String,
+    pub fallback_processor_url: String,
+    pub peer_urls: Vec<String>,
+    // How far ahead of ours each peer's clock was last measured, in microseconds
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub clock_skew_micros: BTreeMap<String, i64>,
+    // What the heartbeats last said of each peer
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub peers: BTreeMap<String, heartbeat::PeerLiveness>,
+    // Internal API version negotiated with each peer asked so far, null when they share none
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub peer_protocol_versions: BTreeMap<String, Option<u32>>,
+    // Estimate of what the in-process Dbs hold
+    pub db_memory_bytes: usize,
+    // How the in-process Dbs' dedup windows fared, absent without `DB_DEDUP`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dedup: Option<dedup::DedupStats>,
+}
+
+#[derive(Deserialize)]
+pub struct ReconcileRequest {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    // Record what the processors counted but the instances missed
+    #[serde(default)]
+    pub patch: bool,
+}
+
+#[derive(Serialize)]
+pub struct ProcessorDiff {
+    pub processor: Summary,
+    pub local: Summary,
+    // Positive when the processor counted more than the instances recorded
+    #[serde(rename = "missingRequests")]
+    pub missing_requests: i64,
+    #[serde(rename = "missingAmount")]
+    pub missing_amount: f64,
+    pub patched: bool,
+}
+
+impl ProcessorDiff {
+    pub fn new(processor: Summary, local: Summary) -> Self {
+        Self {
+            missing_requests: processor.total_requests as i64 - local.total_requests as i64,
+            missing_amount: processor.total_amount - local.total_amount,
+            processor,
+            local,
+            patched: false,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ReconcileReport {
+    pub default: ProcessorDiff,
+    pub fallback: ProcessorDiff,
 }
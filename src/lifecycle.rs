@@ -0,0 +1,90 @@
+//! The latest lifecycle events of every payment, oldest dropped first, so a single payment's
+//! journey can be looked up after the fact through `/admin/trace/{correlationId}` when it went
+//! missing from a summary.
+
+use std::{collections::VecDeque, sync::Mutex};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::{Processor, correlation::CorrelationId, status::PaymentStatus};
+
+#[derive(Clone, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum EventKind {
+    Enqueued,
+    // One submission to a processor, the hedge's when it decided the outcome
+    Attempt {
+        processor: Processor,
+        // Attempts that came before this one
+        retries: u64,
+        // `success`, `rejected` or `retry`, as counted by `payment.processed`
+        outcome: &'static str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+        latency_micros: u64,
+    },
+    // Held back by the fallback budget, to be submitted again later
+    Deferred,
+    // Given up on after retrying past `PAYMENT_MAX_AGE_SECS` or `SYNC_ATTEMPTS`
+    GaveUp,
+}
+
+#[derive(Clone, Serialize)]
+pub struct PaymentEvent {
+    pub at: DateTime<Utc>,
+    #[serde(skip)]
+    pub correlation_id: CorrelationId,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+/// Everything still kept about one payment.
+#[derive(Serialize)]
+pub struct PaymentTrace {
+    #[serde(rename = "correlationId")]
+    pub correlation_id: CorrelationId,
+    pub events: Vec<PaymentEvent>,
+    // Where it stands now, the outcome once final
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<PaymentStatus>,
+}
+
+pub struct EventLog {
+    capacity: usize,
+    events: Mutex<VecDeque<PaymentEvent>>,
+}
+
+impl EventLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            events: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    pub fn record(&self, correlation_id: &CorrelationId, kind: EventKind) {
+        let mut events = self.events.lock().unwrap();
+
+        if events.len() == self.capacity {
+            events.pop_front();
+        }
+
+        events.push_back(PaymentEvent {
+            at: Utc::now(),
+            correlation_id: correlation_id.clone(),
+            kind,
+        });
+    }
+
+    /// The payment's events still kept, oldest first.
+    pub fn of(&self, correlation_id: &CorrelationId) -> Vec<PaymentEvent> {
+        self.events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|event| &event.correlation_id == correlation_id)
+            .cloned()
+            .collect()
+    }
+}
@@ -0,0 +1,106 @@
+use std::{fs, io, net::SocketAddr, os::unix::fs::PermissionsExt};
+
+use socket2::{Domain, Protocol, SockRef, Socket, TcpKeepalive, Type};
+use tokio::net::{TcpListener, TcpStream, UnixListener};
+
+use crate::Config;
+
+/// Binds the public listener with the socket options from `Config`.
+pub fn bind(addr: SocketAddr, config: &Config) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_reuse_address(true)?;
+
+    // Lets several processes accept on the same port, the kernel spreads connections among them
+    if config.listen_reuseport {
+        socket.set_reuse_port(true)?;
+    }
+
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(config.listen_backlog)?;
+
+    TcpListener::from_std(socket.into())
+}
+
+/// Binds a Unix socket at `path`, replacing the one a previous run left behind. Anyone on the host
+/// may connect, the proxy usually runs as another user.
+pub fn bind_unix(path: &str) -> io::Result<UnixListener> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+
+    let listener = UnixListener::bind(path)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o666))?;
+
+    Ok(listener)
+}
+
+/// Applies the per-connection options from `Config` to an accepted socket.
+pub fn tune(stream: &TcpStream, config: &Config) -> io::Result<()> {
+    stream.set_nodelay(config.listen_nodelay)?;
+
+    if !config.listen_keepalive.is_zero() {
+        let keepalive = TcpKeepalive::new()
+            .with_time(config.listen_keepalive)
+            .with_interval(config.listen_keepalive);
+
+        SockRef::from(stream).set_tcp_keepalive(&keepalive)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, time::Duration};
+
+    use super::*;
+
+    fn config() -> Config {
+        Config::from_lookup(|key| (key == "PEER_URL").then(String::new))
+    }
+
+    #[tokio::test]
+    async fn reuseport_lets_listeners_share_a_port() {
+        let mut config = config();
+        config.listen_reuseport = true;
+        let first = bind("127.0.0.1:0".parse().unwrap(), &config).unwrap();
+        let addr = first.local_addr().unwrap();
+
+        assert!(bind(addr, &config).is_ok());
+
+        config.listen_reuseport = false;
+        assert!(bind(addr, &config).is_err());
+    }
+
+    #[tokio::test]
+    async fn accepted_sockets_are_tuned() {
+        let mut config = config();
+        config.listen_nodelay = true;
+        config.listen_keepalive = Duration::from_secs(30);
+        let listener = bind("127.0.0.1:0".parse().unwrap(), &config).unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+
+        tune(&stream, &config).unwrap();
+
+        assert!(stream.nodelay().unwrap());
+        assert!(SockRef::from(&stream).keepalive().unwrap());
+    }
+
+    #[tokio::test]
+    async fn unix_sockets_replace_a_stale_one() {
+        let path = env::temp_dir().join(format!("listener-{}.sock", std::process::id()));
+        let path = path.to_str().unwrap();
+        drop(bind_unix(path).unwrap());
+
+        let _listener = bind_unix(path).unwrap();
+
+        let mode = fs::metadata(path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o666);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,106 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+// Number of recent lag samples the p99 is computed over
+const WINDOW: usize = 200;
+
+/// Tracks how late the Tokio scheduler wakes a task up, a direct measure of how much work is
+/// queued ahead of every request.
+pub struct LagMonitor {
+    budget: Duration,
+    // p99 of the recent samples, in micro seconds
+    p99: AtomicU64,
+}
+
+impl LagMonitor {
+    /// Starts sampling the scheduler lag every `interval`. Requests are shed while its p99 is
+    /// above `budget`.
+    pub fn spawn(interval: Duration, budget: Duration) -> Arc<Self> {
+        let monitor = Arc::new(Self {
+            budget,
+            p99: AtomicU64::new(0),
+        });
+
+        tokio::spawn(monitor.clone().run(interval));
+
+        monitor
+    }
+
+    pub fn p99(&self) -> Duration {
+        Duration::from_micros(self.p99.load(Ordering::Relaxed))
+    }
+
+    pub fn is_overloaded(&self) -> bool {
+        self.p99() > self.budget
+    }
+
+    async fn run(self: Arc<Self>, interval: Duration) {
+        let mut samples = VecDeque::with_capacity(WINDOW);
+        let mut sorted = Vec::with_capacity(WINDOW);
+
+        loop {
+            let start = Instant::now();
+            tokio::time::sleep(interval).await;
+            let lag = start.elapsed().saturating_sub(interval);
+
+            if samples.len() == WINDOW {
+                samples.pop_front();
+            }
+
+            samples.push_back(lag.as_micros() as u64);
+
+            sorted.clear();
+            sorted.extend(samples.iter().copied());
+            sorted.sort_unstable();
+
+            let idx = (sorted.len() - 1) * 99 / 100;
+            self.p99.store(sorted[idx], Ordering::Relaxed);
+        }
+    }
+}
+
+pub async fn load_shed(
+    State(monitor): State<Arc<LagMonitor>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    if monitor.is_overloaded() {
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    }
+
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn a_blocked_scheduler_is_overloaded() {
+        let monitor = LagMonitor::spawn(Duration::from_millis(1), Duration::from_millis(10));
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!monitor.is_overloaded());
+
+        // The monitor shares this single threaded runtime, so it only wakes up late
+        for _ in 0..WINDOW / 10 {
+            std::thread::sleep(Duration::from_millis(20));
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        assert!(monitor.is_overloaded(), "p99 {:?}", monitor.p99());
+    }
+}
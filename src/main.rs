@@ -1,166 +1,132 @@
-use std::sync::Arc;
-use axum::{
-    Json, Router,
-    extract::{Query, State},
-    response::IntoResponse,
-    routing::{get, post},
+use clap::{Parser, Subcommand};
+use client_full::{
+    Config, PaymentGateway,
+    bench::{self, BenchOptions},
+    simulator::{self, Simulation},
+    telemetry,
 };
-use chrono::{DateTime, Utc};
-use client_full::{Db, Payment, PaymentPayload, Processor, ProcessorSummaries, Summary, SummaryQueryParams};
-use reqwest::StatusCode;
-use tokio::sync::{mpsc, Semaphore};
+use tokio::signal::unix::{SignalKind, signal};
+
+#[derive(Parser)]
+#[command(version, about = "Payment proxy for the 2025 Backend Showdown")]
+struct Cli {
+    #[command(subcommand)]
+    mode: Option<Mode>,
+    /// Serve simulated payment processors from within the binary and use them instead of the
+    /// real ones
+    #[arg(long, global = true)]
+    simulate_processors: bool,
+}
 
-#[derive(Clone)]
-struct AppState {
-    req_queue_tx: mpsc::Sender<(Payment, u64)>,
-    default_db: Db,
-    fallback_db: Db,
-    http: reqwest::Client,
-    peer_url: String,
+#[derive(Subcommand)]
+enum Mode {
+    /// Run the payment proxy (default)
+    Serve,
+    /// Run the payment proxy with every Db access going through the worker actor
+    Worker,
+    /// Fire synthetic payments at a running instance and print latency percentiles
+    Bench {
+        #[arg(long, default_value = "http://localhost:9999")]
+        target: String,
+        #[arg(long, default_value_t = 10_000)]
+        requests: usize,
+        #[arg(long, default_value_t = 100)]
+        concurrency: usize,
+        #[arg(long, default_value_t = 19.9)]
+        amount: f64,
+    },
 }
 
 #[tokio::main]
 async fn main() {
-    let (tx, rx) = mpsc::channel::<(Payment, u64)>(10240);
-    let app_state = AppState {
-        req_queue_tx: tx.clone(),
-        default_db: Db::default(),
-        fallback_db: Db::default(),
-        http: reqwest::Client::builder()
-            .tcp_nodelay(true)
-            .build()
-            .unwrap(),
-        peer_url: std::env::var("PEER_URL").ok().unwrap(),
-    };
-
-    tokio::spawn(dispatcher(rx, app_state.clone()));
-
-    let app = Router::new()
-        .route("/payments", post(payments))
-        .route("/payments-summary", get(payments_summary))
-        .with_state(app_state);
-    
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
-
-    println!("Listening on 0.0.0.0:3000");
-
-    axum::serve(listener, app).await.unwrap();
-}
-
-async fn dispatcher(
-    mut rx: mpsc::Receiver<(Payment, u64)>,
-    app_state: AppState,
-) {
-    let concurrency = Arc::new(Semaphore::new(100));
-
-    while let Some((p, retries)) = rx.recv().await {
-        let permit = concurrency.clone().acquire_owned().await.unwrap();
-        let task_state = app_state.clone();
-
-        tokio::spawn(async move {
-            let _permit = permit;
-            process_payment(p, retries, &task_state).await;
-        });
+    let cli = Cli::parse();
+
+    match cli.mode.unwrap_or(Mode::Serve) {
+        Mode::Serve => serve(false, cli.simulate_processors).await,
+        Mode::Worker => serve(true, cli.simulate_processors).await,
+        Mode::Bench {
+            target,
+            requests,
+            concurrency,
+            amount,
+        } => {
+            let opts = BenchOptions {
+                target,
+                requests,
+                concurrency,
+                amount,
+            };
+            let report = bench::run(&opts).await;
+
+            println!(
+                "{} requests ({} failed) in {:.2?}, {:.0} req/s",
+                report.latencies.len(),
+                report.failed,
+                report.elapsed,
+                report.throughput()
+            );
+            println!(
+                "p50 {:.2?} p90 {:.2?} p99 {:.2?} max {:.2?}",
+                report.percentile(50.0),
+                report.percentile(90.0),
+                report.percentile(99.0),
+                report.percentile(100.0)
+            );
+        }
     }
 }
 
-async fn process_payment(p: Payment, retries: u64, task_state: &AppState) {
-    let mut processor = Processor::Default;
+async fn serve(worker: bool, simulate_processors: bool) {
+    let mut config = Config::from_env();
+    config.worker |= worker;
 
-    if retries % 2 != 0 {
-        processor = Processor::Fallback;
+    if simulate_processors {
+        simulate(&mut config).await;
     }
 
-    let url = match processor {
-        Processor::Default => "http://payment-processor-default:8080/payments",
-        Processor::Fallback => "http://payment-processor-fallback:8080/payments",
-    };
-    let status = task_state.http.post(url).json(&p).send().await.unwrap().status();
-
-    if status.is_success() {
-        let timestamp = p.requested_at.timestamp_micros();
-        let amount = (p.amount * 100.0) as u64;
-
-        match processor {
-            Processor::Default => task_state.default_db.set(timestamp, amount),
-            Processor::Fallback => task_state.fallback_db.set(timestamp, amount),
+    let _telemetry = telemetry::init();
+    let gateway = PaymentGateway::new(config).await;
+    let mut serving = tokio::spawn({
+        let gateway = gateway.clone();
+        async move { gateway.serve().await }
+    });
+
+    tokio::select! {
+        served = &mut serving => served.unwrap(),
+        () = terminated() => {
+            println!("Shutting down");
+            gateway.shutdown().await;
+            serving.await.unwrap();
         }
-    } else if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
-        let retries = retries + 1;
-
-        task_state.req_queue_tx.send((p, retries)).await.unwrap();
     }
 }
 
-async fn payments(State(app_state): State<AppState>, Json(payload): Json<PaymentPayload>) {
-    let p = Payment {
-        correlation_id: payload.correlation_id,
-        amount: payload.amount,
-        requested_at: Utc::now(),
+/// Points the config at simulated processors, see `simulator`.
+async fn simulate(config: &mut Config) {
+    let simulation = |failure_rate| Simulation {
+        latency_min: config.simulated_latency_min,
+        latency_max: config.simulated_latency_max,
+        failure_rate,
     };
+    let default = simulation(config.default_simulated_failure_rate);
+    let fallback = simulation(config.fallback_simulated_failure_rate);
 
-    app_state.req_queue_tx.send((p, 0)).await.unwrap();
-}
-
-async fn payments_summary(
-    State(app_state): State<AppState>,
-    Query(params): Query<SummaryQueryParams>,
-) -> impl IntoResponse {
-    let mut total = local_summary(&app_state, params.from, params.to);
-
-    if let None = params.only_local {
-        let remote_data = remote_summary(&app_state, params.from, params.to).await;
-
-        total.default_sum.total_amount += remote_data.default_sum.total_amount;
-        total.default_sum.total_requests += remote_data.default_sum.total_requests;
-        total.fallback.total_amount += remote_data.fallback.total_amount;
-        total.fallback.total_requests += remote_data.fallback.total_requests;
-    }
+    config.default_processor_url = simulator::spawn(default).await.unwrap();
+    config.fallback_processor_url = simulator::spawn(fallback).await.unwrap();
+    config.default_processor_admin_url = None;
+    config.fallback_processor_admin_url = None;
 
-    Json(total)
+    println!(
+        "Simulating processors at {} and {}",
+        config.default_processor_url, config.fallback_processor_url
+    );
 }
 
-fn local_summary(
-    app_state: &AppState,
-    from: Option<DateTime<Utc>>,
-    to: Option<DateTime<Utc>>,
-) -> ProcessorSummaries {
-    let from = from.map(|dt| dt.timestamp_micros());
-    let to = to.map(|dt| dt.timestamp_micros());
+async fn terminated() {
+    let mut terminate = signal(SignalKind::terminate()).unwrap();
 
-    let (d_count, d_total) = app_state.default_db.get(from, to);
-    let (f_count, f_total) = app_state.fallback_db.get(from, to);
-
-    let default_sum = Summary {
-        total_requests: d_count,
-        total_amount: d_total as f64 / 100.0,
-    };
-    let fallback = Summary {
-        total_requests: f_count,
-        total_amount: f_total as f64 / 100.0,
-    };
-
-    ProcessorSummaries {
-        default_sum,
-        fallback,
+    tokio::select! {
+        _ = terminate.recv() => {}
+        _ = tokio::signal::ctrl_c() => {}
     }
 }
-
-async fn remote_summary(
-    app_state: &AppState,
-    from: Option<DateTime<Utc>>,
-    to: Option<DateTime<Utc>>,
-) -> ProcessorSummaries {
-    let endpoint = format!(
-        "{}/payments-summary",
-        app_state.peer_url.trim_end_matches('/')
-    );
-    let params = SummaryQueryParams {
-        from,
-        to,
-        only_local: Some(true),
-    };
-    let resp = app_state.http.get(endpoint).query(&params).send().await.unwrap();
-
-    resp.json::<ProcessorSummaries>().await.unwrap()
-}
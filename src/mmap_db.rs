@@ -0,0 +1,271 @@
+use std::{
+    fmt,
+    fs::OpenOptions,
+    io,
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use async_trait::async_trait;
+use memmap2::MmapMut;
+
+use crate::{
+    Processor,
+    backend::{BackendError, Storage, Totals},
+    correlation::CorrelationId,
+};
+
+const MAGIC: u64 = 0x5348_4f57_444f_574e;
+// magic, capacity and the next free record index
+const HEADER_WORDS: usize = 3;
+// state (0 while being written, processor + 1 once committed), timestamp and amount
+const RECORD_WORDS: usize = 3;
+
+/// Append-only payment log in a memory-mapped file, meant to be opened by every instance on the
+/// host so all of them read and write one store.
+///
+/// Writers claim a record with an atomic increment of the cursor and publish it by storing its
+/// state last, so readers never see half-written records and no lock is shared between processes.
+#[derive(Clone)]
+pub struct MmapDb {
+    inner: Arc<Mapping>,
+}
+
+struct Mapping {
+    // Keeps the mapping alive for as long as `words` is used
+    _map: MmapMut,
+    words: *const AtomicU64,
+    capacity: usize,
+}
+
+// The mapping is only ever accessed through atomics
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl MmapDb {
+    /// Opens the store at `path`, creating it with room for `capacity` records if needed.
+    pub fn open(path: impl AsRef<Path>, capacity: usize) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let len = ((HEADER_WORDS + capacity * RECORD_WORDS) * size_of::<u64>()) as u64;
+
+        if file.metadata()?.len() < len {
+            file.set_len(len)?;
+        }
+
+        // SAFETY: the file is only ever modified through the atomics below, by this and other
+        // processes mapping the same file
+        let mut map = unsafe { MmapMut::map_mut(&file)? };
+        let words = map.as_mut_ptr() as *const AtomicU64;
+        let mapping = Mapping {
+            _map: map,
+            words,
+            capacity,
+        };
+
+        let magic = mapping.word(0);
+        if let Err(found) = magic.compare_exchange(0, MAGIC, Ordering::AcqRel, Ordering::Acquire)
+            && found != MAGIC
+        {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a Db file"));
+        }
+
+        let stored = mapping.word(1);
+        if let Err(found) =
+            stored.compare_exchange(0, capacity as u64, Ordering::AcqRel, Ordering::Acquire)
+            && found != capacity as u64
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Db file was created with capacity {found}"),
+            ));
+        }
+
+        Ok(Self {
+            inner: Arc::new(mapping),
+        })
+    }
+
+    pub fn set(&self, processor: Processor, timestamp: i64, amount: u64) -> Result<(), StoreFull> {
+        let mapping = &self.inner;
+        let idx = mapping.word(2).fetch_add(1, Ordering::Relaxed) as usize;
+
+        if idx >= mapping.capacity {
+            return Err(StoreFull);
+        }
+
+        let base = HEADER_WORDS + idx * RECORD_WORDS;
+        mapping
+            .word(base + 1)
+            .store(timestamp as u64, Ordering::Relaxed);
+        mapping.word(base + 2).store(amount, Ordering::Relaxed);
+        mapping
+            .word(base)
+            .store(state(processor), Ordering::Release);
+
+        Ok(())
+    }
+
+    pub fn get(&self, processor: Processor, from: Option<i64>, to: Option<i64>) -> (u64, u64) {
+        let [default, fallback] = self.get_all(from, to);
+
+        match processor {
+            Processor::Default => default,
+            Processor::Fallback => fallback,
+        }
+    }
+
+    /// Totals of both processors in one walk over the log.
+    pub fn get_all(&self, from: Option<i64>, to: Option<i64>) -> Totals {
+        let mapping = &self.inner;
+        let len = (mapping.word(2).load(Ordering::Acquire) as usize).min(mapping.capacity);
+        let from = from.unwrap_or(i64::MIN);
+        let to = to.unwrap_or(i64::MAX);
+        let mut totals = [(0, 0); 2];
+
+        for idx in 0..len {
+            let base = HEADER_WORDS + idx * RECORD_WORDS;
+            let totals = match mapping.word(base).load(Ordering::Acquire) {
+                1 => &mut totals[0],
+                2 => &mut totals[1],
+                // Still being written
+                _ => continue,
+            };
+            let timestamp = mapping.word(base + 1).load(Ordering::Relaxed) as i64;
+
+            if (from..=to).contains(&timestamp) {
+                totals.0 += 1;
+                totals.1 += mapping.word(base + 2).load(Ordering::Relaxed);
+            }
+        }
+
+        totals
+    }
+}
+
+#[async_trait]
+impl Storage for MmapDb {
+    async fn record(
+        &self,
+        processor: Processor,
+        _correlation_id: &CorrelationId,
+        timestamp: i64,
+        amount: u64,
+    ) -> Result<(), BackendError> {
+        MmapDb::set(self, processor, timestamp, amount)?;
+
+        Ok(())
+    }
+
+    async fn summarize(
+        &self,
+        processor: Processor,
+        from: Option<i64>,
+        to: Option<i64>,
+    ) -> Result<(u64, u64), BackendError> {
+        Ok(MmapDb::get(self, processor, from, to))
+    }
+
+    async fn summarize_all(
+        &self,
+        from: Option<i64>,
+        to: Option<i64>,
+    ) -> Result<Totals, BackendError> {
+        Ok(MmapDb::get_all(self, from, to))
+    }
+
+    fn is_shared(&self) -> bool {
+        true
+    }
+}
+
+impl Mapping {
+    fn word(&self, idx: usize) -> &AtomicU64 {
+        debug_assert!(idx < HEADER_WORDS + self.capacity * RECORD_WORDS);
+        // SAFETY: every index used is within the mapped length and the mapping is page aligned
+        unsafe { &*self.words.add(idx) }
+    }
+}
+
+fn state(processor: Processor) -> u64 {
+    match processor {
+        Processor::Default => 1,
+        Processor::Fallback => 2,
+    }
+}
+
+#[derive(Debug)]
+pub struct StoreFull;
+
+impl fmt::Display for StoreFull {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "shared Db is full")
+    }
+}
+
+impl std::error::Error for StoreFull {}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf};
+
+    use super::*;
+
+    fn path(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("mmap-db-{name}-{}.db", std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn totals_are_shared_between_mappings() {
+        let path = path("shared");
+        let a = MmapDb::open(&path, 8).unwrap();
+        let b = MmapDb::open(&path, 8).unwrap();
+
+        a.set(Processor::Default, 10, 100).unwrap();
+        b.set(Processor::Default, 20, 200).unwrap();
+        b.set(Processor::Fallback, 30, 300).unwrap();
+
+        assert_eq!(a.get_all(None, None), [(2, 300), (1, 300)]);
+        assert_eq!(b.get(Processor::Default, Some(15), None), (1, 200));
+        assert_eq!(a.get(Processor::Fallback, None, Some(29)), (0, 0));
+
+        drop((a, b));
+        assert_eq!(
+            MmapDb::open(&path, 8).unwrap().get_all(None, None),
+            [(2, 300), (1, 300)]
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn writes_past_capacity_are_refused() {
+        let path = path("full");
+        let db = MmapDb::open(&path, 1).unwrap();
+
+        db.set(Processor::Default, 10, 100).unwrap();
+        assert!(db.set(Processor::Default, 20, 200).is_err());
+        assert_eq!(db.get_all(None, None), [(1, 100), (0, 0)]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn mismatched_files_are_refused() {
+        let path = path("mismatch");
+
+        MmapDb::open(&path, 4).unwrap();
+        assert!(MmapDb::open(&path, 8).is_err());
+
+        fs::write(&path, [0xff; 64]).unwrap();
+        assert!(MmapDb::open(&path, 4).is_err());
+        fs::remove_file(path).unwrap();
+    }
+}
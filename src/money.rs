@@ -0,0 +1,55 @@
+use std::{fmt, str::FromStr};
+
+// A u64 of billionths still holds totals up to 18 billion
+const MAX_SCALE: u32 = 9;
+
+/// Decimal places amounts are kept exact to. Every store records amounts as a whole number of the
+/// smallest unit at this scale, so it must stay the same for as long as the stored data is kept.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Scale(u32);
+
+impl Scale {
+    pub const CENTS: Scale = Scale(2);
+
+    /// Whole units of `amount`, rounded instead of truncated since 19.9 * 100.0 is
+    /// 1989.9999999999998.
+    pub fn to_units(self, amount: f64) -> u64 {
+        (amount * self.factor()).round() as u64
+    }
+
+    pub fn to_amount(self, units: u64) -> f64 {
+        units as f64 / self.factor()
+    }
+
+    fn factor(self) -> f64 {
+        10f64.powi(self.0 as i32)
+    }
+}
+
+impl Default for Scale {
+    fn default() -> Self {
+        Self::CENTS
+    }
+}
+
+impl FromStr for Scale {
+    type Err = InvalidScale;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse() {
+            Ok(decimals) if decimals <= MAX_SCALE => Ok(Self(decimals)),
+            _ => Err(InvalidScale),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidScale;
+
+impl fmt::Display for InvalidScale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "amount scale must be 0 to {MAX_SCALE} decimal places")
+    }
+}
+
+impl std::error::Error for InvalidScale {}
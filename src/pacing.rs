@@ -0,0 +1,81 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::rate_limit::TokenBucket;
+
+// Share of the rate kept after a 429
+const BACKOFF: f64 = 0.8;
+// Share of the configured rate won back by every accepted submission
+const RECOVERY: f64 = 0.01;
+// The rate never drops below this share of the configured one
+const FLOOR: f64 = 0.1;
+// 429s within this of a decrease are answers to submissions sent before it
+const DECREASE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Paces submissions to one processor so they stay under its rate limit instead of running into
+/// it. Starts at the configured rate and follows the 429s the processor answers with: each one
+/// cuts the rate by `BACKOFF`, and accepted submissions bring it back up to the configured rate.
+pub struct Pacer {
+    max_rate: f64,
+    state: Mutex<State>,
+}
+
+struct State {
+    bucket: TokenBucket,
+    last_decrease: Option<Instant>,
+}
+
+impl Pacer {
+    /// Paces to `rate` submissions per second, letting up to `burst` through at once. A rate of 0
+    /// disables pacing.
+    pub fn new(rate: f64, burst: f64) -> Option<Self> {
+        (rate > 0.0).then(|| Self {
+            max_rate: rate,
+            state: Mutex::new(State {
+                bucket: TokenBucket::new(rate, burst.max(1.0)),
+                last_decrease: None,
+            }),
+        })
+    }
+
+    /// Waits until the next submission is due.
+    pub async fn acquire(&self) {
+        let wait = self.state.lock().unwrap().bucket.reserve();
+
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Submissions per second currently paced to.
+    pub fn rate(&self) -> f64 {
+        self.state.lock().unwrap().bucket.rate()
+    }
+
+    pub fn on_accepted(&self) {
+        let mut state = self.state.lock().unwrap();
+        let rate = state.bucket.rate();
+
+        if rate < self.max_rate {
+            let rate = (rate + self.max_rate * RECOVERY).min(self.max_rate);
+            state.bucket.set_rate(rate);
+        }
+    }
+
+    pub fn on_rate_limited(&self) {
+        let mut state = self.state.lock().unwrap();
+
+        if state
+            .last_decrease
+            .is_some_and(|at| at.elapsed() < DECREASE_INTERVAL)
+        {
+            return;
+        }
+
+        let rate = (state.bucket.rate() * BACKOFF).max(self.max_rate * FLOOR);
+        state.bucket.set_rate(rate);
+        state.last_decrease = Some(Instant::now());
+    }
+}
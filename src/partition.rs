@@ -0,0 +1,65 @@
+use crate::correlation::{CorrelationId, ENCODED_LEN};
+
+/// Index of the instance that owns `correlation_id` among `instances`.
+pub fn owner(correlation_id: &CorrelationId, instances: u64) -> u64 {
+    hash(correlation_id) % instances
+}
+
+/// Every instance must agree on the hash, so this uses FNV-1a rather than the std hasher whose
+/// output is not guaranteed to be stable. The id is hashed as text, as it was sent in.
+pub fn hash(correlation_id: &CorrelationId) -> u64 {
+    let mut buf = [0; ENCODED_LEN];
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+
+    for byte in correlation_id.encode(&mut buf).bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(n: u32) -> CorrelationId {
+        format!("4a7901b8-7d26-4d9d-aa19-{n:012}").parse().unwrap()
+    }
+
+    #[test]
+    fn hash_is_fnv_1a_of_the_id_text() {
+        let id = "4a7901b8-7d26-4d9d-aa19-4dc1c7cf60b3".parse().unwrap();
+
+        // Every instance, whatever it was built with, must come up with this very value
+        assert_eq!(hash(&id), 0xa6f6_30d1_b535_3739);
+    }
+
+    #[cfg(not(feature = "string-ids"))]
+    #[test]
+    fn uuids_are_hashed_in_lowercase() {
+        let lower = "4a7901b8-7d26-4d9d-aa19-4dc1c7cf60b3".parse().unwrap();
+        let upper = "4A7901B8-7D26-4D9D-AA19-4DC1C7CF60B3".parse().unwrap();
+
+        assert_eq!(hash(&lower), hash(&upper));
+    }
+
+    #[test]
+    fn a_lone_instance_owns_everything() {
+        assert!((0..100).all(|n| owner(&id(n), 1) == 0));
+    }
+
+    #[test]
+    fn ownership_is_spread_across_instances() {
+        let mut owned = [0; 3];
+
+        for n in 0..3000 {
+            owned[owner(&id(n), 3) as usize] += 1;
+        }
+
+        assert!(
+            owned.iter().all(|&count| (800..1200).contains(&count)),
+            "{owned:?}"
+        );
+    }
+}
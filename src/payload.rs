@@ -0,0 +1,197 @@
+use axum::{
+    Json,
+    extract::{
+        FromRequest, Request,
+        rejection::{BytesRejection, JsonRejection},
+    },
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use std::{fmt, str::FromStr};
+
+use serde::Serialize;
+
+use crate::{
+    PaymentPayload,
+    tenant::{InvalidTenant, TENANT_HEADER, TenantId},
+};
+
+#[cfg(feature = "fast-json")]
+use crate::fast_json;
+
+/// A `/payments` body, its `tenantId` taken from the `X-Tenant-Id` header when the body has
+/// none. Bodies that can't be read or parsed are answered with a JSON `{"error": {...}}` instead
+/// of axum's plain text rejections.
+pub struct Payload(pub PaymentPayload);
+
+impl<S: Send + Sync> FromRequest<S> for Payload {
+    type Rejection = PayloadRejection;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let tenant = match req.headers().get(TENANT_HEADER) {
+            Some(value) => Some(
+                value
+                    .to_str()
+                    .ok()
+                    .and_then(|v| v.parse::<TenantId>().ok())
+                    .ok_or_else(PayloadRejection::invalid_tenant)?,
+            ),
+            None => None,
+        };
+        let mut payload = parse(req, state).await?;
+
+        if payload.tenant_id.is_none() {
+            payload.tenant_id = tenant;
+        }
+
+        Ok(Self(payload))
+    }
+}
+
+#[cfg(feature = "fast-json")]
+async fn parse<S: Send + Sync>(
+    req: Request,
+    state: &S,
+) -> Result<PaymentPayload, PayloadRejection> {
+    // Left to serde, so the body is rejected as it would be without the fast path
+    if !fast_json::is_json(req.headers()) {
+        let Json(payload) = Json::from_request(req, state).await?;
+        return Ok(payload);
+    }
+
+    let body = axum::body::Bytes::from_request(req, state).await?;
+
+    match fast_json::parse_payload(&body).and_then(|raw| raw.to_payload()) {
+        Some(payload) => Ok(payload),
+        None => Ok(Json::from_bytes(&body)?.0),
+    }
+}
+
+#[cfg(not(feature = "fast-json"))]
+async fn parse<S: Send + Sync>(
+    req: Request,
+    state: &S,
+) -> Result<PaymentPayload, PayloadRejection> {
+    let Json(payload) = Json::from_request(req, state).await?;
+
+    Ok(payload)
+}
+
+#[derive(Debug)]
+pub struct PayloadRejection {
+    status: StatusCode,
+    kind: &'static str,
+    message: String,
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error: ErrorDetail<'a>,
+}
+
+#[derive(Serialize)]
+struct ErrorDetail<'a> {
+    status: u16,
+    // Stable name of what went wrong, `message` is only meant for humans
+    kind: &'a str,
+    message: &'a str,
+}
+
+impl PayloadRejection {
+    fn invalid_tenant() -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            kind: "invalid_tenant",
+            message: InvalidTenant.to_string(),
+        }
+    }
+}
+
+impl From<JsonRejection> for PayloadRejection {
+    fn from(rejection: JsonRejection) -> Self {
+        let status = rejection.status();
+        let message = rejection.body_text();
+        let kind = match rejection {
+            JsonRejection::JsonSyntaxError(_) => "malformed_json",
+            JsonRejection::JsonDataError(_) => "invalid_payment",
+            JsonRejection::MissingJsonContentType(_) => "missing_content_type",
+            JsonRejection::BytesRejection(inner) => return inner.into(),
+            _ => "invalid_body",
+        };
+
+        Self {
+            status,
+            kind,
+            message,
+        }
+    }
+}
+
+impl From<BytesRejection> for PayloadRejection {
+    fn from(rejection: BytesRejection) -> Self {
+        let status = rejection.status();
+        let kind = match status {
+            StatusCode::PAYLOAD_TOO_LARGE => "body_too_large",
+            _ => "unreadable_body",
+        };
+
+        Self {
+            status,
+            kind,
+            message: rejection.body_text(),
+        }
+    }
+}
+
+impl IntoResponse for PayloadRejection {
+    fn into_response(self) -> Response {
+        error_response(self.status, self.kind, &self.message)
+    }
+}
+
+/// A JSON `{"error": {...}}` answer, as bodies that can't be parsed get.
+pub fn error_response(status: StatusCode, kind: &str, message: &str) -> Response {
+    let body = ErrorBody {
+        error: ErrorDetail {
+            status: status.as_u16(),
+            kind,
+            message,
+        },
+    };
+
+    (status, Json(body)).into_response()
+}
+
+/// What `/payments` answers with once the payment is taken.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ResponseStyle {
+    // 200 with an empty body, 502 when a synchronous submission failed, as the spec expects
+    #[default]
+    Plain,
+    // 202 with `{"status":"queued"}`, or 201 with `{"status":"processed","requestedAt":...}`
+    // with `SYNC_SUBMISSION`, and JSON error bodies
+    Detailed,
+}
+
+impl FromStr for ResponseStyle {
+    type Err = UnknownResponseStyle;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(Self::Plain),
+            "detailed" => Ok(Self::Detailed),
+            _ => Err(UnknownResponseStyle),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownResponseStyle;
+
+impl fmt::Display for UnknownResponseStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown response style")
+    }
+}
+
+impl std::error::Error for UnknownResponseStyle {}
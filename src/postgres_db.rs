@@ -0,0 +1,177 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use sqlx::{PgPool, postgres::PgPoolOptions};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{
+    Processor,
+    backend::{BackendError, Storage, Totals},
+    correlation::CorrelationId,
+};
+
+struct Record {
+    processor: i16,
+    timestamp: i64,
+    amount: i64,
+    // Acknowledged once the batch holding the record is committed
+    ack: oneshot::Sender<Result<(), String>>,
+}
+
+/// Persists every confirmed payment as a row in Postgres, shared by every instance. Writes are
+/// batched by a single task so the dispatcher only pays for one round trip per batch.
+#[derive(Clone)]
+pub struct PostgresDb {
+    pool: PgPool,
+    tx: mpsc::Sender<Record>,
+}
+
+impl PostgresDb {
+    pub async fn connect(
+        url: &str,
+        batch_size: usize,
+        batch_delay: Duration,
+    ) -> sqlx::Result<Self> {
+        let pool = PgPoolOptions::new().max_connections(4).connect(url).await?;
+
+        sqlx::raw_sql(
+            "CREATE TABLE IF NOT EXISTS payments (
+                processor SMALLINT NOT NULL,
+                requested_at BIGINT NOT NULL,
+                amount BIGINT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS payments_processor_requested_at
+                ON payments (processor, requested_at);",
+        )
+        .execute(&pool)
+        .await?;
+
+        let (tx, rx) = mpsc::channel(batch_size * 4);
+
+        tokio::spawn(writer(pool.clone(), rx, batch_size, batch_delay));
+
+        Ok(Self { pool, tx })
+    }
+}
+
+#[async_trait]
+impl Storage for PostgresDb {
+    async fn record(
+        &self,
+        processor: Processor,
+        _correlation_id: &CorrelationId,
+        timestamp: i64,
+        amount: u64,
+    ) -> Result<(), BackendError> {
+        let (ack, rx) = oneshot::channel();
+        let record = Record {
+            processor: processor_id(processor),
+            timestamp,
+            amount: amount as i64,
+            ack,
+        };
+
+        self.tx
+            .send(record)
+            .await
+            .map_err(|_| "Postgres writer stopped")?;
+
+        Ok(rx.await.map_err(|_| "Postgres writer stopped")??)
+    }
+
+    async fn summarize(
+        &self,
+        processor: Processor,
+        from: Option<i64>,
+        to: Option<i64>,
+    ) -> Result<(u64, u64), BackendError> {
+        let (count, amount): (i64, i64) = sqlx::query_as(
+            "SELECT COUNT(*), COALESCE(SUM(amount), 0)::BIGINT FROM payments
+            WHERE processor = $1 AND requested_at BETWEEN $2 AND $3",
+        )
+        .bind(processor_id(processor))
+        .bind(from.unwrap_or(i64::MIN))
+        .bind(to.unwrap_or(i64::MAX))
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok((count as u64, amount as u64))
+    }
+
+    async fn summarize_all(
+        &self,
+        from: Option<i64>,
+        to: Option<i64>,
+    ) -> Result<Totals, BackendError> {
+        let rows: Vec<(i16, i64, i64)> = sqlx::query_as(
+            "SELECT processor, COUNT(*), SUM(amount)::BIGINT FROM payments
+            WHERE requested_at BETWEEN $1 AND $2 GROUP BY processor",
+        )
+        .bind(from.unwrap_or(i64::MIN))
+        .bind(to.unwrap_or(i64::MAX))
+        .fetch_all(&self.pool)
+        .await?;
+        let mut totals = [(0, 0); 2];
+
+        for (processor, count, amount) in rows {
+            totals[processor as usize] = (count as u64, amount as u64);
+        }
+
+        Ok(totals)
+    }
+
+    fn is_shared(&self) -> bool {
+        true
+    }
+}
+
+/// Collects up to `batch_size` records, waiting at most `batch_delay` after the first one, and
+/// inserts them with a single statement.
+async fn writer(
+    pool: PgPool,
+    mut rx: mpsc::Receiver<Record>,
+    batch_size: usize,
+    batch_delay: Duration,
+) {
+    let mut batch = Vec::with_capacity(batch_size);
+
+    while let Some(record) = rx.recv().await {
+        batch.push(record);
+
+        let deadline = tokio::time::sleep(batch_delay);
+        tokio::pin!(deadline);
+
+        while batch.len() < batch_size {
+            tokio::select! {
+                Some(record) = rx.recv() => batch.push(record),
+                _ = &mut deadline => break,
+            }
+        }
+
+        let processors: Vec<i16> = batch.iter().map(|r| r.processor).collect();
+        let timestamps: Vec<i64> = batch.iter().map(|r| r.timestamp).collect();
+        let amounts: Vec<i64> = batch.iter().map(|r| r.amount).collect();
+        let result = sqlx::query(
+            "INSERT INTO payments (processor, requested_at, amount)
+            SELECT * FROM UNNEST($1::SMALLINT[], $2::BIGINT[], $3::BIGINT[])",
+        )
+        .bind(processors)
+        .bind(timestamps)
+        .bind(amounts)
+        .execute(&pool)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string());
+
+        for record in batch.drain(..) {
+            let _ = record.ack.send(result.clone());
+        }
+    }
+}
+
+fn processor_id(processor: Processor) -> i16 {
+    match processor {
+        Processor::Default => 0,
+        Processor::Fallback => 1,
+    }
+}
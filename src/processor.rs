@@ -0,0 +1,382 @@
+use std::{fmt, sync::Arc, time::Duration};
+
+use arc_swap::{ArcSwap, Guard};
+use axum::Json;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use crate::{
+    Config, Processor,
+    dns::DnsWatch,
+    fallback_budget::FallbackBudget,
+    health::{CircuitBreaker, SuccessRate},
+    pacing::Pacer,
+    routing::{Outcome, ProcessorHealth, RoutingContext, RoutingStrategy, Strategy},
+};
+
+/// How a processor's HTTP client connects, set apart for each processor.
+#[derive(Clone, Copy)]
+pub struct HttpSettings {
+    // Talk HTTP/2 without upgrade negotiation
+    pub http2: bool,
+    pub pool_max_idle: usize,
+    // 0 leaves connecting bounded only by the request timeout
+    pub connect_timeout: Duration,
+}
+
+impl HttpSettings {
+    /// Builds a client with a pool of its own, kept apart from the peer client since the peer only
+    /// speaks HTTP/1.1.
+    pub fn build(&self) -> reqwest::Client {
+        let mut builder = reqwest::Client::builder()
+            .tcp_nodelay(true)
+            .pool_max_idle_per_host(self.pool_max_idle);
+
+        if self.http2 {
+            builder = builder.http2_prior_knowledge();
+        }
+        if !self.connect_timeout.is_zero() {
+            builder = builder.connect_timeout(self.connect_timeout);
+        }
+
+        builder.build().unwrap()
+    }
+}
+
+/// Everything needed to submit payments to a single processor. Nothing is shared with the other
+/// processor, so one misbehaving can't slow the other down through the pool or DNS.
+pub struct ProcessorClient {
+    pub url: String,
+    // Base URL of the processor's `/admin` API, usually the same as `url`
+    pub admin_url: String,
+    pub breaker: CircuitBreaker,
+    pub success: SuccessRate,
+    // Spaces submissions out ahead of the processor's own rate limit, when configured
+    pub pacer: Option<Pacer>,
+    // Watches `url` and `admin_url` for address changes
+    pub dns: DnsWatch,
+    settings: HttpSettings,
+    // Swapped for a client with an empty pool by `reconnect`
+    http: ArcSwap<reqwest::Client>,
+    // Caps the requests multiplexed onto this processor at once
+    inflight: Semaphore,
+}
+
+impl ProcessorClient {
+    pub fn new(
+        url: String,
+        admin_url: Option<String>,
+        rate: f64,
+        settings: HttpSettings,
+        config: &Config,
+    ) -> Self {
+        let max_inflight = match config.processor_max_inflight {
+            0 => Semaphore::MAX_PERMITS,
+            n => n,
+        };
+        let admin_url = admin_url.unwrap_or_else(|| url.clone());
+
+        Self {
+            dns: DnsWatch::new([url.as_str(), admin_url.as_str()]),
+            admin_url,
+            url,
+            breaker: CircuitBreaker::new(config.breaker_threshold, config.breaker_cooldown),
+            success: SuccessRate::new(config.success_rate_window),
+            pacer: Pacer::new(rate, config.processor_rate_burst),
+            settings,
+            http: ArcSwap::from_pointee(settings.build()),
+            inflight: Semaphore::new(max_inflight),
+        }
+    }
+
+    /// The client requests to this processor go through.
+    pub fn http(&self) -> Guard<Arc<reqwest::Client>> {
+        self.http.load()
+    }
+
+    /// Drops every pooled connection, as when the processor moved to another address.
+    pub fn reconnect(&self) {
+        self.http.store(Arc::new(self.settings.build()));
+    }
+
+    /// Waits for the submission's turn under the pacer, then for a slot among those in flight.
+    pub async fn acquire(&self) -> SemaphorePermit<'_> {
+        if let Some(pacer) = &self.pacer {
+            pacer.acquire().await;
+        }
+
+        self.inflight.acquire().await.unwrap()
+    }
+}
+
+pub struct ProcessorRouter {
+    pub default: ProcessorClient,
+    pub fallback: ProcessorClient,
+    // Swapped at runtime through `PUT /admin/routing`, along with whatever it learned
+    routing: ArcSwap<Routing>,
+    // Caps what the fallback gets, whatever the strategy chooses
+    budget: Option<FallbackBudget>,
+}
+
+struct Routing {
+    kind: Strategy,
+    strategy: Box<dyn RoutingStrategy>,
+}
+
+impl Routing {
+    fn new(kind: Strategy) -> Self {
+        Self {
+            kind,
+            strategy: kind.build(),
+        }
+    }
+}
+
+impl ProcessorRouter {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            default: ProcessorClient::new(
+                config.default_processor_url.clone(),
+                config.default_processor_admin_url.clone(),
+                config.default_processor_rate,
+                HttpSettings {
+                    http2: config.processor_http2,
+                    pool_max_idle: config.default_processor_pool_max_idle,
+                    connect_timeout: config.default_processor_connect_timeout,
+                },
+                config,
+            ),
+            fallback: ProcessorClient::new(
+                config.fallback_processor_url.clone(),
+                config.fallback_processor_admin_url.clone(),
+                config.fallback_processor_rate,
+                HttpSettings {
+                    http2: config.processor_http2,
+                    pool_max_idle: config.fallback_processor_pool_max_idle,
+                    connect_timeout: config.fallback_processor_connect_timeout,
+                },
+                config,
+            ),
+            routing: ArcSwap::from_pointee(Routing::new(config.routing_strategy)),
+            budget: (config.fallback_budget > 0.0).then(|| {
+                FallbackBudget::new(
+                    config.fallback_budget,
+                    config.fallback_budget_basis,
+                    config.fallback_budget_window,
+                )
+            }),
+        }
+    }
+
+    pub fn strategy(&self) -> Strategy {
+        self.routing.load().kind
+    }
+
+    pub fn set_strategy(&self, strategy: Strategy) {
+        self.routing.store(Arc::new(Routing::new(strategy)));
+    }
+
+    /// Picks the processor for a payment that already failed `retries` times.
+    pub fn choose(&self, retries: u64) -> Processor {
+        let ctx = RoutingContext {
+            retries,
+            default: ProcessorHealth {
+                breaker: &self.default.breaker,
+                success: &self.default.success,
+            },
+            fallback: ProcessorHealth {
+                breaker: &self.fallback.breaker,
+                success: &self.fallback.success,
+            },
+        };
+
+        self.routing.load().strategy.choose(&ctx)
+    }
+
+    /// Counts a submission against the fallback budget, false when it would go over.
+    pub fn try_route(&self, processor: Processor, amount: f64) -> bool {
+        self.budget
+            .as_ref()
+            .is_none_or(|budget| budget.try_route(processor, amount))
+    }
+
+    /// Share of the fallback budget's window that went to the fallback, without a budget none.
+    pub fn fallback_budget_used(&self) -> Option<f64> {
+        self.budget.as_ref().map(FallbackBudget::used)
+    }
+
+    pub fn feedback(&self, processor: Processor, outcome: Outcome) {
+        self.routing.load().strategy.feedback(processor, outcome);
+    }
+
+    pub fn get(&self, processor: Processor) -> &ProcessorClient {
+        match processor {
+            Processor::Default => &self.default,
+            Processor::Fallback => &self.fallback,
+        }
+    }
+}
+
+/// Why a processor didn't take a payment, told apart by its status code and error body.
+#[derive(Debug)]
+pub enum ProcessorError {
+    // The processor already has a payment with this correlationId
+    DuplicateCorrelationId,
+    InvalidAmount,
+    RateLimited,
+    // Any server error
+    Internal(StatusCode),
+    // Any other client error
+    Rejected(StatusCode),
+    // Timed out or never answered
+    Transport(reqwest::Error),
+    // Sent, but the answer was dropped by chaos injection
+    Lost,
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    message: String,
+}
+
+impl ProcessorError {
+    /// Classifies a non-success answer. Processors answer a repeated correlationId with a bare
+    /// 422, anything else is told apart by the message in the body, matched loosely since it is
+    /// only meant for humans.
+    pub fn parse(status: StatusCode, body: &[u8]) -> Self {
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return Self::RateLimited;
+        }
+
+        if status.is_server_error() {
+            return Self::Internal(status);
+        }
+
+        let message = Json::<ErrorBody>::from_bytes(body)
+            .map(|Json(body)| body.message.to_lowercase())
+            .unwrap_or_default();
+        let duplicate = message.contains("correlation")
+            && ["already", "exist", "duplicate"]
+                .iter()
+                .any(|word| message.contains(word));
+
+        if message.contains("amount") {
+            Self::InvalidAmount
+        } else if duplicate
+            || status == StatusCode::CONFLICT
+            || status == StatusCode::UNPROCESSABLE_ENTITY && message.is_empty()
+        {
+            Self::DuplicateCorrelationId
+        } else {
+            Self::Rejected(status)
+        }
+    }
+
+    /// Whether submitting the payment again could go differently.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Self::RateLimited | Self::Internal(_) | Self::Transport(_) | Self::Lost
+        )
+    }
+
+    /// Whether the payment may have gone through without the processor saying so.
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            Self::Transport(e) => e.is_timeout(),
+            Self::Lost => true,
+            _ => false,
+        }
+    }
+
+    /// Whether no connection could be made, as when the processor moved to another address.
+    pub fn is_connect(&self) -> bool {
+        matches!(self, Self::Transport(e) if e.is_connect())
+    }
+}
+
+impl fmt::Display for ProcessorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DuplicateCorrelationId => write!(f, "correlationId already processed"),
+            Self::InvalidAmount => write!(f, "invalid amount"),
+            Self::RateLimited => write!(f, "rate limited"),
+            Self::Internal(status) => write!(f, "processor failed with {status}"),
+            Self::Rejected(status) => write!(f, "rejected with {status}"),
+            Self::Transport(e) => e.fmt(f),
+            Self::Lost => write!(f, "answer lost"),
+        }
+    }
+}
+
+impl std::error::Error for ProcessorError {}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, http::Version, routing::get};
+    use futures_util::FutureExt;
+
+    use super::*;
+
+    fn settings(http2: bool) -> HttpSettings {
+        HttpSettings {
+            http2,
+            pool_max_idle: 1,
+            connect_timeout: Duration::ZERO,
+        }
+    }
+
+    /// Answers with the HTTP version each request came in on.
+    async fn serve_version() -> String {
+        let app = Router::new().route(
+            "/",
+            get(|version: Version| async move { format!("{version:?}") }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        url
+    }
+
+    async fn version(settings: HttpSettings, url: &str) -> String {
+        settings
+            .build()
+            .get(url)
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn http2_is_spoken_without_negotiation() {
+        let url = serve_version().await;
+
+        assert_eq!(version(settings(true), &url).await, "HTTP/2.0");
+        assert_eq!(version(settings(false), &url).await, "HTTP/1.1");
+    }
+
+    #[tokio::test]
+    async fn submissions_past_the_inflight_cap_wait() {
+        let mut config = Config::from_lookup(|key| (key == "PEER_URL").then(String::new));
+        config.processor_max_inflight = 1;
+        let client = ProcessorClient::new(
+            "http://processor:8080".to_string(),
+            None,
+            0.0,
+            settings(false),
+            &config,
+        );
+
+        let permit = client.acquire().await;
+        assert!(client.acquire().now_or_never().is_none());
+
+        drop(permit);
+        assert!(client.acquire().now_or_never().is_some());
+    }
+}
@@ -0,0 +1,99 @@
+//! Versions of the internal peer API, served under `/internal/v{N}`. Instances ask each other's
+//! through the unversioned `/internal/version` at start, and again when a peer comes back up, then
+//! talk the highest version both speak. Instances from before versioning answer that with a 404
+//! and are taken to speak version 0, the bare `/internal` paths every instance still serves.
+//!
+//! A peer sharing no version with this one is sent nothing and left out of summaries, which are
+//! then partial, rather than merged from data it encodes differently.
+
+use std::{collections::BTreeMap, fmt, sync::Mutex};
+
+use serde::{Deserialize, Serialize};
+
+pub const PROTOCOL_VERSION: u32 = 1;
+// Oldest version this instance still speaks
+pub const MIN_PROTOCOL_VERSION: u32 = 0;
+
+/// The versions an instance speaks, answered by `/internal/version`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ProtocolVersion {
+    pub version: u32,
+    #[serde(rename = "minVersion")]
+    pub min_version: u32,
+}
+
+impl ProtocolVersion {
+    pub const OURS: Self = Self {
+        version: PROTOCOL_VERSION,
+        min_version: MIN_PROTOCOL_VERSION,
+    };
+
+    /// What instances from before versioning speak.
+    pub const UNVERSIONED: Self = Self {
+        version: 0,
+        min_version: 0,
+    };
+
+    /// The highest version both speak, none when they share none.
+    pub fn negotiate(&self, peer: &ProtocolVersion) -> Option<u32> {
+        let version = self.version.min(peer.version);
+
+        (version >= self.min_version && version >= peer.min_version).then_some(version)
+    }
+}
+
+/// `path`, relative to `/internal`, at `version`.
+pub fn internal_path(version: u32, path: &str) -> String {
+    match version {
+        0 => format!("/internal{path}"),
+        version => format!("/internal/v{version}{path}"),
+    }
+}
+
+/// What was negotiated with each peer, `None` for a peer sharing no version with this one.
+#[derive(Default)]
+pub struct PeerVersions {
+    peers: Mutex<BTreeMap<String, Option<u32>>>,
+}
+
+impl PeerVersions {
+    pub fn record(&self, peer: &str, negotiated: Option<u32>) {
+        self.peers
+            .lock()
+            .unwrap()
+            .insert(peer.to_string(), negotiated);
+    }
+
+    /// The version to talk to `peer`, ours until it was asked and none when they share none.
+    pub fn speak(&self, peer: &str) -> Option<u32> {
+        self.peers
+            .lock()
+            .unwrap()
+            .get(peer)
+            .copied()
+            .unwrap_or(Some(PROTOCOL_VERSION))
+    }
+
+    pub fn asked(&self, peer: &str) -> bool {
+        self.peers.lock().unwrap().contains_key(peer)
+    }
+
+    pub fn is_compatible(&self, peer: &str) -> bool {
+        self.speak(peer).is_some()
+    }
+
+    pub fn all(&self) -> BTreeMap<String, Option<u32>> {
+        self.peers.lock().unwrap().clone()
+    }
+}
+
+#[derive(Debug)]
+pub struct IncompatiblePeer;
+
+impl fmt::Display for IncompatiblePeer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "peer shares no protocol version with this instance")
+    }
+}
+
+impl std::error::Error for IncompatiblePeer {}
@@ -0,0 +1,174 @@
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    sync::atomic::{self, AtomicUsize},
+};
+
+use tokio::sync::{Notify, mpsc};
+
+use crate::Payment;
+
+/// How many payments the accept queue takes before producers hold off, movable at runtime up to
+/// the channel's own capacity. Soft, since producers woken together can go a few past it.
+pub struct SoftCap {
+    limit: AtomicUsize,
+    freed: Notify,
+}
+
+impl SoftCap {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit: AtomicUsize::new(limit),
+            freed: Notify::new(),
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit.load(atomic::Ordering::Relaxed)
+    }
+
+    pub fn set(&self, limit: usize) {
+        self.limit.store(limit, atomic::Ordering::Relaxed);
+        self.freed.notify_waiters();
+    }
+
+    pub fn is_full<T>(&self, queue: &mpsc::Sender<T>) -> bool {
+        queue.max_capacity() - queue.capacity() >= self.limit()
+    }
+
+    /// Waits until `queue` is below the cap.
+    pub async fn reserve<T>(&self, queue: &mpsc::Sender<T>) {
+        loop {
+            let freed = self.freed.notified();
+            tokio::pin!(freed);
+            freed.as_mut().enable();
+
+            if !self.is_full(queue) {
+                return;
+            }
+
+            freed.await;
+        }
+    }
+
+    /// Wakes producers waiting in `reserve`, once a payment left the queue.
+    pub fn release(&self) {
+        self.freed.notify_waiters();
+    }
+}
+
+/// Pending payments ordered by amount, largest first, and by arrival among equal amounts.
+#[derive(Default)]
+pub struct PriorityQueue {
+    heap: BinaryHeap<Queued>,
+    // Arrival counter breaking ties between equal amounts
+    next_seq: u64,
+}
+
+struct Queued {
+    amount: f64,
+    seq: u64,
+    payment: Payment,
+    retries: u64,
+}
+
+impl PriorityQueue {
+    pub fn push(&mut self, payment: Payment, retries: u64) {
+        let queued = Queued {
+            amount: payment.amount,
+            seq: self.next_seq,
+            payment,
+            retries,
+        };
+
+        self.next_seq += 1;
+        self.heap.push(queued);
+    }
+
+    pub fn pop(&mut self) -> Option<(Payment, u64)> {
+        self.heap
+            .pop()
+            .map(|queued| (queued.payment, queued.retries))
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+}
+
+impl Ord for Queued {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // The heap pops the greatest, so an earlier arrival must compare greater
+        self.amount
+            .total_cmp(&other.amount)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for Queued {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Queued {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Queued {}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::trace::TraceContext;
+
+    fn payment(n: u32, amount: f64) -> Payment {
+        Payment {
+            correlation_id: format!("4a7901b8-7d26-4d9d-aa19-{n:012}").parse().unwrap(),
+            amount,
+            requested_at: Utc::now(),
+            trace: TraceContext::generate(),
+            tenant: None,
+        }
+    }
+
+    /// What is left in `queue` in pop order, as `(n, retries)` of the payments `payment` made.
+    fn drain(queue: &mut PriorityQueue) -> Vec<(u32, u64)> {
+        std::iter::from_fn(|| queue.pop())
+            .map(|(p, retries)| (p.correlation_id.to_string()[24..].parse().unwrap(), retries))
+            .collect()
+    }
+
+    #[test]
+    fn largest_amounts_go_first_and_equal_ones_in_arrival_order() {
+        let mut queue = PriorityQueue::default();
+
+        for (n, amount) in [(1, 10.0), (2, 99.9), (3, 10.0), (4, 0.01), (5, 99.9)] {
+            queue.push(payment(n, amount), n.into());
+        }
+
+        assert_eq!(queue.len(), 5);
+        assert_eq!(drain(&mut queue), [(2, 2), (5, 5), (1, 1), (3, 3), (4, 4)]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn payments_pushed_back_queue_behind_equal_ones() {
+        let mut queue = PriorityQueue::default();
+        queue.push(payment(1, 10.0), 0);
+        queue.push(payment(2, 10.0), 0);
+
+        let (first, retries) = queue.pop().unwrap();
+        queue.push(first, retries + 1);
+
+        assert_eq!(drain(&mut queue), [(2, 0), (1, 1)]);
+    }
+}
@@ -0,0 +1,282 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+// Per-IP buckets are pruned once the map grows past this many sources
+const MAX_TRACKED_SOURCES: usize = 10_000;
+
+pub struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    /// Creates a full bucket refilled with `rate` tokens per second up to `burst` tokens.
+    pub fn new(rate: f64, burst: f64) -> Self {
+        Self {
+            rate,
+            burst,
+            tokens: burst,
+            last: Instant::now(),
+        }
+    }
+
+    pub fn try_acquire(&mut self) -> bool {
+        self.refill();
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Takes a token even when there is none left, returning how long until the bucket is out of
+    /// debt again so callers can wait their turn instead of polling.
+    pub fn reserve(&mut self) -> Duration {
+        self.refill();
+        self.tokens -= 1.0;
+
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Changes the refill rate, keeping the tokens earned at the old one.
+    pub fn set_rate(&mut self, rate: f64) {
+        self.refill();
+        self.rate = rate;
+    }
+
+    /// Gives back a token taken by `try_acquire` for a request turned away after all.
+    pub fn refund(&mut self) {
+        self.refill();
+        self.tokens = (self.tokens + 1.0).min(self.burst);
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.last = now;
+    }
+
+    fn is_full(&mut self) -> bool {
+        self.refill();
+        self.tokens >= self.burst
+    }
+}
+
+/// Token-bucket limiter applied to the whole instance and, optionally, to each source IP.
+pub struct RateLimiter {
+    global: Option<Mutex<TokenBucket>>,
+    per_source: Option<SourceBuckets>,
+    trust_forwarded: bool,
+}
+
+struct SourceBuckets {
+    rate: f64,
+    burst: f64,
+    buckets: Mutex<HashMap<IpAddr, TokenBucket>>,
+}
+
+impl RateLimiter {
+    /// A rate of 0 disables the corresponding limit.
+    pub fn new(rate: f64, burst: f64, per_source_rate: f64, per_source_burst: f64) -> Self {
+        Self {
+            global: (rate > 0.0).then(|| Mutex::new(TokenBucket::new(rate, burst))),
+            per_source: (per_source_rate > 0.0).then(|| SourceBuckets {
+                rate: per_source_rate,
+                burst: per_source_burst,
+                buckets: Mutex::new(HashMap::new()),
+            }),
+            trust_forwarded: false,
+        }
+    }
+
+    /// Takes the source from the last `X-Forwarded-For` address, the one the proxy in front
+    /// appended, rather than the peer address, which is then the proxy's.
+    pub fn with_trusted_forwarding(mut self, trust_forwarded: bool) -> Self {
+        self.trust_forwarded = trust_forwarded;
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.global.is_some() || self.per_source.is_some()
+    }
+
+    /// Takes a token from the source's bucket and one from the global bucket, giving the
+    /// source's back when the global one is empty so an overloaded instance doesn't use up the
+    /// allowance of every client it turns away.
+    pub fn check(&self, source: Option<IpAddr>) -> bool {
+        let source = match (&self.per_source, source) {
+            (Some(per_source), Some(ip)) => {
+                if !per_source.try_acquire(ip) {
+                    return false;
+                }
+
+                Some((per_source, ip))
+            }
+            _ => None,
+        };
+
+        let Some(global) = &self.global else {
+            return true;
+        };
+
+        if global.lock().unwrap().try_acquire() {
+            return true;
+        }
+
+        if let Some((per_source, ip)) = source {
+            per_source.refund(ip);
+        }
+
+        false
+    }
+
+    /// Source of `req`: the peer address, or the last `X-Forwarded-For` address with
+    /// `with_trusted_forwarding`. Earlier ones are whatever the client sent, so never trusted.
+    fn source(&self, req: &Request) -> Option<IpAddr> {
+        let forwarded = self
+            .trust_forwarded
+            .then(|| req.headers().get_all("x-forwarded-for").iter().next_back())
+            .flatten()
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.rsplit(',').next())
+            .and_then(|v| v.trim().parse().ok());
+
+        forwarded.or_else(|| {
+            req.extensions()
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(addr)| addr.ip())
+        })
+    }
+}
+
+impl SourceBuckets {
+    fn try_acquire(&self, ip: IpAddr) -> bool {
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() >= MAX_TRACKED_SOURCES {
+            buckets.retain(|_, bucket| !bucket.is_full());
+        }
+
+        buckets
+            .entry(ip)
+            .or_insert_with(|| TokenBucket::new(self.rate, self.burst))
+            .try_acquire()
+    }
+
+    fn refund(&self, ip: IpAddr) {
+        if let Some(bucket) = self.buckets.lock().unwrap().get_mut(&ip) {
+            bucket.refund();
+        }
+    }
+}
+
+/// Rejects requests with 429 once the limiter runs out of tokens, see `RateLimiter::source` for
+/// how requests are told apart.
+pub async fn rate_limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let source = limiter.source(&req);
+
+    if !limiter.check(source) {
+        return StatusCode::TOO_MANY_REQUESTS.into_response();
+    }
+
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+
+    use super::*;
+
+    const CLIENT: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(10, 0, 0, 1));
+    const OTHER: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(10, 0, 0, 2));
+
+    // Rates low enough that no token comes back while a test runs
+    const SLOW: f64 = 0.001;
+
+    fn request(forwarded: &[&str]) -> Request {
+        let mut req = Request::new(Body::empty());
+
+        for value in forwarded {
+            req.headers_mut()
+                .append("x-forwarded-for", value.parse().unwrap());
+        }
+
+        req.extensions_mut()
+            .insert(ConnectInfo(SocketAddr::new(CLIENT, 40000)));
+        req
+    }
+
+    #[test]
+    fn sources_are_limited_apart() {
+        let limiter = RateLimiter::new(0.0, 0.0, SLOW, 2.0);
+
+        assert!(limiter.check(Some(CLIENT)));
+        assert!(limiter.check(Some(CLIENT)));
+        assert!(!limiter.check(Some(CLIENT)));
+        assert!(limiter.check(Some(OTHER)));
+    }
+
+    #[test]
+    fn global_rejections_give_the_source_token_back() {
+        let limiter = RateLimiter::new(SLOW, 1.0, SLOW, 2.0);
+
+        assert!(limiter.check(Some(OTHER)));
+
+        // The global bucket is empty, so these never count against the client
+        for _ in 0..5 {
+            assert!(!limiter.check(Some(CLIENT)));
+        }
+
+        let buckets = limiter.per_source.as_ref().unwrap().buckets.lock().unwrap();
+        assert!(buckets[&CLIENT].tokens >= 2.0 - 1e-6);
+    }
+
+    #[test]
+    fn forwarded_addresses_are_ignored_unless_trusted() {
+        let limiter = RateLimiter::new(0.0, 0.0, SLOW, 1.0);
+
+        assert_eq!(limiter.source(&request(&["10.9.9.9"])), Some(CLIENT));
+    }
+
+    #[test]
+    fn only_the_last_forwarded_address_is_trusted() {
+        let limiter = RateLimiter::new(0.0, 0.0, SLOW, 1.0).with_trusted_forwarding(true);
+        let spoofed = request(&["10.9.9.9, 10.0.0.2"]);
+        let split = request(&["10.9.9.9", "10.0.0.2"]);
+
+        assert_eq!(limiter.source(&spoofed), Some(OTHER));
+        assert_eq!(limiter.source(&split), Some(OTHER));
+        assert_eq!(limiter.source(&request(&[])), Some(CLIENT));
+        assert_eq!(limiter.source(&request(&["not an ip"])), Some(CLIENT));
+    }
+}
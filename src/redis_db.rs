@@ -0,0 +1,109 @@
+use async_trait::async_trait;
+use redis::{Script, aio::ConnectionManager};
+
+use crate::{
+    Processor,
+    backend::{BackendError, Storage},
+    correlation::CorrelationId,
+};
+
+// Sums the per-timestamp aggregates of every timestamp within the score range
+const SUMMARY_SCRIPT: &str = r#"
+local timestamps = redis.call('ZRANGEBYSCORE', KEYS[1], ARGV[1], ARGV[2])
+local count, amount = 0, 0
+for _, ts in ipairs(timestamps) do
+    count = count + tonumber(redis.call('HGET', KEYS[2], ts))
+    amount = amount + tonumber(redis.call('HGET', KEYS[3], ts))
+end
+return {count, amount}
+"#;
+
+/// Stores aggregates in Redis, shared by every instance. Each processor gets a sorted set of
+/// timestamps (scored by the timestamp itself) plus two hashes holding the request count and
+/// total amount per timestamp, mirroring the in-process `Db` layout.
+#[derive(Clone)]
+pub struct RedisDb {
+    conn: ConnectionManager,
+    prefix: String,
+    summary: Script,
+}
+
+impl RedisDb {
+    pub async fn connect(url: &str, prefix: String) -> redis::RedisResult<Self> {
+        let client = redis::Client::open(url)?;
+        let conn = ConnectionManager::new(client).await?;
+
+        Ok(Self {
+            conn,
+            prefix,
+            summary: Script::new(SUMMARY_SCRIPT),
+        })
+    }
+
+    fn keys(&self, processor: Processor) -> [String; 3] {
+        let processor = match processor {
+            Processor::Default => "default",
+            Processor::Fallback => "fallback",
+        };
+
+        [
+            format!("{}:{processor}:timestamps", self.prefix),
+            format!("{}:{processor}:count", self.prefix),
+            format!("{}:{processor}:amount", self.prefix),
+        ]
+    }
+}
+
+#[async_trait]
+impl Storage for RedisDb {
+    async fn record(
+        &self,
+        processor: Processor,
+        _correlation_id: &CorrelationId,
+        timestamp: i64,
+        amount: u64,
+    ) -> Result<(), BackendError> {
+        let [timestamps, count, total] = self.keys(processor);
+        let mut conn = self.conn.clone();
+
+        redis::pipe()
+            .atomic()
+            .zadd(&timestamps, timestamp, timestamp)
+            .ignore()
+            .hincr(&count, timestamp, 1)
+            .ignore()
+            .hincr(&total, timestamp, amount)
+            .ignore()
+            .exec_async(&mut conn)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn summarize(
+        &self,
+        processor: Processor,
+        from: Option<i64>,
+        to: Option<i64>,
+    ) -> Result<(u64, u64), BackendError> {
+        let [timestamps, count, total] = self.keys(processor);
+        let mut conn = self.conn.clone();
+        let from = from.map_or("-inf".to_string(), |ts| ts.to_string());
+        let to = to.map_or("+inf".to_string(), |ts| ts.to_string());
+        let (count, amount): (u64, u64) = self
+            .summary
+            .key(timestamps)
+            .key(count)
+            .key(total)
+            .arg(from)
+            .arg(to)
+            .invoke_async(&mut conn)
+            .await?;
+
+        Ok((count, amount))
+    }
+
+    fn is_shared(&self) -> bool {
+        true
+    }
+}
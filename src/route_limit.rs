@@ -0,0 +1,61 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tokio::sync::Semaphore;
+
+/// Caps the requests one route runs at once, so a burst on it waits for its own slots rather than
+/// taking time from every other route. Past the cap, a request waits for a slot for up to
+/// `queue_timeout` and is answered 503 after that.
+///
+/// Only the wait is timed out. A request that got its slot runs to the end, a payment cut short
+/// between the processor accepting it and it being recorded would be lost.
+pub struct RouteLimit {
+    // None without a cap
+    slots: Option<Semaphore>,
+    // 0 waits as long as it takes
+    queue_timeout: Duration,
+}
+
+impl RouteLimit {
+    /// At most `max_concurrent` requests at once, 0 meaning unlimited.
+    pub fn new(max_concurrent: usize, queue_timeout: Duration) -> Arc<Self> {
+        Arc::new(Self {
+            slots: (max_concurrent > 0).then(|| Semaphore::new(max_concurrent)),
+            queue_timeout,
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.slots.is_some()
+    }
+}
+
+pub async fn route_limit(
+    State(limit): State<Arc<RouteLimit>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let Some(slots) = &limit.slots else {
+        return next.run(req).await;
+    };
+
+    let permit = if limit.queue_timeout.is_zero() {
+        slots.acquire().await.ok()
+    } else {
+        tokio::time::timeout(limit.queue_timeout, slots.acquire())
+            .await
+            .ok()
+            .and_then(Result::ok)
+    };
+
+    let Some(_permit) = permit else {
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    };
+
+    next.run(req).await
+}
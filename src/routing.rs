@@ -0,0 +1,236 @@
+use std::{
+    fmt,
+    hash::{BuildHasher, Hasher, RandomState},
+    str::FromStr,
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Processor,
+    health::{CircuitBreaker, SuccessRate},
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Strategy {
+    // Start on default, retries are split by each processor's recent success rate
+    #[default]
+    DefaultFirst,
+    // Start on default, then alternate processors with every retry
+    RetryParity,
+    // Default unless its circuit breaker is open
+    #[serde(alias = "adaptive")]
+    HealthBased,
+    // Whichever processor has been answering faster lately
+    LatencyEwma,
+    FallbackOnly,
+    RoundRobin,
+}
+
+impl Strategy {
+    pub fn build(self) -> Box<dyn RoutingStrategy> {
+        match self {
+            Self::DefaultFirst => Box::new(DefaultFirst),
+            Self::RetryParity => Box::new(RetryParity),
+            Self::HealthBased => Box::new(HealthBased),
+            Self::LatencyEwma => Box::new(LatencyEwma::default()),
+            Self::FallbackOnly => Box::new(FallbackOnly),
+            Self::RoundRobin => Box::new(RoundRobin::default()),
+        }
+    }
+}
+
+impl FromStr for Strategy {
+    type Err = UnknownStrategy;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default-first" => Ok(Self::DefaultFirst),
+            "retry-parity" => Ok(Self::RetryParity),
+            "health-based" | "adaptive" => Ok(Self::HealthBased),
+            "latency-ewma" => Ok(Self::LatencyEwma),
+            "fallback-only" => Ok(Self::FallbackOnly),
+            "round-robin" => Ok(Self::RoundRobin),
+            _ => Err(UnknownStrategy),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownStrategy;
+
+impl fmt::Display for UnknownStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown routing strategy")
+    }
+}
+
+impl std::error::Error for UnknownStrategy {}
+
+#[derive(Deserialize, Serialize)]
+pub struct RoutingUpdate {
+    pub strategy: Strategy,
+}
+
+/// Picks the processor for each submission. `feedback` is told how every submission went, for
+/// strategies that learn from it.
+pub trait RoutingStrategy: Send + Sync {
+    fn choose(&self, ctx: &RoutingContext) -> Processor;
+
+    fn feedback(&self, _processor: Processor, _outcome: Outcome) {}
+}
+
+/// What a strategy gets to see of the payment and of both processors.
+pub struct RoutingContext<'a> {
+    // Times the payment already failed
+    pub retries: u64,
+    pub default: ProcessorHealth<'a>,
+    pub fallback: ProcessorHealth<'a>,
+}
+
+pub struct ProcessorHealth<'a> {
+    pub breaker: &'a CircuitBreaker,
+    pub success: &'a SuccessRate,
+}
+
+/// How a submission went. Rejected payments aren't reported, they say nothing about the
+/// processor.
+#[derive(Clone, Copy, Debug)]
+pub enum Outcome {
+    Accepted { latency: Duration },
+    // Server errors, rate limiting, timeouts and transport errors
+    Failed,
+}
+
+pub struct DefaultFirst;
+
+impl RoutingStrategy for DefaultFirst {
+    /// Sends a retry to the default with the odds of it succeeding there, unless the fallback is
+    /// failing too. With the default 90% healthy and the fallback 100%, 90% of retries stay on
+    /// the default.
+    fn choose(&self, ctx: &RoutingContext) -> Processor {
+        if ctx.retries == 0 {
+            return Processor::Default;
+        }
+
+        let default = ctx.default.success.rate();
+        let fallback = ctx.fallback.success.rate();
+        // Either the default succeeds, or it fails and the fallback would have succeeded
+        let weight = default + (1.0 - default) * fallback;
+        // Every `RandomState` is seeded differently, plenty for picking a processor
+        let roll = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+        let threshold = if weight == 0.0 { 0.5 } else { default / weight };
+
+        if roll < threshold {
+            Processor::Default
+        } else {
+            Processor::Fallback
+        }
+    }
+}
+
+pub struct RetryParity;
+
+impl RoutingStrategy for RetryParity {
+    fn choose(&self, ctx: &RoutingContext) -> Processor {
+        if ctx.retries.is_multiple_of(2) {
+            Processor::Default
+        } else {
+            Processor::Fallback
+        }
+    }
+}
+
+pub struct HealthBased;
+
+impl RoutingStrategy for HealthBased {
+    fn choose(&self, ctx: &RoutingContext) -> Processor {
+        if !ctx.default.breaker.is_open() || ctx.fallback.breaker.is_open() {
+            Processor::Default
+        } else {
+            Processor::Fallback
+        }
+    }
+}
+
+// Weight of the newest latency in the average
+const EWMA_WEIGHT: f64 = 0.2;
+// Latency a failed submission counts as
+const FAILURE_PENALTY: Duration = Duration::from_secs(1);
+
+/// Keeps an exponentially weighted average of each processor's latency and picks the lower one,
+/// the default on a tie. A processor not measured yet counts as instant so it gets tried.
+#[derive(Default)]
+pub struct LatencyEwma {
+    // In seconds, indexed by `slot`
+    averages: Mutex<[Option<f64>; 2]>,
+}
+
+impl LatencyEwma {
+    pub fn average(&self, processor: Processor) -> Option<Duration> {
+        self.averages.lock().unwrap()[slot(processor)].map(Duration::from_secs_f64)
+    }
+}
+
+impl RoutingStrategy for LatencyEwma {
+    fn choose(&self, _ctx: &RoutingContext) -> Processor {
+        let [default, fallback] = *self.averages.lock().unwrap();
+
+        if fallback.unwrap_or(0.0) < default.unwrap_or(0.0) {
+            Processor::Fallback
+        } else {
+            Processor::Default
+        }
+    }
+
+    fn feedback(&self, processor: Processor, outcome: Outcome) {
+        let latency = match outcome {
+            Outcome::Accepted { latency } => latency,
+            Outcome::Failed => FAILURE_PENALTY,
+        }
+        .as_secs_f64();
+
+        let mut averages = self.averages.lock().unwrap();
+        let average = &mut averages[slot(processor)];
+        *average = Some(match *average {
+            Some(average) => average + EWMA_WEIGHT * (latency - average),
+            None => latency,
+        });
+    }
+}
+
+pub struct FallbackOnly;
+
+impl RoutingStrategy for FallbackOnly {
+    fn choose(&self, _ctx: &RoutingContext) -> Processor {
+        Processor::Fallback
+    }
+}
+
+#[derive(Default)]
+pub struct RoundRobin {
+    next: AtomicUsize,
+}
+
+impl RoutingStrategy for RoundRobin {
+    fn choose(&self, _ctx: &RoutingContext) -> Processor {
+        if self.next.fetch_add(1, Ordering::Relaxed).is_multiple_of(2) {
+            Processor::Default
+        } else {
+            Processor::Fallback
+        }
+    }
+}
+
+fn slot(processor: Processor) -> usize {
+    match processor {
+        Processor::Default => 0,
+        Processor::Fallback => 1,
+    }
+}
@@ -0,0 +1,124 @@
+use std::{
+    fs, io,
+    path::Path,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Payment, Processor, correlation::CorrelationId, tenant::TenantId, trace::TraceContext,
+};
+
+/// What an instance leaves behind at shutdown with `SHUTDOWN_SNAPSHOT_PATH`, as one MessagePack
+/// file: the in-process Dbs, the ids their dedup windows remember and the payments still queued.
+/// The next start loads it and removes the file, so a crash after that never loads it twice.
+///
+/// Cheaper than the journal, which is written on every submission, but only as good as the last
+/// clean shutdown: payments in flight when it is written, or waiting out a retry, are not in it.
+#[derive(Default, Deserialize, Serialize)]
+pub struct ShutdownSnapshot {
+    // `StateSnapshot` bytes, empty unless the Dbs are in-process
+    pub db: Vec<u8>,
+    // Remembered ids of each shard, oldest first
+    pub dedup: Vec<Vec<(Processor, CorrelationId, i64)>>,
+    pub pending: Vec<PendingPayment>,
+}
+
+/// A queued payment, as it goes back into the accept queue.
+#[derive(Deserialize, Serialize)]
+pub struct PendingPayment {
+    pub correlation_id: CorrelationId,
+    pub amount: f64,
+    // Micro seconds since the epoch
+    pub requested_at: i64,
+    pub tenant: Option<TenantId>,
+    pub retries: u64,
+}
+
+impl PendingPayment {
+    pub fn new(p: Payment, retries: u64) -> Self {
+        Self {
+            correlation_id: p.correlation_id,
+            amount: p.amount,
+            requested_at: p.requested_at.timestamp_micros(),
+            tenant: p.tenant,
+            retries,
+        }
+    }
+
+    pub fn into_payment(self) -> (Payment, u64) {
+        let p = Payment {
+            correlation_id: self.correlation_id,
+            amount: self.amount,
+            requested_at: DateTime::from_timestamp_micros(self.requested_at).unwrap_or_default(),
+            trace: TraceContext::generate(),
+            tenant: self.tenant,
+        };
+
+        (p, self.retries)
+    }
+}
+
+impl ShutdownSnapshot {
+    /// Writes next to `path` first and renames over it, a crash halfway leaves no torn file.
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        let bytes = rmp_serde::to_vec_named(self).map_err(io::Error::other)?;
+
+        fs::write(&tmp, bytes)?;
+        fs::rename(tmp, path)
+    }
+
+    /// Loads and removes the snapshot at `path`, none when there is no file.
+    pub fn take(path: impl AsRef<Path>) -> io::Result<Option<Self>> {
+        let path = path.as_ref();
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let snapshot = rmp_serde::from_slice(&bytes).map_err(io::Error::other)?;
+
+        fs::remove_file(path)?;
+
+        Ok(Some(snapshot))
+    }
+}
+
+/// Payments the dispatcher holds back rather than submitting, once `start` was called at
+/// shutdown, for them to go into the snapshot.
+#[derive(Default)]
+pub struct Parking {
+    started: AtomicBool,
+    parked: Mutex<Vec<PendingPayment>>,
+}
+
+impl Parking {
+    pub fn start(&self) {
+        self.started.store(true, Ordering::Release);
+    }
+
+    /// Keeps `p` once parking started, handing it back to be submitted otherwise.
+    pub fn park(&self, p: Payment, retries: u64) -> Option<(Payment, u64)> {
+        if !self.started.load(Ordering::Acquire) {
+            return Some((p, retries));
+        }
+
+        self.parked
+            .lock()
+            .unwrap()
+            .push(PendingPayment::new(p, retries));
+
+        None
+    }
+
+    pub fn take(&self) -> Vec<PendingPayment> {
+        std::mem::take(&mut self.parked.lock().unwrap())
+    }
+}
@@ -0,0 +1,165 @@
+//! Stand-ins for the payment processors, served from within the binary with
+//! `--simulate-processors` so an instance runs with nothing else around. Each answers
+//! `POST /payments` after a latency drawn between `SIMULATED_LATENCY_MIN_MS` and
+//! `SIMULATED_LATENCY_MAX_MS`, failing with a 500 at its own failure rate, and keeps what it
+//! processed for `/payments/{id}` and `/admin/payments-summary`.
+
+use std::{
+    collections::HashMap,
+    hash::{BuildHasher, Hasher, RandomState},
+    io,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{get, post},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpListener;
+
+use crate::{Summary, timestamp};
+
+/// How one simulated processor behaves.
+#[derive(Clone, Copy, Debug)]
+pub struct Simulation {
+    pub latency_min: Duration,
+    pub latency_max: Duration,
+    // Share of payments failed, between 0 and 1
+    pub failure_rate: f64,
+}
+
+#[derive(Deserialize)]
+struct SimulatedPayment {
+    #[serde(rename = "correlationId")]
+    correlation_id: String,
+    amount: f64,
+    #[serde(rename = "requestedAt")]
+    requested_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+struct Health {
+    failing: bool,
+    #[serde(rename = "minResponseTime")]
+    min_response_time: u64,
+}
+
+#[derive(Deserialize)]
+struct SummaryParams {
+    #[serde(default, deserialize_with = "timestamp::deserialize")]
+    from: Option<DateTime<Utc>>,
+    #[serde(default, deserialize_with = "timestamp::deserialize")]
+    to: Option<DateTime<Utc>>,
+}
+
+struct Processed {
+    simulation: Simulation,
+    // Amount and requested time of every payment processed, by correlation id
+    payments: Mutex<HashMap<String, (f64, DateTime<Utc>)>>,
+}
+
+/// Serves a simulated processor on a free port of the loopback interface, returning its URL.
+pub async fn spawn(simulation: Simulation) -> io::Result<String> {
+    let processed = Arc::new(Processed {
+        simulation,
+        payments: Mutex::default(),
+    });
+    let app = Router::new()
+        .route("/payments", post(payment))
+        .route("/payments/service-health", get(service_health))
+        .route("/payments/{correlation_id}", get(lookup))
+        .route("/admin/payments-summary", get(summary))
+        .with_state(processed);
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}", listener.local_addr()?);
+
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    Ok(url)
+}
+
+async fn payment(
+    State(processed): State<Arc<Processed>>,
+    Json(payment): Json<SimulatedPayment>,
+) -> StatusCode {
+    let Simulation {
+        latency_min,
+        latency_max,
+        failure_rate,
+    } = processed.simulation;
+    let spread = latency_max.saturating_sub(latency_min);
+
+    tokio::time::sleep(latency_min + spread.mul_f64(roll())).await;
+
+    if roll() < failure_rate {
+        return StatusCode::INTERNAL_SERVER_ERROR;
+    }
+
+    let mut payments = processed.payments.lock().unwrap();
+
+    if payments.contains_key(&payment.correlation_id) {
+        return StatusCode::UNPROCESSABLE_ENTITY;
+    }
+
+    payments.insert(
+        payment.correlation_id,
+        (payment.amount, payment.requested_at),
+    );
+
+    StatusCode::OK
+}
+
+async fn service_health(State(processed): State<Arc<Processed>>) -> Json<Health> {
+    let simulation = processed.simulation;
+
+    Json(Health {
+        failing: simulation.failure_rate >= 1.0,
+        min_response_time: simulation.latency_min.as_millis() as u64,
+    })
+}
+
+async fn lookup(
+    State(processed): State<Arc<Processed>>,
+    Path(correlation_id): Path<String>,
+) -> StatusCode {
+    match processed
+        .payments
+        .lock()
+        .unwrap()
+        .contains_key(&correlation_id)
+    {
+        true => StatusCode::OK,
+        false => StatusCode::NOT_FOUND,
+    }
+}
+
+async fn summary(
+    State(processed): State<Arc<Processed>>,
+    Query(params): Query<SummaryParams>,
+) -> Json<Summary> {
+    let payments = processed.payments.lock().unwrap();
+    let mut summary = Summary::default();
+
+    for (amount, requested_at) in payments.values() {
+        if params.from.is_some_and(|from| *requested_at < from)
+            || params.to.is_some_and(|to| *requested_at > to)
+        {
+            continue;
+        }
+
+        summary.total_requests += 1;
+        summary.total_amount += amount;
+    }
+
+    Json(summary)
+}
+
+/// Between 0 and 1. Every `RandomState` is seeded differently, plenty for a simulation.
+fn roll() -> f64 {
+    RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64
+}
@@ -0,0 +1,182 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Seek, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use chrono::DateTime;
+use tokio::sync::{Notify, mpsc};
+
+use crate::{Payment, queue::SoftCap, trace::TraceContext};
+
+/// Overflow for the accept queue. Once the channel is full, or past its soft cap, payments are
+/// appended to a file instead, one per line as `<retries> <requested_at> <amount> <tenant>
+/// <correlation_id>`, and `drain` moves them back into the channel as it frees up. Payments keep
+/// going to the file until it is drained so they are still dispatched in arrival order, and the
+/// file is emptied every time it is.
+///
+/// Payments spilled but not drained when the process stops are picked up on the next `open`.
+pub struct Spill {
+    tx: mpsc::Sender<(Payment, u64)>,
+    // The channel counts as full once past this
+    cap: Arc<SoftCap>,
+    state: Mutex<State>,
+    reader: Mutex<BufReader<File>>,
+    written: Notify,
+}
+
+struct State {
+    file: BufWriter<File>,
+    // Lines written and not yet drained, every one of them flushed
+    pending: usize,
+}
+
+impl Spill {
+    pub fn open(
+        path: impl AsRef<Path>,
+        tx: mpsc::Sender<(Payment, u64)>,
+        cap: Arc<SoftCap>,
+    ) -> io::Result<Self> {
+        let path = path.as_ref();
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let reader = BufReader::new(File::open(path)?);
+        let pending = BufReader::new(File::open(path)?).lines().count();
+
+        if pending > 0 {
+            println!("Replaying {pending} spilled payments");
+        }
+
+        Ok(Self {
+            tx,
+            cap,
+            state: Mutex::new(State {
+                file: BufWriter::new(file),
+                pending,
+            }),
+            reader: Mutex::new(reader),
+            written: Notify::new(),
+        })
+    }
+
+    /// Queues the payment without waiting for room in the channel. Only waits when the file
+    /// can't be written, since dropping the payment is not an option.
+    pub async fn send(&self, p: Payment, retries: u64) {
+        let p = {
+            let mut state = self.state.lock().unwrap();
+
+            let p = if state.pending == 0 && !self.cap.is_full(&self.tx) {
+                match self.tx.try_send((p, retries)) {
+                    Ok(()) => return,
+                    Err(mpsc::error::TrySendError::Full((p, _))) => p,
+                    Err(mpsc::error::TrySendError::Closed(_)) => panic!("Payment queue closed"),
+                }
+            } else {
+                p
+            };
+
+            match write_line(&mut state.file, &p, retries) {
+                Ok(()) => {
+                    if state.pending == 0 {
+                        eprintln!("Payment queue full, spilling to disk");
+                    }
+
+                    state.pending += 1;
+                    self.written.notify_one();
+                    return;
+                }
+                Err(e) => {
+                    eprintln!("Failed to spill {}: {e}", p.correlation_id);
+                    p
+                }
+            }
+        };
+
+        self.tx.send((p, retries)).await.unwrap();
+    }
+
+    /// Moves spilled payments back into the channel, waiting for room for each one.
+    pub async fn drain(self: Arc<Self>) {
+        loop {
+            let written = self.written.notified();
+
+            if self.state.lock().unwrap().pending == 0 {
+                written.await;
+                continue;
+            }
+
+            let line = match read_line(&mut self.reader.lock().unwrap()) {
+                Ok(line) => line,
+                Err(e) => {
+                    eprintln!("Failed to read spill file: {e}");
+                    return;
+                }
+            };
+
+            if let Some(entry) = parse_line(&line) {
+                self.cap.reserve(&self.tx).await;
+                self.tx.send(entry).await.unwrap();
+            } else {
+                eprintln!("Skipping malformed spill line: {}", line.trim_end());
+            }
+
+            let mut state = self.state.lock().unwrap();
+            state.pending -= 1;
+
+            if state.pending == 0 {
+                // Appends go to the end of the file, wherever that now is
+                if let Err(e) = state.file.get_ref().set_len(0) {
+                    eprintln!("Failed to truncate spill file: {e}");
+                }
+
+                self.reader.lock().unwrap().rewind().unwrap();
+                println!("Spilled payments drained");
+            }
+        }
+    }
+}
+
+fn write_line(file: &mut BufWriter<File>, p: &Payment, retries: u64) -> io::Result<()> {
+    writeln!(
+        file,
+        "{retries} {} {} {} {}",
+        p.requested_at.timestamp_micros(),
+        p.amount,
+        p.tenant.as_ref().map_or("", |tenant| tenant.as_str()),
+        p.correlation_id
+    )?;
+
+    file.flush()
+}
+
+fn read_line(reader: &mut BufReader<File>) -> io::Result<String> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+
+    Ok(line)
+}
+
+fn parse_line(line: &str) -> Option<(Payment, u64)> {
+    let mut fields = line.trim_end().splitn(5, ' ');
+    let retries = fields.next()?.parse().ok()?;
+    let requested_at = DateTime::from_timestamp_micros(fields.next()?.parse().ok()?)?;
+    let amount = fields.next()?.parse().ok()?;
+    // Left empty for untagged payments
+    let tenant = match fields.next()? {
+        "" => None,
+        tenant => Some(tenant.parse().ok()?),
+    };
+    let correlation_id = fields.next()?.parse().ok()?;
+
+    Some((
+        Payment {
+            correlation_id,
+            amount,
+            requested_at,
+            // The trace the payment came in with isn't kept across the file
+            trace: TraceContext::generate(),
+            tenant,
+        },
+        retries,
+    ))
+}
@@ -0,0 +1,158 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::{Processor, correlation::CorrelationId};
+
+// Finished payments are pruned once the map grows past this many entries
+const MAX_TRACKED_PAYMENTS: usize = 100_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PaymentState {
+    Queued,
+    // Sent to a processor, waiting on its answer
+    Submitted,
+    Confirmed,
+    // Rejected by the processor
+    Failed,
+    // Given up on after too many retries
+    DeadLettered,
+}
+
+impl PaymentState {
+    fn is_final(self) -> bool {
+        matches!(self, Self::Confirmed | Self::Failed | Self::DeadLettered)
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct PaymentStatus {
+    pub state: PaymentState,
+    pub attempts: u64,
+    // Processor of the latest attempt
+    pub processor: Option<Processor>,
+    #[serde(rename = "requestedAt")]
+    pub requested_at: DateTime<Utc>,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Where each payment handled by this instance is in its lifecycle, by correlationId.
+#[derive(Default)]
+pub struct StatusMap {
+    payments: Mutex<HashMap<CorrelationId, PaymentStatus>>,
+}
+
+impl StatusMap {
+    pub fn queued(&self, correlation_id: &CorrelationId, requested_at: DateTime<Utc>) {
+        let mut payments = self.payments.lock().unwrap();
+
+        if payments.len() >= MAX_TRACKED_PAYMENTS {
+            payments.retain(|_, status| !status.state.is_final());
+        }
+
+        payments.insert(
+            correlation_id.clone(),
+            PaymentStatus {
+                state: PaymentState::Queued,
+                attempts: 0,
+                processor: None,
+                requested_at,
+                updated_at: Utc::now(),
+            },
+        );
+    }
+
+    pub fn submitted(&self, correlation_id: &CorrelationId, processor: Processor) {
+        self.update(correlation_id, |status| {
+            status.state = PaymentState::Submitted;
+            status.attempts += 1;
+            status.processor = Some(processor);
+        });
+    }
+
+    pub fn confirmed(&self, correlation_id: &CorrelationId) {
+        self.update(correlation_id, |status| {
+            status.state = PaymentState::Confirmed
+        });
+    }
+
+    pub fn failed(&self, correlation_id: &CorrelationId) {
+        self.update(correlation_id, |status| status.state = PaymentState::Failed);
+    }
+
+    pub fn dead_lettered(&self, correlation_id: &CorrelationId) {
+        self.update(correlation_id, |status| {
+            status.state = PaymentState::DeadLettered
+        });
+    }
+
+    pub fn get(&self, correlation_id: &CorrelationId) -> Option<PaymentStatus> {
+        self.payments.lock().unwrap().get(correlation_id).cloned()
+    }
+
+    fn update(&self, correlation_id: &CorrelationId, f: impl FnOnce(&mut PaymentStatus)) {
+        if let Some(status) = self.payments.lock().unwrap().get_mut(correlation_id) {
+            f(status);
+            status.updated_at = Utc::now();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(n: usize) -> CorrelationId {
+        format!("4a7901b8-7d26-4d9d-aa19-{n:012}").parse().unwrap()
+    }
+
+    #[test]
+    fn lifecycle_is_tracked() {
+        let statuses = StatusMap::default();
+        let id = id(0);
+
+        statuses.queued(&id, Utc::now());
+        assert_eq!(statuses.get(&id).unwrap().state, PaymentState::Queued);
+
+        statuses.submitted(&id, Processor::Default);
+        statuses.failed(&id);
+        statuses.submitted(&id, Processor::Fallback);
+        statuses.confirmed(&id);
+
+        let status = statuses.get(&id).unwrap();
+        assert_eq!(status.state, PaymentState::Confirmed);
+        assert_eq!(status.attempts, 2);
+        assert_eq!(status.processor, Some(Processor::Fallback));
+        assert!(status.updated_at >= status.requested_at);
+    }
+
+    #[test]
+    fn payments_never_queued_are_not_tracked() {
+        let statuses = StatusMap::default();
+
+        statuses.submitted(&id(0), Processor::Default);
+        statuses.dead_lettered(&id(0));
+
+        assert!(statuses.get(&id(0)).is_none());
+    }
+
+    #[test]
+    fn finished_payments_are_pruned_first() {
+        let statuses = StatusMap::default();
+
+        for n in 0..MAX_TRACKED_PAYMENTS {
+            statuses.queued(&id(n), Utc::now());
+        }
+        statuses.confirmed(&id(0));
+        statuses.dead_lettered(&id(1));
+        statuses.queued(&id(MAX_TRACKED_PAYMENTS), Utc::now());
+
+        assert!(statuses.get(&id(0)).is_none());
+        assert!(statuses.get(&id(1)).is_none());
+        assert!(statuses.get(&id(2)).is_some());
+        assert!(statuses.get(&id(MAX_TRACKED_PAYMENTS)).is_some());
+    }
+}
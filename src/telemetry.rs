@@ -0,0 +1,389 @@
+//! Optional OTLP export of metrics, with the `metrics` feature, and of traces as well, with the
+//! `otel` feature. Every function is a no-op unless its feature is enabled and `init` has been
+//! called, so call sites need no feature gates.
+
+use std::time::Duration;
+
+use crate::{Processor, trace::TraceContext};
+
+#[cfg(feature = "metrics")]
+use std::sync::OnceLock;
+
+#[cfg(feature = "otel")]
+use opentelemetry::{
+    Context,
+    trace::{
+        Span as _, SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState, Tracer,
+        TracerProvider,
+    },
+};
+#[cfg(feature = "metrics")]
+use opentelemetry::{
+    KeyValue,
+    metrics::{Counter, Gauge, Histogram, MeterProvider},
+};
+#[cfg(feature = "otel")]
+use opentelemetry_sdk::trace::{SdkTracer, SdkTracerProvider};
+#[cfg(feature = "metrics")]
+use opentelemetry_sdk::{Resource, metrics::SdkMeterProvider};
+
+#[cfg(feature = "metrics")]
+static INSTRUMENTS: OnceLock<Instruments> = OnceLock::new();
+
+// Payment amounts, fine enough apart to see whether large payments lean on the fallback
+#[cfg(feature = "metrics")]
+const AMOUNT_BUCKETS: [f64; 11] = [
+    1.0, 5.0, 10.0, 20.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 5000.0, 10000.0,
+];
+
+// Seconds, around the processors' usual few milliseconds up to `PROCESSOR_TIMEOUT_MS`
+#[cfg(feature = "metrics")]
+const LATENCY_BUCKETS: [f64; 12] = [
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+#[cfg(feature = "metrics")]
+struct Instruments {
+    #[cfg(feature = "otel")]
+    tracer: SdkTracer,
+    received: Counter<u64>,
+    processed: Counter<u64>,
+    processor_duration: Histogram<f64>,
+    amount: Histogram<f64>,
+    worker_commands: Counter<u64>,
+    dedup: Counter<u64>,
+    summary_duration: Histogram<f64>,
+    summary_inflight_wait: Histogram<f64>,
+    concurrency_limit: Gauge<u64>,
+    db_memory: Gauge<u64>,
+    peer_alive: Gauge<u64>,
+    peer_rtt: Gauge<f64>,
+    peer_diverging_buckets: Gauge<u64>,
+}
+
+/// Flushes whatever is still buffered when dropped.
+pub struct Telemetry {
+    #[cfg(feature = "otel")]
+    tracer_provider: SdkTracerProvider,
+    #[cfg(feature = "metrics")]
+    meter_provider: SdkMeterProvider,
+}
+
+/// Starts exporting to the collector set by the standard `OTEL_EXPORTER_OTLP_*` variables.
+#[cfg(feature = "metrics")]
+pub fn init() -> Option<Telemetry> {
+    #[cfg(feature = "otel")]
+    let spans = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .build()
+        .inspect_err(|e| eprintln!("OTLP export disabled: {e}"))
+        .ok()?;
+    let metrics = opentelemetry_otlp::MetricExporter::builder()
+        .with_http()
+        .build()
+        .inspect_err(|e| eprintln!("OTLP export disabled: {e}"))
+        .ok()?;
+    let resource = Resource::builder().with_service_name("client-full").build();
+    #[cfg(feature = "otel")]
+    let tracer_provider = SdkTracerProvider::builder()
+        .with_batch_exporter(spans)
+        .with_resource(resource.clone())
+        .build();
+    let meter_provider = SdkMeterProvider::builder()
+        .with_periodic_exporter(metrics)
+        .with_resource(resource)
+        .build();
+    let meter = meter_provider.meter("client-full");
+    let instruments = Instruments {
+        #[cfg(feature = "otel")]
+        tracer: tracer_provider.tracer("client-full"),
+        received: meter.u64_counter("payments.received").build(),
+        processed: meter.u64_counter("payments.processed").build(),
+        processor_duration: meter
+            .f64_histogram("processor.duration")
+            .with_unit("s")
+            .with_boundaries(LATENCY_BUCKETS.to_vec())
+            .build(),
+        amount: meter
+            .f64_histogram("payments.amount")
+            .with_boundaries(AMOUNT_BUCKETS.to_vec())
+            .build(),
+        worker_commands: meter.u64_counter("worker.commands").build(),
+        dedup: meter.u64_counter("db.dedup").build(),
+        summary_duration: meter
+            .f64_histogram("summary.duration")
+            .with_unit("s")
+            .build(),
+        summary_inflight_wait: meter
+            .f64_histogram("summary.inflight_wait")
+            .with_unit("s")
+            .build(),
+        concurrency_limit: meter.u64_gauge("dispatch.concurrency_limit").build(),
+        db_memory: meter.u64_gauge("db.memory").with_unit("By").build(),
+        peer_alive: meter.u64_gauge("peer.alive").build(),
+        peer_rtt: meter.f64_gauge("peer.rtt").with_unit("s").build(),
+        peer_diverging_buckets: meter.u64_gauge("peer.diverging_buckets").build(),
+    };
+
+    let _ = INSTRUMENTS.set(instruments);
+
+    Some(Telemetry {
+        #[cfg(feature = "otel")]
+        tracer_provider,
+        meter_provider,
+    })
+}
+
+#[cfg(not(feature = "metrics"))]
+pub fn init() -> Option<Telemetry> {
+    None
+}
+
+#[cfg(feature = "metrics")]
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        #[cfg(feature = "otel")]
+        let _ = self.tracer_provider.shutdown();
+        let _ = self.meter_provider.shutdown();
+    }
+}
+
+/// A span continuing the trace of a request, ended when dropped.
+pub struct Span {
+    trace: TraceContext,
+    #[cfg(feature = "otel")]
+    inner: Option<opentelemetry_sdk::trace::Span>,
+}
+
+impl Span {
+    pub fn start(name: &'static str, trace: TraceContext) -> Self {
+        #[cfg(feature = "otel")]
+        if let Some(instruments) = INSTRUMENTS.get() {
+            let flags = if trace.sampled {
+                TraceFlags::SAMPLED
+            } else {
+                TraceFlags::default()
+            };
+            let parent = SpanContext::new(
+                TraceId::from(trace.trace_id),
+                SpanId::from(trace.parent_id),
+                flags,
+                true,
+                TraceState::NONE,
+            );
+            let cx = Context::new().with_remote_span_context(parent);
+            let inner = instruments.tracer.start_with_context(name, &cx);
+            let span_id = u64::from_be_bytes(inner.span_context().span_id().to_bytes());
+
+            return Self {
+                trace: TraceContext {
+                    parent_id: span_id,
+                    ..trace
+                },
+                inner: Some(inner),
+            };
+        }
+
+        let _ = name;
+
+        Self {
+            trace: trace.child(),
+            #[cfg(feature = "otel")]
+            inner: None,
+        }
+    }
+
+    /// Context to propagate on requests made within this span.
+    pub fn context(&self) -> TraceContext {
+        self.trace
+    }
+
+    pub fn set_error(&mut self, message: String) {
+        #[cfg(feature = "otel")]
+        if let Some(inner) = &mut self.inner {
+            inner.set_status(opentelemetry::trace::Status::error(message));
+            return;
+        }
+
+        let _ = message;
+    }
+
+    pub fn set_attribute(&mut self, key: &'static str, value: f64) {
+        #[cfg(feature = "otel")]
+        if let Some(inner) = &mut self.inner {
+            inner.set_attribute(KeyValue::new(key, value));
+            return;
+        }
+
+        let _ = (key, value);
+    }
+}
+
+pub fn payment_received() {
+    #[cfg(feature = "metrics")]
+    if let Some(instruments) = INSTRUMENTS.get() {
+        instruments.received.add(1, &[]);
+    }
+}
+
+/// Records one processor attempt, `outcome` being `success`, `rejected` or `retry`. Its amount and
+/// latency go into histograms by processor and outcome, the amount one's sum being the value sent
+/// each way.
+pub fn payment_processed(
+    processor: Processor,
+    outcome: &'static str,
+    amount: f64,
+    elapsed: Duration,
+) {
+    #[cfg(feature = "metrics")]
+    if let Some(instruments) = INSTRUMENTS.get() {
+        let processor = match processor {
+            Processor::Default => "default",
+            Processor::Fallback => "fallback",
+        };
+        let attrs = [
+            KeyValue::new("processor", processor),
+            KeyValue::new("outcome", outcome),
+        ];
+
+        instruments.processed.add(1, &attrs);
+        instruments.amount.record(amount, &attrs);
+        instruments
+            .processor_duration
+            .record(elapsed.as_secs_f64(), &attrs);
+        return;
+    }
+
+    let _ = (processor, outcome, amount, elapsed);
+}
+
+pub fn worker_command(kind: &'static str) {
+    #[cfg(feature = "metrics")]
+    if let Some(instruments) = INSTRUMENTS.get() {
+        instruments
+            .worker_commands
+            .add(1, &[KeyValue::new("kind", kind)]);
+        return;
+    }
+
+    let _ = kind;
+}
+
+/// Records a dedup window event, `outcome` being `hit`, `evicted` or `expired`.
+pub fn dedup(outcome: &'static str) {
+    #[cfg(feature = "metrics")]
+    if let Some(instruments) = INSTRUMENTS.get() {
+        instruments
+            .dedup
+            .add(1, &[KeyValue::new("outcome", outcome)]);
+        return;
+    }
+
+    let _ = outcome;
+}
+
+pub fn summary_served(elapsed: Duration) {
+    #[cfg(feature = "metrics")]
+    if let Some(instruments) = INSTRUMENTS.get() {
+        instruments
+            .summary_duration
+            .record(elapsed.as_secs_f64(), &[]);
+        return;
+    }
+
+    let _ = elapsed;
+}
+
+/// Time a summary spent waiting on payments still in flight within its range.
+pub fn summary_waited(elapsed: Duration, timed_out: bool) {
+    #[cfg(feature = "metrics")]
+    if let Some(instruments) = INSTRUMENTS.get() {
+        instruments.summary_inflight_wait.record(
+            elapsed.as_secs_f64(),
+            &[KeyValue::new("timed_out", timed_out)],
+        );
+        return;
+    }
+
+    let _ = (elapsed, timed_out);
+}
+
+pub fn concurrency_limit(limit: usize) {
+    #[cfg(feature = "metrics")]
+    if let Some(instruments) = INSTRUMENTS.get() {
+        instruments.concurrency_limit.record(limit as u64, &[]);
+        return;
+    }
+
+    let _ = limit;
+}
+
+pub fn db_memory(bytes: usize) {
+    #[cfg(feature = "metrics")]
+    if let Some(instruments) = INSTRUMENTS.get() {
+        instruments.db_memory.record(bytes as u64, &[]);
+        return;
+    }
+
+    let _ = bytes;
+}
+
+/// Records a heartbeat of `peer`, with the round trip of the ping when it answered.
+pub fn peer_heartbeat(peer: &str, alive: bool, rtt: Option<Duration>) {
+    #[cfg(feature = "metrics")]
+    if let Some(instruments) = INSTRUMENTS.get() {
+        let attrs = [KeyValue::new("peer", peer.to_string())];
+
+        instruments.peer_alive.record(alive as u64, &attrs);
+        if let Some(rtt) = rtt {
+            instruments.peer_rtt.record(rtt.as_secs_f64(), &attrs);
+        }
+        return;
+    }
+
+    let _ = (peer, alive, rtt);
+}
+
+/// Records how many minutes of the backup of `peer`'s Db differed from its own at the last check.
+pub fn peer_consistency(peer: &str, diverging: usize) {
+    #[cfg(feature = "metrics")]
+    if let Some(instruments) = INSTRUMENTS.get() {
+        instruments
+            .peer_diverging_buckets
+            .record(diverging as u64, &[KeyValue::new("peer", peer.to_string())]);
+        return;
+    }
+
+    let _ = (peer, diverging);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `init` is never called in tests, so nothing is exported
+    #[test]
+    fn spans_continue_the_callers_trace_without_export() {
+        let trace = TraceContext::generate();
+        let mut span = Span::start("test", trace);
+        span.set_attribute("attempt", 1.0);
+        span.set_error("failed".to_string());
+
+        assert_eq!(span.context().trace_id, trace.trace_id);
+        assert_eq!(span.context().sampled, trace.sampled);
+        assert_ne!(span.context().parent_id, trace.parent_id);
+    }
+
+    #[test]
+    fn recording_without_export_is_a_no_op() {
+        payment_received();
+        payment_processed(
+            Processor::Default,
+            "success",
+            19.9,
+            Duration::from_millis(5),
+        );
+        summary_waited(Duration::from_millis(1), true);
+        peer_heartbeat("http://peer:3000", false, None);
+    }
+}
@@ -0,0 +1,176 @@
+//! Payments tagged with a `tenantId`, in the body or the `X-Tenant-Id` header, are recorded in
+//! Dbs of their own, so each tenant's summaries and dedup never see another tenant's payments.
+//! Tenants' Dbs are always in-process, whichever `DB_BACKEND` untagged payments go to, and are
+//! created on a tenant's first payment.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::{Config, Processor, backend::MemoryStorage, correlation::CorrelationId};
+
+pub const TENANT_HEADER: &str = "x-tenant-id";
+
+const MAX_TENANT_LEN: usize = 64;
+
+/// Up to `MAX_TENANT_LEN` ASCII letters, digits, `-`, `_` or `.`, which keeps it a single token
+/// in the journal and spill files.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TenantId(Arc<str>);
+
+impl TenantId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for TenantId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for TenantId {
+    type Err = InvalidTenant;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let valid = !s.is_empty()
+            && s.len() <= MAX_TENANT_LEN
+            && s.bytes()
+                .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'));
+
+        if !valid {
+            return Err(InvalidTenant);
+        }
+
+        Ok(Self(s.into()))
+    }
+}
+
+impl TryFrom<String> for TenantId {
+    type Error = InvalidTenant;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<TenantId> for String {
+    fn from(tenant: TenantId) -> Self {
+        tenant.0.to_string()
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidTenant;
+
+impl fmt::Display for InvalidTenant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "tenantId must be 1 to {MAX_TENANT_LEN} letters, digits, '-', '_' or '.'"
+        )
+    }
+}
+
+impl std::error::Error for InvalidTenant {}
+
+/// One tenant's Dbs, by requested and by processed time.
+#[derive(Clone)]
+pub struct TenantStorage {
+    pub requested: MemoryStorage,
+    pub processed: MemoryStorage,
+}
+
+/// Every tenant seen so far, up to `MAX_TENANTS`.
+pub struct Tenants {
+    config: Arc<Config>,
+    storages: RwLock<HashMap<TenantId, TenantStorage>>,
+}
+
+impl Tenants {
+    pub fn new(config: Arc<Config>) -> Self {
+        Self {
+            config,
+            storages: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, tenant: &TenantId) -> Option<TenantStorage> {
+        self.storages.read().unwrap().get(tenant).cloned()
+    }
+
+    /// The tenant's Dbs, created unless that would go past `MAX_TENANTS`.
+    pub fn get_or_insert(&self, tenant: &TenantId) -> Result<TenantStorage, TooManyTenants> {
+        if let Some(storage) = self.get(tenant) {
+            return Ok(storage);
+        }
+
+        let mut storages = self.storages.write().unwrap();
+        let max = self.config.max_tenants;
+
+        if max > 0 && storages.len() >= max && !storages.contains_key(tenant) {
+            return Err(TooManyTenants);
+        }
+
+        let storage = storages
+            .entry(tenant.clone())
+            .or_insert_with(|| TenantStorage {
+                requested: MemoryStorage::new(&self.config),
+                processed: MemoryStorage::new(&self.config),
+            });
+
+        Ok(storage.clone())
+    }
+
+    /// Records a confirmed payment as `Storage::record` does, processed now.
+    pub fn record(
+        &self,
+        tenant: &TenantId,
+        processor: Processor,
+        correlation_id: &CorrelationId,
+        timestamp: i64,
+        amount: u64,
+    ) -> Result<(), TooManyTenants> {
+        let storage = self.get_or_insert(tenant)?;
+
+        storage
+            .requested
+            .db(processor)
+            .set(correlation_id, timestamp, amount);
+        storage
+            .processed
+            .db(processor)
+            .set(correlation_id, Utc::now().timestamp_micros(), amount);
+
+        Ok(())
+    }
+
+    /// Forgets every tenant along with their Dbs.
+    pub fn clear(&self) {
+        self.storages.write().unwrap().clear();
+    }
+
+    /// Every tenant's Dbs, for compaction and memory accounting.
+    pub fn storages(&self) -> Vec<TenantStorage> {
+        self.storages.read().unwrap().values().cloned().collect()
+    }
+}
+
+#[derive(Debug)]
+pub struct TooManyTenants;
+
+impl fmt::Display for TooManyTenants {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "too many tenants")
+    }
+}
+
+impl std::error::Error for TooManyTenants {}
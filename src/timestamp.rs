@@ -0,0 +1,47 @@
+//! `from` and `to` of summary and export queries. The spec sends RFC 3339 in UTC, like
+//! `2020-07-10T12:34:56.000Z`, but fractional seconds and offsets are optional and a timestamp
+//! without an offset is taken as UTC. An empty value leaves that end of the range open, as
+//! leaving the param out does.
+
+use std::fmt;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Deserializer, de};
+
+/// Parses `s` and normalizes it to UTC.
+pub fn parse(s: &str) -> Result<DateTime<Utc>, InvalidTimestamp> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+        return Ok(dt.to_utc());
+    }
+
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f")
+        .map(|dt| dt.and_utc())
+        .map_err(|_| InvalidTimestamp)
+}
+
+/// For `#[serde(default, deserialize_with = "timestamp::deserialize")]` on an optional end of a
+/// range.
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<String>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(s) if s.trim().is_empty() => Ok(None),
+        Some(s) => parse(s.trim()).map(Some).map_err(de::Error::custom),
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidTimestamp;
+
+impl fmt::Display for InvalidTimestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "timestamps must be ISO 8601, like 2020-07-10T12:34:56.000Z"
+        )
+    }
+}
+
+impl std::error::Error for InvalidTimestamp {}
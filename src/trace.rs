@@ -0,0 +1,175 @@
+use std::{
+    collections::hash_map::RandomState,
+    fmt,
+    hash::{BuildHasher, Hasher},
+};
+
+use axum::{
+    extract::Request,
+    http::{HeaderValue, header::HeaderName},
+    middleware::Next,
+    response::Response,
+};
+
+pub const TRACEPARENT: HeaderName = HeaderName::from_static("traceparent");
+pub const TRACE_ID: HeaderName = HeaderName::from_static("x-trace-id");
+
+/// W3C trace context, carried from `/payments` through the queue to the processor and peer
+/// requests made on its behalf.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceContext {
+    pub trace_id: u128,
+    pub parent_id: u64,
+    pub sampled: bool,
+}
+
+impl TraceContext {
+    pub fn generate() -> Self {
+        Self {
+            trace_id: (random() as u128) << 64 | random() as u128,
+            parent_id: random(),
+            sampled: true,
+        }
+    }
+
+    /// Parses a version 00 `traceparent` header value.
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut fields = value.trim().split('-');
+        let version = fields.next()?;
+        let trace_id = fields.next()?;
+        let parent_id = fields.next()?;
+        let flags = fields.next()?;
+
+        if version != "00" || trace_id.len() != 32 || parent_id.len() != 16 || flags.len() != 2 {
+            return None;
+        }
+
+        let trace_id = u128::from_str_radix(trace_id, 16).ok()?;
+        let parent_id = u64::from_str_radix(parent_id, 16).ok()?;
+        let flags = u8::from_str_radix(flags, 16).ok()?;
+
+        // All-zero ids are invalid per the spec
+        if trace_id == 0 || parent_id == 0 {
+            return None;
+        }
+
+        Some(Self {
+            trace_id,
+            parent_id,
+            sampled: flags & 1 == 1,
+        })
+    }
+
+    /// Same trace with a fresh span id, for a request made by this service.
+    pub fn child(&self) -> Self {
+        Self {
+            parent_id: random(),
+            ..*self
+        }
+    }
+
+    pub fn trace_id(&self) -> String {
+        format!("{:032x}", self.trace_id)
+    }
+
+    pub fn header_value(&self) -> HeaderValue {
+        HeaderValue::from_str(&self.to_string()).unwrap()
+    }
+}
+
+impl fmt::Display for TraceContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "00-{:032x}-{:016x}-{:02x}",
+            self.trace_id, self.parent_id, self.sampled as u8
+        )
+    }
+}
+
+/// Continues the caller's trace or starts a new one, making it available to handlers as an
+/// extension. Error responses carry the trace id so failures can be looked up in the logs.
+pub async fn trace_context(mut req: Request, next: Next) -> Response {
+    let ctx = req
+        .headers()
+        .get(TRACEPARENT)
+        .and_then(|v| v.to_str().ok())
+        .and_then(TraceContext::parse)
+        .unwrap_or_else(TraceContext::generate);
+
+    req.extensions_mut().insert(ctx);
+
+    let mut resp = next.run(req).await;
+
+    if !resp.status().is_success() {
+        let trace_id = HeaderValue::from_str(&ctx.trace_id()).unwrap();
+        resp.headers_mut().insert(TRACE_ID, trace_id);
+    }
+
+    resp
+}
+
+// Every `RandomState` is seeded differently, which is all the randomness ids need
+fn random() -> u64 {
+    loop {
+        let value = RandomState::new().build_hasher().finish();
+
+        if value != 0 {
+            return value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn traceparent_round_trips() {
+        let ctx = TraceContext::parse(HEADER).unwrap();
+
+        assert_eq!(ctx.trace_id(), "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(ctx.parent_id, 0x00f0_67aa_0ba9_02b7);
+        assert!(ctx.sampled);
+        assert_eq!(ctx.to_string(), HEADER);
+        assert_eq!(ctx.header_value(), HEADER);
+    }
+
+    #[test]
+    fn unsampled_flag_is_kept() {
+        let ctx = TraceContext::parse(&HEADER.replace("-01", "-00")).unwrap();
+
+        assert!(!ctx.sampled);
+        assert!(ctx.to_string().ends_with("-00"));
+    }
+
+    #[test]
+    fn malformed_headers_are_rejected() {
+        for value in [
+            "",
+            "01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e473-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "00-4bf92f3577b34da6a3ce929d0e0e473z-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+        ] {
+            assert_eq!(TraceContext::parse(value), None, "{value:?}");
+        }
+    }
+
+    #[test]
+    fn children_keep_the_trace() {
+        let ctx = TraceContext::generate();
+        let child = ctx.child();
+
+        assert_ne!(ctx.trace_id, 0);
+        assert_eq!(child.trace_id, ctx.trace_id);
+        assert_eq!(child.sampled, ctx.sampled);
+        assert_ne!(child.parent_id, ctx.parent_id);
+        assert_eq!(TraceContext::parse(&child.to_string()), Some(child));
+    }
+}
@@ -0,0 +1,150 @@
+use std::{fmt, str::FromStr};
+
+use axum::http::HeaderValue;
+
+pub const MSGPACK: &str = "application/msgpack";
+
+/// How instances talk to each other for summaries and snapshots.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PeerTransport {
+    #[default]
+    Http,
+    // `proto/peer.proto`, needs the grpc-peer feature
+    Grpc,
+}
+
+impl FromStr for PeerTransport {
+    type Err = UnknownTransport;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "http" => Ok(Self::Http),
+            "grpc" => Ok(Self::Grpc),
+            _ => Err(UnknownTransport),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownTransport;
+
+impl fmt::Display for UnknownTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown peer transport")
+    }
+}
+
+impl std::error::Error for UnknownTransport {}
+
+/// Body encoding of the internal HTTP endpoints. The public API always speaks JSON.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PeerEncoding {
+    #[default]
+    Json,
+    MessagePack,
+}
+
+impl PeerEncoding {
+    /// Picks the encoding named by an `Accept` or `Content-Type` header, JSON when none is.
+    pub fn negotiate(header: Option<&HeaderValue>) -> Self {
+        match header.and_then(|v| v.to_str().ok()) {
+            Some(v) if v.contains(MSGPACK) => Self::MessagePack,
+            _ => Self::Json,
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::MessagePack => MSGPACK,
+        }
+    }
+}
+
+impl FromStr for PeerEncoding {
+    type Err = UnknownEncoding;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "msgpack" => Ok(Self::MessagePack),
+            _ => Err(UnknownEncoding),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownEncoding;
+
+impl fmt::Display for UnknownEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown peer encoding")
+    }
+}
+
+impl std::error::Error for UnknownEncoding {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProcessorSummaries;
+
+    fn negotiate(header: &str) -> PeerEncoding {
+        PeerEncoding::negotiate(Some(&HeaderValue::from_str(header).unwrap()))
+    }
+
+    #[test]
+    fn msgpack_is_only_spoken_when_asked_for() {
+        assert_eq!(PeerEncoding::negotiate(None), PeerEncoding::Json);
+        assert_eq!(negotiate("application/json"), PeerEncoding::Json);
+        assert_eq!(negotiate("*/*"), PeerEncoding::Json);
+        assert_eq!(negotiate(MSGPACK), PeerEncoding::MessagePack);
+        assert_eq!(
+            negotiate("application/msgpack, application/json;q=0.5"),
+            PeerEncoding::MessagePack
+        );
+        assert_eq!(
+            PeerEncoding::negotiate(Some(&HeaderValue::from_bytes(b"\xff").unwrap())),
+            PeerEncoding::Json
+        );
+    }
+
+    #[test]
+    fn content_types_negotiate_back_to_their_encoding() {
+        for encoding in [PeerEncoding::Json, PeerEncoding::MessagePack] {
+            assert_eq!(negotiate(encoding.content_type()), encoding);
+        }
+    }
+
+    #[test]
+    fn settings_parse() {
+        assert_eq!(
+            "msgpack".parse::<PeerEncoding>().unwrap(),
+            PeerEncoding::MessagePack
+        );
+        assert_eq!(
+            "grpc".parse::<PeerTransport>().unwrap(),
+            PeerTransport::Grpc
+        );
+        assert!("bincode".parse::<PeerEncoding>().is_err());
+        assert!("HTTP".parse::<PeerTransport>().is_err());
+    }
+
+    #[test]
+    fn summaries_survive_msgpack() {
+        let mut summary = ProcessorSummaries::default();
+        summary.default_sum.total_requests = 3;
+        summary.default_sum.total_amount = 59.7;
+        summary.fallback.total_fee = Some(0.15);
+        let mut summary = summary.attributed_to("api1".to_string());
+        summary.partial = true;
+
+        let bytes = rmp_serde::to_vec_named(&summary).unwrap();
+        let decoded: ProcessorSummaries = rmp_serde::from_slice(&bytes).unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&decoded).unwrap(),
+            serde_json::to_value(&summary).unwrap()
+        );
+    }
+}
@@ -0,0 +1,201 @@
+use std::{fmt, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::Config;
+
+/// The part of `Config` that can change while running, through `PUT /admin/config` or by editing
+/// `CONFIG_FILE` and sending SIGHUP. Starts out from the env like everything else.
+#[derive(Clone, Debug, Serialize)]
+pub struct Tunables {
+    pub processor_timeout_ms: u64,
+    // 0 keeps retrying forever
+    pub payment_max_age_secs: u64,
+    pub dispatch_concurrency: usize,
+    // Payments the accept queue takes before the handlers hold off, up to `ACCEPT_QUEUE_CAPACITY`
+    pub queue_capacity: usize,
+    pub failover_check_interval_ms: u64,
+    pub breaker_threshold: u32,
+    pub breaker_cooldown_ms: u64,
+}
+
+/// Body of `PUT /admin/config` and content of `CONFIG_FILE`, fields left out keep their value.
+#[derive(Default, Deserialize)]
+pub struct TunablesUpdate {
+    pub processor_timeout_ms: Option<u64>,
+    pub payment_max_age_secs: Option<u64>,
+    pub dispatch_concurrency: Option<usize>,
+    pub queue_capacity: Option<usize>,
+    pub failover_check_interval_ms: Option<u64>,
+    pub breaker_threshold: Option<u32>,
+    pub breaker_cooldown_ms: Option<u64>,
+}
+
+impl Tunables {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            processor_timeout_ms: config.processor_timeout.as_millis() as u64,
+            payment_max_age_secs: config.payment_max_age.as_secs(),
+            dispatch_concurrency: config.dispatch_concurrency,
+            queue_capacity: config.accept_queue_capacity,
+            failover_check_interval_ms: config.failover_check_interval.as_millis() as u64,
+            breaker_threshold: config.breaker_threshold,
+            breaker_cooldown_ms: config.breaker_cooldown.as_millis() as u64,
+        }
+    }
+
+    /// Returns these tunables with `update` applied, or why it can't be.
+    pub fn apply(&self, update: &TunablesUpdate) -> Result<Self, InvalidTunables> {
+        let tunables = Self {
+            processor_timeout_ms: update
+                .processor_timeout_ms
+                .unwrap_or(self.processor_timeout_ms),
+            payment_max_age_secs: update
+                .payment_max_age_secs
+                .unwrap_or(self.payment_max_age_secs),
+            dispatch_concurrency: update
+                .dispatch_concurrency
+                .unwrap_or(self.dispatch_concurrency),
+            queue_capacity: update.queue_capacity.unwrap_or(self.queue_capacity),
+            failover_check_interval_ms: update
+                .failover_check_interval_ms
+                .unwrap_or(self.failover_check_interval_ms),
+            breaker_threshold: update.breaker_threshold.unwrap_or(self.breaker_threshold),
+            breaker_cooldown_ms: update
+                .breaker_cooldown_ms
+                .unwrap_or(self.breaker_cooldown_ms),
+        };
+
+        if tunables.processor_timeout_ms == 0 {
+            return Err(InvalidTunables("processor_timeout_ms must be positive"));
+        }
+        if tunables.dispatch_concurrency == 0 {
+            return Err(InvalidTunables("dispatch_concurrency must be positive"));
+        }
+        if tunables.queue_capacity == 0 {
+            return Err(InvalidTunables("queue_capacity must be positive"));
+        }
+        if tunables.failover_check_interval_ms == 0 {
+            return Err(InvalidTunables(
+                "failover_check_interval_ms must be positive",
+            ));
+        }
+
+        Ok(tunables)
+    }
+
+    pub fn processor_timeout(&self) -> Duration {
+        Duration::from_millis(self.processor_timeout_ms)
+    }
+
+    pub fn payment_max_age(&self) -> Duration {
+        Duration::from_secs(self.payment_max_age_secs)
+    }
+
+    pub fn failover_check_interval(&self) -> Duration {
+        Duration::from_millis(self.failover_check_interval_ms)
+    }
+
+    pub fn breaker_cooldown(&self) -> Duration {
+        Duration::from_millis(self.breaker_cooldown_ms)
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidTunables(pub &'static str);
+
+impl fmt::Display for InvalidTunables {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidTunables {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tunables() -> Tunables {
+        Tunables::from_config(&Config::from_lookup(|key| match key {
+            "PROCESSOR_TIMEOUT_MS" => Some("250".to_string()),
+            "DISPATCH_CONCURRENCY" => Some("8".to_string()),
+            "PEER_URL" => Some(String::new()),
+            _ => None,
+        }))
+    }
+
+    #[test]
+    fn starts_out_from_the_config() {
+        let tunables = tunables();
+
+        assert_eq!(tunables.processor_timeout_ms, 250);
+        assert_eq!(tunables.processor_timeout(), Duration::from_millis(250));
+        assert_eq!(tunables.dispatch_concurrency, 8);
+    }
+
+    #[test]
+    fn fields_left_out_keep_their_value() {
+        let tunables = tunables();
+        let updated = tunables
+            .apply(&TunablesUpdate {
+                breaker_threshold: Some(tunables.breaker_threshold + 1),
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert_eq!(updated.breaker_threshold, tunables.breaker_threshold + 1);
+        assert_eq!(updated.processor_timeout_ms, 250);
+        assert_eq!(updated.dispatch_concurrency, 8);
+        assert_eq!(updated.queue_capacity, tunables.queue_capacity);
+        assert_eq!(updated.payment_max_age_secs, tunables.payment_max_age_secs);
+    }
+
+    #[test]
+    fn zero_retry_age_is_allowed() {
+        let update = TunablesUpdate {
+            payment_max_age_secs: Some(0),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            tunables().apply(&update).unwrap().payment_max_age(),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn zero_limits_are_rejected() {
+        let tunables = tunables();
+        let updates = [
+            TunablesUpdate {
+                processor_timeout_ms: Some(0),
+                ..Default::default()
+            },
+            TunablesUpdate {
+                dispatch_concurrency: Some(0),
+                ..Default::default()
+            },
+            TunablesUpdate {
+                queue_capacity: Some(0),
+                ..Default::default()
+            },
+            TunablesUpdate {
+                failover_check_interval_ms: Some(0),
+                ..Default::default()
+            },
+        ];
+
+        for update in updates {
+            assert!(tunables.apply(&update).is_err());
+        }
+    }
+
+    #[test]
+    fn updates_parse_from_partial_json() {
+        let update: TunablesUpdate = serde_json::from_str(r#"{"queue_capacity": 64}"#).unwrap();
+
+        assert_eq!(update.queue_capacity, Some(64));
+        assert!(update.dispatch_concurrency.is_none());
+    }
+}
@@ -0,0 +1,144 @@
+//! Notifies a downstream service of every payment a processor confirmed, with `WEBHOOK_URL`.
+//! Each request is a JSON array of `Confirmation`s, up to `WEBHOOK_BATCH_SIZE` of them. Requests
+//! that fail are retried with exponential backoff, and dropped with a log line once
+//! `WEBHOOK_MAX_RETRIES` ran out, so a downstream outage never holds payments up.
+//!
+//! Delivery is at least once while the instance runs: a request that timed out after reaching
+//! the webhook is sent again. Notifications still queued at shutdown are lost.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::{Payment, Processor, config::Config, correlation::CorrelationId, tenant::TenantId};
+
+// Backoff stops doubling past this
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// One confirmed payment, as the webhook receives it.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Confirmation {
+    pub correlation_id: CorrelationId,
+    pub processor: Processor,
+    pub amount: f64,
+    pub requested_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant_id: Option<TenantId>,
+}
+
+impl Confirmation {
+    pub fn new(p: &Payment, processor: Processor) -> Self {
+        Self {
+            correlation_id: p.correlation_id.clone(),
+            processor,
+            amount: p.amount,
+            requested_at: p.requested_at,
+            tenant_id: p.tenant.clone(),
+        }
+    }
+}
+
+/// Queues confirmations for a single task that batches and sends them.
+pub struct Webhook {
+    tx: mpsc::Sender<Confirmation>,
+}
+
+impl Webhook {
+    pub fn spawn(url: String, config: &Config) -> Self {
+        let (tx, rx) = mpsc::channel(config.webhook_queue_capacity.max(1));
+        let sender = Sender {
+            // Pooled apart from the processors' and peers' connections
+            http: reqwest::Client::new(),
+            url,
+            batch_size: config.webhook_batch_size.max(1),
+            batch_delay: config.webhook_batch_delay,
+            timeout: config.webhook_timeout,
+            max_retries: config.webhook_max_retries,
+            backoff: config.webhook_retry_backoff,
+        };
+
+        tokio::spawn(sender.run(rx));
+
+        Self { tx }
+    }
+
+    /// Queues `confirmation` without waiting, dropping it when the queue is full.
+    pub fn notify(&self, confirmation: Confirmation) {
+        if let Err(e) = self.tx.try_send(confirmation) {
+            let confirmation = e.into_inner();
+            eprintln!(
+                "Webhook queue full, not notifying {}",
+                confirmation.correlation_id
+            );
+        }
+    }
+}
+
+struct Sender {
+    http: reqwest::Client,
+    url: String,
+    batch_size: usize,
+    batch_delay: Duration,
+    timeout: Duration,
+    max_retries: u32,
+    backoff: Duration,
+}
+
+impl Sender {
+    /// Collects up to `batch_size` confirmations, waiting at most `batch_delay` after the first
+    /// one, and sends them in one request.
+    async fn run(self, mut rx: mpsc::Receiver<Confirmation>) {
+        let mut batch = Vec::with_capacity(self.batch_size);
+
+        while let Some(confirmation) = rx.recv().await {
+            batch.push(confirmation);
+
+            let deadline = tokio::time::sleep(self.batch_delay);
+            tokio::pin!(deadline);
+
+            while batch.len() < self.batch_size {
+                tokio::select! {
+                    Some(confirmation) = rx.recv() => batch.push(confirmation),
+                    _ = &mut deadline => break,
+                }
+            }
+
+            self.send(&batch).await;
+            batch.clear();
+        }
+    }
+
+    async fn send(&self, batch: &[Confirmation]) {
+        let mut backoff = self.backoff;
+
+        for attempt in 0..=self.max_retries {
+            let sent = self
+                .http
+                .post(&self.url)
+                .json(batch)
+                .timeout(self.timeout)
+                .send()
+                .await
+                .and_then(|resp| resp.error_for_status());
+
+            let Err(e) = sent else {
+                return;
+            };
+
+            if attempt == self.max_retries {
+                eprintln!(
+                    "Dropping webhook notification of {} payments after {} attempts: {e}",
+                    batch.len(),
+                    attempt + 1
+                );
+                return;
+            }
+
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+}
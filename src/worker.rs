@@ -0,0 +1,418 @@
+use async_trait::async_trait;
+use futures_util::future::join_all;
+use serde::Serialize;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{
+    Db, Processor,
+    backend::{BackendError, MemoryStorage, Storage, Totals},
+    correlation::CorrelationId,
+    telemetry,
+};
+
+// Most commands the worker takes off the queue per wakeup
+const BATCH_SIZE: usize = 256;
+
+pub enum Command {
+    Set {
+        processor: Processor,
+        correlation_id: CorrelationId,
+        timestamp: i64,
+        amount: u64,
+    },
+    Get {
+        from: Option<i64>,
+        to: Option<i64>,
+        resp: oneshot::Sender<Totals>,
+    },
+    // Drops everything recorded so far
+    Purge,
+    // Answered once every command queued before it has been applied
+    Flush(oneshot::Sender<()>),
+    Stats(oneshot::Sender<WorkerStats>),
+}
+
+impl Command {
+    fn kind(&self) -> &'static str {
+        match self {
+            Command::Set { .. } => "set",
+            Command::Get { .. } => "get",
+            Command::Purge => "purge",
+            Command::Flush(_) => "flush",
+            Command::Stats(_) => "stats",
+        }
+    }
+}
+
+/// Actor that serializes every Db write through a single task, so request handlers never
+/// contend on the Db locks. Writes are queued without waiting for the worker to apply them. Reads
+/// are summed on a task of their own once every write queued before them has been applied, so they
+/// see all of those, and writes queued after them go on meanwhile. The queue is bounded: once it
+/// is full, writers wait for room, which holds the dispatcher back instead of growing memory
+/// unchecked.
+pub struct Worker {
+    rx: mpsc::Receiver<Command>,
+    default_db: Db,
+    fallback_db: Db,
+    processed: u64,
+    failed: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct WorkerStats {
+    // Commands queued but not applied yet
+    pub queue_depth: usize,
+    // Commands applied since start
+    pub processed: u64,
+    // Answers the requester was no longer waiting for
+    pub failed: u64,
+}
+
+#[derive(Clone)]
+pub struct WorkerHandle {
+    tx: mpsc::Sender<Command>,
+}
+
+impl Worker {
+    /// Queues at most `capacity` commands.
+    pub fn spawn(default_db: Db, fallback_db: Db, capacity: usize) -> WorkerHandle {
+        let (tx, rx) = mpsc::channel(capacity.max(1));
+        let worker = Worker {
+            rx,
+            default_db,
+            fallback_db,
+            processed: 0,
+            failed: 0,
+        };
+
+        tokio::spawn(worker.run());
+
+        WorkerHandle { tx }
+    }
+
+    async fn run(mut self) {
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+
+        while self.rx.recv_many(&mut batch, BATCH_SIZE).await > 0 {
+            let mut cmds = batch.drain(..);
+
+            while let Some(cmd) = cmds.next() {
+                self.apply(cmd, cmds.len());
+            }
+        }
+    }
+
+    // `pending` is what is left of the current batch, already taken off the queue
+    fn apply(&mut self, cmd: Command, pending: usize) {
+        telemetry::worker_command(cmd.kind());
+        self.processed += 1;
+
+        match cmd {
+            Command::Set {
+                processor: Processor::Default,
+                correlation_id,
+                timestamp,
+                amount,
+            } => self.default_db.set(&correlation_id, timestamp, amount),
+            Command::Set {
+                processor: Processor::Fallback,
+                correlation_id,
+                timestamp,
+                amount,
+            } => self.fallback_db.set(&correlation_id, timestamp, amount),
+            Command::Get { from, to, resp } => {
+                if resp.is_closed() {
+                    self.failed += 1;
+                    return;
+                }
+
+                // Summed off the actor, a large summary would otherwise hold up every write
+                // queued behind it
+                let (default_db, fallback_db) = (self.default_db.clone(), self.fallback_db.clone());
+                tokio::spawn(async move {
+                    let totals = [
+                        default_db.summarize_async(from, to).await,
+                        fallback_db.summarize_async(from, to).await,
+                    ];
+                    let _ = resp.send(totals);
+                });
+            }
+            Command::Purge => {
+                self.default_db.clear();
+                self.fallback_db.clear();
+            }
+            Command::Flush(resp) => self.reply(resp, ()),
+            Command::Stats(resp) => {
+                let stats = WorkerStats {
+                    queue_depth: self.rx.len() + pending,
+                    processed: self.processed,
+                    failed: self.failed,
+                };
+                self.reply(resp, stats);
+            }
+        }
+    }
+
+    fn reply<T>(&mut self, resp: oneshot::Sender<T>, value: T) {
+        if resp.send(value).is_err() {
+            self.failed += 1;
+        }
+    }
+}
+
+impl WorkerHandle {
+    pub async fn set(
+        &self,
+        processor: Processor,
+        correlation_id: CorrelationId,
+        timestamp: i64,
+        amount: u64,
+    ) {
+        let cmd = Command::Set {
+            processor,
+            correlation_id,
+            timestamp,
+            amount,
+        };
+
+        self.tx.send(cmd).await.unwrap();
+    }
+
+    pub async fn get(&self, from: Option<i64>, to: Option<i64>) -> Totals {
+        let (resp, rx) = oneshot::channel();
+
+        self.tx.send(Command::Get { from, to, resp }).await.unwrap();
+
+        rx.await.unwrap()
+    }
+
+    pub async fn purge(&self) {
+        self.tx.send(Command::Purge).await.unwrap();
+    }
+
+    pub async fn flush(&self) {
+        let (resp, rx) = oneshot::channel();
+
+        self.tx.send(Command::Flush(resp)).await.unwrap();
+
+        rx.await.unwrap()
+    }
+
+    pub async fn stats(&self) -> WorkerStats {
+        let (resp, rx) = oneshot::channel();
+
+        self.tx.send(Command::Stats(resp)).await.unwrap();
+
+        rx.await.unwrap()
+    }
+}
+
+/// Which of `shards` shards holds payments at `timestamp`, for anything writing the Dbs besides
+/// the pool itself.
+pub fn shard_index(timestamp: i64, shards: usize) -> usize {
+    // Fibonacci hashing, consecutive timestamps land on different shards. The low bits of the
+    // product are the timestamp's own, so millisecond timestamps would all share a shard
+    let hash = (timestamp as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 32;
+
+    (hash % shards as u64) as usize
+}
+
+/// One `Worker` per shard of the in-process Dbs. Payments go to the shard their timestamp hashes
+/// to, which keeps every confirmation of a correlation id at a given timestamp on the same shard
+/// for `Db::new`'s dedup. Summaries ask every shard and add up the answers, so unlike a single
+/// worker they are only consistent per shard.
+#[derive(Clone)]
+pub struct WorkerPool {
+    workers: Vec<WorkerHandle>,
+}
+
+impl WorkerPool {
+    /// Each worker queues at most `capacity` commands, see `Worker`.
+    pub fn spawn(shards: &[MemoryStorage], capacity: usize) -> Self {
+        assert!(!shards.is_empty(), "worker pool needs at least one shard");
+
+        let workers = shards
+            .iter()
+            .map(|shard| Worker::spawn(shard.default.clone(), shard.fallback.clone(), capacity))
+            .collect();
+
+        Self { workers }
+    }
+
+    fn shard(&self, timestamp: i64) -> &WorkerHandle {
+        &self.workers[shard_index(timestamp, self.workers.len())]
+    }
+
+    pub async fn get(&self, from: Option<i64>, to: Option<i64>) -> Totals {
+        let totals = join_all(self.workers.iter().map(|worker| worker.get(from, to))).await;
+
+        totals
+            .into_iter()
+            .fold([(0, 0); 2], |acc, [default, fallback]| {
+                [
+                    (acc[0].0 + default.0, acc[0].1 + default.1),
+                    (acc[1].0 + fallback.0, acc[1].1 + fallback.1),
+                ]
+            })
+    }
+
+    pub async fn purge(&self) {
+        join_all(self.workers.iter().map(WorkerHandle::purge)).await;
+    }
+
+    /// Waits until every command queued so far, on any shard, has been applied.
+    pub async fn flush(&self) {
+        join_all(self.workers.iter().map(WorkerHandle::flush)).await;
+    }
+
+    /// Stats of every shard, in shard order.
+    pub async fn stats(&self) -> Vec<WorkerStats> {
+        join_all(self.workers.iter().map(WorkerHandle::stats)).await
+    }
+}
+
+#[async_trait]
+impl Storage for WorkerPool {
+    async fn record(
+        &self,
+        processor: Processor,
+        correlation_id: &CorrelationId,
+        timestamp: i64,
+        amount: u64,
+    ) -> Result<(), BackendError> {
+        self.shard(timestamp)
+            .set(processor, correlation_id.clone(), timestamp, amount)
+            .await;
+
+        Ok(())
+    }
+
+    async fn summarize(
+        &self,
+        processor: Processor,
+        from: Option<i64>,
+        to: Option<i64>,
+    ) -> Result<(u64, u64), BackendError> {
+        let [default, fallback] = self.get(from, to).await;
+
+        Ok(match processor {
+            Processor::Default => default,
+            Processor::Fallback => fallback,
+        })
+    }
+
+    async fn summarize_all(
+        &self,
+        from: Option<i64>,
+        to: Option<i64>,
+    ) -> Result<Totals, BackendError> {
+        Ok(self.get(from, to).await)
+    }
+
+    fn is_shared(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::FutureExt;
+
+    use super::*;
+    use crate::db::SCAN_CHUNK;
+
+    fn id(n: u32) -> CorrelationId {
+        format!("4a7901b8-7d26-4d9d-aa19-{n:012}").parse().unwrap()
+    }
+
+    #[test]
+    fn millisecond_timestamps_spread_over_every_shard() {
+        let mut used = [0; 4];
+
+        for ms in 0..64 {
+            used[shard_index(ms * 1000, used.len())] += 1;
+        }
+
+        assert!(used.iter().all(|&n| n > 0), "{used:?}");
+    }
+
+    #[tokio::test]
+    async fn reads_see_every_write_queued_before_them() {
+        let worker = Worker::spawn(Db::new(true), Db::new(true), 16);
+
+        worker.set(Processor::Default, id(1), 10, 100).await;
+        worker.set(Processor::Fallback, id(2), 20, 200).await;
+        // Same id at the same timestamp, deduplicated
+        worker.set(Processor::Default, id(1), 10, 100).await;
+
+        assert_eq!(worker.get(None, None).await, [(1, 100), (1, 200)]);
+        assert_eq!(worker.get(Some(15), None).await, [(0, 0), (1, 200)]);
+
+        worker.purge().await;
+        assert_eq!(worker.get(None, None).await, [(0, 0), (0, 0)]);
+    }
+
+    #[tokio::test]
+    async fn writers_wait_once_the_queue_is_full() {
+        let worker = Worker::spawn(Db::new(false), Db::new(false), 1);
+
+        // The worker task can't run before this test yields
+        assert!(
+            worker
+                .set(Processor::Default, id(1), 10, 100)
+                .now_or_never()
+                .is_some()
+        );
+        assert!(
+            worker
+                .set(Processor::Default, id(2), 20, 100)
+                .now_or_never()
+                .is_none()
+        );
+
+        worker.flush().await;
+        assert_eq!(worker.get(None, None).await, [(1, 100), (0, 0)]);
+        assert_eq!(worker.stats().await.queue_depth, 0);
+    }
+
+    #[tokio::test]
+    async fn pools_add_up_their_shards() {
+        let shards: Vec<_> = (0..3).map(|_| MemoryStorage::default()).collect();
+        let pool = WorkerPool::spawn(&shards, 16);
+
+        for n in 0..30 {
+            pool.record(Processor::Default, &id(n), n as i64, 10)
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(pool.get(None, None).await, [(30, 300), (0, 0)]);
+        assert!(shards.iter().all(|shard| shard.default.len() < 30));
+    }
+
+    #[tokio::test]
+    async fn large_shards_are_summed_in_chunks() {
+        let shards: Vec<_> = (0..2).map(|_| MemoryStorage::default()).collect();
+        let pool = WorkerPool::spawn(&shards, 1024);
+        let entries = 3 * SCAN_CHUNK as u32;
+
+        for n in 0..entries {
+            pool.record(Processor::Fallback, &id(n), n as i64, 2)
+                .await
+                .unwrap();
+        }
+        assert!(shards.iter().all(|shard| shard.fallback.len() > SCAN_CHUNK));
+
+        let (from, to) = (100, 2 * SCAN_CHUNK as i64);
+        let count = (to - from + 1) as u64;
+
+        assert_eq!(
+            pool.get(Some(from), Some(to)).await,
+            [(0, 0), (count, 2 * count)]
+        );
+        assert_eq!(
+            pool.get(None, None).await,
+            [(0, 0), (entries as u64, 2 * entries as u64)]
+        );
+    }
+}
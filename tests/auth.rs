@@ -0,0 +1,86 @@
+//! With `INTERNAL_TOKEN` set, the `/admin` and `/internal` endpoints answer only requests carrying
+//! it, while the public API stays open.
+
+mod common;
+
+use axum::{
+    Router,
+    body::Body,
+    http::{Request, StatusCode},
+};
+use client_full::{AppState, client::ShowdownClient, router};
+use tower::ServiceExt;
+
+const TOKEN: &str = "s3cret";
+
+async fn app(token: Option<&str>) -> Router {
+    let mut config = common::config(&common::processor().await);
+    config.internal_token = token.map(str::to_string);
+
+    router(AppState::start(config).await)
+}
+
+async fn status(app: &Router, path: &str, token: Option<&str>) -> StatusCode {
+    let mut request = Request::get(path);
+
+    if let Some(token) = token {
+        request = request.header("x-internal-token", token);
+    }
+
+    let response = app
+        .clone()
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    response.status()
+}
+
+#[tokio::test]
+async fn admin_and_internal_endpoints_need_the_token() {
+    let app = app(Some(TOKEN)).await;
+
+    for path in ["/admin/info", "/admin/config", "/internal/ping"] {
+        assert_eq!(status(&app, path, None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            status(&app, path, Some("s3cre")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(status(&app, path, Some(TOKEN)).await, StatusCode::OK);
+    }
+}
+
+#[tokio::test]
+async fn public_endpoints_stay_open() {
+    let app = app(Some(TOKEN)).await;
+
+    for path in ["/healthz", "/payments-summary?only_local=true"] {
+        assert_eq!(status(&app, path, None).await, StatusCode::OK);
+    }
+}
+
+#[tokio::test]
+async fn everything_is_open_without_a_token() {
+    let app = app(None).await;
+
+    assert_eq!(status(&app, "/admin/info", None).await, StatusCode::OK);
+    assert_eq!(status(&app, "/internal/ping", None).await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn client_sends_its_token() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let app = app(Some(TOKEN)).await;
+
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    assert!(ShowdownClient::new(&url).purge().await.is_err());
+    assert!(
+        ShowdownClient::new(&url)
+            .with_token(TOKEN)
+            .purge()
+            .await
+            .is_ok()
+    );
+}
@@ -0,0 +1,149 @@
+//! With `BALANCE_MODE` set, the instance taking public traffic proxies its share of `/payments`
+//! to its peers.
+
+mod common;
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use axum::{
+    Router,
+    body::Body,
+    http::{Request, StatusCode, header},
+    routing::post,
+};
+use client_full::{
+    AppState,
+    balance::{BalanceMode, Balancer},
+    config::Config,
+    correlation::CorrelationId,
+    router,
+};
+use tower::ServiceExt;
+use uuid::Uuid;
+
+/// Answers every request with 200, counting them.
+async fn counting_server(path: &str) -> (String, Arc<AtomicUsize>) {
+    let count = Arc::new(AtomicUsize::new(0));
+    let counted = count.clone();
+    let app = Router::new().route(
+        path,
+        post(move || async move {
+            counted.fetch_add(1, Ordering::Relaxed);
+            StatusCode::OK
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    (url, count)
+}
+
+fn config(mode: BalanceMode, fraction: f64, peers: usize) -> Config {
+    let mut config = common::CONFIG.clone();
+    config.peer_urls = (0..peers).map(|i| format!("http://peer-{i}")).collect();
+    config.balance_mode = mode;
+    config.balance_fraction = fraction;
+    config
+}
+
+fn ids(n: usize) -> Vec<CorrelationId> {
+    (0..n as u128)
+        .map(|i| Uuid::from_u128(i.wrapping_mul(0x9e37_79b9_7f4a_7c15_f39c_c060_5ced_c835)).into())
+        .collect()
+}
+
+#[test]
+fn proxies_about_the_configured_fraction() {
+    let balancer = Balancer::new(&config(BalanceMode::Hash, 0.25, 1));
+    let proxied = ids(10_000)
+        .iter()
+        .filter(|id| balancer.pick(id).is_some())
+        .count();
+
+    assert!((2_000..3_000).contains(&proxied), "{proxied} proxied");
+}
+
+#[test]
+fn off_and_zero_keep_everything() {
+    let off = Balancer::new(&config(BalanceMode::Off, 1.0, 1));
+    let zero = Balancer::new(&config(BalanceMode::Hash, 0.0, 1));
+
+    for id in ids(1_000) {
+        assert_eq!(off.pick(&id), None);
+        assert_eq!(zero.pick(&id), None);
+    }
+}
+
+#[test]
+fn hash_mode_is_consistent_and_even() {
+    let balancer = Balancer::new(&config(BalanceMode::Hash, 1.0, 2));
+    let mut per_peer = [0; 2];
+
+    for id in ids(10_000) {
+        let peer = balancer.pick(&id).unwrap();
+        assert_eq!(balancer.pick(&id), Some(peer));
+        per_peer[peer] += 1;
+    }
+
+    assert!(
+        per_peer.iter().all(|n| (4_500..5_500).contains(n)),
+        "{per_peer:?}"
+    );
+}
+
+#[test]
+fn least_loaded_mode_picks_the_idlest_peer() {
+    let balancer = Arc::new(Balancer::new(&config(BalanceMode::LeastLoaded, 1.0, 2)));
+    let id = ids(1).remove(0);
+    let first = balancer.track(0);
+
+    assert_eq!(balancer.pick(&id), Some(1));
+    drop(first);
+    assert_eq!(balancer.in_flight(0), 0);
+
+    let _second = balancer.track(1);
+    assert_eq!(balancer.pick(&id), Some(0));
+}
+
+#[tokio::test]
+async fn proxied_payments_reach_the_peer() {
+    let (processor, submitted) = counting_server("/payments").await;
+    let (peer, forwarded) = counting_server("/internal/payments").await;
+
+    let mut config = config(BalanceMode::Hash, 1.0, 1);
+    config.peer_urls = vec![peer];
+    config.default_processor_url = processor.clone();
+    config.fallback_processor_url = processor;
+    config.bootstrap_timeout = Duration::from_millis(100);
+    let app = router(AppState::start(config).await);
+
+    for id in ids(10) {
+        let request = Request::post("/payments")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(format!(
+                "{{\"correlationId\":\"{id}\",\"amount\":19.9}}"
+            )))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    for _ in 0..100 {
+        if forwarded.load(Ordering::Relaxed) == 10 {
+            break;
+        }
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    assert_eq!(forwarded.load(Ordering::Relaxed), 10);
+    assert_eq!(submitted.load(Ordering::Relaxed), 0);
+}
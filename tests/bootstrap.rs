@@ -0,0 +1,86 @@
+//! A starting instance fills its Db from the backup its peer keeps of it.
+
+mod common;
+
+use axum::{
+    Json, Router,
+    body::{Body, to_bytes},
+    extract::Query,
+    http::Request,
+    routing::get,
+};
+use client_full::{
+    AppState, Processor, ProcessorSummaries, SnapshotQueryParams, SnapshotScope, db::StateSnapshot,
+    protocol::ProtocolVersion, router,
+};
+use tower::ServiceExt;
+
+/// A peer holding a backup of two payments, and nothing of its own.
+async fn peer() -> String {
+    async fn state_snapshot(Query(params): Query<SnapshotQueryParams>) -> Vec<u8> {
+        if !matches!(params.scope, Some(SnapshotScope::Peer)) {
+            return Vec::new();
+        }
+
+        let mut snapshot = StateSnapshot::default();
+        snapshot.push(Processor::Default, 1_000_000, 1, 1990);
+        snapshot.push(Processor::Fallback, 2_000_000, 1, 500);
+
+        snapshot.to_bytes()
+    }
+
+    common::serve(
+        Router::new()
+            .route(
+                "/internal/version",
+                get(|| async { Json(ProtocolVersion::OURS) }),
+            )
+            .route("/internal/v1/state-snapshot", get(state_snapshot)),
+    )
+    .await
+}
+
+async fn summary(app: &Router, query: &str) -> ProcessorSummaries {
+    let request = Request::get(format!("/payments-summary?only_local=true{query}"))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+    serde_json::from_slice(&body).unwrap()
+}
+
+async fn bootstrapped() -> Router {
+    let mut config = common::CONFIG.clone();
+    config.peer_urls = vec![peer().await];
+
+    router(AppState::start(config).await)
+}
+
+#[tokio::test]
+async fn db_is_restored_from_the_peer_backup() {
+    let summary = summary(&bootstrapped().await, "").await;
+
+    assert!(!summary.partial);
+    assert_eq!(summary.default_sum.total_requests, 1);
+    assert_eq!(summary.default_sum.total_amount, 19.9);
+    assert_eq!(summary.fallback.total_requests, 1);
+    assert_eq!(summary.fallback.total_amount, 5.0);
+}
+
+#[tokio::test]
+async fn tenant_and_processed_summaries_over_restored_payments_are_partial() {
+    let app = bootstrapped().await;
+
+    assert!(summary(&app, "&tenant=acme").await.partial);
+    assert!(summary(&app, "&timestamp_basis=processed").await.partial);
+    assert!(
+        summary(&app, "&timestamp_basis=processed&from=1970-01-01T00:00:01Z")
+            .await
+            .partial
+    );
+
+    // Nothing was restored after the latest backed up payment
+    let after = "&tenant=acme&timestamp_basis=processed&from=1970-01-01T00:00:03Z";
+    assert!(!summary(&app, after).await.partial);
+}
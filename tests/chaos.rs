@@ -0,0 +1,67 @@
+//! Faults `Chaos` injects for the odds it was set to.
+
+#![cfg(feature = "chaos")]
+
+use std::time::{Duration, Instant};
+
+use client_full::chaos::{Chaos, ChaosSettings, Fault, Faults, Target};
+
+#[tokio::test]
+async fn certain_faults_always_happen_and_only_on_their_target() {
+    let chaos = Chaos::default();
+    chaos
+        .set(ChaosSettings {
+            processor: Faults {
+                fail_probability: 1.0,
+                ..Default::default()
+            },
+            peer: Faults {
+                drop_probability: 1.0,
+                ..Default::default()
+            },
+        })
+        .unwrap();
+
+    for _ in 0..10 {
+        assert_eq!(chaos.inject(Target::Processor).await, Some(Fault::Fail));
+        assert_eq!(chaos.inject(Target::Peer).await, Some(Fault::Drop));
+    }
+}
+
+#[tokio::test]
+async fn delays_wait_before_letting_the_request_through() {
+    let chaos = Chaos::default();
+    chaos
+        .set(ChaosSettings {
+            peer: Faults {
+                delay_probability: 1.0,
+                delay_ms: 50,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .unwrap();
+
+    let started = Instant::now();
+    assert_eq!(chaos.inject(Target::Peer).await, None);
+    assert!(started.elapsed() >= Duration::from_millis(50));
+
+    let started = Instant::now();
+    assert_eq!(chaos.inject(Target::Processor).await, None);
+    assert!(started.elapsed() < Duration::from_millis(50));
+}
+
+#[test]
+fn probabilities_outside_zero_to_one_are_rejected() {
+    let chaos = Chaos::default();
+    let settings = ChaosSettings {
+        processor: Faults {
+            drop_probability: 1.5,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    assert!(chaos.set(settings).is_err());
+    assert_eq!(chaos.settings().processor.drop_probability, 0.0);
+}
@@ -0,0 +1,28 @@
+//! `requestedAt` stamps from the monotonic clock never repeat or go back, however many threads
+//! take them at once.
+
+use std::{sync::Arc, thread};
+
+use client_full::clock::RequestClock;
+
+#[test]
+fn monotonic_stamps_are_strictly_increasing() {
+    let clock = Arc::new(RequestClock::new(true));
+    let threads = (0..4).map(|_| {
+        let clock = clock.clone();
+        thread::spawn(move || (0..10_000).map(|_| clock.now()).collect::<Vec<_>>())
+    });
+    let mut all = Vec::new();
+
+    for thread in threads {
+        let stamps = thread.join().unwrap();
+        assert!(stamps.windows(2).all(|pair| pair[0] < pair[1]));
+        all.extend(stamps);
+    }
+
+    let taken = all.len();
+    all.sort();
+    all.dedup();
+
+    assert_eq!(all.len(), taken);
+}
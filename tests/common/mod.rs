@@ -0,0 +1,52 @@
+//! Fixtures shared by the integration tests. Each test binary only uses some of them.
+#![allow(dead_code)]
+
+use std::{sync::LazyLock, time::Duration};
+
+use axum::{Router, http::StatusCode, routing::post};
+use client_full::config::Config;
+
+// Nothing listens on port 1, so every peer and processor request fails right away
+pub const UNREACHABLE: &str = "http://127.0.0.1:1";
+
+/// The config every test starts from. Read once and never from the process env, which tests
+/// running on other threads may be reading at the same time.
+pub static CONFIG: LazyLock<Config> = LazyLock::new(|| {
+    let mut config = Config::from_lookup(|key| match key {
+        "PEER_URL" | "DEFAULT_PROCESSOR_URL" | "FALLBACK_PROCESSOR_URL" => {
+            Some(UNREACHABLE.to_string())
+        }
+        "INSTANCE_ID" => Some("test".to_string()),
+        _ => None,
+    });
+    config.bootstrap_timeout = Duration::from_millis(100);
+    config
+});
+
+/// `CONFIG` with both processors at `processor`.
+pub fn config(processor: &str) -> Config {
+    let mut config = CONFIG.clone();
+    config.default_processor_url = processor.to_string();
+    config.fallback_processor_url = processor.to_string();
+    config
+}
+
+/// Serves `app` on a loopback port, returning its URL.
+pub async fn serve(app: Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    url
+}
+
+/// A processor accepting every payment.
+pub async fn processor() -> String {
+    processor_answering(StatusCode::OK).await
+}
+
+/// A processor answering every payment with `status`.
+pub async fn processor_answering(status: StatusCode) -> String {
+    serve(Router::new().route("/payments", post(move || async move { status }))).await
+}
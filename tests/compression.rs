@@ -0,0 +1,69 @@
+//! Summaries and exports are compressed for clients that accept it, and imports may be.
+
+#![cfg(feature = "compression")]
+
+mod common;
+
+use std::io::{Read, Write};
+
+use axum::{
+    Router,
+    body::{Body, to_bytes},
+    http::{Request, StatusCode, header},
+};
+use client_full::{AppState, router};
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use tower::ServiceExt;
+
+async fn app() -> Router {
+    router(AppState::start(common::CONFIG.clone()).await)
+}
+
+#[tokio::test]
+async fn gzipped_import_round_trips_through_a_gzipped_export() {
+    let app = app().await;
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(b"{\"processor\":\"default\",\"timestamp\":5,\"count\":1,\"amount\":100}\n")
+        .unwrap();
+
+    let import = Request::post("/admin/import?format=jsonl")
+        .header(header::CONTENT_ENCODING, "gzip")
+        .body(Body::from(encoder.finish().unwrap()))
+        .unwrap();
+    let response = app.clone().oneshot(import).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let export = Request::get("/admin/export")
+        .header(header::ACCEPT_ENCODING, "gzip")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(export).await.unwrap();
+    assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let mut csv = String::new();
+    GzDecoder::new(&body[..]).read_to_string(&mut csv).unwrap();
+
+    assert_eq!(csv, "processor,timestamp,count,amount\ndefault,5,1,100\n");
+}
+
+#[tokio::test]
+async fn summaries_are_compressed_only_when_accepted() {
+    let app = app().await;
+    let summary = |encoding: Option<&str>| {
+        let mut request = Request::get("/payments-summary?only_local=true");
+
+        if let Some(encoding) = encoding {
+            request = request.header(header::ACCEPT_ENCODING, encoding);
+        }
+
+        request.body(Body::empty()).unwrap()
+    };
+
+    let response = app.clone().oneshot(summary(Some("br"))).await.unwrap();
+    assert_eq!(response.headers()[header::CONTENT_ENCODING], "br");
+
+    let response = app.oneshot(summary(None)).await.unwrap();
+    assert!(response.headers().get(header::CONTENT_ENCODING).is_none());
+}
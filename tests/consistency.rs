@@ -0,0 +1,154 @@
+//! The backup an instance keeps of its peer's Db is checked against the peer's own minute by
+//! minute, and the minutes that differ are fetched again.
+
+mod common;
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    Json, Router,
+    body::{Body, to_bytes},
+    extract::{Query, State},
+    http::Request,
+    routing::get,
+};
+use chrono::{TimeZone, Utc};
+use client_full::{
+    AppState, Processor, SnapshotQueryParams, SnapshotScope,
+    consistency::{BUCKET, Checksums},
+    db::StateSnapshot,
+    protocol::ProtocolVersion,
+    router,
+};
+use tower::ServiceExt;
+
+type Records = Arc<Mutex<Vec<(Processor, i64, u64, u64)>>>;
+
+fn minute(n: i64) -> i64 {
+    Utc.with_ymd_and_hms(2025, 7, 10, 12, 0, 0)
+        .unwrap()
+        .timestamp_micros()
+        + n * BUCKET
+}
+
+fn snapshot(records: &[(Processor, i64, u64, u64)]) -> StateSnapshot {
+    let mut snapshot = StateSnapshot::default();
+
+    for (processor, timestamp, count, amount) in records {
+        snapshot.push(*processor, *timestamp, *count, *amount);
+    }
+
+    snapshot
+}
+
+/// A peer whose Db holds `records`, holding no backup of anyone's.
+async fn peer(records: Records) -> String {
+    async fn state_snapshot(
+        State(records): State<Records>,
+        Query(params): Query<SnapshotQueryParams>,
+    ) -> Vec<u8> {
+        if matches!(params.scope, Some(SnapshotScope::Peer)) {
+            return Vec::new();
+        }
+
+        let from = params.from.map_or(i64::MIN, |dt| dt.timestamp_micros());
+        let to = params.to.map_or(i64::MAX, |dt| dt.timestamp_micros());
+        let mut snapshot = snapshot(&records.lock().unwrap());
+        snapshot.retain(|_, timestamp| (from..=to).contains(&timestamp));
+
+        snapshot.to_bytes()
+    }
+
+    common::serve(
+        Router::new()
+            .route(
+                "/internal/version",
+                get(|| async { Json(ProtocolVersion::OURS) }),
+            )
+            .route("/internal/v1/state-snapshot", get(state_snapshot))
+            .route(
+                "/internal/v1/checksums",
+                get(|State(records): State<Records>| async move {
+                    Json(Checksums::of(&snapshot(&records.lock().unwrap())))
+                }),
+            )
+            .with_state(records),
+    )
+    .await
+}
+
+async fn backup_checksums(app: &Router) -> Checksums {
+    let request = Request::get("/internal/v1/checksums?scope=peer")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[test]
+fn only_settled_minutes_that_differ_diverge() {
+    let ours = Checksums::of(&snapshot(&[
+        (Processor::Default, minute(0), 1, 100),
+        (Processor::Default, minute(1) + 5, 1, 100),
+        (Processor::Fallback, minute(2), 1, 100),
+    ]));
+    let theirs = Checksums::of(&snapshot(&[
+        (Processor::Default, minute(0), 1, 100),
+        (Processor::Default, minute(1) + 5, 2, 300),
+        (Processor::Fallback, minute(3), 1, 100),
+    ]));
+
+    assert_eq!(
+        ours.diverging(&theirs, minute(3)),
+        [
+            (Processor::Default, minute(1)),
+            (Processor::Fallback, minute(2))
+        ]
+    );
+    assert!(ours.diverging(&theirs, minute(1)).is_empty());
+}
+
+#[tokio::test]
+async fn diverging_minutes_are_fetched_again() {
+    let records: Records = Arc::new(Mutex::new(vec![
+        (Processor::Default, minute(0), 1, 100),
+        (Processor::Fallback, minute(1), 1, 100),
+    ]));
+    let peer = peer(records.clone()).await;
+    let mut config = common::config(&common::processor().await);
+    config.peer_urls = vec![peer];
+    // Backed up once right away, never again
+    config.peer_backup_interval = Duration::from_secs(3600);
+    config.consistency_check_interval = Duration::from_millis(50);
+    config.consistency_check_settle = Duration::ZERO;
+    config.consistency_resync = true;
+    let app = router(AppState::start(config).await);
+
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert_eq!(
+        backup_checksums(&app).await,
+        Checksums::of(&snapshot(&records.lock().unwrap()))
+    );
+
+    // As if the backup had missed a payment
+    records
+        .lock()
+        .unwrap()
+        .push((Processor::Fallback, minute(1) + 7, 1, 250));
+    let expected = Checksums::of(&snapshot(&records.lock().unwrap()));
+
+    for _ in 0..100 {
+        if backup_checksums(&app).await == expected {
+            return;
+        }
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    panic!("backup never caught up with the peer");
+}
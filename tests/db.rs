@@ -0,0 +1,239 @@
+//! Checks `Db` against a plain list of payments for arbitrary writes and ranges, and its dedup
+//! window.
+
+use std::time::Duration;
+
+use client_full::{Db, correlation::CorrelationId, db::Resolution};
+use proptest::prelude::*;
+use uuid::Uuid;
+
+#[derive(Clone, Debug)]
+enum Op {
+    Set { id: u8, timestamp: i64, amount: u64 },
+    Get { from: Option<i64>, to: Option<i64> },
+}
+
+fn correlation_id(id: u8) -> CorrelationId {
+    Uuid::from_u128(id as u128).into()
+}
+
+// Timestamps are drawn from a narrow range so ranges often start or end right on a payment
+fn timestamp() -> impl Strategy<Value = i64> {
+    -20i64..20
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        (any::<u8>(), timestamp(), 0u64..1_000_000).prop_map(|(id, timestamp, amount)| Op::Set {
+            id,
+            timestamp,
+            amount
+        }),
+        (
+            proptest::option::of(timestamp()),
+            proptest::option::of(timestamp())
+        )
+            .prop_map(|(from, to)| Op::Get { from, to }),
+    ]
+}
+
+/// Sums the payments within `[from, to]`, with `None` leaving that side unbounded.
+fn expected(payments: &[(u8, i64, u64)], from: Option<i64>, to: Option<i64>) -> (u64, u64) {
+    payments
+        .iter()
+        .filter(|(_, ts, _)| from.is_none_or(|from| *ts >= from) && to.is_none_or(|to| *ts <= to))
+        .fold((0, 0), |acc, (_, _, amount)| (acc.0 + 1, acc.1 + amount))
+}
+
+fn resolution() -> impl Strategy<Value = Resolution> {
+    prop_oneof![
+        Just(Resolution::Micro),
+        Just(Resolution::Milli),
+        Just(Resolution::Second),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn get_matches_every_set_within_the_range(ops in proptest::collection::vec(op(), 0..200)) {
+        let db = Db::new(false);
+        let mut payments = Vec::new();
+
+        // Reads are interleaved with the writes so cached summaries get invalidated along the way
+        for op in ops {
+            match op {
+                Op::Set { id, timestamp, amount } => {
+                    db.set(&correlation_id(id), timestamp, amount);
+                    payments.push((id, timestamp, amount));
+                }
+                Op::Get { from, to } => {
+                    prop_assert_eq!(db.get(from, to), expected(&payments, from, to));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn dedup_counts_an_id_once_per_timestamp(ops in proptest::collection::vec(op(), 0..200)) {
+        let db = Db::new(true);
+        let mut payments: Vec<(u8, i64, u64)> = Vec::new();
+
+        for op in ops {
+            match op {
+                Op::Set { id, timestamp, amount } => {
+                    db.set(&correlation_id(id), timestamp, amount);
+
+                    if !payments.iter().any(|(i, ts, _)| *i == id && *ts == timestamp) {
+                        payments.push((id, timestamp, amount));
+                    }
+                }
+                Op::Get { from, to } => {
+                    prop_assert_eq!(db.get(from, to), expected(&payments, from, to));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ranges_are_inclusive_on_both_ends(timestamp in timestamp(), amount in 0u64..1_000_000) {
+        let db = Db::new(false);
+        db.set(&correlation_id(0), timestamp, amount);
+
+        prop_assert_eq!(db.get(Some(timestamp), Some(timestamp)), (1, amount));
+        prop_assert_eq!(db.get(Some(timestamp), None), (1, amount));
+        prop_assert_eq!(db.get(None, Some(timestamp)), (1, amount));
+        prop_assert_eq!(db.get(Some(timestamp + 1), None), (0, 0));
+        prop_assert_eq!(db.get(None, Some(timestamp - 1)), (0, 0));
+    }
+
+    #[test]
+    fn coarser_resolutions_count_whole_steps(
+        resolution in resolution(),
+        payments in proptest::collection::vec(
+            (-3_000_000i64..3_000_000, 0u64..1_000_000),
+            0..100,
+        ),
+        from in proptest::option::of(-3_000_000i64..3_000_000),
+        to in proptest::option::of(-3_000_000i64..3_000_000),
+    ) {
+        let db = Db::with_resolution(false, resolution);
+        let truncated: Vec<_> = payments
+            .iter()
+            .enumerate()
+            .map(|(i, (timestamp, amount))| {
+                db.set(&correlation_id(i as u8), *timestamp, *amount);
+                (i as u8, resolution.truncate(*timestamp), *amount)
+            })
+            .collect();
+        // A bound partway through a step takes in the whole step
+        let step_from = from.map(|from| resolution.truncate(from));
+        let step_to = to.map(|to| resolution.truncate(to));
+
+        prop_assert_eq!(db.get(from, to), expected(&truncated, step_from, step_to));
+    }
+
+    #[test]
+    fn eviction_keeps_totals_under_the_cap(
+        max_entries in 0usize..64,
+        payments in proptest::collection::vec((-3_000_000i64..3_000_000, 0u64..1_000_000), 0..300),
+    ) {
+        let db = Db::new(false).with_limit(max_entries, 1_000);
+        let mut newest = i64::MIN;
+
+        for (i, (timestamp, amount)) in payments.iter().enumerate() {
+            db.set(&correlation_id(i as u8), *timestamp, *amount);
+            newest = newest.max(*timestamp);
+        }
+
+        let indexed: Vec<_> = payments.iter().map(|(ts, amount)| (0, *ts, *amount)).collect();
+        prop_assert_eq!(db.get(None, None), expected(&indexed, None, None));
+
+        if max_entries > 0 {
+            prop_assert!(db.len() <= max_entries.max(8));
+            // Only older entries are ever compacted
+            prop_assert_eq!(db.get(Some(newest), None), expected(&indexed, Some(newest), None));
+        }
+    }
+
+    #[test]
+    fn export_adds_up_to_get(
+        payments in proptest::collection::vec((timestamp(), 0u64..1_000_000), 0..100),
+        from in proptest::option::of(timestamp()),
+        to in proptest::option::of(timestamp()),
+    ) {
+        let db = Db::new(false);
+
+        for (i, (timestamp, amount)) in payments.iter().enumerate() {
+            db.set(&correlation_id(i as u8), *timestamp, *amount);
+        }
+
+        let exported = db
+            .export(from, to)
+            .fold((0, 0), |acc, (_, count, amount)| (acc.0 + count, acc.1 + amount));
+        prop_assert_eq!(exported, db.get(from, to));
+    }
+}
+
+#[tokio::test]
+async fn summarize_async_matches_get_past_one_chunk() {
+    // Two copies, so neither answers from a summary the other cached
+    let db = Db::new(false);
+    let reference = Db::new(false);
+
+    for ts in 0..10_000 {
+        db.add(ts, 1, 10);
+        reference.add(ts, 1, 10);
+    }
+
+    for (from, to) in [
+        (None, None),
+        (Some(0), Some(9_999)),
+        (Some(4_095), Some(8_192)),
+        (Some(9_999), None),
+        (None, Some(-1)),
+        (Some(5_000), Some(4_000)),
+    ] {
+        assert_eq!(db.summarize_async(from, to).await, reference.get(from, to));
+    }
+
+    // A write after the summary was cached must still show up
+    db.add(9_999, 1, 10);
+    assert_eq!(db.summarize_async(Some(9_999), None).await, (2, 20));
+}
+
+#[test]
+fn dedup_window_forgets_the_least_recently_seen_past_capacity() {
+    let db = Db::new(true).with_dedup_window(Duration::ZERO, 2);
+
+    db.set(&correlation_id(1), 0, 10);
+    db.set(&correlation_id(2), 0, 10);
+    // Seeing 1 again keeps it over 2
+    db.set(&correlation_id(1), 0, 10);
+    db.set(&correlation_id(3), 0, 10);
+
+    // 2 was forgotten so counts again, 1 was not
+    db.set(&correlation_id(2), 0, 10);
+    db.set(&correlation_id(3), 0, 10);
+    assert_eq!(db.get(None, None), (4, 40));
+
+    let stats = db.dedup_stats().unwrap();
+    assert_eq!(stats.len, 2);
+    assert_eq!(stats.hits, 2);
+    assert_eq!(stats.evictions, 2);
+    assert_eq!(stats.expirations, 0);
+}
+
+#[test]
+fn dedup_window_forgets_ids_after_the_ttl() {
+    let db = Db::new(true).with_dedup_window(Duration::from_millis(20), 0);
+
+    db.set(&correlation_id(1), 0, 10);
+    db.set(&correlation_id(1), 0, 10);
+    std::thread::sleep(Duration::from_millis(30));
+    db.set(&correlation_id(1), 0, 10);
+
+    assert_eq!(db.get(None, None), (2, 20));
+
+    let stats = db.dedup_stats().unwrap();
+    assert_eq!((stats.len, stats.hits, stats.expirations), (1, 1, 1));
+}
@@ -0,0 +1,37 @@
+//! The processors' hostnames are resolved again so a processor moving to another address is
+//! noticed.
+
+use std::net::SocketAddr;
+
+use client_full::dns::DnsWatch;
+
+fn addrs(addrs: &[&str]) -> Vec<SocketAddr> {
+    addrs.iter().map(|addr| addr.parse().unwrap()).collect()
+}
+
+#[test]
+fn only_a_different_address_set_is_a_change() {
+    let dns = DnsWatch::new(["http://payment-processor-default:8080"]);
+    let host = "payment-processor-default:8080";
+
+    assert!(!dns.record(host, addrs(&["172.18.0.2:8080", "172.18.0.3:8080"])));
+    assert!(!dns.record(host, addrs(&["172.18.0.3:8080", "172.18.0.2:8080"])));
+    assert!(dns.record(host, addrs(&["172.18.0.4:8080"])));
+    assert!(!dns.record(host, addrs(&["172.18.0.4:8080"])));
+}
+
+#[test]
+fn ip_literals_are_not_watched() {
+    let dns = DnsWatch::new(["http://127.0.0.1:8001", "http://[::1]:8002"]);
+
+    assert!(dns.is_empty());
+}
+
+#[tokio::test]
+async fn refreshing_a_stable_host_changes_nothing() {
+    let dns = DnsWatch::new(["http://localhost:8001", "http://localhost:8001/admin"]);
+
+    assert!(!dns.is_empty());
+    assert!(!dns.refresh().await);
+    assert!(!dns.refresh().await);
+}
@@ -0,0 +1,89 @@
+//! A processor that takes a payment but fails to say so answers the retry with a 422, which
+//! must still count the payment.
+
+mod common;
+
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    Json, Router,
+    body::{Body, to_bytes},
+    extract::State,
+    http::{Request, StatusCode, header},
+    routing::post,
+};
+use client_full::{
+    AppState, PaymentPayload, ProcessorSummaries, correlation::CorrelationId, router,
+    routing::Strategy,
+};
+use tower::ServiceExt;
+
+/// Processes every payment, but answers 500 the first time it sees a correlationId and 422 after.
+async fn forgetful_processor() -> String {
+    let seen = Arc::new(Mutex::new(HashSet::<CorrelationId>::new()));
+    let app = Router::new()
+        .route("/payments", post(forgetful_payment))
+        .with_state(seen);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    url
+}
+
+async fn forgetful_payment(
+    State(seen): State<Arc<Mutex<HashSet<CorrelationId>>>>,
+    Json(payload): Json<PaymentPayload>,
+) -> StatusCode {
+    if seen.lock().unwrap().insert(payload.correlation_id) {
+        StatusCode::INTERNAL_SERVER_ERROR
+    } else {
+        StatusCode::UNPROCESSABLE_ENTITY
+    }
+}
+
+#[tokio::test]
+async fn duplicate_on_retry_is_recorded_as_processed() {
+    let processor = forgetful_processor().await;
+
+    let mut config = common::config(&processor);
+    // Keeps the retry on the default, which is the one that saw the payment
+    config.routing_strategy = Strategy::HealthBased;
+
+    let app = router(AppState::start(config).await);
+    let payment = Request::post("/payments")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            r#"{"correlationId":"4a7901b8-7d26-4d9d-aa19-4dc1c7cf60b3","amount":19.9}"#,
+        ))
+        .unwrap();
+
+    let response = app.clone().oneshot(payment).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let mut summaries = ProcessorSummaries::default();
+
+    for _ in 0..100 {
+        let request = Request::get("/payments-summary?only_local=true")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        summaries = serde_json::from_slice(&body).unwrap();
+
+        if summaries.default_sum.total_requests > 0 {
+            break;
+        }
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    assert_eq!(summaries.default_sum.total_requests, 1);
+    assert_eq!(summaries.default_sum.total_amount, 19.9);
+    assert_eq!(summaries.fallback.total_requests, 0);
+}
@@ -0,0 +1,210 @@
+//! Runs two instances of the proxy against in-process mock processors and checks that the
+//! summaries of both add up to exactly what was submitted.
+
+use std::{
+    collections::HashSet,
+    net::{SocketAddr, TcpListener},
+    process::{Child, Command, Stdio},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    Json, Router,
+    extract::State,
+    http::StatusCode,
+    routing::{get, post},
+};
+use client_full::{
+    PaymentPayload, ProcessorSummaries, SummaryQueryParams, client::ShowdownClient,
+    correlation::CorrelationId,
+};
+use futures_util::future::join_all;
+
+const PAYMENTS: usize = 500;
+const AMOUNT: f64 = 19.9;
+
+#[derive(Default)]
+struct Processed {
+    ids: HashSet<CorrelationId>,
+    amount_cents: u64,
+}
+
+/// Answers like the real processors: 200 for a new correlationId and 422 for a repeated one.
+async fn mock_processor() -> (String, Arc<Mutex<Processed>>) {
+    let processed = Arc::new(Mutex::new(Processed::default()));
+    let app = Router::new()
+        .route("/payments", post(mock_payment))
+        .route("/payments/service-health", get(|| async { "{}" }))
+        .with_state(processed.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    (url, processed)
+}
+
+async fn mock_payment(
+    State(processed): State<Arc<Mutex<Processed>>>,
+    Json(payload): Json<PaymentPayload>,
+) -> StatusCode {
+    let mut processed = processed.lock().unwrap();
+
+    if !processed.ids.insert(payload.correlation_id) {
+        return StatusCode::UNPROCESSABLE_ENTITY;
+    }
+
+    processed.amount_cents += (payload.amount * 100.0).round() as u64;
+
+    StatusCode::OK
+}
+
+/// A spawned instance, killed when dropped.
+struct Instance {
+    client: ShowdownClient,
+    child: Child,
+}
+
+impl Instance {
+    fn spawn(port: u16, peer_port: u16, default_url: &str, fallback_url: &str) -> Self {
+        let child = Command::new(env!("CARGO_BIN_EXE_client-full"))
+            .arg("serve")
+            .env("PORT", port.to_string())
+            .env("INSTANCE_ID", format!("api-{port}"))
+            .env("PEER_URL", format!("http://127.0.0.1:{peer_port}"))
+            .env("DEFAULT_PROCESSOR_URL", default_url)
+            .env("FALLBACK_PROCESSOR_URL", fallback_url)
+            .env("BOOTSTRAP_TIMEOUT_MS", "100")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        Self {
+            client: ShowdownClient::new(format!("http://127.0.0.1:{port}")),
+            child,
+        }
+    }
+
+    async fn wait_ready(&self) {
+        for _ in 0..100 {
+            if self.client.is_healthy().await {
+                return;
+            }
+
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        panic!("{} never became healthy", self.client.base_url());
+    }
+
+    async fn summary(&self) -> ProcessorSummaries {
+        self.client
+            .get_summary(&SummaryQueryParams::default())
+            .await
+            .unwrap()
+    }
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn free_port() -> u16 {
+    let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+
+    listener.local_addr().unwrap().port()
+}
+
+fn totals(summary: &ProcessorSummaries) -> (u64, u64) {
+    let requests = summary.default_sum.total_requests + summary.fallback.total_requests;
+    let amount = summary.default_sum.total_amount + summary.fallback.total_amount;
+
+    (requests, (amount * 100.0).round() as u64)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn summaries_of_both_instances_match_submitted_payments() {
+    let (default_url, default_processed) = mock_processor().await;
+    let (fallback_url, fallback_processed) = mock_processor().await;
+    let (port_a, port_b) = (free_port(), free_port());
+    let instances = [
+        Instance::spawn(port_a, port_b, &default_url, &fallback_url),
+        Instance::spawn(port_b, port_a, &default_url, &fallback_url),
+    ];
+    for instance in &instances {
+        instance.wait_ready().await;
+    }
+
+    let submissions = (0..PAYMENTS).map(|i| {
+        let instance = &instances[i % instances.len()];
+        let payload = PaymentPayload {
+            correlation_id: format!("00000000-0000-4000-8000-{i:012}").parse().unwrap(),
+            amount: AMOUNT,
+            tenant_id: None,
+        };
+
+        async move { instance.client.submit_payment(&payload).await }
+    });
+
+    for submitted in join_all(submissions).await {
+        submitted.unwrap();
+    }
+
+    let expected = (
+        PAYMENTS as u64,
+        (AMOUNT * 100.0).round() as u64 * PAYMENTS as u64,
+    );
+    let mut summaries = Vec::new();
+
+    // Payments are submitted in the background, give them time to land
+    for _ in 0..100 {
+        summaries = join_all(instances.iter().map(Instance::summary)).await;
+
+        if summaries.iter().all(|summary| totals(summary) == expected) {
+            break;
+        }
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    for summary in &summaries {
+        assert_eq!(totals(summary), expected);
+    }
+
+    {
+        let default = default_processed.lock().unwrap();
+        let fallback = fallback_processed.lock().unwrap();
+
+        assert_eq!(
+            (
+                (default.ids.len() + fallback.ids.len()) as u64,
+                default.amount_cents + fallback.amount_cents
+            ),
+            expected
+        );
+    }
+
+    for instance in &instances {
+        instance.client.purge().await.unwrap();
+    }
+
+    // Peer summaries fetched just before the purge may still be served for `PEER_SUMMARY_TTL_MS`
+    for _ in 0..100 {
+        summaries = join_all(instances.iter().map(Instance::summary)).await;
+
+        if summaries.iter().all(|summary| totals(summary) == (0, 0)) {
+            break;
+        }
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    for summary in &summaries {
+        assert_eq!(totals(summary), (0, 0));
+    }
+}
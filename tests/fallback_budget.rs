@@ -0,0 +1,114 @@
+//! The fallback budget caps the share of each window routed to the fallback, holding payments
+//! back past it.
+
+mod common;
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use axum::{
+    Router,
+    body::{Body, to_bytes},
+    http::{Request, StatusCode, header},
+    routing::post,
+};
+use client_full::{
+    AppState, Processor,
+    fallback_budget::{BudgetBasis, FallbackBudget},
+    router,
+    routing::Strategy,
+};
+use serde_json::Value;
+use tower::ServiceExt;
+
+#[test]
+fn fallback_stays_within_its_share_of_requests() {
+    let budget = FallbackBudget::new(0.2, BudgetBasis::Requests, Duration::from_secs(60));
+
+    assert!(!budget.try_route(Processor::Fallback, 10.0));
+
+    for _ in 0..4 {
+        assert!(budget.try_route(Processor::Default, 10.0));
+    }
+
+    assert!(budget.try_route(Processor::Fallback, 10.0));
+    assert!(!budget.try_route(Processor::Fallback, 10.0));
+    assert_eq!(budget.used(), 0.2);
+}
+
+#[test]
+fn amount_basis_weighs_by_amount() {
+    let budget = FallbackBudget::new(0.5, BudgetBasis::Amount, Duration::from_secs(60));
+
+    assert!(budget.try_route(Processor::Default, 100.0));
+    assert!(!budget.try_route(Processor::Fallback, 150.0));
+    assert!(budget.try_route(Processor::Fallback, 60.0));
+    assert!(budget.try_route(Processor::Fallback, 40.0));
+    assert!(!budget.try_route(Processor::Fallback, 1.0));
+}
+
+#[test]
+fn every_window_starts_over() {
+    let budget = FallbackBudget::new(0.5, BudgetBasis::Requests, Duration::from_millis(20));
+
+    assert!(budget.try_route(Processor::Default, 1.0));
+    assert!(budget.try_route(Processor::Fallback, 1.0));
+    assert!(!budget.try_route(Processor::Fallback, 1.0));
+
+    std::thread::sleep(Duration::from_millis(30));
+
+    assert_eq!(budget.used(), 0.0);
+    assert!(budget.try_route(Processor::Default, 1.0));
+    assert!(budget.try_route(Processor::Fallback, 1.0));
+}
+
+#[tokio::test]
+async fn payments_over_budget_are_held_back() {
+    let submitted = Arc::new(AtomicUsize::new(0));
+    let counter = submitted.clone();
+    let processor = Router::new().route(
+        "/payments",
+        post(move || async move {
+            counter.fetch_add(1, Ordering::Relaxed);
+            StatusCode::OK
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, processor).await.unwrap() });
+
+    let mut config = common::config(&url);
+    config.routing_strategy = Strategy::FallbackOnly;
+    config.fallback_budget = 0.5;
+    config.fallback_budget_delay = Duration::from_millis(10);
+    config.payment_events_capacity = 100;
+    let app = router(AppState::start(config).await);
+
+    let payment = Request::post("/payments")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            r#"{"correlationId":"4a7901b8-7d26-4d9d-aa19-4dc1c7cf60b3","amount":19.9}"#,
+        ))
+        .unwrap();
+    app.clone().oneshot(payment).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let request = Request::get("/admin/trace/4a7901b8-7d26-4d9d-aa19-4dc1c7cf60b3")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let trace: Value = serde_json::from_slice(&body).unwrap();
+    let events = trace["events"].as_array().unwrap();
+
+    // Nothing went to the default to make room for the fallback
+    assert_eq!(submitted.load(Ordering::Relaxed), 0);
+    assert!(events.len() > 2);
+    assert!(events[1..].iter().all(|event| event["event"] == "deferred"));
+}
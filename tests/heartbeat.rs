@@ -0,0 +1,97 @@
+//! Peers that stop answering heartbeats are left out of summaries, which say so.
+
+mod common;
+
+use std::time::Duration;
+
+use axum::{
+    Json, Router,
+    body::{Body, to_bytes},
+    http::{Request, StatusCode},
+    routing::get,
+};
+use client_full::{AppState, ProcessorSummaries, heartbeat::Heartbeats, router};
+use serde_json::Value;
+use tower::ServiceExt;
+
+/// An instance with nothing recorded, answering pings.
+async fn live_peer() -> String {
+    common::serve(
+        Router::new()
+            .route("/internal/ping", get(|| async { StatusCode::OK }))
+            .route(
+                "/payments-summary",
+                get(|| async { Json(ProcessorSummaries::default()) }),
+            ),
+    )
+    .await
+}
+
+async fn app(peer: String) -> Router {
+    let mut config = common::config(&common::processor().await);
+    config.peer_urls = vec![peer];
+    config.heartbeat_interval = Duration::from_millis(20);
+    config.heartbeat_misses = 2;
+    config.peer_summary_ttl = Duration::ZERO;
+
+    router(AppState::start(config).await)
+}
+
+async fn get_json(app: &Router, uri: &str) -> Value {
+    let request = Request::get(uri).body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+    serde_json::from_slice(&body).unwrap()
+}
+
+/// The peer's liveness in `/admin/info`, once its first heartbeats are in.
+async fn liveness(app: &Router, peer: &str, alive: bool) -> Value {
+    for _ in 0..100 {
+        let info = get_json(app, "/admin/info").await;
+
+        if info["peers"][peer]["alive"] == alive {
+            return info["peers"][peer].clone();
+        }
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    panic!("{peer} never marked alive={alive}");
+}
+
+#[tokio::test]
+async fn dead_peer_makes_the_summary_partial() {
+    let peer = "http://127.0.0.1:1".to_string();
+    let app = app(peer.clone()).await;
+
+    liveness(&app, &peer, false).await;
+
+    let summary = get_json(&app, "/payments-summary").await;
+    assert_eq!(summary["partial"], true);
+    assert_eq!(summary["default"]["totalRequests"], 0);
+}
+
+#[tokio::test]
+async fn live_peer_reports_its_round_trip() {
+    let peer = live_peer().await;
+    let app = app(peer.clone()).await;
+
+    let liveness = liveness(&app, &peer, true).await;
+    assert!(liveness["rtt_micros"].is_u64());
+
+    let summary = get_json(&app, "/payments-summary").await;
+    assert!(summary.get("partial").is_none());
+}
+
+#[test]
+fn peers_are_dead_after_enough_misses_in_a_row() {
+    let heartbeats = Heartbeats::new(2);
+
+    assert!(heartbeats.is_alive("peer"));
+    assert!(heartbeats.record("peer", None).alive);
+    assert!(!heartbeats.record("peer", None).alive);
+    assert!(!heartbeats.is_alive("peer"));
+    assert!(heartbeats.record("peer", Some(300)).alive);
+    assert!(heartbeats.record("peer", None).alive);
+}
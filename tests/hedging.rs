@@ -0,0 +1,107 @@
+//! With `HEDGE_DELAY_MS`, a payment the chosen processor is slow to answer is also sent to the
+//! other one, and what both answered counts towards their health.
+
+mod common;
+
+use std::time::Duration;
+
+use axum::{
+    Router,
+    body::{Body, to_bytes},
+    http::{Request, StatusCode, header},
+    routing::post,
+};
+use client_full::{AppState, ProcessorSummaries, router};
+use serde_json::Value;
+use tower::ServiceExt;
+
+/// A processor answering every payment with `status` after `delay`.
+async fn processor(status: StatusCode, delay: Duration) -> String {
+    common::serve(Router::new().route(
+        "/payments",
+        post(move || async move {
+            tokio::time::sleep(delay).await;
+            status
+        }),
+    ))
+    .await
+}
+
+async fn app(default: (StatusCode, u64), fallback: (StatusCode, u64)) -> Router {
+    let mut config = common::CONFIG.clone();
+    config.default_processor_url = processor(default.0, Duration::from_millis(default.1)).await;
+    config.fallback_processor_url = processor(fallback.0, Duration::from_millis(fallback.1)).await;
+    config.hedge_delay = Duration::from_millis(20);
+    config.sync_submission = true;
+
+    router(AppState::start(config).await)
+}
+
+async fn get(app: &Router, uri: &str) -> Value {
+    let request = Request::get(uri).body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+    serde_json::from_slice(&body).unwrap()
+}
+
+async fn pay(app: &Router) -> (StatusCode, ProcessorSummaries) {
+    let request = Request::post("/payments")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            r#"{"correlationId":"4a7901b8-7d26-4d9d-aa19-4dc1c7cf60b3","amount":19.9}"#,
+        ))
+        .unwrap();
+    let status = app.clone().oneshot(request).await.unwrap().status();
+    let summary = get(app, "/payments-summary?only_local=true").await;
+
+    (status, serde_json::from_value(summary).unwrap())
+}
+
+#[tokio::test]
+async fn the_primary_failing_counts_when_the_hedge_wins() {
+    let app = app(
+        (StatusCode::INTERNAL_SERVER_ERROR, 100),
+        (StatusCode::OK, 300),
+    )
+    .await;
+
+    let (status, summary) = pay(&app).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(summary.default_sum.total_requests, 0);
+    assert_eq!(summary.fallback.total_requests, 1);
+
+    let info = get(&app, "/admin/info").await;
+    assert!(info["default_success_rate"].as_f64().unwrap() < 1.0);
+    assert_eq!(info["fallback_success_rate"], 1.0);
+}
+
+#[tokio::test]
+async fn the_hedge_failing_counts_when_the_primary_wins() {
+    let app = app(
+        (StatusCode::OK, 200),
+        (StatusCode::INTERNAL_SERVER_ERROR, 0),
+    )
+    .await;
+
+    let (status, summary) = pay(&app).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(summary.default_sum.total_requests, 1);
+    assert_eq!(summary.fallback.total_requests, 0);
+
+    let info = get(&app, "/admin/info").await;
+    assert_eq!(info["default_success_rate"], 1.0);
+    assert!(info["fallback_success_rate"].as_f64().unwrap() < 1.0);
+}
+
+#[tokio::test]
+async fn fast_answers_are_never_hedged() {
+    let app = app((StatusCode::OK, 0), (StatusCode::INTERNAL_SERVER_ERROR, 0)).await;
+
+    let (status, summary) = pay(&app).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(summary.default_sum.total_requests, 1);
+
+    let info = get(&app, "/admin/info").await;
+    assert_eq!(info["fallback_success_rate"], 1.0);
+}
@@ -0,0 +1,126 @@
+//! Overlap rules of `Inflight` registrations: bounds are inclusive and `None` is unbounded.
+
+use std::{sync::Arc, time::Duration};
+
+use client_full::inflight::Inflight;
+
+#[test]
+fn single_timestamp_locks_only_ranges_containing_it() {
+    let inflight = Arc::new(Inflight::default());
+    let _guard = inflight.register(10);
+
+    assert!(inflight.is_locked(Some(10), Some(10)));
+    assert!(inflight.is_locked(Some(0), Some(10)));
+    assert!(inflight.is_locked(Some(10), None));
+    assert!(inflight.is_locked(None, None));
+    assert!(!inflight.is_locked(Some(11), None));
+    assert!(!inflight.is_locked(None, Some(9)));
+}
+
+#[test]
+fn nested_ranges_lock_until_both_are_released() {
+    let inflight = Arc::new(Inflight::default());
+    let outer = inflight.register_range(0, 100);
+    let inner = inflight.register_range(40, 60);
+
+    // Inside the outer range only, and inside both
+    assert!(inflight.is_locked(Some(10), Some(20)));
+    assert!(inflight.is_locked(Some(45), Some(55)));
+
+    drop(outer);
+    assert!(!inflight.is_locked(Some(10), Some(20)));
+    assert!(inflight.is_locked(Some(45), Some(55)));
+    // A query around the remaining range still overlaps it
+    assert!(inflight.is_locked(Some(0), Some(100)));
+
+    drop(inner);
+    assert!(!inflight.is_locked(None, None));
+}
+
+#[test]
+fn adjacent_ranges_share_no_timestamp() {
+    let inflight = Arc::new(Inflight::default());
+    let _left = inflight.register_range(0, 9);
+    let _right = inflight.register_range(20, 29);
+
+    assert!(!inflight.is_locked(Some(10), Some(19)));
+    assert!(inflight.is_locked(Some(9), Some(19)));
+    assert!(inflight.is_locked(Some(10), Some(20)));
+}
+
+#[test]
+fn range_bounds_may_come_in_any_order() {
+    let inflight = Arc::new(Inflight::default());
+    let _guard = inflight.register_range(30, 20);
+
+    assert!(inflight.is_locked(Some(25), Some(25)));
+    assert!(!inflight.is_locked(Some(31), None));
+}
+
+#[test]
+fn equal_ranges_are_tracked_separately() {
+    let inflight = Arc::new(Inflight::default());
+    let first = inflight.register(5);
+    let _second = inflight.register(5);
+
+    drop(first);
+    assert!(inflight.is_locked(Some(5), Some(5)));
+}
+
+#[tokio::test]
+async fn wait_returns_once_the_overlapping_guard_is_dropped() {
+    let inflight = Arc::new(Inflight::default());
+    let guard = inflight.register_range(0, 10);
+    let _unrelated = inflight.register(50);
+
+    let waiter = tokio::spawn({
+        let inflight = inflight.clone();
+        async move { inflight.wait_until_unlocked(Some(5), Some(20)).await }
+    });
+
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(!waiter.is_finished());
+
+    drop(guard);
+    tokio::time::timeout(Duration::from_secs(1), waiter)
+        .await
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn wait_ignores_registrations_made_after_it_started() {
+    let inflight = Arc::new(Inflight::default());
+    let guard = inflight.register(5);
+
+    let waiter = tokio::spawn({
+        let inflight = inflight.clone();
+        async move { inflight.wait_until_unlocked(None, None).await }
+    });
+
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let _later = inflight.register(5);
+    drop(guard);
+
+    tokio::time::timeout(Duration::from_secs(1), waiter)
+        .await
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn wait_with_timeout_reports_a_leaked_guard() {
+    let inflight = Arc::new(Inflight::default());
+    let _leaked = inflight.register(5);
+
+    assert!(
+        inflight
+            .wait_until_unlocked_timeout(Some(0), Some(10), Duration::from_millis(20))
+            .await
+    );
+    assert!(
+        !inflight
+            .wait_until_unlocked_timeout(Some(6), Some(10), Duration::from_millis(20))
+            .await
+    );
+}
@@ -0,0 +1,69 @@
+//! Summaries include what peers recorded unless `only_local=true`, or the internal header peers
+//! send each other, asks for this instance's alone.
+
+mod common;
+
+use axum::{
+    Json, Router,
+    body::{Body, to_bytes},
+    http::{Request, StatusCode},
+    routing::get,
+};
+use client_full::{AppState, INTERNAL_SUMMARY_HEADER, ProcessorSummaries, router};
+use tower::ServiceExt;
+
+/// A peer that recorded 5 payments.
+async fn peer() -> String {
+    let mut theirs = ProcessorSummaries::default();
+    theirs.default_sum.total_requests = 5;
+    theirs.default_sum.total_amount = 50.0;
+    let theirs = theirs.attributed_to("peer".to_string());
+
+    common::serve(
+        Router::new()
+            .route("/internal/ping", get(|| async { StatusCode::OK }))
+            .route(
+                "/payments-summary",
+                get(move || async move { Json(theirs.clone()) }),
+            ),
+    )
+    .await
+}
+
+async fn requests(app: &Router, request: Request<Body>) -> u64 {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let summary: ProcessorSummaries = serde_json::from_slice(&body).unwrap();
+
+    summary.default_sum.total_requests
+}
+
+fn get_summary(query: &str) -> Request<Body> {
+    Request::get(format!("/payments-summary{query}"))
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn only_local_is_a_boolean() {
+    let mut config = common::CONFIG.clone();
+    config.peer_urls = vec![peer().await];
+    let app = router(AppState::start(config).await);
+
+    assert_eq!(requests(&app, get_summary("")).await, 5);
+    assert_eq!(requests(&app, get_summary("?only_local=false")).await, 5);
+    assert_eq!(requests(&app, get_summary("?only_local=true")).await, 0);
+}
+
+#[tokio::test]
+async fn peer_queries_are_local() {
+    let mut config = common::CONFIG.clone();
+    config.peer_urls = vec![peer().await];
+    let app = router(AppState::start(config).await);
+    let request = Request::get("/payments-summary")
+        .header(INTERNAL_SUMMARY_HEADER, "true")
+        .body(Body::empty())
+        .unwrap();
+
+    assert_eq!(requests(&app, request).await, 0);
+}
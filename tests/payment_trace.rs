@@ -0,0 +1,74 @@
+//! `/admin/trace/{correlationId}` tells what happened to a single payment, attempt by attempt.
+
+mod common;
+
+use std::time::Duration;
+
+use axum::{
+    Router,
+    body::{Body, to_bytes},
+    http::{Request, StatusCode, header},
+};
+use client_full::{AppState, router};
+use serde_json::Value;
+use tower::ServiceExt;
+
+const ID: &str = "4a7901b8-7d26-4d9d-aa19-4dc1c7cf60b3";
+
+async fn app(capacity: usize) -> Router {
+    let mut config = common::config(&common::processor().await);
+    config.payment_events_capacity = capacity;
+
+    router(AppState::start(config).await)
+}
+
+async fn trace(app: &Router, id: &str) -> (StatusCode, Option<Value>) {
+    let request = Request::get(format!("/admin/trace/{id}"))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+    (status, serde_json::from_slice(&body).ok())
+}
+
+#[tokio::test]
+async fn confirmed_payment_shows_every_step() {
+    let app = app(100).await;
+    let payment = Request::post("/payments")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(format!(
+            r#"{{"correlationId":"{ID}","amount":19.9}}"#
+        )))
+        .unwrap();
+    app.clone().oneshot(payment).await.unwrap();
+
+    let mut body = Value::Null;
+
+    for _ in 0..100 {
+        body = trace(&app, ID).await.1.unwrap();
+
+        if body["status"]["state"] == "confirmed" {
+            break;
+        }
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    let events = body["events"].as_array().unwrap();
+
+    assert_eq!(body["correlationId"], ID);
+    assert_eq!(body["status"]["state"], "confirmed");
+    assert_eq!(events[0]["event"], "enqueued");
+    assert_eq!(events[1]["event"], "attempt");
+    assert_eq!(events[1]["outcome"], "success");
+    assert_eq!(events[1]["retries"], 0);
+    assert!(events[1]["latency_micros"].is_u64());
+}
+
+#[tokio::test]
+async fn unknown_payment_or_disabled_log_is_not_found() {
+    assert_eq!(trace(&app(100).await, ID).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(trace(&app(0).await, ID).await.0, StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,64 @@
+//! With `PAYMENTS_RESPONSE=detailed`, `/payments` tells queued payments apart from processed ones.
+
+mod common;
+
+use axum::{
+    body::{Body, to_bytes},
+    http::{Request, StatusCode, header},
+    response::Response,
+};
+use client_full::{AppState, PaymentReceipt, payload::ResponseStyle, router};
+use tower::ServiceExt;
+
+async fn pay(status: StatusCode, sync: bool) -> Response {
+    let mut config = common::config(&common::processor_answering(status).await);
+    config.payments_response = ResponseStyle::Detailed;
+    config.sync_submission = sync;
+
+    let request = Request::post("/payments")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            r#"{"correlationId":"4a7901b8-7d26-4d9d-aa19-4dc1c7cf60b3","amount":19.9}"#,
+        ))
+        .unwrap();
+
+    router(AppState::start(config).await)
+        .oneshot(request)
+        .await
+        .unwrap()
+}
+
+async fn body_json(response: Response) -> serde_json::Value {
+    let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+#[tokio::test]
+async fn queued_payments_answer_202() {
+    let response = pay(StatusCode::OK, false).await;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+    let receipt: PaymentReceipt = serde_json::from_value(body_json(response).await).unwrap();
+    assert_eq!(receipt.status, "queued");
+    assert!(receipt.requested_at.is_none());
+}
+
+#[tokio::test]
+async fn processed_payments_answer_201_with_requested_at() {
+    let response = pay(StatusCode::OK, true).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let receipt: PaymentReceipt = serde_json::from_value(body_json(response).await).unwrap();
+    assert_eq!(receipt.status, "processed");
+    assert!(receipt.requested_at.is_some());
+}
+
+#[tokio::test]
+async fn failed_payments_answer_a_json_error() {
+    let response = pay(StatusCode::INTERNAL_SERVER_ERROR, true).await;
+    assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+
+    let body = body_json(response).await;
+    assert_eq!(body["error"]["status"], 502);
+    assert_eq!(body["error"]["kind"], "processor_unavailable");
+}
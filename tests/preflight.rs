@@ -0,0 +1,53 @@
+//! With `PREFLIGHT_REQUIRE_PROCESSOR`, `/readyz` reports 503 until the boot preflight reached a
+//! processor.
+
+mod common;
+
+use std::time::Duration;
+
+use axum::{
+    body::{Body, to_bytes},
+    http::{Request, StatusCode},
+};
+use client_full::{AppState, router};
+use serde_json::Value;
+use tower::ServiceExt;
+
+async fn readiness(processor: &str, require_processor: bool) -> (StatusCode, Value) {
+    let mut config = common::config(processor);
+    config.peer_urls.clear();
+    config.preflight = true;
+    config.preflight_require_processor = require_processor;
+    config.preflight_timeout = Duration::from_millis(100);
+    let app = router(AppState::start(config).await);
+
+    let request = Request::get("/readyz").body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn ready_once_a_processor_answered() {
+    let (status, body) = readiness(&common::processor().await, true).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["processor_reachable"], true);
+}
+
+#[tokio::test]
+async fn unreachable_processors_keep_it_unready() {
+    let (status, body) = readiness(common::UNREACHABLE, true).await;
+
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["processor_reachable"], false);
+}
+
+#[tokio::test]
+async fn unreachable_processors_are_only_logged_unless_required() {
+    let (status, _) = readiness(common::UNREACHABLE, false).await;
+
+    assert_eq!(status, StatusCode::OK);
+}
@@ -0,0 +1,90 @@
+//! How `ProcessorError::parse` classifies processor answers, and which of them are retried, and
+//! how each processor's client is kept apart from the other's.
+
+mod common;
+
+use std::sync::Arc;
+
+use client_full::{
+    Processor,
+    processor::{ProcessorError, ProcessorRouter},
+};
+use reqwest::StatusCode;
+
+fn parse(status: StatusCode, body: &str) -> ProcessorError {
+    ProcessorError::parse(status, body.as_bytes())
+}
+
+#[test]
+fn bare_422_is_a_repeated_correlation_id() {
+    assert!(matches!(
+        parse(StatusCode::UNPROCESSABLE_ENTITY, ""),
+        ProcessorError::DuplicateCorrelationId
+    ));
+    assert!(matches!(
+        parse(
+            StatusCode::BAD_REQUEST,
+            r#"{"message":"CorrelationId already exists"}"#
+        ),
+        ProcessorError::DuplicateCorrelationId
+    ));
+    assert!(matches!(
+        parse(StatusCode::CONFLICT, "conflict"),
+        ProcessorError::DuplicateCorrelationId
+    ));
+}
+
+#[test]
+fn amount_errors_are_told_apart_by_message() {
+    assert!(matches!(
+        parse(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            r#"{"message":"Amount must be greater than zero"}"#
+        ),
+        ProcessorError::InvalidAmount
+    ));
+    assert!(matches!(
+        parse(
+            StatusCode::BAD_REQUEST,
+            r#"{"message":"requestedAt is required"}"#
+        ),
+        ProcessorError::Rejected(StatusCode::BAD_REQUEST)
+    ));
+}
+
+#[test]
+fn only_busy_or_failing_processors_are_retried() {
+    let rate_limited = parse(StatusCode::TOO_MANY_REQUESTS, "");
+    let internal = parse(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        r#"{"message":"amount overflow"}"#,
+    );
+
+    assert!(matches!(rate_limited, ProcessorError::RateLimited));
+    assert!(matches!(
+        internal,
+        ProcessorError::Internal(StatusCode::INTERNAL_SERVER_ERROR)
+    ));
+    assert!(rate_limited.is_retryable());
+    assert!(internal.is_retryable());
+
+    for rejected in [
+        parse(StatusCode::UNPROCESSABLE_ENTITY, ""),
+        parse(StatusCode::BAD_REQUEST, r#"{"message":"bad amount"}"#),
+        parse(StatusCode::NOT_FOUND, "not json"),
+    ] {
+        assert!(!rejected.is_retryable());
+    }
+}
+
+#[test]
+fn reconnecting_one_processor_leaves_the_other_alone() {
+    let processors = ProcessorRouter::new(&common::CONFIG);
+    let default = processors.get(Processor::Default).http().clone();
+    let fallback = processors.get(Processor::Fallback).http().clone();
+
+    processors.get(Processor::Default).reconnect();
+
+    assert!(!Arc::ptr_eq(&default, &processors.default.http()));
+    assert!(Arc::ptr_eq(&fallback, &processors.fallback.http()));
+}
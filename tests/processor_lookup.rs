@@ -0,0 +1,79 @@
+//! With `PROCESSOR_LOOKUP`, a payment whose request timed out is looked up on the processor, which
+//! may have taken it after all.
+
+mod common;
+
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    Json, Router,
+    body::Body,
+    extract::{Path, State},
+    http::{Request, StatusCode, header},
+    routing::{get, post},
+};
+use client_full::{AppState, router};
+use serde_json::Value;
+use tower::ServiceExt;
+
+const TIMEOUT: Duration = Duration::from_millis(100);
+
+type Taken = Arc<Mutex<HashSet<String>>>;
+
+/// A processor taking every payment, but answering only after the client gave up on it.
+async fn slow_processor() -> String {
+    async fn pay(State(taken): State<Taken>, Json(body): Json<Value>) -> StatusCode {
+        let id = body["correlationId"].as_str().unwrap().to_string();
+        taken.lock().unwrap().insert(id);
+        tokio::time::sleep(TIMEOUT * 3).await;
+
+        StatusCode::OK
+    }
+
+    async fn lookup(State(taken): State<Taken>, Path(id): Path<String>) -> StatusCode {
+        if taken.lock().unwrap().contains(&id) {
+            StatusCode::OK
+        } else {
+            StatusCode::NOT_FOUND
+        }
+    }
+
+    common::serve(
+        Router::new()
+            .route("/payments", post(pay))
+            .route("/payments/{id}", get(lookup))
+            .with_state(Taken::default()),
+    )
+    .await
+}
+
+async fn pay(lookup: bool) -> StatusCode {
+    let mut config = common::config(&slow_processor().await);
+    config.sync_submission = true;
+    config.processor_timeout = TIMEOUT;
+    config.processor_lookup = lookup;
+    let app = router(AppState::start(config).await);
+
+    let request = Request::post("/payments")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            r#"{"correlationId":"4a7901b8-7d26-4d9d-aa19-4dc1c7cf60b3","amount":19.9}"#,
+        ))
+        .unwrap();
+
+    app.oneshot(request).await.unwrap().status()
+}
+
+#[tokio::test]
+async fn timed_out_payments_the_processor_took_are_confirmed() {
+    assert_eq!(pay(true).await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn without_lookup_timeouts_fail() {
+    assert_eq!(pay(false).await, StatusCode::BAD_GATEWAY);
+}
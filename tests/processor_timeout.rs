@@ -0,0 +1,84 @@
+//! A processor that never answers only holds a payment up for `PROCESSOR_TIMEOUT_MS`, after which
+//! it is retried and its dispatch slot freed.
+
+mod common;
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use axum::{
+    Router,
+    body::{Body, to_bytes},
+    extract::State,
+    http::{Request, StatusCode, header},
+    routing::post,
+};
+use client_full::{AppState, ProcessorSummaries, router};
+use tower::ServiceExt;
+
+/// Hangs on its first request, then takes every payment.
+async fn stalling_processor() -> String {
+    common::serve(
+        Router::new()
+            .route(
+                "/payments",
+                post(|State(requests): State<Arc<AtomicUsize>>| async move {
+                    if requests.fetch_add(1, Ordering::Relaxed) == 0 {
+                        std::future::pending::<()>().await;
+                    }
+
+                    StatusCode::OK
+                }),
+            )
+            .with_state(Arc::new(AtomicUsize::new(0))),
+    )
+    .await
+}
+
+async fn recorded(app: &Router) -> u64 {
+    let request = Request::get("/payments-summary?only_local=true")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let summary: ProcessorSummaries = serde_json::from_slice(&body).unwrap();
+
+    summary.default_sum.total_requests + summary.fallback.total_requests
+}
+
+#[tokio::test]
+async fn hung_requests_are_retried() {
+    let mut config = common::config(&stalling_processor().await);
+    config.processor_timeout = Duration::from_millis(100);
+    // The hung request would hold the only slot forever
+    config.dispatch_concurrency = 1;
+    let app = router(AppState::start(config).await);
+
+    for id in [
+        "4a7901b8-7d26-4d9d-aa19-4dc1c7cf60b3",
+        "4a7901b8-7d26-4d9d-aa19-4dc1c7cf60b4",
+    ] {
+        let request = Request::post("/payments")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(format!(
+                r#"{{"correlationId":"{id}","amount":19.9}}"#
+            )))
+            .unwrap();
+        app.clone().oneshot(request).await.unwrap();
+    }
+
+    for _ in 0..100 {
+        if recorded(&app).await == 2 {
+            return;
+        }
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    panic!("hung request was never given up on");
+}
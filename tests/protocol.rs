@@ -0,0 +1,112 @@
+//! The internal API is served under `/internal/v1` and the bare `/internal`, and peers sharing no
+//! version with an instance are left out of its summaries.
+
+mod common;
+
+use std::time::Duration;
+
+use axum::{
+    Json, Router,
+    body::{Body, to_bytes},
+    http::{Request, StatusCode},
+    routing::get,
+};
+use client_full::{
+    AppState, ProcessorSummaries,
+    protocol::{PROTOCOL_VERSION, ProtocolVersion},
+    router,
+};
+use serde_json::Value;
+use tower::ServiceExt;
+
+/// An instance with nothing recorded that only speaks `version`.
+async fn peer(version: ProtocolVersion) -> String {
+    common::serve(
+        Router::new()
+            .route(
+                "/internal/version",
+                get(move || async move { Json(version) }),
+            )
+            .route("/internal/v9/ping", get(|| async { StatusCode::OK }))
+            .route(
+                "/payments-summary",
+                get(|| async { Json(ProcessorSummaries::default()) }),
+            ),
+    )
+    .await
+}
+
+async fn app(peer: String) -> Router {
+    let mut config = common::config(&common::processor().await);
+    config.peer_urls = vec![peer];
+    config.peer_summary_ttl = Duration::ZERO;
+
+    router(AppState::start(config).await)
+}
+
+async fn get_json(app: &Router, uri: &str) -> (StatusCode, Value) {
+    let request = Request::get(uri).body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+#[test]
+fn negotiates_the_highest_shared_version() {
+    let ours = ProtocolVersion::OURS;
+
+    assert_eq!(ours.negotiate(&ours), Some(PROTOCOL_VERSION));
+    assert_eq!(ours.negotiate(&ProtocolVersion::UNVERSIONED), Some(0));
+    assert_eq!(
+        ours.negotiate(&ProtocolVersion {
+            version: PROTOCOL_VERSION + 1,
+            min_version: PROTOCOL_VERSION,
+        }),
+        Some(PROTOCOL_VERSION)
+    );
+    assert_eq!(
+        ours.negotiate(&ProtocolVersion {
+            version: 9,
+            min_version: 9,
+        }),
+        None
+    );
+}
+
+#[tokio::test]
+async fn serves_versioned_and_unversioned_paths() {
+    let app = app("http://127.0.0.1:1".to_string()).await;
+
+    let (status, version) = get_json(&app, "/internal/version").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(version["version"], PROTOCOL_VERSION);
+
+    for uri in ["/internal/ping", "/internal/v1/ping", "/internal/v1/clock"] {
+        assert_eq!(get_json(&app, uri).await.0, StatusCode::OK, "{uri}");
+    }
+}
+
+#[tokio::test]
+async fn incompatible_peer_is_left_out_of_the_summary() {
+    let peer = peer(ProtocolVersion {
+        version: 9,
+        min_version: 9,
+    })
+    .await;
+    let app = app(peer.clone()).await;
+
+    let (_, info) = get_json(&app, "/admin/info").await;
+    assert_eq!(info["peer_protocol_versions"][&peer], Value::Null);
+    assert!(
+        info["peer_protocol_versions"]
+            .as_object()
+            .unwrap()
+            .contains_key(&peer)
+    );
+
+    let (status, summary) = get_json(&app, "/payments-summary").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(summary["partial"], true);
+}
@@ -0,0 +1,145 @@
+//! Payments are accepted into a queue of their own, whatever the dispatcher can keep up with, up
+//! to a capacity that can be changed while running.
+
+mod common;
+
+use std::time::Duration;
+
+use axum::{
+    Router,
+    body::{Body, to_bytes},
+    http::{Request, StatusCode, header},
+    routing::post,
+};
+use client_full::{AppState, router};
+use tower::ServiceExt;
+
+/// Never answers a payment.
+async fn stuck_processor() -> String {
+    let app = Router::new().route("/payments", post(std::future::pending::<StatusCode>));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    url
+}
+
+/// One payment at a time submitted to a processor that never answers.
+async fn stuck_app() -> Router {
+    let processor = stuck_processor().await;
+
+    let mut config = common::config(&processor);
+    config.processor_timeout = Duration::from_secs(60);
+    config.dispatch_concurrency = 1;
+    config.concurrency_target_latency = Duration::ZERO;
+    config.accept_queue_capacity = 64;
+    config.submit_queue_capacity = 2;
+
+    router(AppState::start(config).await)
+}
+
+fn payment(id: usize) -> Request<Body> {
+    Request::post("/payments")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(format!(
+            "{{\"correlationId\":\"4a7901b8-7d26-4d9d-aa19-4dc1c7cf60{id:02}\",\"amount\":10}}"
+        )))
+        .unwrap()
+}
+
+async fn info(app: &Router) -> serde_json::Value {
+    let request = Request::get("/admin/info").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+    serde_json::from_slice(&body).unwrap()
+}
+
+async fn set_queue_capacity(app: &Router, capacity: usize) -> StatusCode {
+    let request = Request::put("/admin/config")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(format!("{{\"queue_capacity\":{capacity}}}")))
+        .unwrap();
+
+    app.clone().oneshot(request).await.unwrap().status()
+}
+
+#[tokio::test]
+async fn handlers_keep_accepting_while_the_submit_queue_is_full() {
+    let app = stuck_app().await;
+
+    for id in 0..20 {
+        let response =
+            tokio::time::timeout(Duration::from_secs(1), app.clone().oneshot(payment(id)))
+                .await
+                .expect("the handler waited on the dispatcher")
+                .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    // One payment is stuck at the processor, the dispatcher holds the next waiting for a slot, the
+    // submit queue two more and the transfer task one waiting for room in it
+    let mut queues = (0, 0);
+
+    for _ in 0..100 {
+        let info = info(&app).await;
+        queues = (
+            info["queue_depth"].as_u64().unwrap(),
+            info["submit_queue_depth"].as_u64().unwrap(),
+        );
+
+        if queues == (15, 2) {
+            break;
+        }
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    assert_eq!(queues, (15, 2));
+}
+
+#[tokio::test]
+async fn handlers_hold_off_past_the_queue_capacity_until_it_grows() {
+    let app = stuck_app().await;
+
+    assert_eq!(set_queue_capacity(&app, 5).await, StatusCode::OK);
+    assert_eq!(info(&app).await["queue_capacity"], 5);
+
+    let handlers: Vec<_> = (0..20)
+        .map(|id| tokio::spawn(app.clone().oneshot(payment(id))))
+        .collect();
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // Five payments left the accept queue as in the test above, five more fill it
+    assert_eq!(info(&app).await["queue_depth"], 5);
+    assert_eq!(handlers.iter().filter(|h| h.is_finished()).count(), 10);
+
+    assert_eq!(set_queue_capacity(&app, 64).await, StatusCode::OK);
+
+    for handler in handlers {
+        let response = tokio::time::timeout(Duration::from_secs(1), handler)
+            .await
+            .expect("the handler still waited on the queue")
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
+
+#[tokio::test]
+async fn queue_capacity_stays_within_the_channel() {
+    let app = stuck_app().await;
+
+    assert_eq!(
+        set_queue_capacity(&app, 65).await,
+        StatusCode::UNPROCESSABLE_ENTITY
+    );
+    assert_eq!(
+        set_queue_capacity(&app, 0).await,
+        StatusCode::UNPROCESSABLE_ENTITY
+    );
+}
@@ -0,0 +1,79 @@
+//! `/admin/reconcile` reports what each processor counted that the instances didn't, and with
+//! `patch` records it, as long as the Db can take it.
+
+mod common;
+
+use axum::{
+    Json, Router,
+    body::{Body, to_bytes},
+    http::{Request, StatusCode, header},
+    routing::{get, post},
+};
+use client_full::{AppState, config::Config, router};
+use serde_json::{Value, json};
+use tower::ServiceExt;
+
+/// A processor that counted three payments of 10.0 in any window.
+async fn processor() -> String {
+    common::serve(
+        Router::new()
+            .route("/payments", post(|| async { StatusCode::OK }))
+            .route(
+                "/admin/payments-summary",
+                get(|| async { Json(json!({"totalRequests": 3, "totalAmount": 30.0})) }),
+            ),
+    )
+    .await
+}
+
+async fn reconcile(app: &Router) -> Value {
+    let request = Request::post("/admin/reconcile")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            r#"{"from":"2025-07-10T12:00:00Z","to":"2025-07-10T12:01:00Z","patch":true}"#,
+        ))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+async fn app(config: Config) -> Router {
+    router(AppState::start(config).await)
+}
+
+#[tokio::test]
+async fn missing_payments_are_patched_into_the_in_process_db() {
+    let app = app(common::config(&processor().await)).await;
+
+    let report = reconcile(&app).await;
+    assert_eq!(report["default"]["missingRequests"], 3);
+    assert_eq!(report["default"]["patched"], true);
+
+    let report = reconcile(&app).await;
+    assert_eq!(report["default"]["missingRequests"], 0);
+    assert_eq!(report["default"]["patched"], false);
+}
+
+#[cfg(feature = "persistence")]
+#[tokio::test]
+async fn other_backends_are_never_patched() {
+    use client_full::backend::BackendKind;
+
+    let path = std::env::temp_dir().join(format!("reconcile-{}.db", std::process::id()));
+    let mut config = common::config(&processor().await);
+    config.db_backend = BackendKind::Mmap;
+    config.mmap_db_path = path.display().to_string();
+    config.mmap_db_capacity = 1024;
+    let app = app(config).await;
+
+    for _ in 0..2 {
+        let report = reconcile(&app).await;
+        assert_eq!(report["default"]["missingRequests"], 3);
+        assert_eq!(report["default"]["patched"], false);
+    }
+
+    std::fs::remove_file(path).unwrap();
+}
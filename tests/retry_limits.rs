@@ -0,0 +1,105 @@
+//! Payments a processor keeps failing are retried within the shared `RETRY_BUDGET_RPS`, and given
+//! up on once older than `PAYMENT_MAX_AGE_SECS`.
+
+mod common;
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use axum::{
+    Router,
+    body::{Body, to_bytes},
+    extract::State,
+    http::{Request, StatusCode, header},
+    routing::post,
+};
+use client_full::{AppState, config::Config, router};
+use serde_json::Value;
+use tower::ServiceExt;
+
+const ID: &str = "4a7901b8-7d26-4d9d-aa19-4dc1c7cf60b3";
+
+/// A processor failing every payment, counting the attempts.
+async fn failing_processor() -> (String, Arc<AtomicUsize>) {
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let url = common::serve(
+        Router::new()
+            .route(
+                "/payments",
+                post(|State(attempts): State<Arc<AtomicUsize>>| async move {
+                    attempts.fetch_add(1, Ordering::Relaxed);
+                    StatusCode::INTERNAL_SERVER_ERROR
+                }),
+            )
+            .with_state(attempts.clone()),
+    )
+    .await;
+
+    (url, attempts)
+}
+
+async fn pay(config: Config) -> Router {
+    let app = router(AppState::start(config).await);
+    let request = Request::post("/payments")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(format!(
+            r#"{{"correlationId":"{ID}","amount":19.9}}"#
+        )))
+        .unwrap();
+    app.clone().oneshot(request).await.unwrap();
+
+    app
+}
+
+async fn state(app: &Router) -> Value {
+    let request = Request::get(format!("/payments/{ID}/status"))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let status: Value = serde_json::from_slice(&body).unwrap();
+
+    status["state"].clone()
+}
+
+#[tokio::test]
+async fn retries_stay_within_the_budget() {
+    let (processor, attempts) = failing_processor().await;
+    let mut config = common::config(&processor);
+    config.retry_budget_rps = 10.0;
+    config.retry_budget_burst = 1.0;
+    let _app = pay(config).await;
+
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    // The first attempt, the burst and 5 more over half a second, give or take scheduling
+    let attempts = attempts.load(Ordering::Relaxed);
+    assert!((2..=12).contains(&attempts), "{attempts} attempts");
+}
+
+#[tokio::test]
+async fn old_payments_are_given_up_on() {
+    let (processor, _) = failing_processor().await;
+    let mut config = common::config(&processor);
+    config.payment_max_age = Duration::from_secs(1);
+    config.retry_budget_rps = 20.0;
+    let app = pay(config).await;
+
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_ne!(state(&app).await, "dead-lettered");
+
+    for _ in 0..100 {
+        if state(&app).await == "dead-lettered" {
+            return;
+        }
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    panic!("payment was never given up on");
+}
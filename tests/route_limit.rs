@@ -0,0 +1,92 @@
+//! `/payments` and `/payments-summary` each have their own slots, one of them backed up answers
+//! 503 past its queue timeout while the other keeps going.
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use axum::{
+    Router,
+    body::Body,
+    http::{Request, StatusCode, header},
+    routing::post,
+};
+use client_full::{AppState, config::Config, router};
+use tower::ServiceExt;
+
+/// Against a processor holding every payment for two seconds before accepting it.
+async fn config() -> Config {
+    let processor = common::serve(Router::new().route(
+        "/payments",
+        post(|| async {
+            tokio::time::sleep(Duration::from_secs(2)).await;
+            StatusCode::OK
+        }),
+    ))
+    .await;
+
+    common::config(&processor)
+}
+
+fn payment(id: u8) -> Request<Body> {
+    Request::post("/payments")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(format!(
+            "{{\"correlationId\":\"4a7901b8-7d26-4d9d-aa19-4dc1c7cf60{id:02}\",\"amount\":10}}"
+        )))
+        .unwrap()
+}
+
+fn summary() -> Request<Body> {
+    Request::get("/payments-summary?only_local=true")
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn slow_summaries_leave_payments_alone() {
+    let mut config = config().await;
+    // Summaries wait on the payment held by the processor, a second away
+    config.summary_inflight_wait = Duration::from_secs(1);
+    config.summary_max_concurrent = 1;
+    config.summary_queue_timeout = Duration::from_millis(50);
+    let app = router(AppState::start(config).await);
+
+    app.clone().oneshot(payment(1)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let slow = tokio::spawn(app.clone().oneshot(summary()));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let turned_away = app.clone().oneshot(summary()).await.unwrap();
+    assert_eq!(turned_away.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    let started = Instant::now();
+    let accepted = app.oneshot(payment(2)).await.unwrap();
+    assert_eq!(accepted.status(), StatusCode::OK);
+    assert!(started.elapsed() < Duration::from_millis(500));
+
+    assert_eq!(slow.await.unwrap().unwrap().status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn payments_past_their_slots_are_turned_away() {
+    let mut config = config().await;
+    config.sync_submission = true;
+    config.payments_max_concurrent = 1;
+    config.payments_queue_timeout = Duration::from_millis(50);
+    let app = router(AppState::start(config).await);
+
+    let held = tokio::spawn(app.clone().oneshot(payment(1)));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let turned_away = app.clone().oneshot(payment(2)).await.unwrap();
+    assert_eq!(turned_away.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    let started = Instant::now();
+    let summary = app.oneshot(summary()).await.unwrap();
+    assert_eq!(summary.status(), StatusCode::OK);
+    assert!(started.elapsed() < Duration::from_millis(500));
+
+    held.abort();
+}
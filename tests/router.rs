@@ -0,0 +1,246 @@
+//! The routes mounted in-process through `router`, without binding a socket.
+
+mod common;
+
+use axum::{
+    Router,
+    body::{Body, to_bytes},
+    http::{Request, StatusCode, header},
+};
+use client_full::{AppState, ProcessorSummaries, router, worker::shard_index};
+use tower::ServiceExt;
+
+async fn app() -> Router {
+    router(AppState::start(common::CONFIG.clone()).await)
+}
+
+async fn body_json(response: axum::response::Response) -> serde_json::Value {
+    let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+#[tokio::test]
+async fn healthz_answers_ok() {
+    let response = app()
+        .await
+        .oneshot(Request::get("/healthz").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn malformed_payment_is_rejected_with_its_kind() {
+    let request = Request::post("/payments")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from("{\"correlationId\":"))
+        .unwrap();
+    let response = app().await.oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(body_json(response).await["error"]["kind"], "malformed_json");
+}
+
+#[tokio::test]
+async fn local_summary_starts_empty() {
+    let request = Request::get("/payments-summary?only_local=true")
+        .body(Body::empty())
+        .unwrap();
+    let response = app().await.oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let summaries: ProcessorSummaries = serde_json::from_value(body_json(response).await).unwrap();
+    assert_eq!(summaries.default_sum.total_requests, 0);
+    assert_eq!(summaries.fallback.total_requests, 0);
+}
+
+#[tokio::test]
+async fn lone_instances_start_and_summarize() {
+    let mut config = common::CONFIG.clone();
+    config.peer_urls.clear();
+    let request = Request::get("/payments-summary")
+        .body(Body::empty())
+        .unwrap();
+    let response = router(AppState::start(config).await)
+        .oneshot(request)
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn routes_nest_under_a_prefix() {
+    let app = Router::new().nest("/api", app().await);
+    let response = app
+        .oneshot(Request::get("/api/healthz").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn empty_export_is_just_the_csv_header() {
+    let response = app()
+        .await
+        .oneshot(Request::get("/admin/export").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "text/csv");
+
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(&body[..], b"processor,timestamp,count,amount\n");
+}
+
+#[tokio::test]
+async fn imported_records_are_exported_and_summarized() {
+    let app = app().await;
+    let csv = "processor,timestamp,count,amount\n\
+               default,1000000,2,3980\n\
+               fallback,2000000,1,1990\n";
+    let import = Request::post("/admin/import?format=csv")
+        .body(Body::from(csv))
+        .unwrap();
+    let response = app.clone().oneshot(import).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let export = Request::get("/admin/export?format=jsonl")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(export).await.unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+    assert_eq!(
+        std::str::from_utf8(&body).unwrap(),
+        "{\"processor\":\"default\",\"timestamp\":1000000,\"count\":2,\"amount\":3980}\n\
+         {\"processor\":\"fallback\",\"timestamp\":2000000,\"count\":1,\"amount\":1990}\n"
+    );
+
+    let summary = Request::get("/payments-summary?only_local=true")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(summary).await.unwrap();
+    let summaries: ProcessorSummaries = serde_json::from_value(body_json(response).await).unwrap();
+
+    assert_eq!(summaries.default_sum.total_requests, 2);
+    assert_eq!(summaries.fallback.total_requests, 1);
+}
+
+#[tokio::test]
+async fn snapshot_export_imports_into_another_instance() {
+    let source = app().await;
+    let import = Request::post("/admin/import?format=jsonl")
+        .body(Body::from(
+            "{\"processor\":\"default\",\"timestamp\":5,\"count\":1,\"amount\":100}\n",
+        ))
+        .unwrap();
+    source.clone().oneshot(import).await.unwrap();
+
+    let export = Request::get("/admin/export?format=snapshot")
+        .body(Body::empty())
+        .unwrap();
+    let response = source.oneshot(export).await.unwrap();
+    let snapshot = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+    let target = app().await;
+    let import = Request::post("/admin/import?format=snapshot")
+        .body(Body::from(snapshot))
+        .unwrap();
+    let response = target.clone().oneshot(import).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let export = Request::get("/admin/export").body(Body::empty()).unwrap();
+    let response = target.oneshot(export).await.unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+    assert_eq!(
+        &body[..],
+        b"processor,timestamp,count,amount\ndefault,5,1,100\n"
+    );
+}
+
+#[tokio::test]
+async fn imports_land_on_the_shard_their_timestamp_hashes_to() {
+    const SHARDS: usize = 4;
+
+    let mut config = common::CONFIG.clone();
+    config.worker = true;
+    config.worker_shards = SHARDS;
+    let app = router(AppState::start(config).await);
+    let timestamps: Vec<i64> = (1..=16).map(|i| i * 1_000_000).collect();
+    let csv: String = timestamps
+        .iter()
+        .map(|timestamp| format!("default,{timestamp},1,100\n"))
+        .collect();
+    let import = Request::post("/admin/import?format=csv")
+        .body(Body::from(csv))
+        .unwrap();
+    let response = app.clone().oneshot(import).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    // Exports go shard by shard, each in timestamp order
+    let mut expected = timestamps.clone();
+    expected.sort_by_key(|&timestamp| shard_index(timestamp, SHARDS));
+    // Shard 0 alone would export them in timestamp order
+    assert_ne!(expected, timestamps);
+    let expected: String = expected
+        .iter()
+        .map(|timestamp| format!("default,{timestamp},1,100\n"))
+        .collect();
+
+    let export = Request::get("/admin/export").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(export).await.unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(
+        std::str::from_utf8(&body).unwrap(),
+        format!("processor,timestamp,count,amount\n{expected}")
+    );
+
+    let summary = Request::get("/payments-summary?only_local=true")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(summary).await.unwrap();
+    let summaries: ProcessorSummaries = serde_json::from_value(body_json(response).await).unwrap();
+    assert_eq!(summaries.default_sum.total_requests, 16);
+}
+
+#[tokio::test]
+async fn malformed_import_changes_nothing() {
+    let app = app().await;
+    let import = Request::post("/admin/import?format=csv")
+        .body(Body::from("default,1,1,100\ndefault,2,one,100\n"))
+        .unwrap();
+    let response = app.clone().oneshot(import).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(&body[..], b"malformed record on line 2");
+
+    let export = Request::get("/admin/export").body(Body::empty()).unwrap();
+    let response = app.oneshot(export).await.unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(&body[..], b"processor,timestamp,count,amount\n");
+}
+
+#[cfg(feature = "dashboard")]
+#[tokio::test]
+async fn dashboard_is_served_as_html() {
+    let request = Request::get("/admin/dashboard")
+        .body(Body::empty())
+        .unwrap();
+    let response = app().await.oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(
+        response.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .starts_with("text/html")
+    );
+}
@@ -0,0 +1,188 @@
+//! Choices of each built-in `RoutingStrategy`, given the health of both processors and the
+//! feedback it was sent.
+
+use std::time::Duration;
+
+use client_full::{
+    Processor,
+    health::{CircuitBreaker, SuccessRate},
+    routing::{
+        DefaultFirst, FallbackOnly, HealthBased, LatencyEwma, Outcome, ProcessorHealth,
+        RetryParity, RoundRobin, RoutingContext, RoutingStrategy, Strategy,
+    },
+};
+
+/// Breakers open on the first failure and stay open for the whole test.
+struct Processors {
+    default_breaker: CircuitBreaker,
+    default_success: SuccessRate,
+    fallback_breaker: CircuitBreaker,
+    fallback_success: SuccessRate,
+}
+
+impl Processors {
+    fn new() -> Self {
+        Self {
+            default_breaker: CircuitBreaker::new(1, Duration::from_secs(60)),
+            default_success: SuccessRate::new(10),
+            fallback_breaker: CircuitBreaker::new(1, Duration::from_secs(60)),
+            fallback_success: SuccessRate::new(10),
+        }
+    }
+
+    fn ctx(&self, retries: u64) -> RoutingContext<'_> {
+        RoutingContext {
+            retries,
+            default: ProcessorHealth {
+                breaker: &self.default_breaker,
+                success: &self.default_success,
+            },
+            fallback: ProcessorHealth {
+                breaker: &self.fallback_breaker,
+                success: &self.fallback_success,
+            },
+        }
+    }
+}
+
+fn accepted(millis: u64) -> Outcome {
+    Outcome::Accepted {
+        latency: Duration::from_millis(millis),
+    }
+}
+
+#[test]
+fn default_first_starts_on_default_and_retries_where_it_can_succeed() {
+    let processors = Processors::new();
+    let strategy = DefaultFirst;
+
+    assert_eq!(strategy.choose(&processors.ctx(0)), Processor::Default);
+
+    for _ in 0..10 {
+        processors.default_success.record(false);
+    }
+
+    // A default that never succeeds gets no retries, first attempts still go to it
+    assert_eq!(strategy.choose(&processors.ctx(0)), Processor::Default);
+    for retries in 1..10 {
+        assert_eq!(
+            strategy.choose(&processors.ctx(retries)),
+            Processor::Fallback
+        );
+    }
+}
+
+#[test]
+fn retry_parity_alternates_from_default() {
+    let processors = Processors::new();
+    let strategy = RetryParity;
+    let chosen: Vec<_> = (0..4)
+        .map(|retries| strategy.choose(&processors.ctx(retries)))
+        .collect();
+
+    assert_eq!(
+        chosen,
+        [
+            Processor::Default,
+            Processor::Fallback,
+            Processor::Default,
+            Processor::Fallback
+        ]
+    );
+}
+
+#[test]
+fn health_based_leaves_default_only_while_its_breaker_is_open() {
+    let processors = Processors::new();
+    let strategy = HealthBased;
+
+    assert_eq!(strategy.choose(&processors.ctx(3)), Processor::Default);
+
+    processors.default_breaker.record_failure();
+    assert_eq!(strategy.choose(&processors.ctx(0)), Processor::Fallback);
+
+    // With both open, the default is the cheaper one to wait on
+    processors.fallback_breaker.record_failure();
+    assert_eq!(strategy.choose(&processors.ctx(0)), Processor::Default);
+
+    processors.default_breaker.record_success();
+    processors.fallback_breaker.record_success();
+    assert_eq!(strategy.choose(&processors.ctx(0)), Processor::Default);
+}
+
+#[test]
+fn latency_ewma_follows_the_faster_processor() {
+    let processors = Processors::new();
+    let strategy = LatencyEwma::default();
+
+    assert_eq!(strategy.choose(&processors.ctx(0)), Processor::Default);
+
+    // The fallback gets tried once the default has a latency to beat
+    strategy.feedback(Processor::Default, accepted(100));
+    assert_eq!(strategy.choose(&processors.ctx(0)), Processor::Fallback);
+
+    strategy.feedback(Processor::Fallback, accepted(20));
+    assert_eq!(strategy.choose(&processors.ctx(0)), Processor::Fallback);
+
+    // Failures count as slow, so the fallback soon averages above the default
+    for _ in 0..3 {
+        strategy.feedback(Processor::Fallback, Outcome::Failed);
+    }
+    assert_eq!(strategy.choose(&processors.ctx(0)), Processor::Default);
+}
+
+#[test]
+fn latency_ewma_weighs_recent_samples() {
+    let strategy = LatencyEwma::default();
+
+    strategy.feedback(Processor::Default, accepted(100));
+    assert_eq!(
+        strategy.average(Processor::Default),
+        Some(Duration::from_millis(100))
+    );
+
+    strategy.feedback(Processor::Default, accepted(200));
+    let average = strategy.average(Processor::Default).unwrap();
+    assert!(average > Duration::from_millis(100) && average < Duration::from_millis(150));
+    assert_eq!(strategy.average(Processor::Fallback), None);
+}
+
+#[test]
+fn fallback_only_ignores_health() {
+    let processors = Processors::new();
+    processors.fallback_breaker.record_failure();
+
+    for retries in 0..4 {
+        assert_eq!(
+            FallbackOnly.choose(&processors.ctx(retries)),
+            Processor::Fallback
+        );
+    }
+}
+
+#[test]
+fn round_robin_alternates_regardless_of_retries() {
+    let processors = Processors::new();
+    let strategy = RoundRobin::default();
+
+    assert_eq!(strategy.choose(&processors.ctx(5)), Processor::Default);
+    assert_eq!(strategy.choose(&processors.ctx(5)), Processor::Fallback);
+    assert_eq!(strategy.choose(&processors.ctx(0)), Processor::Default);
+}
+
+#[test]
+fn strategies_parse_from_config_names() {
+    for (name, strategy) in [
+        ("default-first", Strategy::DefaultFirst),
+        ("retry-parity", Strategy::RetryParity),
+        ("health-based", Strategy::HealthBased),
+        ("adaptive", Strategy::HealthBased),
+        ("latency-ewma", Strategy::LatencyEwma),
+        ("fallback-only", Strategy::FallbackOnly),
+        ("round-robin", Strategy::RoundRobin),
+    ] {
+        assert_eq!(name.parse::<Strategy>().unwrap(), strategy);
+    }
+
+    assert!("fastest".parse::<Strategy>().is_err());
+}
@@ -0,0 +1,111 @@
+//! With `SHUTDOWN_SNAPSHOT_PATH`, what an instance recorded and what it still had queued at
+//! shutdown is back after a restart.
+#![cfg(feature = "persistence")]
+
+mod common;
+
+use std::{
+    env,
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use axum::{Router, extract::State, http::StatusCode, routing::post};
+use client_full::{
+    PaymentGateway, PaymentPayload, SummaryQueryParams, config::Config,
+    shutdown_snapshot::ShutdownSnapshot,
+};
+
+/// Accepts payments while `up`, fails them otherwise.
+async fn processor(up: Arc<AtomicBool>) -> String {
+    let app = Router::new()
+        .route(
+            "/payments",
+            post(|State(up): State<Arc<AtomicBool>>| async move {
+                match up.load(Ordering::Relaxed) {
+                    true => StatusCode::OK,
+                    false => StatusCode::INTERNAL_SERVER_ERROR,
+                }
+            }),
+        )
+        .with_state(up);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    url
+}
+
+fn config(processor: &str, path: &Path) -> Config {
+    let mut config = common::config(processor);
+    config.shutdown_grace = Duration::from_millis(100);
+    config.shutdown_snapshot_path = Some(path.to_str().unwrap().to_string());
+    config
+}
+
+fn payment(id: u8) -> PaymentPayload {
+    PaymentPayload {
+        correlation_id: format!("4a7901b8-7d26-4d9d-aa19-4dc1c7cf60{id:02}")
+            .parse()
+            .unwrap(),
+        amount: 10.0,
+        tenant_id: None,
+    }
+}
+
+async fn recorded(gateway: &PaymentGateway, expected: u64) -> u64 {
+    let params = SummaryQueryParams {
+        only_local: Some(true),
+        ..Default::default()
+    };
+    let mut requests = 0;
+
+    for _ in 0..100 {
+        let summary = gateway.summary(params.clone()).await.unwrap();
+        requests = summary.default_sum.total_requests + summary.fallback.total_requests;
+
+        if requests == expected {
+            break;
+        }
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    requests
+}
+
+#[tokio::test]
+async fn recorded_and_queued_payments_survive_a_restart() {
+    let path = env::temp_dir().join(format!("shutdown-snapshot-{}.bin", std::process::id()));
+    let up = Arc::new(AtomicBool::new(true));
+    let processor = processor(up.clone()).await;
+
+    let first = PaymentGateway::new(config(&processor, &path)).await;
+
+    for id in 0..3 {
+        first.enqueue(payment(id)).await;
+    }
+    assert_eq!(recorded(&first, 3).await, 3);
+
+    // These keep failing, so they are still queued at shutdown
+    up.store(false, Ordering::Relaxed);
+    for id in 3..7 {
+        first.enqueue(payment(id)).await;
+    }
+    first.shutdown().await;
+
+    let snapshot = ShutdownSnapshot::take(&path).unwrap().unwrap();
+    assert_eq!(snapshot.pending.len(), 4);
+    snapshot.write(&path).unwrap();
+
+    up.store(true, Ordering::Relaxed);
+    let second = PaymentGateway::new(config(&processor, &path)).await;
+
+    assert!(!path.exists());
+    assert_eq!(recorded(&second, 7).await, 7);
+}
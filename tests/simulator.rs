@@ -0,0 +1,71 @@
+//! An instance runs against simulated processors as it would against the real ones.
+
+mod common;
+
+use std::time::Duration;
+
+use client_full::{
+    PaymentGateway, PaymentPayload, Summary, SummaryQueryParams,
+    simulator::{self, Simulation},
+};
+
+const PAYMENTS: u64 = 20;
+
+async fn processor_summary(url: &str) -> Summary {
+    reqwest::get(format!("{url}/admin/payments-summary"))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn payments_go_through_simulated_processors() {
+    let simulation = Simulation {
+        latency_min: Duration::from_millis(1),
+        latency_max: Duration::from_millis(5),
+        failure_rate: 0.0,
+    };
+    let default = simulator::spawn(simulation).await.unwrap();
+    let fallback = simulator::spawn(simulation).await.unwrap();
+
+    let mut config = common::CONFIG.clone();
+    config.default_processor_url = default.clone();
+    config.fallback_processor_url = fallback.clone();
+
+    let gateway = PaymentGateway::new(config).await;
+
+    for i in 0..PAYMENTS {
+        gateway
+            .enqueue(PaymentPayload {
+                correlation_id: format!("4a7901b8-7d26-4d9d-aa19-{i:012}").parse().unwrap(),
+                amount: 10.0,
+                tenant_id: None,
+            })
+            .await;
+    }
+
+    let params = SummaryQueryParams {
+        only_local: Some(true),
+        ..Default::default()
+    };
+    let mut recorded = 0;
+
+    for _ in 0..100 {
+        let summary = gateway.summary(params.clone()).await.unwrap();
+        recorded = summary.default_sum.total_requests + summary.fallback.total_requests;
+
+        if recorded == PAYMENTS {
+            break;
+        }
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    let processed = processor_summary(&default).await.total_requests
+        + processor_summary(&fallback).await.total_requests;
+
+    assert_eq!(recorded, PAYMENTS);
+    assert_eq!(processed, PAYMENTS);
+}
@@ -0,0 +1,51 @@
+//! With `include_fees=true`, each processor's summary also reports the fee it charged and what is
+//! left after it.
+
+mod common;
+
+use axum::{
+    Router,
+    body::{Body, to_bytes},
+    http::{Request, StatusCode, header},
+};
+use client_full::{AppState, router};
+use tower::ServiceExt;
+
+async fn summary(app: &Router, query: &str) -> serde_json::Value {
+    let request = Request::get(format!("/payments-summary?only_local=true{query}"))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn fees_are_only_reported_when_asked_for() {
+    let mut config = common::config(&common::processor().await);
+    config.sync_submission = true;
+    config.default_processor_fee = 0.05;
+    let app = router(AppState::start(config).await);
+
+    let request = Request::post("/payments")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            r#"{"correlationId":"4a7901b8-7d26-4d9d-aa19-4dc1c7cf60b3","amount":100.0}"#,
+        ))
+        .unwrap();
+    assert_eq!(
+        app.clone().oneshot(request).await.unwrap().status(),
+        StatusCode::OK
+    );
+
+    let plain = summary(&app, "").await;
+    assert!(plain["default"].get("totalFee").is_none());
+    assert!(plain["default"].get("netAmount").is_none());
+
+    let with_fees = summary(&app, "&include_fees=true").await;
+    assert_eq!(with_fees["default"]["totalAmount"], 100.0);
+    assert_eq!(with_fees["default"]["totalFee"], 5.0);
+    assert_eq!(with_fees["default"]["netAmount"], 95.0);
+    assert_eq!(with_fees["fallback"]["totalFee"], 0.0);
+}
@@ -0,0 +1,225 @@
+//! Two gateways in one process, each other's peer over loopback, record payments concurrently and
+//! either one's merged summary matches what the processor saw for any time range. A peer going by
+//! the same instance id is left out of the merge instead.
+
+mod common;
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    Json, Router,
+    extract::State,
+    http::StatusCode,
+    routing::{get, post},
+};
+use chrono::{DateTime, Utc};
+use client_full::{
+    AppState, PaymentPayload, ProcessorSummaries, SummaryQueryParams, client::ShowdownClient,
+    router,
+};
+use serde::Deserialize;
+use tokio::net::TcpListener;
+
+const PAYMENTS: u64 = 400;
+const RANGES: usize = 300;
+
+#[derive(Deserialize)]
+struct Received {
+    #[serde(rename = "requestedAt")]
+    requested_at: DateTime<Utc>,
+    amount: f64,
+}
+
+/// Every payment the processor accepted, as (requested at in micros, amount in cents).
+type GroundTruth = Arc<Mutex<Vec<(i64, u64)>>>;
+
+/// Accepts every payment and keeps it as the ground truth.
+async fn processor(truth: GroundTruth) -> String {
+    let app = Router::new()
+        .route(
+            "/payments",
+            post(
+                |State(truth): State<GroundTruth>, Json(payment): Json<Received>| async move {
+                    let cents = (payment.amount * 100.0).round() as u64;
+                    truth
+                        .lock()
+                        .unwrap()
+                        .push((payment.requested_at.timestamp_micros(), cents));
+                    StatusCode::OK
+                },
+            ),
+        )
+        .with_state(truth);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    url
+}
+
+/// Two instances that know each other as peers, neither with an instance index so both record
+/// every payment they're sent.
+async fn instances(processor: &str) -> [ShowdownClient; 2] {
+    let listeners = [
+        TcpListener::bind("127.0.0.1:0").await.unwrap(),
+        TcpListener::bind("127.0.0.1:0").await.unwrap(),
+    ];
+    let urls = listeners
+        .each_ref()
+        .map(|l| format!("http://{}", l.local_addr().unwrap()));
+
+    for (i, listener) in listeners.into_iter().enumerate() {
+        let mut config = common::config(processor);
+        config.instance_id = format!("instance-{i}");
+        config.peer_urls = vec![urls[1 - i].clone()];
+        config.instance_index = None;
+
+        let app = router(AppState::start(config).await);
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    }
+
+    urls.map(ShowdownClient::new)
+}
+
+/// xorshift64, enough to pick ranges reproducibly.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
+
+fn totals(summary: &ProcessorSummaries) -> (u64, u64) {
+    let requests = summary.default_sum.total_requests + summary.fallback.total_requests;
+    let amount = summary.default_sum.total_amount + summary.fallback.total_amount;
+
+    (requests, (amount * 100.0).round() as u64)
+}
+
+#[tokio::test]
+async fn merged_summaries_match_the_ground_truth() {
+    let truth = GroundTruth::default();
+    let processor = processor(truth.clone()).await;
+    let clients = instances(&processor).await;
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+
+    let submissions = (0..PAYMENTS).map(|i| {
+        let client = clients[i as usize % 2].clone();
+        let cents = 1 + rng.below(100_000);
+        let payload = PaymentPayload {
+            correlation_id: format!("4a7901b8-7d26-4d9d-aa19-{i:012}").parse().unwrap(),
+            amount: cents as f64 / 100.0,
+            tenant_id: None,
+        };
+
+        async move { client.submit_payment(&payload).await.unwrap() }
+    });
+    futures_util::future::join_all(submissions).await;
+
+    let everything = SummaryQueryParams::default();
+
+    for _ in 0..100 {
+        let recorded = futures_util::future::join_all(
+            clients.iter().map(|client| client.get_summary(&everything)),
+        )
+        .await;
+
+        if recorded
+            .iter()
+            .all(|summary| totals(summary.as_ref().unwrap()).0 == PAYMENTS)
+        {
+            break;
+        }
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    let truth = truth.lock().unwrap().clone();
+    assert_eq!(truth.len() as u64, PAYMENTS);
+
+    let first = truth.iter().map(|&(at, _)| at).min().unwrap();
+    let last = truth.iter().map(|&(at, _)| at).max().unwrap();
+    let span = (last - first) as u64 + 1;
+
+    for _ in 0..RANGES {
+        // Ends land on recorded timestamps half the time, to catch off-by-ones at the boundaries,
+        // and anywhere around them otherwise
+        let mut pick = || {
+            if rng.below(2) == 0 {
+                truth[rng.below(truth.len() as u64) as usize].0
+            } else {
+                first - 1_000 + rng.below(span + 2_000) as i64
+            }
+        };
+        let (a, b) = (pick(), pick());
+        let (from, to) = (a.min(b), a.max(b));
+
+        let expected = truth
+            .iter()
+            .filter(|&&(at, _)| (from..=to).contains(&at))
+            .fold((0, 0), |(requests, cents), &(_, amount)| {
+                (requests + 1, cents + amount)
+            });
+        let params = SummaryQueryParams {
+            from: DateTime::from_timestamp_micros(from),
+            to: DateTime::from_timestamp_micros(to),
+            ..Default::default()
+        };
+
+        for client in &clients {
+            let summary = client.get_summary(&params).await.unwrap();
+
+            assert!(!summary.partial);
+            assert_eq!(
+                totals(&summary),
+                expected,
+                "{} over {from}..={to}",
+                client.base_url()
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn peers_sharing_our_instance_id_are_left_out() {
+    let mut theirs = ProcessorSummaries::default();
+    theirs.default_sum.total_requests = 5;
+    theirs.default_sum.total_amount = 50.0;
+    let theirs = theirs.attributed_to(common::CONFIG.instance_id.clone());
+    let peer = common::serve(
+        Router::new()
+            .route("/internal/ping", get(|| async { StatusCode::OK }))
+            .route(
+                "/payments-summary",
+                get(move || async move { Json(theirs.clone()) }),
+            ),
+    )
+    .await;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let mut config = common::config(&common::processor().await);
+    config.peer_urls = vec![peer];
+    let app = router(AppState::start(config).await);
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let summary = ShowdownClient::new(&url)
+        .get_summary(&SummaryQueryParams::default())
+        .await
+        .unwrap();
+
+    assert!(summary.partial);
+    assert_eq!(totals(&summary), (0, 0));
+}
@@ -0,0 +1,106 @@
+//! Parsing and validation of the `from` and `to` summary query params.
+
+mod common;
+
+use axum::{
+    Router,
+    body::{Body, to_bytes},
+    http::{Request, StatusCode},
+};
+use chrono::{DateTime, TimeZone, Utc};
+use client_full::{AppState, router, timestamp};
+use tower::ServiceExt;
+
+fn utc(s: &str) -> DateTime<Utc> {
+    timestamp::parse(s).unwrap()
+}
+
+#[test]
+fn contest_timestamps_parse() {
+    let expected = Utc.with_ymd_and_hms(2020, 7, 10, 12, 34, 56).unwrap();
+
+    assert_eq!(utc("2020-07-10T12:34:56.000Z"), expected);
+    assert_eq!(utc("2020-07-10T12:34:56Z"), expected);
+}
+
+#[test]
+fn fractions_are_kept_to_the_microsecond() {
+    let parsed = utc("2020-07-10T12:34:56.123456Z");
+
+    assert_eq!(parsed.timestamp_subsec_micros(), 123_456);
+    assert_eq!(utc("2020-07-10T12:34:56.5Z").timestamp_subsec_millis(), 500);
+}
+
+#[test]
+fn offsets_are_normalized_to_utc() {
+    let expected = utc("2020-07-10T12:34:56Z");
+
+    assert_eq!(utc("2020-07-10T09:34:56-03:00"), expected);
+    assert_eq!(utc("2020-07-10T14:34:56.000+02:00"), expected);
+}
+
+#[test]
+fn timestamps_without_an_offset_are_utc() {
+    assert_eq!(utc("2020-07-10T12:34:56"), utc("2020-07-10T12:34:56Z"));
+    assert_eq!(utc("2020-07-10T12:34:56.000"), utc("2020-07-10T12:34:56Z"));
+}
+
+#[test]
+fn anything_else_is_refused() {
+    for s in [
+        "",
+        "yesterday",
+        "2020-07-10",
+        "2020-13-10T12:34:56Z",
+        "12:34:56",
+    ] {
+        assert!(timestamp::parse(s).is_err(), "{s:?} parsed");
+    }
+}
+
+async fn get(uri: &str) -> (StatusCode, serde_json::Value) {
+    let app: Router = router(AppState::start(common::CONFIG.clone()).await);
+
+    let request = Request::get(uri).body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+#[tokio::test]
+async fn inverted_ranges_are_rejected() {
+    let (status, body) = get(
+        "/payments-summary?only_local=true&from=2020-07-10T12:35:00.000Z&to=2020-07-10T12:34:00.000Z",
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"]["kind"], "invalid_range");
+}
+
+#[tokio::test]
+async fn empty_ends_leave_the_range_open() {
+    let (status, body) = get("/payments-summary?only_local=true&from=&to=").await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["default"]["totalRequests"], 0);
+}
+
+#[tokio::test]
+async fn equal_ends_and_offsets_are_accepted() {
+    let (status, _) = get(
+        "/payments-summary?only_local=true&from=2020-07-10T09:34:56-03:00&to=2020-07-10T12:34:56Z",
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn malformed_timestamps_are_rejected() {
+    let (status, _) = get("/payments-summary?only_local=true&from=yesterday").await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
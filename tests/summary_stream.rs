@@ -0,0 +1,79 @@
+//! `/payments-summary/stream` pushes the summary right away and again as payments are recorded.
+
+mod common;
+
+use std::time::Duration;
+
+use axum::{
+    Router,
+    body::{Body, BodyDataStream},
+    http::{Request, StatusCode, header},
+};
+use client_full::{AppState, ProcessorSummaries, router};
+use futures_util::StreamExt;
+use tower::ServiceExt;
+
+async fn app() -> Router {
+    let config = common::config(&common::processor().await);
+
+    router(AppState::start(config).await)
+}
+
+/// The next `summary` event, skipping keep-alive comments.
+async fn next_summary(body: &mut BodyDataStream) -> ProcessorSummaries {
+    loop {
+        let chunk = tokio::time::timeout(Duration::from_secs(5), body.next())
+            .await
+            .expect("no event within 5s")
+            .unwrap()
+            .unwrap();
+        let text = String::from_utf8(chunk.to_vec()).unwrap();
+
+        if let Some(data) = text.lines().find_map(|line| line.strip_prefix("data: ")) {
+            assert!(text.contains("event: summary"));
+            return serde_json::from_str(data).unwrap();
+        }
+    }
+}
+
+#[tokio::test]
+async fn pushes_after_every_recorded_payment() {
+    let app = app().await;
+    let stream = Request::get("/payments-summary/stream?only_local=true&interval_ms=60000&every=1")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(stream).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "text/event-stream"
+    );
+
+    let mut body = response.into_body().into_data_stream();
+    assert_eq!(next_summary(&mut body).await.default_sum.total_requests, 0);
+
+    let payment = Request::post("/payments")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            r#"{"correlationId":"4a7901b8-7d26-4d9d-aa19-4dc1c7cf60b3","amount":19.9}"#,
+        ))
+        .unwrap();
+    app.oneshot(payment).await.unwrap();
+
+    let summary = next_summary(&mut body).await;
+    assert_eq!(
+        summary.default_sum.total_requests + summary.fallback.total_requests,
+        1
+    );
+}
+
+#[tokio::test]
+async fn pushing_never_is_rejected() {
+    let request = Request::get("/payments-summary/stream?interval_ms=0&every=0")
+        .body(Body::empty())
+        .unwrap();
+    let response = app().await.oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
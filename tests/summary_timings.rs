@@ -0,0 +1,66 @@
+//! With `SUMMARY_TIMING_HEADERS`, summaries say how long they spent on peers, on the local Dbs
+//! and waiting on payments in flight.
+
+mod common;
+
+use std::time::Duration;
+
+use axum::{
+    Json, Router,
+    body::Body,
+    http::{Request, StatusCode},
+    response::Response,
+    routing::get,
+};
+use client_full::{AppState, ProcessorSummaries, router};
+use tower::ServiceExt;
+
+const PEER_DELAY: Duration = Duration::from_millis(200);
+
+/// An instance with nothing recorded that takes `PEER_DELAY` to answer summaries.
+async fn slow_peer() -> String {
+    common::serve(Router::new().route(
+        "/payments-summary",
+        get(|| async {
+            tokio::time::sleep(PEER_DELAY).await;
+            Json(ProcessorSummaries::default())
+        }),
+    ))
+    .await
+}
+
+async fn summary(timing_headers: bool) -> Response {
+    let mut config = common::config(&common::processor().await);
+    config.peer_urls = vec![slow_peer().await];
+    config.summary_timing_headers = timing_headers;
+    let app = router(AppState::start(config).await);
+
+    let request = Request::get("/payments-summary")
+        .body(Body::empty())
+        .unwrap();
+    app.oneshot(request).await.unwrap()
+}
+
+fn micros(resp: &Response, name: &str) -> Duration {
+    let value = resp.headers()[name].to_str().unwrap();
+
+    Duration::from_micros(value.parse().unwrap())
+}
+
+#[tokio::test]
+async fn time_spent_on_peers_is_reported() {
+    let resp = summary(true).await;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(micros(&resp, "x-remote-us") >= PEER_DELAY);
+    assert!(micros(&resp, "x-local-us") < PEER_DELAY);
+    assert_eq!(micros(&resp, "x-wait-inflight-us"), Duration::ZERO);
+}
+
+#[tokio::test]
+async fn headers_are_off_by_default() {
+    let resp = summary(false).await;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(!resp.headers().contains_key("x-remote-us"));
+}
@@ -0,0 +1,60 @@
+//! With `SYNC_SUBMISSION`, `/payments` only answers once the processors did.
+
+mod common;
+
+use std::time::Duration;
+
+use axum::{
+    Router,
+    body::{Body, to_bytes},
+    http::{Request, StatusCode, header},
+};
+use client_full::{AppState, ProcessorSummaries, router};
+use tower::ServiceExt;
+
+async fn app(status: StatusCode) -> Router {
+    let mut config = common::config(&common::processor_answering(status).await);
+    config.sync_submission = true;
+
+    router(AppState::start(config).await)
+}
+
+async fn pay(app: &Router) -> StatusCode {
+    let request = Request::post("/payments")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            r#"{"correlationId":"4a7901b8-7d26-4d9d-aa19-4dc1c7cf60b3","amount":19.9}"#,
+        ))
+        .unwrap();
+
+    app.clone().oneshot(request).await.unwrap().status()
+}
+
+async fn recorded(app: &Router) -> u64 {
+    let request = Request::get("/payments-summary?only_local=true")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let summary: ProcessorSummaries = serde_json::from_slice(&body).unwrap();
+
+    summary.default_sum.total_requests + summary.fallback.total_requests
+}
+
+#[tokio::test]
+async fn confirmed_payments_are_recorded_before_the_answer() {
+    let app = app(StatusCode::OK).await;
+
+    assert_eq!(pay(&app).await, StatusCode::OK);
+    assert_eq!(recorded(&app).await, 1);
+}
+
+#[tokio::test]
+async fn failed_payments_answer_502_and_are_not_retried() {
+    let app = app(StatusCode::INTERNAL_SERVER_ERROR).await;
+
+    assert_eq!(pay(&app).await, StatusCode::BAD_GATEWAY);
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(recorded(&app).await, 0);
+}
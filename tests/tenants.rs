@@ -0,0 +1,110 @@
+//! Payments tagged with a tenant are summarized apart from every other tenant's and from untagged
+//! payments.
+
+mod common;
+
+use std::time::Duration;
+
+use axum::{
+    Router,
+    body::{Body, to_bytes},
+    http::{Request, StatusCode, header},
+};
+use client_full::{AppState, ProcessorSummaries, router};
+use tower::ServiceExt;
+
+async fn app(max_tenants: usize) -> Router {
+    let mut config = common::config(&common::processor().await);
+    config.max_tenants = max_tenants;
+
+    router(AppState::start(config).await)
+}
+
+fn payment(id: u8, body_tenant: Option<&str>, header_tenant: Option<&str>) -> Request<Body> {
+    let tenant = body_tenant.map_or(String::new(), |t| format!(",\"tenantId\":\"{t}\""));
+    let body = format!(
+        "{{\"correlationId\":\"4a7901b8-7d26-4d9d-aa19-4dc1c7cf60{id:02}\",\"amount\":10{tenant}}}"
+    );
+    let mut request = Request::post("/payments").header(header::CONTENT_TYPE, "application/json");
+
+    if let Some(tenant) = header_tenant {
+        request = request.header("x-tenant-id", tenant);
+    }
+
+    request.body(Body::from(body)).unwrap()
+}
+
+async fn summary(app: &Router, query: &str) -> ProcessorSummaries {
+    let request = Request::get(format!("/payments-summary?only_local=true{query}"))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+    serde_json::from_slice(&body).unwrap()
+}
+
+fn requests(summary: &ProcessorSummaries) -> u64 {
+    summary.default_sum.total_requests + summary.fallback.total_requests
+}
+
+#[tokio::test]
+async fn tenants_are_summarized_apart() {
+    let app = app(0).await;
+
+    for request in [
+        payment(1, Some("acme"), None),
+        payment(2, Some("acme"), Some("globex")),
+        payment(3, None, Some("globex")),
+        payment(4, None, None),
+        // acme's first correlationId, a payment of its own under another tenant
+        payment(1, None, Some("globex")),
+    ] {
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    let mut counts = [0; 3];
+
+    for _ in 0..100 {
+        counts = [
+            requests(&summary(&app, "&tenant=acme").await),
+            requests(&summary(&app, "&tenant=globex").await),
+            requests(&summary(&app, "").await),
+        ];
+
+        if counts == [2, 2, 1] {
+            break;
+        }
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    assert_eq!(counts, [2, 2, 1]);
+    assert_eq!(requests(&summary(&app, "&tenant=initech").await), 0);
+}
+
+#[tokio::test]
+async fn invalid_tenant_header_is_rejected() {
+    let response = app(0)
+        .await
+        .oneshot(payment(1, None, Some("no spaces")))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn tenants_past_the_limit_are_refused() {
+    let app = app(1).await;
+    let first = app
+        .clone()
+        .oneshot(payment(1, Some("acme"), None))
+        .await
+        .unwrap();
+    let second = app.oneshot(payment(2, Some("globex"), None)).await.unwrap();
+
+    assert_eq!(first.status(), StatusCode::OK);
+    assert_eq!(second.status(), StatusCode::UNPROCESSABLE_ENTITY);
+}
@@ -0,0 +1,68 @@
+//! Summaries filter by when payments were received unless `timestamp_basis=processed` asks for
+//! when the processor confirmed them.
+
+mod common;
+
+use std::time::Duration;
+
+use axum::{
+    Router,
+    body::{Body, to_bytes},
+    http::{Request, StatusCode, header},
+    routing::post,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use client_full::{AppState, ProcessorSummaries, router};
+use tower::ServiceExt;
+
+const DELAY: Duration = Duration::from_millis(200);
+
+async fn count(app: &Router, from: DateTime<Utc>, basis: &str) -> u64 {
+    let from = from.to_rfc3339_opts(SecondsFormat::Micros, true);
+    let request = Request::get(format!(
+        "/payments-summary?only_local=true&from={from}{basis}"
+    ))
+    .body(Body::empty())
+    .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let summary: ProcessorSummaries = serde_json::from_slice(&body).unwrap();
+
+    summary.default_sum.total_requests + summary.fallback.total_requests
+}
+
+#[tokio::test]
+async fn processed_basis_filters_by_confirmation_time() {
+    // Takes `DELAY` to confirm every payment
+    let processor = common::serve(Router::new().route(
+        "/payments",
+        post(|| async {
+            tokio::time::sleep(DELAY).await;
+            StatusCode::OK
+        }),
+    ))
+    .await;
+    let mut config = common::config(&processor);
+    config.sync_submission = true;
+    let app = router(AppState::start(config).await);
+
+    let received = Utc::now();
+    let request = Request::post("/payments")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            r#"{"correlationId":"4a7901b8-7d26-4d9d-aa19-4dc1c7cf60b3","amount":19.9}"#,
+        ))
+        .unwrap();
+    assert_eq!(
+        app.clone().oneshot(request).await.unwrap().status(),
+        StatusCode::OK
+    );
+
+    // Received before this, confirmed after it
+    let between = received + DELAY / 2;
+
+    assert_eq!(count(&app, between, "").await, 0);
+    assert_eq!(count(&app, between, "&timestamp_basis=requested").await, 0);
+    assert_eq!(count(&app, between, "&timestamp_basis=processed").await, 1);
+    assert_eq!(count(&app, received, "").await, 1);
+}
@@ -0,0 +1,85 @@
+//! With `WEBHOOK_URL`, every confirmed payment is POSTed downstream in batches, requests the
+//! webhook fails being sent again.
+
+mod common;
+
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use axum::{Json, Router, extract::State, http::StatusCode, routing::post};
+use client_full::{PaymentGateway, PaymentPayload, Processor, webhook::Confirmation};
+use common::serve;
+
+const PAYMENTS: usize = 10;
+
+#[derive(Clone, Default)]
+struct Received {
+    requests: Arc<AtomicUsize>,
+    confirmations: Arc<Mutex<Vec<Confirmation>>>,
+}
+
+/// Fails its first request, then keeps whatever it is sent.
+async fn hook(
+    State(received): State<Received>,
+    Json(batch): Json<Vec<Confirmation>>,
+) -> StatusCode {
+    if received.requests.fetch_add(1, Ordering::Relaxed) == 0 {
+        return StatusCode::INTERNAL_SERVER_ERROR;
+    }
+
+    received.confirmations.lock().unwrap().extend(batch);
+    StatusCode::OK
+}
+
+#[tokio::test]
+async fn confirmed_payments_are_posted_downstream() {
+    let received = Received::default();
+    let webhook = serve(
+        Router::new()
+            .route("/hook", post(hook))
+            .with_state(received.clone()),
+    )
+    .await;
+    let mut config = common::config(&common::processor().await);
+    config.webhook_url = Some(format!("{webhook}/hook"));
+    config.webhook_batch_size = 4;
+    config.webhook_batch_delay = Duration::from_millis(20);
+    config.webhook_retry_backoff = Duration::from_millis(10);
+
+    let gateway = PaymentGateway::new(config).await;
+
+    for i in 0..PAYMENTS {
+        gateway
+            .enqueue(PaymentPayload {
+                correlation_id: format!("4a7901b8-7d26-4d9d-aa19-{i:012}").parse().unwrap(),
+                amount: 10.0,
+                tenant_id: None,
+            })
+            .await;
+    }
+
+    for _ in 0..100 {
+        if received.confirmations.lock().unwrap().len() >= PAYMENTS {
+            break;
+        }
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    let mut confirmations = received.confirmations.lock().unwrap().clone();
+    confirmations.sort_by(|a, b| a.correlation_id.cmp(&b.correlation_id));
+    confirmations.dedup_by(|a, b| a.correlation_id == b.correlation_id);
+
+    assert_eq!(confirmations.len(), PAYMENTS);
+    assert!(
+        confirmations
+            .iter()
+            .all(|c| c.processor == Processor::Default && c.amount == 10.0)
+    );
+    assert!(received.requests.load(Ordering::Relaxed) > 1);
+}